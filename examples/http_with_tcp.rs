@@ -1,5 +1,3 @@
-use std::net::ToSocketAddrs;
-
 use bytes::Bytes;
 use http::{request, Uri};
 use monoio::net::TcpListener;
@@ -11,7 +9,7 @@ use monoio_http::{
     h1::payload::Payload,
 };
 use monoio_transports::{
-    connectors::{Connector, TcpConnector},
+    connectors::{Connector, HostPort, TcpConnector},
     http::HttpConnector,
 };
 
@@ -66,10 +64,13 @@ async fn main() -> Result<(), monoio_transports::TransportError> {
         port: u16,
     }
 
-    impl ToSocketAddrs for Key {
-        type Iter = std::vec::IntoIter<std::net::SocketAddr>;
-        fn to_socket_addrs(&self) -> std::io::Result<Self::Iter> {
-            (self.host.as_str(), self.port).to_socket_addrs()
+    impl HostPort for Key {
+        fn host(&self) -> &str {
+            &self.host
+        }
+
+        fn port(&self) -> u16 {
+            self.port
         }
     }
 
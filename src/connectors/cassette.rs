@@ -0,0 +1,510 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    future::poll_fn,
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    task::{Poll, Waker},
+};
+
+use monoio::{
+    buf::{IoBuf, IoBufMut, IoVecBuf, IoVecBufMut},
+    io::{AsyncReadRent, AsyncWriteRent, Split},
+    BufResult,
+};
+
+use super::{layer::ConnectorLayer, mock::request_len, Connector, TransportConnMeta};
+use crate::connectors::TransportConnMetadata;
+
+/// Whether a [`CassetteConnector`] records or replays its connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CassetteMode {
+    /// Connects with the inner connector and stores the response to each request.
+    Record,
+    /// Answers requests with the stored responses, never connecting.
+    Replay,
+}
+
+/// A 64-bit FNV-1a hasher, stable across runs and builds unlike the std ones.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// The cassette entries of the connections to one key.
+#[derive(Debug, Clone)]
+struct Tape {
+    dir: Rc<PathBuf>,
+    key_hash: u64,
+}
+
+impl Tape {
+    /// Returns the file storing the response to `request`.
+    fn path(&self, request: &[u8]) -> PathBuf {
+        let mut hasher = Fnv(self.key_hash);
+        hasher.write(request);
+        self.dir.join(format!("{:016x}.http", hasher.finish()))
+    }
+}
+
+/// A layer wrapping connectors into a [`CassetteConnector`].
+#[derive(Debug, Clone)]
+pub struct CassetteLayer {
+    dir: PathBuf,
+    mode: CassetteMode,
+}
+
+impl CassetteLayer {
+    #[inline]
+    pub fn new(dir: impl Into<PathBuf>, mode: CassetteMode) -> Self {
+        Self {
+            dir: dir.into(),
+            mode,
+        }
+    }
+}
+
+impl<C> ConnectorLayer<C> for CassetteLayer {
+    type Connector = CassetteConnector<C>;
+
+    #[inline]
+    fn layer(&self, inner: C) -> Self::Connector {
+        CassetteConnector::new(inner, self.dir.clone(), self.mode)
+    }
+}
+
+/// A connector recording the HTTP/1.1 exchanges over its connections to a directory, to replay
+/// them later without network access.
+///
+/// Each response is stored raw in a file named after a hash of the connection key and of the
+/// bytes of its request, so replayed requests must be sent byte for byte as they were recorded:
+/// headers carrying timestamps or random values do not match. A request recorded twice keeps its
+/// last response.
+///
+/// Requests are delimited by the end of their head and their `Content-Length` body, chunked
+/// request bodies are not supported. The connector must see plaintext, wrapped around a
+/// [`TlsConnector`](super::TlsConnector) restricted to HTTP/1.1 rather than inside it. Replayed
+/// connections report empty metadata, and requests without a recorded response fail with
+/// [`io::ErrorKind::NotFound`] when their response is read.
+#[derive(Debug, Clone)]
+pub struct CassetteConnector<C> {
+    inner_connector: C,
+    dir: Rc<PathBuf>,
+    mode: CassetteMode,
+}
+
+impl<C> CassetteConnector<C> {
+    #[inline]
+    pub fn new(inner_connector: C, dir: impl Into<PathBuf>, mode: CassetteMode) -> Self {
+        Self {
+            inner_connector,
+            dir: Rc::new(dir.into()),
+            mode,
+        }
+    }
+
+    #[inline]
+    pub fn inner_connector(&self) -> &C {
+        &self.inner_connector
+    }
+
+    #[inline]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    #[inline]
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+}
+
+impl<C, K> Connector<K> for CassetteConnector<C>
+where
+    C: Connector<K>,
+    C::Error: From<io::Error>,
+    K: Hash,
+{
+    type Connection = CassetteStream<C::Connection>;
+    type Error = C::Error;
+
+    async fn connect(&self, key: K) -> Result<Self::Connection, Self::Error> {
+        let mut hasher = Fnv::default();
+        key.hash(&mut hasher);
+        let tape = Tape {
+            dir: self.dir.clone(),
+            key_hash: hasher.finish(),
+        };
+        let inner = match self.mode {
+            CassetteMode::Record => {
+                std::fs::create_dir_all(&*self.dir)?;
+                Inner::Record {
+                    io: self.inner_connector.connect(key).await?,
+                    tape,
+                    request: RefCell::new(Vec::new()),
+                    response: RefCell::new(None),
+                }
+            }
+            CassetteMode::Replay => Inner::Replay {
+                tape,
+                state: RefCell::new(Replay::default()),
+            },
+        };
+        Ok(CassetteStream { inner })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Replay {
+    request: Vec<u8>,
+    response: VecDeque<u8>,
+    missing: Option<PathBuf>,
+    closed: bool,
+    reader: Option<Waker>,
+}
+
+#[derive(Debug)]
+enum Inner<S> {
+    Record {
+        io: S,
+        tape: Tape,
+        request: RefCell<Vec<u8>>,
+        // The file storing the response being read.
+        response: RefCell<Option<std::fs::File>>,
+    },
+    Replay {
+        tape: Tape,
+        state: RefCell<Replay>,
+    },
+}
+
+/// A connection of a [`CassetteConnector`], recorded or replayed.
+#[derive(Debug)]
+pub struct CassetteStream<S> {
+    inner: Inner<S>,
+}
+
+impl<S> CassetteStream<S> {
+    /// Whether this connection is replayed.
+    #[inline]
+    pub fn is_replayed(&self) -> bool {
+        matches!(self.inner, Inner::Replay { .. })
+    }
+
+    /// Accounts for bytes written, storing or loading the responses of the requests they
+    /// complete.
+    fn written(&self, bytes: &[u8]) -> io::Result<()> {
+        match &self.inner {
+            Inner::Record {
+                tape,
+                request,
+                response,
+                ..
+            } => {
+                let mut request = request.borrow_mut();
+                request.extend_from_slice(bytes);
+                while let Some(len) = request_len(&request) {
+                    if request.len() < len {
+                        break;
+                    }
+                    let file = std::fs::File::create(tape.path(&request[..len]))?;
+                    *response.borrow_mut() = Some(file);
+                    request.drain(..len);
+                }
+                Ok(())
+            }
+            Inner::Replay { tape, state } => {
+                let mut state = state.borrow_mut();
+                state.request.extend_from_slice(bytes);
+                while let Some(len) = request_len(&state.request) {
+                    if state.request.len() < len {
+                        break;
+                    }
+                    let path = tape.path(&state.request[..len]);
+                    state.request.drain(..len);
+                    match std::fs::read(&path) {
+                        Ok(response) => state.response.extend(response),
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                            state.missing = Some(path);
+                        }
+                        Err(e) => return Err(e),
+                    }
+                    if let Some(reader) = state.reader.take() {
+                        reader.wake();
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Stores bytes read from a recorded connection.
+    fn read_recorded(&self, bytes: &[u8]) -> io::Result<()> {
+        if let Inner::Record { response, .. } = &self.inner {
+            if let Some(file) = response.borrow_mut().as_mut() {
+                file.write_all(bytes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for replayed bytes, then moves up to `len` of them to `dst`.
+    ///
+    /// # Safety
+    ///
+    /// `dst` must be valid for writes of `len` bytes.
+    async unsafe fn replay(state: &RefCell<Replay>, dst: *mut u8, len: usize) -> io::Result<usize> {
+        poll_fn(|cx| {
+            let mut state = state.borrow_mut();
+            if !state.response.is_empty() || state.missing.is_some() || state.closed {
+                return Poll::Ready(());
+            }
+            state.reader = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await;
+        let mut state = state.borrow_mut();
+        if state.response.is_empty() {
+            if let Some(path) = state.missing.take() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no recorded response in {}", path.display()),
+                ));
+            }
+        }
+        let n = len.min(state.response.len());
+        for (i, b) in state.response.drain(..n).enumerate() {
+            dst.add(i).write(b);
+        }
+        Ok(n)
+    }
+}
+
+/// Collects the first `n` bytes of `iovecs`.
+///
+/// # Safety
+///
+/// The iovecs must be valid and initialized up to `n` bytes.
+unsafe fn iovec_bytes(iovecs: &[libc::iovec], mut n: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for iovec in iovecs {
+        let len = iovec.iov_len.min(n);
+        bytes.extend_from_slice(std::slice::from_raw_parts(iovec.iov_base as *const u8, len));
+        n -= len;
+    }
+    bytes
+}
+
+impl<S: TransportConnMetadata<Metadata = TransportConnMeta>> TransportConnMetadata
+    for CassetteStream<S>
+{
+    type Metadata = TransportConnMeta;
+
+    fn get_conn_metadata(&self) -> Self::Metadata {
+        match &self.inner {
+            Inner::Record { io, .. } => io.get_conn_metadata(),
+            Inner::Replay { .. } => TransportConnMeta::default(),
+        }
+    }
+}
+
+impl<S: AsyncReadRent> AsyncReadRent for CassetteStream<S> {
+    async fn read<T: IoBufMut>(&mut self, mut buf: T) -> BufResult<usize, T> {
+        match &mut self.inner {
+            Inner::Record { io, .. } => {
+                let (res, mut buf) = io.read(buf).await;
+                let res = res.and_then(|n| {
+                    // SAFETY: the buffer was initialized up to `n` bytes.
+                    let read = unsafe { std::slice::from_raw_parts(buf.write_ptr(), n) };
+                    self.read_recorded(read).map(|_| n)
+                });
+                (res, buf)
+            }
+            Inner::Replay { state, .. } => {
+                // SAFETY: the buffer is valid for `bytes_total` bytes, `n` of which are written.
+                unsafe {
+                    let res = Self::replay(state, buf.write_ptr(), buf.bytes_total()).await;
+                    if let Ok(n) = res {
+                        buf.set_init(n);
+                    }
+                    (res, buf)
+                }
+            }
+        }
+    }
+
+    async fn readv<T: IoVecBufMut>(&mut self, mut buf: T) -> BufResult<usize, T> {
+        match &mut self.inner {
+            Inner::Record { io, .. } => {
+                let (res, mut buf) = io.readv(buf).await;
+                let res = res.and_then(|n| {
+                    // SAFETY: the iovecs are valid and were initialized up to `n` bytes.
+                    let read = unsafe {
+                        let iovecs = std::slice::from_raw_parts(
+                            buf.write_iovec_ptr(),
+                            buf.write_iovec_len(),
+                        );
+                        iovec_bytes(iovecs, n)
+                    };
+                    self.read_recorded(&read).map(|_| n)
+                });
+                (res, buf)
+            }
+            Inner::Replay { state, .. } => {
+                // SAFETY: the iovecs are valid, the first non-empty one is written up to `n`
+                // bytes as a single read would.
+                unsafe {
+                    let iovecs =
+                        std::slice::from_raw_parts(buf.write_iovec_ptr(), buf.write_iovec_len());
+                    let Some(iovec) = iovecs.iter().find(|iovec| iovec.iov_len > 0) else {
+                        return (Ok(0), buf);
+                    };
+                    let res = Self::replay(state, iovec.iov_base.cast(), iovec.iov_len).await;
+                    if let Ok(n) = res {
+                        buf.set_init(n);
+                    }
+                    (res, buf)
+                }
+            }
+        }
+    }
+}
+
+impl<S: AsyncWriteRent> AsyncWriteRent for CassetteStream<S> {
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        let (res, buf) = match &mut self.inner {
+            Inner::Record { io, .. } => io.write(buf).await,
+            Inner::Replay { .. } => {
+                let n = buf.bytes_init();
+                (Ok(n), buf)
+            }
+        };
+        let res = res.and_then(|n| {
+            // SAFETY: the buffer has `bytes_init` initialized bytes, at least `n`.
+            let written = unsafe { std::slice::from_raw_parts(buf.read_ptr(), n) };
+            self.written(written).map(|_| n)
+        });
+        (res, buf)
+    }
+
+    async fn writev<T: IoVecBuf>(&mut self, buf_vec: T) -> BufResult<usize, T> {
+        let (res, buf_vec) = match &mut self.inner {
+            Inner::Record { io, .. } => io.writev(buf_vec).await,
+            Inner::Replay { .. } => (Ok(usize::MAX), buf_vec),
+        };
+        let res = res.and_then(|n| {
+            // SAFETY: the iovecs are valid and initialized.
+            let written = unsafe {
+                let iovecs =
+                    std::slice::from_raw_parts(buf_vec.read_iovec_ptr(), buf_vec.read_iovec_len());
+                iovec_bytes(iovecs, n)
+            };
+            self.written(&written).map(|_| written.len())
+        });
+        (res, buf_vec)
+    }
+
+    #[inline]
+    async fn flush(&mut self) -> io::Result<()> {
+        match &mut self.inner {
+            Inner::Record { io, .. } => io.flush().await,
+            Inner::Replay { .. } => Ok(()),
+        }
+    }
+
+    async fn shutdown(&mut self) -> io::Result<()> {
+        match &mut self.inner {
+            Inner::Record { io, .. } => io.shutdown().await,
+            Inner::Replay { state, .. } => {
+                let mut state = state.borrow_mut();
+                state.closed = true;
+                if let Some(reader) = state.reader.take() {
+                    reader.wake();
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Recorded streams borrow their buffers only for the time of a copy, after the inner stream
+// returned, and replayed ones share no state with another task.
+unsafe impl<S: Split> Split for CassetteStream<S> {}
+
+#[cfg(test)]
+mod tests {
+    use http::request;
+    use monoio_http::common::body::HttpBody;
+
+    use super::*;
+    use crate::{
+        connectors::MockConnector,
+        http::{response::ResponseExt, HttpConnector},
+    };
+
+    #[monoio::test(enable_timer = true)]
+    async fn replays_recorded_exchanges() {
+        let dir = std::env::temp_dir().join(format!("monoio-cassette-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let request = |path: &'static str| {
+            move || {
+                request::Builder::new()
+                    .uri(path)
+                    .header("Host", "localhost")
+                    .body(HttpBody::Ready(None))
+                    .unwrap()
+            }
+        };
+
+        let server = MockConnector::new().with_responses(
+            "api",
+            [
+                "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nfirst",
+                "HTTP/1.1 404 Not Found\r\ncontent-length: 7\r\n\r\nmissing",
+            ],
+        );
+        let mut recorder =
+            HttpConnector::new(CassetteConnector::new(server, &dir, CassetteMode::Record));
+        recorder.set_http1_only();
+        let first = recorder.request("api", request("/a")).await.unwrap();
+        assert_eq!(first.bytes().await.unwrap(), "first");
+        let second = recorder.request("api", request("/b")).await.unwrap();
+        assert_eq!(second.bytes().await.unwrap(), "missing");
+
+        // Nothing is served anymore.
+        let offline = MockConnector::<&str>::new();
+        let mut player = HttpConnector::new(CassetteConnector::new(
+            offline.clone(),
+            &dir,
+            CassetteMode::Replay,
+        ));
+        player.set_http1_only();
+        let second = player.request("api", request("/b")).await.unwrap();
+        assert_eq!(second.status(), 404);
+        assert_eq!(second.bytes().await.unwrap(), "missing");
+        let first = player.request("api", request("/a")).await.unwrap();
+        assert_eq!(first.status(), 200);
+        assert_eq!(first.bytes().await.unwrap(), "first");
+        assert!(player.request("api", request("/c")).await.is_err());
+        assert!(player.request("other", request("/a")).await.is_err());
+        assert_eq!(offline.connects(&"api"), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error as ThisError;
+
+use super::Connector;
+use crate::pool::Key;
+
+const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+const DEFAULT_COOL_DOWN: Duration = Duration::from_secs(30);
+
+/// The error returned while the circuit of a key is open.
+#[derive(ThisError, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("circuit open, retry in {retry_in:?}")]
+pub struct CircuitOpen {
+    /// The time left until a probe is allowed again.
+    pub retry_in: Duration,
+}
+
+impl From<CircuitOpen> for io::Error {
+    #[inline]
+    fn from(e: CircuitOpen) -> Self {
+        io::Error::new(io::ErrorKind::ConnectionRefused, e)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    // A single probe is in flight since the given instant.
+    HalfOpen { since: Instant },
+}
+
+/// Tracks consecutive failures per key and fails fast while a key is considered down.
+///
+/// After `failure_threshold` consecutive failures the circuit of a key opens and every attempt is
+/// rejected with [`CircuitOpen`] for `cool_down`. Then a single probe is let through: its success
+/// closes the circuit again, its failure reopens it. A probe whose outcome is never recorded is
+/// given up after another `cool_down`.
+///
+/// Clones share the same state, so the breaker can be kept next to a connector to also record
+/// request level failures.
+#[derive(Clone)]
+pub struct CircuitBreaker<K> {
+    states: Rc<RefCell<HashMap<K, State>>>,
+    failure_threshold: u32,
+    cool_down: Duration,
+}
+
+impl<K> Default for CircuitBreaker<K> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            states: Rc::new(RefCell::new(HashMap::new())),
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            cool_down: DEFAULT_COOL_DOWN,
+        }
+    }
+}
+
+impl<K> std::fmt::Debug for CircuitBreaker<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("keys", &self.states.borrow().len())
+            .field("failure_threshold", &self.failure_threshold)
+            .field("cool_down", &self.cool_down)
+            .finish()
+    }
+}
+
+impl<K: Key> CircuitBreaker<K> {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of consecutive failures opening the circuit.
+    #[inline]
+    pub fn with_failure_threshold(mut self, threshold: u32) -> Self {
+        self.failure_threshold = threshold.max(1);
+        self
+    }
+
+    /// Sets how long an open circuit rejects attempts before probing.
+    #[inline]
+    pub fn with_cool_down(mut self, cool_down: Duration) -> Self {
+        self.cool_down = cool_down;
+        self
+    }
+
+    /// Checks whether an attempt to `key` may proceed, claiming the probe when the cool down of
+    /// an open circuit has elapsed.
+    pub fn check(&self, key: &K) -> Result<(), CircuitOpen> {
+        let mut states = self.states.borrow_mut();
+        let Some(state) = states.get_mut(key) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let busy_until = match *state {
+            State::Closed { .. } => return Ok(()),
+            State::Open { until } => until,
+            State::HalfOpen { since } => since + self.cool_down,
+        };
+        if now < busy_until {
+            return Err(CircuitOpen {
+                retry_in: busy_until - now,
+            });
+        }
+        *state = State::HalfOpen { since: now };
+        Ok(())
+    }
+
+    /// Records a successful attempt to `key`, closing its circuit.
+    #[inline]
+    pub fn record_success(&self, key: &K) {
+        self.states.borrow_mut().remove(key);
+    }
+
+    /// Records a failed attempt to `key`.
+    pub fn record_failure(&self, key: &K) {
+        let mut states = self.states.borrow_mut();
+        let state = states
+            .entry(key.clone())
+            .or_insert(State::Closed { failures: 0 });
+        let failures = match *state {
+            State::Closed { failures } => failures + 1,
+            State::Open { .. } | State::HalfOpen { .. } => self.failure_threshold,
+        };
+        *state = if failures >= self.failure_threshold {
+            #[cfg(feature = "logging")]
+            tracing::debug!("circuit opened after {failures} consecutive failures");
+            State::Open {
+                until: Instant::now() + self.cool_down,
+            }
+        } else {
+            State::Closed { failures }
+        };
+    }
+
+    /// Returns true if attempts to `key` are currently rejected.
+    pub fn is_open(&self, key: &K) -> bool {
+        match self.states.borrow().get(key) {
+            Some(State::Open { until }) => Instant::now() < *until,
+            Some(State::HalfOpen { since }) => Instant::now() < *since + self.cool_down,
+            _ => false,
+        }
+    }
+}
+
+/// A connector failing fast for keys whose connections keep failing, see [`CircuitBreaker`].
+///
+/// Connect outcomes are recorded automatically. Request level failures can be fed to the shared
+/// [`breaker`](Self::breaker). The inner error type must be constructible from [`CircuitOpen`],
+/// which holds for `io::Error` and [`TransportError`](crate::TransportError), so this can wrap L4
+/// connectors taking keys by value as well as `HttpConnector`.
+#[derive(Clone, Debug)]
+pub struct CircuitBreakerConnector<C, K> {
+    inner_connector: C,
+    breaker: CircuitBreaker<K>,
+}
+
+impl<C, K: Key> CircuitBreakerConnector<C, K> {
+    /// Creates a new connector with a default breaker.
+    #[inline]
+    pub fn new(inner_connector: C) -> Self {
+        Self::with_breaker(inner_connector, CircuitBreaker::new())
+    }
+
+    /// Creates a new connector sharing `breaker`.
+    #[inline]
+    pub fn with_breaker(inner_connector: C, breaker: CircuitBreaker<K>) -> Self {
+        Self {
+            inner_connector,
+            breaker,
+        }
+    }
+
+    #[inline]
+    pub fn inner_connector(&self) -> &C {
+        &self.inner_connector
+    }
+
+    #[inline]
+    pub fn breaker(&self) -> &CircuitBreaker<K> {
+        &self.breaker
+    }
+}
+
+impl<C, K> Connector<K> for CircuitBreakerConnector<C, K>
+where
+    C: Connector<K>,
+    C::Error: From<CircuitOpen>,
+    K: Key,
+{
+    type Connection = C::Connection;
+    type Error = C::Error;
+
+    async fn connect(&self, key: K) -> Result<Self::Connection, Self::Error> {
+        self.breaker.check(&key)?;
+        match self.inner_connector.connect(key.clone()).await {
+            Ok(conn) => {
+                self.breaker.record_success(&key);
+                Ok(conn)
+            }
+            Err(e) => {
+                self.breaker.record_failure(&key);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// A layer wrapping connectors with a [`CircuitBreakerConnector`] sharing its breaker.
+#[derive(Clone, Debug, Default)]
+pub struct CircuitBreakerLayer<K> {
+    breaker: CircuitBreaker<K>,
+}
+
+impl<K> CircuitBreakerLayer<K> {
+    #[inline]
+    pub fn new(breaker: CircuitBreaker<K>) -> Self {
+        Self { breaker }
+    }
+}
+
+impl<C, K: Key> super::layer::ConnectorLayer<C> for CircuitBreakerLayer<K> {
+    type Connector = CircuitBreakerConnector<C, K>;
+
+    #[inline]
+    fn layer(&self, inner: C) -> Self::Connector {
+        CircuitBreakerConnector::with_breaker(inner, self.breaker.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use crate::connectors::TcpConnector;
+
+    #[monoio::test(enable_timer = true)]
+    async fn opens_and_half_opens() {
+        // The std listener closes synchronously, so nothing listens on the port afterwards.
+        let dead: SocketAddr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let breaker = CircuitBreaker::new()
+            .with_failure_threshold(2)
+            .with_cool_down(Duration::from_millis(20));
+        let connector =
+            CircuitBreakerConnector::with_breaker(TcpConnector::default(), breaker.clone());
+
+        for _ in 0..2 {
+            let err = connector.connect(dead).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+            assert!(!matches!(err.get_ref(), Some(e) if e.is::<CircuitOpen>()));
+        }
+        let err = connector.connect(dead).await.unwrap_err();
+        assert!(err.get_ref().is_some_and(|e| e.is::<CircuitOpen>()));
+        assert!(breaker.is_open(&dead));
+
+        // After the cool down one probe goes through, and a failed probe reopens the circuit.
+        monoio::time::sleep(Duration::from_millis(30)).await;
+        assert!(breaker.check(&dead).is_ok());
+        assert!(breaker.check(&dead).is_err());
+        breaker.record_failure(&dead);
+        assert!(breaker.is_open(&dead));
+
+        breaker.record_success(&dead);
+        assert!(breaker.check(&dead).is_ok());
+    }
+}
@@ -0,0 +1,346 @@
+use std::{cell::Cell, io, time::Duration};
+
+use monoio::{
+    buf::{IoBuf, IoBufMut, IoVecBuf, IoVecBufMut},
+    io::{AsyncReadRent, AsyncWriteRent, Split},
+    BufResult,
+};
+
+use super::{layer::ConnectorLayer, Connector, TransportConnMetadata};
+
+/// Returns whether an event of `probability` happens.
+fn happens(probability: f64) -> bool {
+    use std::hash::{BuildHasher, Hasher};
+
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    ((random >> 11) as f64 / (1u64 << 53) as f64) < probability
+}
+
+/// The faults injected by a [`FaultConnector`], none by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Faults {
+    connect_delay: Option<Duration>,
+    connect_failure: Option<(f64, io::ErrorKind)>,
+    reset: Option<(f64, u64)>,
+    throttle: Option<u64>,
+}
+
+impl Faults {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delays every connection by `delay` before connecting.
+    #[inline]
+    pub fn with_connect_delay(mut self, delay: Duration) -> Self {
+        self.connect_delay = Some(delay);
+        self
+    }
+
+    /// Fails connections with `kind` with the given `probability`, between `0.0` and `1.0`,
+    /// without connecting.
+    #[inline]
+    pub fn with_connect_failure(mut self, probability: f64, kind: io::ErrorKind) -> Self {
+        self.connect_failure = Some((probability, kind));
+        self
+    }
+
+    /// Resets connections with the given `probability` once `after` bytes were read or written
+    /// over them: reads and writes then fail with [`io::ErrorKind::ConnectionReset`].
+    #[inline]
+    pub fn with_reset(mut self, probability: f64, after: u64) -> Self {
+        self.reset = Some((probability, after));
+        self
+    }
+
+    /// Slows reads and writes down to `bytes_per_second` in each direction.
+    #[inline]
+    pub fn with_throttle(mut self, bytes_per_second: u64) -> Self {
+        self.throttle = Some(bytes_per_second.max(1));
+        self
+    }
+
+    #[inline]
+    pub fn connect_delay(&self) -> Option<Duration> {
+        self.connect_delay
+    }
+
+    #[inline]
+    pub fn connect_failure(&self) -> Option<(f64, io::ErrorKind)> {
+        self.connect_failure
+    }
+
+    #[inline]
+    pub fn reset(&self) -> Option<(f64, u64)> {
+        self.reset
+    }
+
+    #[inline]
+    pub fn throttle(&self) -> Option<u64> {
+        self.throttle
+    }
+}
+
+/// A layer wrapping connectors into a [`FaultConnector`].
+#[derive(Debug, Clone, Copy)]
+pub struct FaultLayer {
+    faults: Faults,
+}
+
+impl FaultLayer {
+    #[inline]
+    pub fn new(faults: Faults) -> Self {
+        Self { faults }
+    }
+}
+
+impl<C> ConnectorLayer<C> for FaultLayer {
+    type Connector = FaultConnector<C>;
+
+    #[inline]
+    fn layer(&self, inner: C) -> Self::Connector {
+        FaultConnector::new(inner, self.faults)
+    }
+}
+
+/// A connector injecting [`Faults`] into its inner connector and its connections, to test how
+/// retries, circuit breakers and timeouts behave against the same client stack. Delays and
+/// throttling require the monoio timer driver.
+#[derive(Debug, Clone)]
+pub struct FaultConnector<C> {
+    inner_connector: C,
+    faults: Faults,
+}
+
+impl<C> FaultConnector<C> {
+    #[inline]
+    pub fn new(inner_connector: C, faults: Faults) -> Self {
+        Self {
+            inner_connector,
+            faults,
+        }
+    }
+
+    #[inline]
+    pub fn inner_connector(&self) -> &C {
+        &self.inner_connector
+    }
+
+    #[inline]
+    pub fn faults(&self) -> &Faults {
+        &self.faults
+    }
+}
+
+impl<C, K> Connector<K> for FaultConnector<C>
+where
+    C: Connector<K>,
+    C::Error: From<io::Error>,
+{
+    type Connection = FaultStream<C::Connection>;
+    type Error = C::Error;
+
+    async fn connect(&self, key: K) -> Result<Self::Connection, Self::Error> {
+        if let Some(delay) = self.faults.connect_delay {
+            monoio::time::sleep(delay).await;
+        }
+        if let Some((probability, kind)) = self.faults.connect_failure {
+            if happens(probability) {
+                return Err(io::Error::new(kind, "injected connect failure").into());
+            }
+        }
+        let inner = self.inner_connector.connect(key).await?;
+        let reset_after = self
+            .faults
+            .reset
+            .and_then(|(probability, after)| happens(probability).then_some(after));
+        Ok(FaultStream {
+            inner,
+            reset_after,
+            transferred: Cell::new(0),
+            throttle: self.faults.throttle,
+        })
+    }
+}
+
+/// A connection of a [`FaultConnector`].
+#[derive(Debug)]
+pub struct FaultStream<S> {
+    inner: S,
+    // The bytes after which the connection is reset, if it is.
+    reset_after: Option<u64>,
+    transferred: Cell<u64>,
+    throttle: Option<u64>,
+}
+
+impl<S> FaultStream<S> {
+    /// Whether this connection is reset once enough bytes went through it.
+    #[inline]
+    pub fn will_reset(&self) -> bool {
+        self.reset_after.is_some()
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn check_reset(&self) -> io::Result<()> {
+        match self.reset_after {
+            Some(after) if self.transferred.get() >= after => Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "injected connection reset",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Accounts for `n` bytes transferred, waiting for the throttling rate.
+    async fn transferred(&self, n: usize) {
+        self.transferred.set(self.transferred.get() + n as u64);
+        if let (Some(rate), true) = (self.throttle, n > 0) {
+            monoio::time::sleep(Duration::from_secs_f64(n as f64 / rate as f64)).await;
+        }
+    }
+}
+
+impl<S: TransportConnMetadata> TransportConnMetadata for FaultStream<S> {
+    type Metadata = S::Metadata;
+
+    #[inline]
+    fn get_conn_metadata(&self) -> Self::Metadata {
+        self.inner.get_conn_metadata()
+    }
+}
+
+impl<S: AsyncReadRent> AsyncReadRent for FaultStream<S> {
+    async fn read<T: IoBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        if let Err(e) = self.check_reset() {
+            return (Err(e), buf);
+        }
+        let (res, buf) = self.inner.read(buf).await;
+        if let Ok(n) = res {
+            self.transferred(n).await;
+        }
+        (res, buf)
+    }
+
+    async fn readv<T: IoVecBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        if let Err(e) = self.check_reset() {
+            return (Err(e), buf);
+        }
+        let (res, buf) = self.inner.readv(buf).await;
+        if let Ok(n) = res {
+            self.transferred(n).await;
+        }
+        (res, buf)
+    }
+}
+
+impl<S: AsyncWriteRent> AsyncWriteRent for FaultStream<S> {
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        if let Err(e) = self.check_reset() {
+            return (Err(e), buf);
+        }
+        let (res, buf) = self.inner.write(buf).await;
+        if let Ok(n) = res {
+            self.transferred(n).await;
+        }
+        (res, buf)
+    }
+
+    async fn writev<T: IoVecBuf>(&mut self, buf_vec: T) -> BufResult<usize, T> {
+        if let Err(e) = self.check_reset() {
+            return (Err(e), buf_vec);
+        }
+        let (res, buf_vec) = self.inner.writev(buf_vec).await;
+        if let Ok(n) = res {
+            self.transferred(n).await;
+        }
+        (res, buf_vec)
+    }
+
+    #[inline]
+    async fn flush(&mut self) -> io::Result<()> {
+        self.check_reset()?;
+        self.inner.flush().await
+    }
+
+    #[inline]
+    async fn shutdown(&mut self) -> io::Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+unsafe impl<S: Split> Split for FaultStream<S> {}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use monoio::io::{AsyncReadRentExt, AsyncWriteRentExt};
+
+    use super::*;
+    use crate::connectors::MockConnector;
+
+    /// Echoes what it reads on every connection to `"echo"`.
+    fn echo() -> MockConnector<&'static str> {
+        MockConnector::new().with_handler("echo", |mut stream| async move {
+            loop {
+                let (res, buf) = stream.read(vec![0; 64]).await;
+                match res {
+                    Ok(n) if n > 0 => {
+                        if stream.write_all(buf[..n].to_vec()).await.0.is_err() {
+                            return;
+                        }
+                    }
+                    _ => return,
+                }
+            }
+        })
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn injects_connect_faults() {
+        let failing = Faults::new().with_connect_failure(1.0, io::ErrorKind::ConnectionRefused);
+        let err = FaultConnector::new(echo(), failing)
+            .connect("echo")
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+
+        let delayed = Faults::new()
+            .with_connect_delay(Duration::from_millis(20))
+            .with_connect_failure(0.0, io::ErrorKind::ConnectionRefused);
+        let start = Instant::now();
+        let conn = FaultConnector::new(echo(), delayed).connect("echo").await;
+        assert!(conn.is_ok_and(|conn| !conn.will_reset()));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn resets_and_throttles_streams() {
+        let faults = Faults::new().with_reset(1.0, 8).with_throttle(1000);
+        let mut conn = FaultConnector::new(echo(), faults)
+            .connect("echo")
+            .await
+            .unwrap();
+        assert!(conn.will_reset());
+        let start = Instant::now();
+        conn.write_all(b"ping".to_vec()).await.0.unwrap();
+        let (res, _) = conn.read_exact(vec![0; 4]).await;
+        res.unwrap();
+        // 8 bytes at 1000 bytes per second.
+        assert!(start.elapsed() >= Duration::from_millis(8));
+        let err = conn.write(b"ping".to_vec()).await.0.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+    }
+}
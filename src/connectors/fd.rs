@@ -0,0 +1,179 @@
+use std::{cell::RefCell, collections::HashMap, io, os::fd::OwnedFd, rc::Rc};
+
+use monoio::net::{TcpStream, UnixStream};
+use socket2::{Domain, Socket};
+
+use super::{socket::is_uring_driver, Connector, UnifiedL4Stream};
+use crate::pool::Key;
+
+/// A connection that can be built from a connected socket handed to the process.
+pub trait FromConnectedFd: Sized {
+    /// Takes ownership of `fd`, which must be a connected stream socket.
+    fn from_connected_fd(fd: OwnedFd) -> io::Result<Self>;
+}
+
+/// Switches `fd` to the blocking mode the IO driver of the current thread expects.
+fn adopt(fd: OwnedFd) -> io::Result<Socket> {
+    let socket = Socket::from(fd);
+    socket.set_nonblocking(!is_uring_driver()?)?;
+    Ok(socket)
+}
+
+impl FromConnectedFd for TcpStream {
+    #[inline]
+    fn from_connected_fd(fd: OwnedFd) -> io::Result<Self> {
+        TcpStream::from_std(adopt(fd)?.into())
+    }
+}
+
+impl FromConnectedFd for UnixStream {
+    #[inline]
+    fn from_connected_fd(fd: OwnedFd) -> io::Result<Self> {
+        UnixStream::from_std(adopt(fd)?.into())
+    }
+}
+
+impl FromConnectedFd for UnifiedL4Stream {
+    fn from_connected_fd(fd: OwnedFd) -> io::Result<Self> {
+        let socket = adopt(fd)?;
+        match socket.domain()? {
+            Domain::UNIX => UnixStream::from_std(socket.into()).map(UnifiedL4Stream::Unix),
+            _ => TcpStream::from_std(socket.into()).map(UnifiedL4Stream::Tcp),
+        }
+    }
+}
+
+/// A connector handing out sockets connected before being given to the process, such as the
+/// ones of systemd socket activation or received over `SCM_RIGHTS`, before dialing new ones.
+///
+/// Sockets are added for a key with [`add`](Self::add), and the next connections to that key
+/// use them in order. Once they are used up, connections are established by the inner
+/// connector. Wrapped in a pool or an `HttpConnector`, the adopted sockets are then reused like
+/// any other connection.
+///
+/// Clones share the same sockets.
+pub struct FromFdConnector<C, K> {
+    inner_connector: C,
+    fds: Rc<RefCell<HashMap<K, Vec<OwnedFd>>>>,
+}
+
+impl<C: Clone, K> Clone for FromFdConnector<C, K> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            inner_connector: self.inner_connector.clone(),
+            fds: self.fds.clone(),
+        }
+    }
+}
+
+impl<C: std::fmt::Debug, K> std::fmt::Debug for FromFdConnector<C, K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FromFdConnector")
+            .field("inner_connector", &self.inner_connector)
+            .field("keys", &self.fds.borrow().len())
+            .finish()
+    }
+}
+
+impl<C, K: Key> FromFdConnector<C, K> {
+    #[inline]
+    pub fn new(inner_connector: C) -> Self {
+        Self {
+            inner_connector,
+            fds: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    #[inline]
+    pub fn inner_connector(&self) -> &C {
+        &self.inner_connector
+    }
+
+    /// Queues `fd`, a connected stream socket, for the next connection to `key`.
+    pub fn add(&self, key: K, fd: impl Into<OwnedFd>) {
+        self.fds
+            .borrow_mut()
+            .entry(key)
+            .or_default()
+            .push(fd.into());
+    }
+
+    /// Returns how many sockets are queued for `key`.
+    pub fn queued(&self, key: &K) -> usize {
+        self.fds.borrow().get(key).map_or(0, Vec::len)
+    }
+
+    fn take(&self, key: &K) -> Option<OwnedFd> {
+        let mut fds = self.fds.borrow_mut();
+        let queued = fds.get_mut(key)?;
+        let fd = queued.remove(0);
+        if queued.is_empty() {
+            fds.remove(key);
+        }
+        Some(fd)
+    }
+}
+
+impl<C, K> Connector<K> for FromFdConnector<C, K>
+where
+    C: Connector<K>,
+    C::Connection: FromConnectedFd,
+    C::Error: From<io::Error>,
+    K: Key,
+{
+    type Connection = C::Connection;
+    type Error = C::Error;
+
+    async fn connect(&self, key: K) -> Result<Self::Connection, Self::Error> {
+        match self.take(&key) {
+            Some(fd) => Ok(C::Connection::from_connected_fd(fd)?),
+            None => self.inner_connector.connect(key).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use monoio::{io::AsyncReadRent, net::TcpListener};
+
+    use super::*;
+    use crate::connectors::{TcpConnector, UnifiedL4Addr, UnifiedL4Connector};
+
+    #[monoio::test(enable_timer = true)]
+    async fn uses_fds_before_dialing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        let handed = std::net::TcpStream::connect(addr).unwrap();
+        let handed_port = handed.local_addr().unwrap().port();
+        monoio::spawn(async move {
+            let mut conns = Vec::new();
+            while let Ok((conn, _)) = listener.accept().await {
+                conns.push(conn);
+            }
+        });
+
+        let connector = FromFdConnector::new(TcpConnector::default());
+        connector.add(addr, handed);
+        assert_eq!(connector.queued(&addr), 1);
+        let adopted = connector.connect(addr).await.unwrap();
+        assert_eq!(adopted.local_addr().unwrap().port(), handed_port);
+        assert_eq!(connector.queued(&addr), 0);
+        let dialed = connector.connect(addr).await.unwrap();
+        assert_ne!(dialed.local_addr().unwrap().port(), handed_port);
+
+        let (ours, theirs) = std::os::unix::net::UnixStream::pair().unwrap();
+        let key = UnifiedL4Addr::Unix("/nonexistent.sock".into());
+        let unified = FromFdConnector::new(UnifiedL4Connector::default());
+        unified.add(key.clone(), ours);
+        let Ok(UnifiedL4Stream::Unix(mut stream)) = unified.connect(key).await else {
+            panic!("expected the unix socket pair");
+        };
+        std::io::Write::write_all(&mut &theirs, b"hello").unwrap();
+        let (res, buf) = stream.read(vec![0; 5]).await;
+        assert_eq!(res.unwrap(), 5);
+        assert_eq!(buf, b"hello");
+    }
+}
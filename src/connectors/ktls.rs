@@ -0,0 +1,391 @@
+use std::{
+    io,
+    os::fd::{AsRawFd, RawFd},
+};
+
+use monoio::{
+    buf::{IoBuf, IoBufMut, IoVecBuf, IoVecBufMut},
+    io::{AsyncReadRent, AsyncWriteRent, Split},
+    BufResult,
+};
+use rustls::{ClientConnection, ConnectionTrafficSecrets, ProtocolVersion};
+
+use super::{TlsStream, TransportConnMeta, TransportConnMetadata};
+
+// From linux/tls.h, not exposed by every libc version.
+const TLS_TX: libc::c_int = 1;
+const TLS_RX: libc::c_int = 2;
+const TLS_SET_RECORD_TYPE: libc::c_int = 1;
+const TLS_GET_RECORD_TYPE: libc::c_int = 2;
+const TLS_1_2_VERSION: u16 = 0x0303;
+const TLS_1_3_VERSION: u16 = 0x0304;
+const TLS_CIPHER_AES_GCM_128: u16 = 51;
+const TLS_CIPHER_AES_GCM_256: u16 = 52;
+const TLS_CIPHER_CHACHA20_POLY1305: u16 = 54;
+
+// TLS record and message types.
+const ALERT: u8 = 21;
+const HANDSHAKE: u8 = 22;
+const CLOSE_NOTIFY: u8 = 0;
+const NEW_SESSION_TICKET: u8 = 4;
+
+/// A TLS connection whose records are sealed and opened by the kernel, or by rustls when kTLS
+/// could not be set up. See [`TlsConnector::connect_ktls`](super::TlsConnector::connect_ktls).
+///
+/// Once offloaded, reads and writes go straight to the socket, so io_uring moves plaintext and
+/// the kernel encrypts it. TLS 1.3 session tickets received later are discarded, and a key
+/// update from the server fails the read since the kernel cannot rekey.
+#[derive(Debug)]
+pub struct KtlsStream<S>(Inner<S>);
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+enum Inner<S> {
+    Kernel { io: S, meta: TransportConnMeta },
+    User(TlsStream<S>),
+}
+
+impl<S> KtlsStream<S> {
+    /// Whether the kernel encrypts the records of this connection.
+    #[inline]
+    pub fn is_kernel(&self) -> bool {
+        matches!(self.0, Inner::Kernel { .. })
+    }
+
+    #[inline]
+    pub fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        match &self.0 {
+            Inner::Kernel { meta, .. } => meta.alpn().protocol().map(|p| p.as_bytes().to_vec()),
+            Inner::User(stream) => stream.alpn_protocol(),
+        }
+    }
+}
+
+impl<S> From<TlsStream<S>> for KtlsStream<S> {
+    #[inline]
+    fn from(stream: TlsStream<S>) -> Self {
+        Self(Inner::User(stream))
+    }
+}
+
+impl<S: AsRawFd> KtlsStream<S> {
+    /// Offloads `session`, whose handshake completed over `io`, to the kernel, keeping it in
+    /// userspace when the kernel or its state does not allow it. `meta` describes the
+    /// connection, the ALPN protocol aside.
+    pub(crate) fn offload(
+        io: S,
+        mut session: ClientConnection,
+        mut meta: TransportConnMeta,
+    ) -> io::Result<Self> {
+        let user = |io, session| {
+            let stream = monoio_rustls::ClientTlsStream::new(io, session);
+            Ok(Self(Inner::User(TlsStream::new(stream, meta.clone()))))
+        };
+        let version = match session.protocol_version() {
+            Some(ProtocolVersion::TLSv1_2) => TLS_1_2_VERSION,
+            Some(ProtocolVersion::TLSv1_3) => TLS_1_3_VERSION,
+            _ => return user(io, session),
+        };
+        let buffered = session
+            .process_new_packets()
+            .map_err(io::Error::other)?
+            .plaintext_bytes_to_read();
+        // Records already read or still to write belong to rustls.
+        if buffered > 0 || session.wants_write() || set_ulp(io.as_raw_fd()).is_err() {
+            return user(io, session);
+        }
+        meta.set_alpn(session.alpn_protocol().map(<[u8]>::to_vec));
+        // The session cannot be used past this point, failures are errors.
+        let secrets = session
+            .dangerous_extract_secrets()
+            .map_err(io::Error::other)?;
+        let fd = io.as_raw_fd();
+        set_crypto_info(fd, TLS_TX, &crypto_info(version, secrets.tx)?)?;
+        set_crypto_info(fd, TLS_RX, &crypto_info(version, secrets.rx)?)?;
+        Ok(Self(Inner::Kernel { io, meta }))
+    }
+}
+
+fn set_ulp(fd: RawFd) -> io::Result<()> {
+    setsockopt(fd, libc::SOL_TCP, libc::TCP_ULP, b"tls")
+}
+
+fn set_crypto_info(fd: RawFd, direction: libc::c_int, info: &[u8]) -> io::Result<()> {
+    setsockopt(fd, libc::SOL_TLS, direction, info)
+}
+
+fn setsockopt(fd: RawFd, level: libc::c_int, name: libc::c_int, value: &[u8]) -> io::Result<()> {
+    // SAFETY: `value` is valid for `value.len()` bytes.
+    let res = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            value.as_ptr().cast(),
+            value.len() as libc::socklen_t,
+        )
+    };
+    match res {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Encodes the `tls12_crypto_info_*` struct of linux/tls.h for `secrets`, whose next record
+/// has sequence number `seq`.
+fn crypto_info(
+    version: u16,
+    (seq, secrets): (u64, ConnectionTrafficSecrets),
+) -> io::Result<Vec<u8>> {
+    let (cipher, key, iv) = match &secrets {
+        ConnectionTrafficSecrets::Aes128Gcm { key, iv } => (TLS_CIPHER_AES_GCM_128, key, iv),
+        ConnectionTrafficSecrets::Aes256Gcm { key, iv } => (TLS_CIPHER_AES_GCM_256, key, iv),
+        ConnectionTrafficSecrets::Chacha20Poly1305 { key, iv } => {
+            (TLS_CIPHER_CHACHA20_POLY1305, key, iv)
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "cipher not supported by kTLS",
+            ))
+        }
+    };
+    // AES-GCM splits the nonce into a 4 byte salt and an 8 byte IV.
+    let (salt, iv) = match cipher {
+        TLS_CIPHER_CHACHA20_POLY1305 => (&[][..], iv.as_ref()),
+        _ => iv.as_ref().split_at(4),
+    };
+    let mut info = Vec::with_capacity(4 + iv.len() + key.as_ref().len() + salt.len() + 8);
+    info.extend_from_slice(&version.to_ne_bytes());
+    info.extend_from_slice(&cipher.to_ne_bytes());
+    info.extend_from_slice(iv);
+    info.extend_from_slice(key.as_ref());
+    info.extend_from_slice(salt);
+    info.extend_from_slice(&seq.to_be_bytes());
+    Ok(info)
+}
+
+// Control message space for a single record type byte.
+type Cmsg = [usize; 4];
+
+/// Reads the non application data record the kernel refused to return with a plain read.
+///
+/// Returns whether the record closes the connection; session tickets are discarded.
+fn read_control_record(fd: RawFd) -> io::Result<bool> {
+    let mut record = vec![0u8; 16 * 1024 + 256];
+    let mut cmsg: Cmsg = [0; 4];
+    let mut iov = libc::iovec {
+        iov_base: record.as_mut_ptr().cast(),
+        iov_len: record.len(),
+    };
+    // SAFETY: an all zero msghdr is valid, its buffers are set below and outlive the call.
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = cmsg.as_mut_ptr().cast();
+    msg.msg_controllen = std::mem::size_of_val(&cmsg) as _;
+    // The record is queued already, so this does not block.
+    let n = unsafe { libc::recvmsg(fd, &mut msg, 0) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `msg` was filled by the kernel.
+    let record_type = unsafe {
+        let header = libc::CMSG_FIRSTHDR(&msg);
+        if header.is_null()
+            || (*header).cmsg_level != libc::SOL_TLS
+            || (*header).cmsg_type != TLS_GET_RECORD_TYPE
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "kTLS record without a type",
+            ));
+        }
+        *libc::CMSG_DATA(header)
+    };
+    let record = &record[..n as usize];
+    match (record_type, record) {
+        (ALERT, [_, CLOSE_NOTIFY]) => Ok(true),
+        (ALERT, [_, description]) => Err(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            format!("received TLS alert {description}"),
+        )),
+        (HANDSHAKE, _) if only_session_tickets(record) => Ok(false),
+        (HANDSHAKE, _) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "post-handshake message not supported by kTLS",
+        )),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected TLS record",
+        )),
+    }
+}
+
+/// Whether the handshake `record` only holds whole `NewSessionTicket` messages.
+fn only_session_tickets(mut record: &[u8]) -> bool {
+    while let [kind, a, b, c, rest @ ..] = record {
+        let len = u32::from_be_bytes([0, *a, *b, *c]) as usize;
+        if *kind != NEW_SESSION_TICKET || rest.len() < len {
+            return false;
+        }
+        record = &rest[len..];
+    }
+    record.is_empty()
+}
+
+/// Sends a `close_notify` alert through the kernel.
+fn send_close_notify(fd: RawFd) -> io::Result<()> {
+    let mut alert = [1u8, CLOSE_NOTIFY];
+    let mut cmsg: Cmsg = [0; 4];
+    let mut iov = libc::iovec {
+        iov_base: alert.as_mut_ptr().cast(),
+        iov_len: alert.len(),
+    };
+    // SAFETY: an all zero msghdr is valid, its buffers are set below and outlive the call, and
+    // `cmsg` has room for a header with one byte of data.
+    let n = unsafe {
+        let mut msg: libc::msghdr = std::mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = cmsg.as_mut_ptr().cast();
+        msg.msg_controllen = libc::CMSG_SPACE(1) as _;
+        let header = libc::CMSG_FIRSTHDR(&msg);
+        (*header).cmsg_level = libc::SOL_TLS;
+        (*header).cmsg_type = TLS_SET_RECORD_TYPE;
+        (*header).cmsg_len = libc::CMSG_LEN(1) as _;
+        *libc::CMSG_DATA(header) = ALERT;
+        libc::sendmsg(fd, &msg, 0)
+    };
+    match n {
+        n if n < 0 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Handles a failed read of an offloaded connection, returning `None` to read again.
+fn on_read_error(fd: RawFd, error: io::Error) -> Option<io::Result<usize>> {
+    if error.raw_os_error() != Some(libc::EIO) {
+        return Some(Err(error));
+    }
+    match read_control_record(fd) {
+        Ok(true) => Some(Ok(0)),
+        Ok(false) => None,
+        Err(e) => Some(Err(e)),
+    }
+}
+
+impl<S> TransportConnMetadata for KtlsStream<S> {
+    type Metadata = TransportConnMeta;
+
+    fn get_conn_metadata(&self) -> Self::Metadata {
+        match &self.0 {
+            Inner::Kernel { meta, .. } => meta.clone(),
+            Inner::User(stream) => stream.get_conn_metadata(),
+        }
+    }
+}
+
+impl<S: AsyncReadRent + AsyncWriteRent + AsRawFd> AsyncReadRent for KtlsStream<S> {
+    async fn read<T: IoBufMut>(&mut self, mut buf: T) -> BufResult<usize, T> {
+        match &mut self.0 {
+            Inner::Kernel { io, .. } => loop {
+                let (res, read) = io.read(buf).await;
+                buf = read;
+                match res {
+                    Err(e) => match on_read_error(io.as_raw_fd(), e) {
+                        Some(res) => return (res, buf),
+                        None => continue,
+                    },
+                    res => return (res, buf),
+                }
+            },
+            Inner::User(stream) => stream.read(buf).await,
+        }
+    }
+
+    async fn readv<T: IoVecBufMut>(&mut self, mut buf: T) -> BufResult<usize, T> {
+        match &mut self.0 {
+            Inner::Kernel { io, .. } => loop {
+                let (res, read) = io.readv(buf).await;
+                buf = read;
+                match res {
+                    Err(e) => match on_read_error(io.as_raw_fd(), e) {
+                        Some(res) => return (res, buf),
+                        None => continue,
+                    },
+                    res => return (res, buf),
+                }
+            },
+            Inner::User(stream) => stream.readv(buf).await,
+        }
+    }
+}
+
+impl<S: AsyncReadRent + AsyncWriteRent + AsRawFd> AsyncWriteRent for KtlsStream<S> {
+    #[inline]
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        match &mut self.0 {
+            Inner::Kernel { io, .. } => io.write(buf).await,
+            Inner::User(stream) => stream.write(buf).await,
+        }
+    }
+
+    #[inline]
+    async fn writev<T: IoVecBuf>(&mut self, buf_vec: T) -> BufResult<usize, T> {
+        match &mut self.0 {
+            Inner::Kernel { io, .. } => io.writev(buf_vec).await,
+            Inner::User(stream) => stream.writev(buf_vec).await,
+        }
+    }
+
+    #[inline]
+    async fn flush(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Inner::Kernel { io, .. } => io.flush().await,
+            Inner::User(stream) => stream.flush().await,
+        }
+    }
+
+    async fn shutdown(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Inner::Kernel { io, .. } => {
+                // The peer may be gone already, the socket is shut down in any case.
+                let _ = send_close_notify(io.as_raw_fd());
+                io.shutdown().await
+            }
+            Inner::User(stream) => stream.shutdown().await,
+        }
+    }
+}
+
+unsafe impl<S: Split> Split for KtlsStream<S> {}
+
+#[cfg(test)]
+mod tests {
+    use rustls::crypto::cipher::{AeadKey, Iv};
+
+    use super::*;
+
+    #[test]
+    fn encodes_crypto_info() {
+        let iv = Iv::new(std::array::from_fn(|i| i as u8));
+        let key = AeadKey::from([0xaa; 32]);
+        let info = crypto_info(
+            TLS_1_3_VERSION,
+            (7, ConnectionTrafficSecrets::Aes256Gcm { key, iv }),
+        )
+        .unwrap();
+        // version, cipher, iv, key, salt and record sequence.
+        assert_eq!(info.len(), 2 + 2 + 8 + 32 + 4 + 8);
+        assert_eq!(&info[..2], TLS_1_3_VERSION.to_ne_bytes());
+        assert_eq!(&info[2..4], TLS_CIPHER_AES_GCM_256.to_ne_bytes());
+        assert_eq!(&info[4..12], [4, 5, 6, 7, 8, 9, 10, 11]);
+        assert_eq!(&info[44..48], [0, 1, 2, 3]);
+        assert_eq!(&info[48..], 7u64.to_be_bytes());
+
+        assert!(only_session_tickets(&[4, 0, 0, 2, 1, 2, 4, 0, 0, 0]));
+        assert!(!only_session_tickets(&[4, 0, 0, 3, 1, 2]));
+        assert!(!only_session_tickets(&[24, 0, 0, 1, 0]));
+    }
+}
@@ -1,117 +1,861 @@
 use std::{
+    future::{poll_fn, Future},
     io,
-    net::{SocketAddr, ToSocketAddrs},
+    net::{IpAddr, SocketAddr},
+    os::fd::{AsFd, BorrowedFd},
     path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::Poll,
+    time::Duration,
 };
 
 use http::Uri;
 use monoio::{
-    io::{AsyncReadRent, AsyncWriteRent, Split, AsyncWriteRentExt},
-    net::{TcpStream, UnixStream},
+    io::{AsyncReadRent, AsyncWriteRent, Split},
+    net::{TcpConnectOpts, TcpStream, UnixStream},
 };
+#[cfg(target_os = "linux")]
+use socket2::Protocol;
+use socket2::{Domain, Socket, Type};
 
-use super::{Connector, TransportConnMeta, TransportConnMetadata};
+#[cfg(target_os = "linux")]
+use super::socket::set_fast_open_connect;
+use super::{socket::connect_socket, Connector, TransportConnMeta, TransportConnMetadata};
+use crate::{
+    dns::{parse_ip_literal, GaiResolver, Resolve},
+    pool::DeriveKey,
+};
+
+/// The delay between staggered connection attempts recommended by RFC 8305.
+pub const DEFAULT_HAPPY_EYEBALLS_DELAY: Duration = Duration::from_millis(250);
+
+/// Which address families to use when a key resolves to both IPv4 and IPv6 addresses.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IpPreference {
+    /// Keep the resolver's order.
+    #[default]
+    Any,
+    /// Try IPv4 addresses first, falling back to IPv6.
+    PreferIpv4,
+    /// Try IPv6 addresses first, falling back to IPv4.
+    PreferIpv6,
+    /// Only use IPv4 addresses.
+    Ipv4Only,
+    /// Only use IPv6 addresses.
+    Ipv6Only,
+}
+
+impl IpPreference {
+    /// Reorders or filters `addrs` according to the preference, keeping the relative order
+    /// within each family.
+    ///
+    /// Returns an error if a required family left no address to connect to.
+    pub fn apply(self, addrs: Vec<SocketAddr>) -> io::Result<Vec<SocketAddr>> {
+        let addrs: Vec<_> = match self {
+            IpPreference::Any => return Ok(addrs),
+            IpPreference::PreferIpv4 | IpPreference::PreferIpv6 => {
+                let v6_first = self == IpPreference::PreferIpv6;
+                let (mut preferred, other): (Vec<_>, Vec<_>) = addrs
+                    .into_iter()
+                    .partition(|addr| addr.is_ipv6() == v6_first);
+                preferred.extend(other);
+                return Ok(preferred);
+            }
+            IpPreference::Ipv4Only => addrs.into_iter().filter(SocketAddr::is_ipv4).collect(),
+            IpPreference::Ipv6Only => addrs.into_iter().filter(SocketAddr::is_ipv6).collect(),
+        };
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no address of the required family ({self:?})"),
+            ));
+        }
+        Ok(addrs)
+    }
+}
 
 /// A connector for establishing TCP connections.
-#[derive(Clone, Copy, Debug)]
-pub struct TcpConnector {
+///
+/// Keys are [`TcpTarget`]s: socket addresses are connected to as they are, and host names are
+/// resolved with `resolver`, [`GaiResolver`] by default, so lookups never block the reactor
+/// thread. When a key resolves to several addresses, they are tried alternating between IPv6 and
+/// IPv4 as described by Happy Eyeballs (RFC 8305): a new attempt starts every
+/// `happy_eyeballs_delay` or as soon as the previous one fails, and the first established stream
+/// wins. Racing needs the monoio timer driver, so it is only enabled by default with the `time`
+/// feature; otherwise addresses are tried one after another, moving on to the next address whenever
+/// one fails. Set `shuffle_addrs` to spread connections over the resolved addresses, and
+/// `connect_timeout` to bound the whole connection establishment, see also [`WithConnectTimeout`].
+/// `ip_preference` decides which family goes first, or restricts connections to a single family.
+///
+/// On multi-homed hosts, `local_address` and, on Linux, `interface` pick the address and the
+/// network interface connections leave from.
+///
+/// Set `keepalive` so idle pooled connections are probed, and kept in the tables of NAT gateways
+/// that would otherwise drop them silently.
+///
+/// With `fast_open`, a connection to a server that granted a TCP Fast Open cookie before sends
+/// the first bytes written, such as the HTTP request or the TLS client hello, along with its SYN.
+/// The handshake then happens on that first write, so connection errors surface there instead
+/// and all addresses but the first are never tried.
+///
+/// On Linux, `mptcp` opts into Multipath TCP, so connections survive the loss of one of the links
+/// of the host. Kernels without MPTCP support get plain TCP sockets instead.
+///
+/// Options the connector does not wrap, such as `SO_MARK` or the socket buffer sizes, can be set
+/// by a [`SocketConfig`] called on each socket before it connects.
+///
+/// With the `proxy` feature, connections are routed through the proxy named by `http_proxy`,
+/// `https_proxy` for keys reached over TLS, or `all_proxy` when set, using HTTP `CONNECT` or
+/// SOCKS5 for `socks5://` urls, except to the hosts listed in `NO_PROXY`. Set `env_proxy` to false
+/// to opt out, e.g. when proxying is configured through a `ProxyConnector` or a `ProxyLayer`.
+#[derive(Clone, Debug)]
+pub struct TcpConnector<R = GaiResolver> {
+    /// The resolver of the host names of keys.
+    pub resolver: R,
     /// Whether to set TCP_NODELAY on the created connection.
     pub no_delay: bool,
+    /// The delay before racing the next address, or `None` to try addresses sequentially.
+    pub happy_eyeballs_delay: Option<Duration>,
+    /// The address families to connect to, and in which order.
+    pub ip_preference: IpPreference,
+    /// Whether to shuffle the resolved addresses before connecting, for crude load spreading.
+    pub shuffle_addrs: bool,
+    /// The time allowed for establishing a connection, across all addresses. Requires the
+    /// monoio timer driver.
+    pub connect_timeout: Option<Duration>,
+    /// The TCP keepalive probing of the created connections, disabled when `None`.
+    pub keepalive: Option<TcpKeepalive>,
+    /// Whether to use TCP Fast Open, on Linux and macOS.
+    pub fast_open: bool,
+    /// Whether to create Multipath TCP sockets where the kernel supports them.
+    #[cfg(target_os = "linux")]
+    pub mptcp: bool,
+    /// The local address to bind connections to, with a port chosen by the system. Addresses of
+    /// the other family cannot be connected to.
+    pub local_address: Option<IpAddr>,
+    /// The network interface to bind connections to, with `SO_BINDTODEVICE`.
+    #[cfg(target_os = "linux")]
+    pub interface: Option<InterfaceName>,
+    /// A callback setting options on sockets before they connect.
+    pub socket_config: Option<SocketConfig>,
+    /// Whether to tunnel through the proxy named by the environment.
+    #[cfg(feature = "proxy")]
+    pub env_proxy: bool,
 }
 
 impl Default for TcpConnector {
     #[inline]
     fn default() -> Self {
-        Self { no_delay: true }
+        Self {
+            resolver: GaiResolver,
+            no_delay: true,
+            #[cfg(feature = "time")]
+            happy_eyeballs_delay: Some(DEFAULT_HAPPY_EYEBALLS_DELAY),
+            #[cfg(not(feature = "time"))]
+            happy_eyeballs_delay: None,
+            ip_preference: IpPreference::Any,
+            shuffle_addrs: false,
+            connect_timeout: None,
+            keepalive: None,
+            fast_open: false,
+            #[cfg(target_os = "linux")]
+            mptcp: false,
+            local_address: None,
+            #[cfg(target_os = "linux")]
+            interface: None,
+            socket_config: None,
+            #[cfg(feature = "proxy")]
+            env_proxy: true,
+        }
+    }
+}
+
+/// A callback setting options on a socket, after its creation but before it connects.
+///
+/// An error fails the connection attempt to the address of the socket.
+#[derive(Clone)]
+pub struct SocketConfig(Arc<dyn Fn(BorrowedFd<'_>) -> io::Result<()> + Send + Sync>);
+
+impl SocketConfig {
+    #[inline]
+    pub fn new(f: impl Fn(BorrowedFd<'_>) -> io::Result<()> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+}
+
+impl std::fmt::Debug for SocketConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SocketConfig")
     }
 }
 
-impl<T: ToSocketAddrs> Connector<T> for TcpConnector {
+/// The TCP keepalive settings of a connection, the system defaults apply to those left unset.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TcpKeepalive {
+    idle: Option<Duration>,
+    interval: Option<Duration>,
+    retries: Option<u32>,
+}
+
+impl TcpKeepalive {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how long a connection stays idle before the first probe is sent.
+    #[inline]
+    pub fn with_idle(mut self, idle: Duration) -> Self {
+        self.idle = Some(idle);
+        self
+    }
+
+    #[inline]
+    pub fn idle(&self) -> Option<Duration> {
+        self.idle
+    }
+
+    /// Sets the time between unanswered probes.
+    #[inline]
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    #[inline]
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    /// Sets how many unanswered probes close the connection.
+    #[inline]
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = Some(retries);
+        self
+    }
+
+    #[inline]
+    pub fn retries(&self) -> Option<u32> {
+        self.retries
+    }
+}
+
+/// The name of a network interface, as accepted by `SO_BINDTODEVICE`.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InterfaceName {
+    name: [u8; libc::IFNAMSIZ - 1],
+    len: u8,
+}
+
+#[cfg(target_os = "linux")]
+impl InterfaceName {
+    /// Returns an error if `name` is empty, contains a NUL byte, or is longer than the 15 bytes
+    /// allowed by Linux.
+    pub fn new(name: &str) -> io::Result<Self> {
+        let mut buf = [0; libc::IFNAMSIZ - 1];
+        if name.is_empty() || name.len() > buf.len() || name.contains('\0') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid interface name {name:?}"),
+            ));
+        }
+        buf[..name.len()].copy_from_slice(name.as_bytes());
+        Ok(Self {
+            name: buf,
+            len: name.len() as u8,
+        })
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        // Only built from a str.
+        std::str::from_utf8(&self.name[..self.len as usize]).unwrap_or_default()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl std::fmt::Debug for InterfaceName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// The target of a [`TcpConnector`] connection, see [`TcpTarget::tcp_target`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TcpDestination<'a> {
+    /// Addresses connected to as they are.
+    Addrs(Vec<SocketAddr>),
+    /// A host name or IP literal and a port, resolved by the connector.
+    Host(&'a str, u16),
+}
+
+/// A key [`TcpConnector`] connects to.
+///
+/// Implemented by socket addresses and slices of them, `host:port` strings, and every
+/// [`HostPort`] such as `(host, port)` tuples and the keys of the TLS connectors. Keys only
+/// implementing [`ToSocketAddrs`](std::net::ToSocketAddrs), which `TcpConnector` used to take,
+/// connect wrapped in a [`SocketAddrsKey`].
+pub trait TcpTarget {
+    /// Returns the addresses to connect to, or the host to resolve. Fails on malformed targets.
+    fn tcp_target(&self) -> io::Result<TcpDestination<'_>>;
+
+    /// Whether the target is reached over TLS, see [`HostPort::is_tls`]. False by default.
+    #[inline]
+    fn is_tls(&self) -> bool {
+        false
+    }
+}
+
+impl<T: HostPort> TcpTarget for T {
+    #[inline]
+    fn tcp_target(&self) -> io::Result<TcpDestination<'_>> {
+        Ok(TcpDestination::Host(self.host(), self.port()))
+    }
+
+    #[inline]
+    fn is_tls(&self) -> bool {
+        HostPort::is_tls(self)
+    }
+}
+
+impl TcpTarget for SocketAddr {
+    #[inline]
+    fn tcp_target(&self) -> io::Result<TcpDestination<'_>> {
+        Ok(TcpDestination::Addrs(vec![*self]))
+    }
+}
+
+impl TcpTarget for &SocketAddr {
+    #[inline]
+    fn tcp_target(&self) -> io::Result<TcpDestination<'_>> {
+        Ok(TcpDestination::Addrs(vec![**self]))
+    }
+}
+
+impl TcpTarget for &[SocketAddr] {
+    #[inline]
+    fn tcp_target(&self) -> io::Result<TcpDestination<'_>> {
+        Ok(TcpDestination::Addrs(self.to_vec()))
+    }
+}
+
+impl TcpTarget for Vec<SocketAddr> {
+    #[inline]
+    fn tcp_target(&self) -> io::Result<TcpDestination<'_>> {
+        Ok(TcpDestination::Addrs(self.clone()))
+    }
+}
+
+/// Splits a `host:port` string.
+fn split_host_port(s: &str) -> io::Result<TcpDestination<'_>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "invalid socket address");
+    let (host, port) = s.rsplit_once(':').ok_or_else(invalid)?;
+    let port = port.parse().map_err(|_| invalid())?;
+    Ok(TcpDestination::Host(host, port))
+}
+
+impl TcpTarget for &str {
+    #[inline]
+    fn tcp_target(&self) -> io::Result<TcpDestination<'_>> {
+        split_host_port(self)
+    }
+}
+
+impl TcpTarget for String {
+    #[inline]
+    fn tcp_target(&self) -> io::Result<TcpDestination<'_>> {
+        split_host_port(self)
+    }
+}
+
+/// A [`TcpConnector`] key resolved with its [`ToSocketAddrs`](std::net::ToSocketAddrs)
+/// implementation instead of the resolver of the connector.
+///
+/// This keeps keys written for the former `ToSocketAddrs` bound of `TcpConnector` working. Their
+/// names are resolved on the calling thread, blocking the runtime like before: implement
+/// [`HostPort`] instead to resolve them asynchronously.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SocketAddrsKey<T>(pub T);
+
+impl<T: std::net::ToSocketAddrs> TcpTarget for SocketAddrsKey<T> {
+    #[inline]
+    fn tcp_target(&self) -> io::Result<TcpDestination<'_>> {
+        Ok(TcpDestination::Addrs(self.0.to_socket_addrs()?.collect()))
+    }
+}
+
+impl<T: TcpTarget, R: Resolve> Connector<T> for TcpConnector<R> {
     type Connection = TcpStream;
     type Error = io::Error;
 
     #[inline]
     async fn connect(&self, key: T) -> Result<Self::Connection, Self::Error> {
+        with_connect_timeout(self.connect_timeout, self.connect_inner(key)).await
+    }
+}
+
+impl<T: TcpTarget, R: Resolve> Connector<WithConnectTimeout<T>> for TcpConnector<R> {
+    type Connection = TcpStream;
+    type Error = io::Error;
+
+    #[inline]
+    async fn connect(&self, key: WithConnectTimeout<T>) -> Result<Self::Connection, Self::Error> {
+        let timeout = key.timeout.or(self.connect_timeout);
+        with_connect_timeout(timeout, self.connect_inner(key.key)).await
+    }
+}
+
+/// A connector key overriding the connect timeout of [`TcpConnector`] or
+/// [`UnifiedL4Connector`] for a single connection.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WithConnectTimeout<K> {
+    /// The target of the connection.
+    pub key: K,
+    /// The timeout to use instead of the connector's, or `None` to keep the connector's.
+    pub timeout: Option<Duration>,
+}
+
+impl<K> WithConnectTimeout<K> {
+    /// Wraps `key`, bounding its connection establishment by `timeout`.
+    #[inline]
+    pub fn new(key: K, timeout: Duration) -> Self {
+        Self {
+            key,
+            timeout: Some(timeout),
+        }
+    }
+}
+
+/// Runs `connect`, failing with [`io::ErrorKind::TimedOut`] once `timeout` elapses.
+async fn with_connect_timeout<T>(
+    timeout: Option<Duration>,
+    connect: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    match timeout {
+        Some(timeout) => monoio::time::timeout(timeout, connect)
+            .await
+            .unwrap_or_else(|_| Err(crate::error::Elapsed::Connect.into())),
+        None => connect.await,
+    }
+}
+
+impl<R> TcpConnector<R> {
+    /// Resolves the host names of keys with `resolver`.
+    pub fn with_resolver<S>(self, resolver: S) -> TcpConnector<S> {
+        TcpConnector {
+            resolver,
+            no_delay: self.no_delay,
+            happy_eyeballs_delay: self.happy_eyeballs_delay,
+            ip_preference: self.ip_preference,
+            shuffle_addrs: self.shuffle_addrs,
+            connect_timeout: self.connect_timeout,
+            keepalive: self.keepalive,
+            fast_open: self.fast_open,
+            #[cfg(target_os = "linux")]
+            mptcp: self.mptcp,
+            local_address: self.local_address,
+            #[cfg(target_os = "linux")]
+            interface: self.interface,
+            socket_config: self.socket_config,
+            #[cfg(feature = "proxy")]
+            env_proxy: self.env_proxy,
+        }
+    }
+
+    /// Binds connections to `addr`.
+    #[inline]
+    pub fn with_local_address(mut self, addr: IpAddr) -> Self {
+        self.local_address = Some(addr);
+        self
+    }
+
+    /// Binds connections to the network interface named `name`, see [`InterfaceName::new`].
+    #[cfg(target_os = "linux")]
+    #[inline]
+    pub fn with_interface(mut self, name: &str) -> io::Result<Self> {
+        self.interface = Some(InterfaceName::new(name)?);
+        Ok(self)
+    }
+
+    /// Enables keepalive probing on connections.
+    #[inline]
+    pub fn with_keepalive(mut self, keepalive: TcpKeepalive) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Sends the first bytes written on connections along with their SYN.
+    #[inline]
+    pub fn with_fast_open(mut self, enabled: bool) -> Self {
+        self.fast_open = enabled;
+        self
+    }
+
+    /// Creates Multipath TCP sockets, falling back to TCP.
+    #[cfg(target_os = "linux")]
+    #[inline]
+    pub fn with_mptcp(mut self, enabled: bool) -> Self {
+        self.mptcp = enabled;
+        self
+    }
+
+    /// Sets whether to tunnel through the proxy named by the environment.
+    #[cfg(feature = "proxy")]
+    #[inline]
+    pub fn with_env_proxy(mut self, enabled: bool) -> Self {
+        self.env_proxy = enabled;
+        self
+    }
+
+    /// Calls `f` on each socket before it connects.
+    #[inline]
+    pub fn with_socket_config(
+        mut self,
+        f: impl Fn(BorrowedFd<'_>) -> io::Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.socket_config = Some(SocketConfig::new(f));
+        self
+    }
+
+    /// Sets the options of an established connection.
+    fn configure_stream(&self, stream: &TcpStream) -> io::Result<()> {
+        if self.no_delay {
+            // we will ignore the set nodelay error
+            let _ = stream.set_nodelay(true);
+        }
+        if let Some(keepalive) = self.keepalive {
+            stream.set_tcp_keepalive(keepalive.idle, keepalive.interval, keepalive.retries)?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if sockets need options set before connecting.
+    fn configures_socket(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        if self.interface.is_some() || self.mptcp {
+            return true;
+        }
+        self.local_address.is_some() || self.socket_config.is_some()
+    }
+
+    fn new_socket(&self, addr: SocketAddr) -> io::Result<Socket> {
+        let domain = Domain::for_address(addr);
+        #[cfg(target_os = "linux")]
+        if self.mptcp {
+            match Socket::new(domain, Type::STREAM, Some(Protocol::MPTCP)) {
+                // MPTCP is not built in, or disabled with net.mptcp.enabled.
+                Err(e)
+                    if matches!(
+                        e.raw_os_error(),
+                        Some(libc::EPROTONOSUPPORT | libc::EINVAL | libc::ENOPROTOOPT)
+                    ) => {}
+                res => return res,
+            }
+        }
+        Socket::new(domain, Type::STREAM, None)
+    }
+
+    /// Connects to a single address, binding the socket first if required.
+    async fn connect_addr(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        let connect = self.connect_socket_addr(addr);
+        #[cfg(feature = "tracing")]
+        let connect =
+            tracing::Instrument::instrument(connect, tracing::debug_span!("tcp_connect", %addr));
+        connect.await
+    }
+
+    async fn connect_socket_addr(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        if !self.configures_socket() {
+            let opts = TcpConnectOpts::default().tcp_fast_open(self.fast_open);
+            return TcpStream::connect_addr_with_config(addr, &opts).await;
+        }
+        let socket = self.new_socket(addr)?;
+        if let Some(ip) = self.local_address {
+            socket.bind(&SocketAddr::new(ip, 0).into())?;
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(name) = self.interface {
+            socket.bind_device(Some(name.as_str().as_bytes()))?;
+        }
+        #[cfg(target_os = "linux")]
+        if self.fast_open {
+            // Like monoio, fall back to a regular handshake where it is not supported.
+            let _ = set_fast_open_connect(&socket);
+        }
+        if let Some(SocketConfig(f)) = &self.socket_config {
+            f(socket.as_fd())?;
+        }
+        connect_socket(socket, &addr.into()).await
+    }
+
+    async fn connect_inner<T: TcpTarget>(&self, key: T) -> io::Result<TcpStream>
+    where
+        R: Resolve,
+    {
+        let target = key.tcp_target()?;
         #[cfg(feature = "proxy")]
-        {
-            let proxy = std::env::var("http_proxy")
-                .or_else(|_| std::env::var("HTTP_PROXY"))
-                .ok();
-            
-            match proxy {
-                Some(addr) => {
-                    let proxy_url = addr.parse::<hyper::Uri>().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
-                    let addr = format!{"{}:{}", proxy_url.host().unwrap(), proxy_url.port_u16().unwrap_or(7890)};
-                    let stream = TcpStream::connect(addr).await?;
-                    // stream.set_nodelay(true);
-                    tunnel::<T>(stream, key).await.inspect(|io| {
-                        // we will ignore the set nodelay error
-                        let _ = io.set_nodelay(true);
-                    })
+        if self.env_proxy {
+            let scheme = if key.is_tls() { "https" } else { "http" };
+            let proxy = super::ProxyConfig::from_env_for(scheme)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            if let Some(proxy) = proxy {
+                return self
+                    .connect_via(target, &proxy, &super::NoProxy::from_env())
+                    .await;
+            }
+        }
+        self.connect_direct(target).await
+    }
+
+    /// Tunnels to `target` through `proxy`, unless `no_proxy` lists it.
+    #[cfg(feature = "proxy")]
+    async fn connect_via(
+        &self,
+        target: TcpDestination<'_>,
+        proxy: &super::ProxyConfig,
+        no_proxy: &super::NoProxy,
+    ) -> io::Result<TcpStream>
+    where
+        R: Resolve,
+    {
+        let ip;
+        let (host, port) = match &target {
+            TcpDestination::Host(host, port) => (*host, *port),
+            TcpDestination::Addrs(addrs) => {
+                let addr = self.ip_preference.apply(addrs.clone())?.into_iter().next();
+                let addr = addr
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty address"))?;
+                ip = addr.ip().to_string();
+                (ip.as_str(), addr.port())
+            }
+        };
+        if no_proxy.matches(host) {
+            return self.connect_direct(target).await;
+        }
+        let stream = self
+            .connect_direct(TcpDestination::Host(&proxy.host, proxy.port))
+            .await?;
+        super::proxy::tunnel(stream, proxy, &(host, port), None, &self.resolver).await
+    }
+
+    async fn connect_direct(&self, target: TcpDestination<'_>) -> io::Result<TcpStream>
+    where
+        R: Resolve,
+    {
+        let mut addrs = match target {
+            TcpDestination::Addrs(addrs) => addrs,
+            TcpDestination::Host(host, port) => match parse_ip_literal(host, port) {
+                Some(addr) => vec![addr],
+                None => self.resolver.resolve(host, port).await?.addrs,
+            },
+        };
+        if self.shuffle_addrs {
+            shuffle(&mut addrs);
+        }
+        let addrs = self.ip_preference.apply(addrs)?;
+        let stream = match (addrs.as_slice(), self.happy_eyeballs_delay) {
+            ([], _) => Err(io::Error::new(io::ErrorKind::InvalidInput, "empty address")),
+            ([addr], _) => self.connect_addr(*addr).await,
+            (_, Some(delay)) => self.connect_happy_eyeballs(addrs, delay).await,
+            (_, None) => self.connect_sequential(addrs).await,
+        };
+        let stream = stream?;
+        self.configure_stream(&stream)?;
+        Ok(stream)
+    }
+
+    /// Tries `addrs` one after another.
+    async fn connect_sequential(&self, addrs: Vec<SocketAddr>) -> io::Result<TcpStream> {
+        let mut last_err = None;
+        for addr in addrs {
+            match self.connect_addr(addr).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty address")))
+    }
+
+    /// Races `addrs`, starting a new attempt every `delay`.
+    async fn connect_happy_eyeballs(
+        &self,
+        addrs: Vec<SocketAddr>,
+        delay: Duration,
+    ) -> io::Result<TcpStream> {
+        type Attempt<'a> = Pin<Box<dyn Future<Output = io::Result<TcpStream>> + 'a>>;
+
+        let mut addrs = interleave_families(addrs).into_iter();
+        let mut attempts: Vec<Attempt<'_>> = Vec::new();
+        let mut last_err = None;
+        let mut stagger = Box::pin(monoio::time::sleep(delay));
+        let mut start_next = true;
+        poll_fn(|cx| loop {
+            if start_next {
+                start_next = false;
+                match addrs.next() {
+                    Some(addr) => {
+                        attempts.push(Box::pin(self.connect_addr(addr)));
+                        stagger.set(monoio::time::sleep(delay));
+                    }
+                    None if attempts.is_empty() => {
+                        return Poll::Ready(Err(last_err.take().unwrap_or_else(|| {
+                            io::Error::new(io::ErrorKind::InvalidInput, "empty address")
+                        })));
+                    }
+                    None => {}
                 }
-                None => {
-                    TcpStream::connect(key).await.inspect(|io| {
-                        // we will ignore the set nodelay error
-                        let _ = io.set_nodelay(true);
-                    })
+            }
+
+            let mut i = 0;
+            while i < attempts.len() {
+                match attempts[i].as_mut().poll(cx) {
+                    Poll::Ready(Ok(stream)) => return Poll::Ready(Ok(stream)),
+                    Poll::Ready(Err(e)) => {
+                        #[cfg(feature = "logging")]
+                        tracing::debug!("connection attempt failed: {e}");
+                        last_err = Some(e);
+                        drop(attempts.swap_remove(i));
+                        // A failed attempt lets the next one start right away.
+                        start_next = true;
+                    }
+                    Poll::Pending => i += 1,
                 }
             }
-        }
-        #[cfg(not(feature = "proxy"))]
-        TcpStream::connect(key).await.inspect(|io| {
-            if self.no_delay {
-                // we will ignore the set nodelay error
-                let _ = io.set_nodelay(true);
+            if start_next {
+                continue;
+            }
+            if addrs.len() > 0 && stagger.as_mut().poll(cx).is_ready() {
+                start_next = true;
+                continue;
             }
+            return Poll::Pending;
         })
+        .await
     }
 }
 
-#[cfg(feature = "proxy")]
-async fn tunnel<A>(mut conn: TcpStream, addr: A) -> Result<TcpStream, std::io::Error> 
-    where  A: ToSocketAddrs
-{
-    type Error = io::Error;
-    let addr = addr.to_socket_addrs().unwrap().next().unwrap();
-    let connect_req = format!("CONNECT {addr} HTTP/1.1\r\nHOST: {addr}\r\n\r\n");
-    let mut buf = Vec::with_capacity(8 * 1024);
-    buf.extend_from_slice(connect_req.as_bytes());
-    let (mut res,mut buf) = conn.write_all(buf).await;
-    res?;
-    buf.clear();
-    let mut pos = 0;
-    loop{
-        (res, buf) = conn.read(buf).await;
-        let res = res?;
-        if res == 0 {
-            return Err(Error::new(std::io::ErrorKind::UnexpectedEof, "unexpected eof"));
-        }
-        pos += res;
-        let recvd = std::str::from_utf8(&buf[..pos]);
-        let recvd = recvd.map_err(|e| Error::new(std::io::ErrorKind::InvalidData, e))?;
-        if recvd.starts_with("HTTP/1.1 200") || recvd.starts_with("HTTP/1.0 200") || recvd.starts_with("HTTP/2 200"){
-            if recvd.ends_with("\r\n\r\n") {
-                return Ok(conn);
-            }
-            if res == buf.len() {
-                return Err(Error::new(std::io::ErrorKind::InvalidData, "invalid data"));
-            }
+/// Shuffles addresses in place with a randomly seeded xorshift generator.
+fn shuffle(addrs: &mut [SocketAddr]) {
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut state = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish()
+        | 1;
+    for i in (1..addrs.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        addrs.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+/// Orders addresses alternating between families, starting with the family of the first one.
+fn interleave_families(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_v6 = addrs.first().is_some_and(SocketAddr::is_ipv6);
+    let (preferred, other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_v6);
+    let mut ordered = Vec::with_capacity(preferred.len() + other.len());
+    let (mut preferred, mut other) = (preferred.into_iter(), other.into_iter());
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return ordered,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
         }
     }
 }
 
+/// Exposes the unresolved host and port of a connection target.
+///
+/// Connectors that hand the destination to another party, such as a proxy, use this instead of
+/// [`ToSocketAddrs`](std::net::ToSocketAddrs) so that name resolution happens on the remote side.
+pub trait HostPort {
+    /// The host name or IP literal of the target.
+    fn host(&self) -> &str;
+    /// The port of the target.
+    fn port(&self) -> u16;
+
+    /// Whether the target is reached over TLS, e.g. an `https://` origin, which selects
+    /// `https_proxy` over `http_proxy` from the environment. False by default.
+    #[inline]
+    fn is_tls(&self) -> bool {
+        false
+    }
+
+    /// Formats the target as an authority (`host:port`), bracketing IPv6 literals.
+    fn authority(&self) -> String {
+        let host = self.host();
+        if host.contains(':') && !host.starts_with('[') {
+            format!("[{host}]:{}", self.port())
+        } else {
+            format!("{host}:{}", self.port())
+        }
+    }
+}
+
+impl<T: HostPort + ?Sized> HostPort for &T {
+    #[inline]
+    fn host(&self) -> &str {
+        (**self).host()
+    }
+
+    #[inline]
+    fn port(&self) -> u16 {
+        (**self).port()
+    }
+
+    #[inline]
+    fn is_tls(&self) -> bool {
+        (**self).is_tls()
+    }
+}
+
+impl<S: AsRef<str>> HostPort for (S, u16) {
+    #[inline]
+    fn host(&self) -> &str {
+        self.0.as_ref()
+    }
+
+    #[inline]
+    fn port(&self) -> u16 {
+        self.1
+    }
+}
+
 impl TransportConnMetadata for TcpStream {
     type Metadata = TransportConnMeta;
 
     fn get_conn_metadata(&self) -> Self::Metadata {
-        TransportConnMeta::default()
+        let mut meta = TransportConnMeta::default();
+        if let (Ok(local), Ok(peer)) = (self.local_addr(), self.peer_addr()) {
+            meta.set_addrs(local, peer);
+        }
+        meta
     }
 }
 
-// A connector for establishing Unix domain socket connections.
+/// A connector for establishing Unix domain socket connections.
+///
+/// Paths starting with a NUL byte, like the ones of [`abstract_unix_path`], are names in the Linux
+/// abstract namespace.
 #[derive(Default, Clone, Copy, Debug)]
 pub struct UnixConnector;
 
+/// Returns the path `UnixConnector` connects to the Linux abstract namespace socket `name` at,
+/// which is `name` after a NUL byte.
+#[cfg(target_os = "linux")]
+pub fn abstract_unix_path(name: impl AsRef<[u8]>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+
+    let mut path = vec![0];
+    path.extend_from_slice(name.as_ref());
+    PathBuf::from(std::ffi::OsString::from_vec(path))
+}
+
 impl<P: AsRef<Path>> Connector<P> for UnixConnector {
     type Connection = UnixStream;
     type Error = io::Error;
@@ -126,21 +870,78 @@ impl TransportConnMetadata for UnixStream {
     type Metadata = TransportConnMeta;
 
     fn get_conn_metadata(&self) -> Self::Metadata {
-        TransportConnMeta::default()
+        let path = |addr: io::Result<monoio::net::unix::SocketAddr>| {
+            Some(addr.ok()?.as_pathname()?.to_path_buf())
+        };
+        let mut meta = TransportConnMeta::default();
+        meta.set_paths(path(self.local_addr()), path(self.peer_addr()));
+        meta
     }
 }
 
-/// A connector that can establish either TCP or Unix domain socket connections.
-#[derive(Default, Clone, Copy, Debug)]
+/// A connector that can establish either TCP or Unix domain socket connections, or `AF_VSOCK`
+/// ones on Linux.
+#[derive(Default, Clone, Debug)]
 pub struct UnifiedL4Connector {
     tcp: TcpConnector,
     unix: UnixConnector,
+    #[cfg(target_os = "linux")]
+    vsock: super::VsockConnector,
+    connect_timeout: Option<Duration>,
+}
+
+impl UnifiedL4Connector {
+    /// Sets the time allowed for establishing both TCP and Unix domain socket connections.
+    #[inline]
+    pub fn with_connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Returns the connect timeout.
+    #[inline]
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout
+    }
+
+    /// Calls `f` on each TCP socket before it connects, see [`TcpConnector::socket_config`].
+    #[inline]
+    pub fn with_socket_config(
+        mut self,
+        f: impl Fn(BorrowedFd<'_>) -> io::Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.tcp.socket_config = Some(SocketConfig::new(f));
+        self
+    }
+
+    async fn connect_addr(&self, addr: &UnifiedL4Addr) -> io::Result<UnifiedL4Stream> {
+        match addr {
+            UnifiedL4Addr::Tcp(addr) => self.tcp.connect(addr).await.map(UnifiedL4Stream::Tcp),
+            UnifiedL4Addr::TcpHost(host, port) => self
+                .tcp
+                .connect((host.as_str(), *port))
+                .await
+                .map(UnifiedL4Stream::Tcp),
+            UnifiedL4Addr::Unix(path) => self.unix.connect(path).await.map(UnifiedL4Stream::Unix),
+            #[cfg(target_os = "linux")]
+            UnifiedL4Addr::Vsock(cid, port) => self
+                .vsock
+                .connect(super::VsockAddr::new(*cid, *port))
+                .await
+                .map(UnifiedL4Stream::Vsock),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum UnifiedL4Addr {
     Tcp(SocketAddr),
+    /// A host name and port, resolved without blocking when connecting.
+    TcpHost(smol_str::SmolStr, u16),
     Unix(PathBuf),
+    /// The context id and port of a `AF_VSOCK` address.
+    #[cfg(target_os = "linux")]
+    Vsock(u32, u32),
 }
 
 impl AsRef<UnifiedL4Addr> for UnifiedL4Addr {
@@ -150,28 +951,120 @@ impl AsRef<UnifiedL4Addr> for UnifiedL4Addr {
     }
 }
 
+/// The scheme of uris addressing an HTTP server on a Unix domain socket, whose host is the
+/// hex-encoded path of the socket like in `hyperlocal`, as uri hosts cannot hold paths. See
+/// [`http_unix_uri`]. The paths of abstract namespace sockets keep their leading NUL byte.
+pub const HTTP_UNIX_SCHEME: &str = "http+unix";
+
+/// Returns the `http+unix` uri of `path_and_query` on the server listening on `socket`.
+pub fn http_unix_uri(socket: impl AsRef<Path>, path_and_query: &str) -> Result<Uri, http::Error> {
+    use std::{fmt::Write, os::unix::ffi::OsStrExt};
+
+    let socket = socket.as_ref().as_os_str().as_bytes();
+    let mut host = String::with_capacity(socket.len() * 2);
+    for b in socket {
+        let _ = write!(host, "{b:02x}");
+    }
+    Uri::builder()
+        .scheme(HTTP_UNIX_SCHEME)
+        .authority(host)
+        .path_and_query(path_and_query)
+        .build()
+}
+
+/// Returns the socket path of `http+unix` uris.
+pub(crate) fn uri_unix_path(uri: &Uri) -> Option<Result<PathBuf, crate::FromUriError>> {
+    use std::os::unix::ffi::OsStringExt;
+
+    if uri.scheme_str() != Some(HTTP_UNIX_SCHEME) {
+        return None;
+    }
+    let Some(host) = uri.host() else {
+        return Some(Err(crate::FromUriError::NoAuthority));
+    };
+    let path: Option<Vec<u8>> = host
+        .as_bytes()
+        .chunks(2)
+        .map(|hex| {
+            let hex = std::str::from_utf8(hex).ok().filter(|hex| hex.len() == 2)?;
+            u8::from_str_radix(hex, 16).ok()
+        })
+        .collect();
+    Some(
+        path.filter(|path| !path.is_empty())
+            .map(|path| PathBuf::from(std::ffi::OsString::from_vec(path)))
+            .ok_or(crate::FromUriError::InvalidUnixPath),
+    )
+}
+
+/// Extracts the host and port from `uri`, defaulting the port from the scheme.
+pub(crate) fn uri_host_port(uri: &Uri) -> Result<(&str, u16), crate::FromUriError> {
+    let host = match uri.host() {
+        Some(a) => a,
+        None => return Err(crate::FromUriError::NoAuthority),
+    };
+
+    let default_port = match uri.scheme() {
+        Some(scheme) if scheme == &http::uri::Scheme::HTTP => 80,
+        Some(scheme) if scheme == &http::uri::Scheme::HTTPS => 443,
+        _ => 0,
+    };
+    Ok((host, uri.port_u16().unwrap_or(default_port)))
+}
+
+impl UnifiedL4Addr {
+    /// Returns the address of the Linux abstract namespace socket `name`.
+    #[cfg(target_os = "linux")]
+    #[inline]
+    pub fn unix_abstract(name: impl AsRef<[u8]>) -> Self {
+        Self::Unix(abstract_unix_path(name))
+    }
+
+    /// Returns the name of abstract namespace socket addresses.
+    pub fn abstract_name(&self) -> Option<&[u8]> {
+        use std::os::unix::ffi::OsStrExt;
+
+        match self {
+            Self::Unix(path) => path.as_os_str().as_bytes().strip_prefix(&[0]),
+            _ => None,
+        }
+    }
+
+    /// Converts `uri` into an address like `TryFrom<&Uri>` does, but resolves the host with
+    /// `resolver` instead of blocking on the system resolver.
+    pub async fn resolve_uri<R: Resolve>(
+        uri: &Uri,
+        resolver: &R,
+    ) -> Result<Self, crate::FromUriError> {
+        if let Some(path) = uri_unix_path(uri) {
+            return path.map(Self::Unix);
+        }
+        let (host, port) = uri_host_port(uri)?;
+        let lookup = resolver.resolve(host, port).await?;
+        lookup
+            .addrs
+            .first()
+            .copied()
+            .map(Self::Tcp)
+            .ok_or(crate::FromUriError::NoResolve)
+    }
+}
+
 impl TryFrom<&Uri> for UnifiedL4Addr {
     type Error = crate::FromUriError;
 
+    /// Converts `http+unix` uris to the path of their socket, and others to their IP address or
+    /// to their host name, resolved when connecting.
     #[inline]
     fn try_from(uri: &Uri) -> Result<Self, Self::Error> {
-        let host = match uri.host() {
-            Some(a) => a,
-            None => return Err(crate::FromUriError::NoAuthority),
-        };
-
-        let default_port = match uri.scheme() {
-            Some(scheme) if scheme == &http::uri::Scheme::HTTP => 80,
-            Some(scheme) if scheme == &http::uri::Scheme::HTTPS => 443,
-            _ => 0,
-        };
-        let port = uri.port_u16().unwrap_or(default_port);
-        let addr = (host, port)
-            .to_socket_addrs()?
-            .next()
-            .ok_or(crate::FromUriError::NoResolve)?;
-
-        Ok(Self::Tcp(addr))
+        if let Some(path) = uri_unix_path(uri) {
+            return path.map(Self::Unix);
+        }
+        let (host, port) = uri_host_port(uri)?;
+        Ok(match parse_ip_literal(host, port) {
+            Some(addr) => Self::Tcp(addr),
+            None => Self::TcpHost(host.into(), port),
+        })
     }
 }
 
@@ -183,11 +1076,35 @@ impl TryFrom<Uri> for UnifiedL4Addr {
     }
 }
 
+/// Derives the keys of every uri as the Unix domain socket at its path, so requests whose uris
+/// name the server, e.g. `http://docker/info`, reach a local socket.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UnixSocketPath(pub PathBuf);
+
+impl DeriveKey<UnifiedL4Addr> for UnixSocketPath {
+    #[inline]
+    fn derive_key(&self, _uri: &Uri) -> Result<UnifiedL4Addr, crate::FromUriError> {
+        Ok(UnifiedL4Addr::Unix(self.0.clone()))
+    }
+}
+
+impl DeriveKey<super::UnifiedAddr> for UnixSocketPath {
+    #[inline]
+    fn derive_key(&self, uri: &Uri) -> Result<super::UnifiedAddr, crate::FromUriError> {
+        Ok(super::UnifiedAddr {
+            addr: self.derive_key(uri)?,
+            sn: None,
+        })
+    }
+}
+
 /// A unified L4 stream that can be either a TCP or Unix stream.
 #[derive(Debug)]
 pub enum UnifiedL4Stream {
     Tcp(TcpStream),
     Unix(UnixStream),
+    #[cfg(target_os = "linux")]
+    Vsock(super::VsockStream),
 }
 
 impl<T: AsRef<UnifiedL4Addr>> Connector<T> for UnifiedL4Connector {
@@ -196,9 +1113,30 @@ impl<T: AsRef<UnifiedL4Addr>> Connector<T> for UnifiedL4Connector {
 
     #[inline]
     async fn connect(&self, key: T) -> Result<Self::Connection, Self::Error> {
-        match key.as_ref() {
-            UnifiedL4Addr::Tcp(addr) => self.tcp.connect(addr).await.map(UnifiedL4Stream::Tcp),
-            UnifiedL4Addr::Unix(path) => self.unix.connect(path).await.map(UnifiedL4Stream::Unix),
+        with_connect_timeout(self.connect_timeout, self.connect_addr(key.as_ref())).await
+    }
+}
+
+impl<T: AsRef<UnifiedL4Addr>> Connector<WithConnectTimeout<T>> for UnifiedL4Connector {
+    type Connection = UnifiedL4Stream;
+    type Error = io::Error;
+
+    #[inline]
+    async fn connect(&self, key: WithConnectTimeout<T>) -> Result<Self::Connection, Self::Error> {
+        let timeout = key.timeout.or(self.connect_timeout);
+        with_connect_timeout(timeout, self.connect_addr(key.key.as_ref())).await
+    }
+}
+
+impl TransportConnMetadata for UnifiedL4Stream {
+    type Metadata = TransportConnMeta;
+
+    fn get_conn_metadata(&self) -> Self::Metadata {
+        match self {
+            UnifiedL4Stream::Tcp(inner) => inner.get_conn_metadata(),
+            UnifiedL4Stream::Unix(inner) => inner.get_conn_metadata(),
+            #[cfg(target_os = "linux")]
+            UnifiedL4Stream::Vsock(inner) => inner.get_conn_metadata(),
         }
     }
 }
@@ -209,6 +1147,8 @@ impl AsyncReadRent for UnifiedL4Stream {
         match self {
             UnifiedL4Stream::Tcp(inner) => inner.read(buf).await,
             UnifiedL4Stream::Unix(inner) => inner.read(buf).await,
+            #[cfg(target_os = "linux")]
+            UnifiedL4Stream::Vsock(inner) => inner.read(buf).await,
         }
     }
 
@@ -217,6 +1157,8 @@ impl AsyncReadRent for UnifiedL4Stream {
         match self {
             UnifiedL4Stream::Tcp(inner) => inner.readv(buf).await,
             UnifiedL4Stream::Unix(inner) => inner.readv(buf).await,
+            #[cfg(target_os = "linux")]
+            UnifiedL4Stream::Vsock(inner) => inner.readv(buf).await,
         }
     }
 }
@@ -227,6 +1169,8 @@ impl AsyncWriteRent for UnifiedL4Stream {
         match self {
             UnifiedL4Stream::Tcp(inner) => inner.write(buf).await,
             UnifiedL4Stream::Unix(inner) => inner.write(buf).await,
+            #[cfg(target_os = "linux")]
+            UnifiedL4Stream::Vsock(inner) => inner.write(buf).await,
         }
     }
 
@@ -238,6 +1182,8 @@ impl AsyncWriteRent for UnifiedL4Stream {
         match self {
             UnifiedL4Stream::Tcp(inner) => inner.writev(buf_vec).await,
             UnifiedL4Stream::Unix(inner) => inner.writev(buf_vec).await,
+            #[cfg(target_os = "linux")]
+            UnifiedL4Stream::Vsock(inner) => inner.writev(buf_vec).await,
         }
     }
 
@@ -246,6 +1192,8 @@ impl AsyncWriteRent for UnifiedL4Stream {
         match self {
             UnifiedL4Stream::Tcp(inner) => inner.flush().await,
             UnifiedL4Stream::Unix(inner) => inner.flush().await,
+            #[cfg(target_os = "linux")]
+            UnifiedL4Stream::Vsock(inner) => inner.flush().await,
         }
     }
 
@@ -254,8 +1202,361 @@ impl AsyncWriteRent for UnifiedL4Stream {
         match self {
             UnifiedL4Stream::Tcp(inner) => inner.shutdown().await,
             UnifiedL4Stream::Unix(inner) => inner.shutdown().await,
+            #[cfg(target_os = "linux")]
+            UnifiedL4Stream::Vsock(inner) => inner.shutdown().await,
         }
     }
 }
 
 unsafe impl Split for UnifiedL4Stream {}
+
+#[cfg(test)]
+mod tests {
+    use monoio::{io::AsyncWriteRentExt, net::TcpListener};
+
+    use super::*;
+    use crate::connectors::socket::socket_of;
+
+    #[cfg(target_os = "linux")]
+    #[monoio::test(enable_timer = true)]
+    async fn connects_to_abstract_unix_sockets() {
+        let name = format!("monoio-transports-{}", std::process::id());
+        let addr = UnifiedL4Addr::unix_abstract(&name);
+        assert_eq!(addr.abstract_name(), Some(name.as_bytes()));
+        assert_eq!(
+            UnifiedL4Addr::Unix("/tmp/a.sock".into()).abstract_name(),
+            None
+        );
+
+        let opts = monoio::net::ListenerOpts::new().reuse_port(false);
+        let UnifiedL4Addr::Unix(path) = &addr else {
+            unreachable!()
+        };
+        let listener = monoio::net::UnixListener::bind_with_config(path, &opts).unwrap();
+        let accepted = monoio::spawn(async move { listener.accept().await.unwrap() });
+        let stream = UnifiedL4Connector::default().connect(&addr).await.unwrap();
+        assert!(matches!(stream, UnifiedL4Stream::Unix(_)));
+        accepted.await;
+    }
+
+    #[test]
+    fn interleave_address_families() {
+        let addrs: Vec<SocketAddr> = ["[::1]:1", "[::2]:1", "[::3]:1", "10.0.0.1:1", "10.0.0.2:1"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        let ordered: Vec<String> = interleave_families(addrs)
+            .into_iter()
+            .map(|a| a.to_string())
+            .collect();
+        assert_eq!(
+            ordered,
+            ["[::1]:1", "10.0.0.1:1", "[::2]:1", "10.0.0.2:1", "[::3]:1"]
+        );
+    }
+
+    #[test]
+    fn ip_preference_orders_and_filters() {
+        let addrs: Vec<SocketAddr> = ["[::1]:1", "10.0.0.1:1", "[::2]:1"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        let apply = |pref: IpPreference| -> Vec<String> {
+            pref.apply(addrs.clone())
+                .unwrap()
+                .into_iter()
+                .map(|a| a.to_string())
+                .collect()
+        };
+        assert_eq!(
+            apply(IpPreference::PreferIpv4),
+            ["10.0.0.1:1", "[::1]:1", "[::2]:1"]
+        );
+        assert_eq!(apply(IpPreference::Ipv6Only), ["[::1]:1", "[::2]:1"]);
+        let err = IpPreference::Ipv4Only
+            .apply(vec!["[::1]:1".parse().unwrap()])
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn sequential_fails_over_to_next_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let live = listener.local_addr().unwrap();
+        let dead = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        monoio::spawn(async move {
+            let _conn = listener.accept().await.unwrap();
+        });
+
+        let connector = TcpConnector {
+            happy_eyeballs_delay: None,
+            ..Default::default()
+        };
+        let stream = connector.connect(&[dead, live][..]).await.unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), live);
+
+        let mut addrs = vec![dead, live];
+        shuffle(&mut addrs);
+        assert!(addrs.contains(&dead) && addrs.contains(&live));
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn resolves_hosts_when_connecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            loop {
+                let _conn = listener.accept().await.unwrap();
+            }
+        });
+
+        let uri: Uri = format!("http://backend.test:{}/", addr.port())
+            .parse()
+            .unwrap();
+        let key = UnifiedL4Addr::try_from(&uri).unwrap();
+        assert_eq!(
+            key,
+            UnifiedL4Addr::TcpHost("backend.test".into(), addr.port())
+        );
+        let uri: Uri = format!("http://{addr}/").parse().unwrap();
+        assert_eq!(
+            UnifiedL4Addr::try_from(&uri).unwrap(),
+            UnifiedL4Addr::Tcp(addr)
+        );
+
+        let resolver =
+            crate::dns::OverrideResolver::new(GaiResolver).resolve_to("backend.test", addr);
+        let connector = TcpConnector::default().with_resolver(resolver);
+        let stream = connector
+            .connect(("backend.test", addr.port()))
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+        let stream = connector
+            .connect(format!("backend.test:{}", addr.port()))
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+        let key = UnifiedL4Addr::TcpHost("localhost".into(), addr.port());
+        assert!(UnifiedL4Connector::default().connect(&key).await.is_ok());
+
+        // Keys only implementing `ToSocketAddrs` resolve themselves.
+        struct Legacy(u16);
+        impl std::net::ToSocketAddrs for Legacy {
+            type Iter = std::vec::IntoIter<SocketAddr>;
+            fn to_socket_addrs(&self) -> io::Result<Self::Iter> {
+                ("127.0.0.1", self.0).to_socket_addrs()
+            }
+        }
+        let stream = connector
+            .connect(SocketAddrsKey(Legacy(addr.port())))
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn connect_timeout_aborts_pending_connect() {
+        let err = with_connect_timeout(
+            Some(Duration::from_millis(10)),
+            std::future::pending::<io::Result<()>>(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connector = TcpConnector {
+            connect_timeout: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let key = WithConnectTimeout::new(addr, Duration::from_secs(5));
+        assert!(connector.connect(key).await.is_ok());
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn binds_local_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let local: IpAddr = "127.0.0.2".parse().unwrap();
+        let accept = monoio::spawn(async move { listener.accept().await.unwrap().1 });
+
+        let connector = TcpConnector::default().with_local_address(local);
+        let stream = connector.connect(addr).await.unwrap();
+        assert_eq!(stream.local_addr().unwrap().ip(), local);
+        assert_eq!(accept.await.ip(), local);
+        assert!(connector.no_delay && stream.nodelay().unwrap());
+        assert!(!socket_of(&stream).keepalive().unwrap());
+
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(InterfaceName::new("lo").unwrap().as_str(), "lo");
+            assert!(InterfaceName::new("").is_err());
+            assert!(InterfaceName::new("a-very-long-name").is_err());
+            let connector = TcpConnector::default()
+                .with_interface("no-such-if0")
+                .unwrap();
+            assert!(connector.connect(addr).await.is_err());
+        }
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn sets_keepalive() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            let _conn = listener.accept().await.unwrap();
+        });
+
+        let keepalive = TcpKeepalive::new()
+            .with_idle(Duration::from_secs(30))
+            .with_interval(Duration::from_secs(5))
+            .with_retries(3);
+        let connector = TcpConnector::default().with_keepalive(keepalive);
+        let stream = connector.connect(addr).await.unwrap();
+        let socket = socket_of(&stream);
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(30));
+        assert_eq!(socket.keepalive_interval().unwrap(), Duration::from_secs(5));
+        assert_eq!(socket.keepalive_retries().unwrap(), 3);
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn connects_with_fast_open() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            loop {
+                let (mut conn, _) = listener.accept().await.unwrap();
+                let (res, buf) = conn.read(vec![0; 5]).await;
+                let n = res.unwrap();
+                let _ = conn.write_all(buf[..n].to_vec()).await;
+            }
+        });
+
+        let fast_open = TcpConnector::default().with_fast_open(true);
+        let local = fast_open
+            .clone()
+            .with_local_address("127.0.0.1".parse().unwrap());
+        for connector in [fast_open, local] {
+            let mut stream = connector.connect(addr).await.unwrap();
+            let (res, _) = stream.write_all(b"hello").await;
+            res.unwrap();
+            let (res, buf) = stream.read(vec![0; 5]).await;
+            assert_eq!(res.unwrap(), 5);
+            assert_eq!(buf, b"hello");
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[monoio::test(enable_timer = true)]
+    async fn connects_with_mptcp_or_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let _ = conn.write_all(b"hello").await;
+        });
+
+        let connector = TcpConnector::default().with_mptcp(true);
+        let mut stream = connector.connect(addr).await.unwrap();
+        let protocol = socket_of(&stream).protocol().unwrap();
+        assert!(protocol == Some(Protocol::MPTCP) || protocol == Some(Protocol::TCP));
+        let (res, buf) = stream.read(vec![0; 5]).await;
+        assert_eq!(res.unwrap(), 5);
+        assert_eq!(buf, b"hello");
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn configures_sockets_before_connecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            let _conn = listener.accept().await.unwrap();
+        });
+
+        let connector = TcpConnector::default()
+            .with_socket_config(|fd| socket2::SockRef::from(&fd).set_recv_buffer_size(64 * 1024));
+        let stream = connector.connect(addr).await.unwrap();
+        // Linux doubles the requested size.
+        assert!(socket_of(&stream).recv_buffer_size().unwrap() >= 64 * 1024);
+
+        let failing =
+            UnifiedL4Connector::default().with_socket_config(|_| Err(io::Error::other("rejected")));
+        let err = failing.connect(UnifiedL4Addr::Tcp(addr)).await.unwrap_err();
+        assert_eq!(err.to_string(), "rejected");
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn happy_eyeballs_skips_failed_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let live = listener.local_addr().unwrap();
+        // Bind and drop to get a port nothing listens on. The std listener closes synchronously.
+        let dead = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        monoio::spawn(async move {
+            let (conn, _) = listener.accept().await.unwrap();
+            monoio::time::sleep(Duration::from_secs(1)).await;
+            drop(conn);
+        });
+
+        let connector = TcpConnector {
+            happy_eyeballs_delay: Some(Duration::from_secs(5)),
+            ..Default::default()
+        };
+        let stream = monoio::time::timeout(
+            Duration::from_secs(1),
+            connector.connect([dead, live].as_slice()),
+        )
+        .await
+        .expect("failure should start the next attempt without waiting")
+        .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), live);
+    }
+
+    #[cfg(feature = "proxy")]
+    #[monoio::test(enable_timer = true)]
+    async fn tunnels_through_proxy_unless_bypassed() {
+        use monoio::io::AsyncReadRent;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+        let request = monoio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let (res, buf) = conn.read(vec![0; 1024]).await;
+            let request = String::from_utf8(buf[..res.unwrap()].to_vec()).unwrap();
+            let (res, _) = conn.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await;
+            res.unwrap();
+            request
+        });
+        let proxy = crate::connectors::ProxyConfig::new(
+            crate::connectors::ProxyScheme::Http,
+            "127.0.0.1",
+            proxy_addr.port(),
+        );
+        let connector = TcpConnector::default();
+        let no_proxy = crate::connectors::NoProxy::parse("127.0.0.2");
+        let stream = connector
+            .connect_via(TcpDestination::Host("backend.test", 80), &proxy, &no_proxy)
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), proxy_addr);
+        assert!(request
+            .await
+            .starts_with("CONNECT backend.test:80 HTTP/1.1\r\n"));
+
+        let listener = TcpListener::bind("127.0.0.2:0").unwrap();
+        let direct = listener.local_addr().unwrap();
+        let stream = connector
+            .connect_via(TcpDestination::Addrs(vec![direct]), &proxy, &no_proxy)
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), direct);
+    }
+}
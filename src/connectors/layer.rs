@@ -0,0 +1,249 @@
+//! Composition of connectors from layers.
+//!
+//! Cross-cutting concerns are added by wrapping a connector into another one, such as a
+//! [`TlsConnector`](super::TlsConnector) over a [`TcpConnector`](super::TcpConnector). A
+//! [`ConnectorLayer`] describes one such wrapping, so stacks can be assembled with a
+//! [`ConnectorBuilder`] and shared between clients:
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use monoio_transports::connectors::{
+//!     layer::{ConnectorBuilder, TimeoutLayer},
+//!     CircuitBreaker, CircuitBreakerLayer, TcpConnector,
+//! };
+//!
+//! let breaker = CircuitBreaker::<std::net::SocketAddr>::new();
+//! let connector = ConnectorBuilder::new()
+//!     .layer(CircuitBreakerLayer::new(breaker))
+//!     .layer(TimeoutLayer::new(Duration::from_secs(3)))
+//!     .build(TcpConnector::default());
+//! ```
+//!
+//! Layers added first wrap the ones added after them, so the circuit breaker above counts timed
+//! out connections as failures. Any function wrapping a connector is a layer with [`layer_fn`].
+use std::{io, time::Duration};
+
+use super::Connector;
+
+/// Wraps a connector into another one, the connector counterpart of tower's `Layer`.
+pub trait ConnectorLayer<C> {
+    /// The wrapping connector.
+    type Connector;
+
+    fn layer(&self, inner: C) -> Self::Connector;
+}
+
+/// A layer returning the connector unchanged.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Identity;
+
+impl<C> ConnectorLayer<C> for Identity {
+    type Connector = C;
+
+    #[inline]
+    fn layer(&self, inner: C) -> C {
+        inner
+    }
+}
+
+/// Two layers applied in turn, `inner` first.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stack<Inner, Outer> {
+    inner: Inner,
+    outer: Outer,
+}
+
+impl<Inner, Outer> Stack<Inner, Outer> {
+    #[inline]
+    pub fn new(inner: Inner, outer: Outer) -> Self {
+        Self { inner, outer }
+    }
+}
+
+impl<C, Inner, Outer> ConnectorLayer<C> for Stack<Inner, Outer>
+where
+    Inner: ConnectorLayer<C>,
+    Outer: ConnectorLayer<Inner::Connector>,
+{
+    type Connector = Outer::Connector;
+
+    #[inline]
+    fn layer(&self, inner: C) -> Self::Connector {
+        self.outer.layer(self.inner.layer(inner))
+    }
+}
+
+/// A layer calling a function, see [`layer_fn`].
+#[derive(Debug, Clone, Copy)]
+pub struct LayerFn<F>(F);
+
+/// Returns a layer wrapping connectors with `f`, such as the `new` function of a connector.
+#[inline]
+pub fn layer_fn<F>(f: F) -> LayerFn<F> {
+    LayerFn(f)
+}
+
+impl<C, D, F: Fn(C) -> D> ConnectorLayer<C> for LayerFn<F> {
+    type Connector = D;
+
+    #[inline]
+    fn layer(&self, inner: C) -> D {
+        (self.0)(inner)
+    }
+}
+
+/// Assembles a connector from layers, the first one added being the outermost.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConnectorBuilder<L> {
+    layer: L,
+}
+
+impl ConnectorBuilder<Identity> {
+    #[inline]
+    pub fn new() -> Self {
+        Self { layer: Identity }
+    }
+}
+
+impl<L> ConnectorBuilder<L> {
+    /// Adds `layer`, wrapped by the layers added before it.
+    #[inline]
+    pub fn layer<T>(self, layer: T) -> ConnectorBuilder<Stack<T, L>> {
+        ConnectorBuilder {
+            layer: Stack::new(layer, self.layer),
+        }
+    }
+
+    /// Returns the stacked layers.
+    #[inline]
+    pub fn into_layer(self) -> L {
+        self.layer
+    }
+
+    /// Wraps `connector` into the layers.
+    #[inline]
+    pub fn build<C>(&self, connector: C) -> L::Connector
+    where
+        L: ConnectorLayer<C>,
+    {
+        self.layer.layer(connector)
+    }
+}
+
+/// A layer bounding connection establishment with a [`TimeoutConnector`].
+#[derive(Debug, Clone, Copy)]
+pub struct TimeoutLayer {
+    timeout: Duration,
+}
+
+impl TimeoutLayer {
+    #[inline]
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+impl<C> ConnectorLayer<C> for TimeoutLayer {
+    type Connector = TimeoutConnector<C>;
+
+    #[inline]
+    fn layer(&self, inner: C) -> Self::Connector {
+        TimeoutConnector::new(inner, self.timeout)
+    }
+}
+
+/// A connector failing with [`io::ErrorKind::TimedOut`] when its inner connector does not
+/// connect in time. Requires the monoio timer driver.
+///
+/// Unlike the connect timeout of [`TcpConnector`](super::TcpConnector), this bounds everything
+/// below it, TLS handshakes and proxy tunnels included.
+#[derive(Debug, Clone)]
+pub struct TimeoutConnector<C> {
+    inner_connector: C,
+    timeout: Duration,
+}
+
+impl<C> TimeoutConnector<C> {
+    #[inline]
+    pub fn new(inner_connector: C, timeout: Duration) -> Self {
+        Self {
+            inner_connector,
+            timeout,
+        }
+    }
+
+    #[inline]
+    pub fn inner_connector(&self) -> &C {
+        &self.inner_connector
+    }
+
+    #[inline]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+impl<C, K> Connector<K> for TimeoutConnector<C>
+where
+    C: Connector<K>,
+    C::Error: From<io::Error>,
+{
+    type Connection = C::Connection;
+    type Error = C::Error;
+
+    async fn connect(&self, key: K) -> Result<Self::Connection, Self::Error> {
+        match monoio::time::timeout(self.timeout, self.inner_connector.connect(key)).await {
+            Ok(res) => res,
+            Err(_) => Err(io::Error::from(crate::error::Elapsed::Connect).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    /// Records the order layers were applied in.
+    struct Tag(&'static str);
+
+    impl ConnectorLayer<Vec<&'static str>> for Tag {
+        type Connector = Vec<&'static str>;
+
+        fn layer(&self, mut inner: Vec<&'static str>) -> Self::Connector {
+            inner.push(self.0);
+            inner
+        }
+    }
+
+    struct Pending<'a>(&'a Cell<u32>);
+
+    impl Connector<()> for Pending<'_> {
+        type Connection = ();
+        type Error = io::Error;
+
+        async fn connect(&self, _key: ()) -> io::Result<()> {
+            self.0.set(self.0.get() + 1);
+            std::future::pending().await
+        }
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn stacks_layers() {
+        let builder = ConnectorBuilder::new()
+            .layer(Tag("outer"))
+            .layer(Tag("inner"));
+        assert_eq!(builder.build(vec!["base"]), ["base", "inner", "outer"]);
+
+        let attempts = Cell::new(0);
+        let connector = ConnectorBuilder::new()
+            .layer(TimeoutLayer::new(Duration::from_millis(10)))
+            .layer(layer_fn(|c| c))
+            .build(Pending(&attempts));
+        let err = connector.connect(()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(attempts.get(), 1);
+    }
+}
@@ -0,0 +1,461 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    future::{poll_fn, Future},
+    hash::Hash,
+    io,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+use bytes::Bytes;
+use monoio::{
+    buf::{IoBuf, IoBufMut, IoVecBuf, IoVecBufMut},
+    io::{AsyncReadRent, AsyncWriteRent, AsyncWriteRentExt, Split},
+    BufResult,
+};
+
+use super::{Connector, TransportConnMeta, TransportConnMetadata};
+
+/// The bytes buffered in each direction of the streams of a [`MockConnector`].
+const DEFAULT_CAPACITY: usize = 64 * 1024;
+
+/// One direction of a duplex stream.
+#[derive(Debug)]
+struct Pipe {
+    buf: VecDeque<u8>,
+    capacity: usize,
+    // The writing end shut down or was dropped.
+    closed: bool,
+    // The reading end was dropped.
+    abandoned: bool,
+    reader: Option<Waker>,
+    writer: Option<Waker>,
+}
+
+impl Pipe {
+    fn new(capacity: usize) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            buf: VecDeque::new(),
+            capacity: capacity.max(1),
+            closed: false,
+            abandoned: false,
+            reader: None,
+            writer: None,
+        }))
+    }
+
+    fn poll_readable(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.buf.is_empty() || self.closed {
+            return Poll::Ready(());
+        }
+        self.reader = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn poll_writable(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.abandoned || self.closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        if self.buf.len() < self.capacity {
+            return Poll::Ready(Ok(()));
+        }
+        self.writer = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Moves up to `len` buffered bytes to `dst`.
+    ///
+    /// # Safety
+    ///
+    /// `dst` must be valid for writes of `len` bytes.
+    unsafe fn take(&mut self, dst: *mut u8, len: usize) -> usize {
+        let n = len.min(self.buf.len());
+        let (front, back) = self.buf.as_slices();
+        let split = n.min(front.len());
+        std::ptr::copy_nonoverlapping(front.as_ptr(), dst, split);
+        std::ptr::copy_nonoverlapping(back.as_ptr(), dst.add(split), n - split);
+        self.buf.drain(..n);
+        if let Some(writer) = self.writer.take() {
+            writer.wake();
+        }
+        n
+    }
+
+    /// Buffers as much of `src` as the capacity allows.
+    fn put(&mut self, src: &[u8]) -> usize {
+        let n = src.len().min(self.capacity - self.buf.len());
+        self.buf.extend(&src[..n]);
+        if let Some(reader) = self.reader.take() {
+            reader.wake();
+        }
+        n
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        if let Some(reader) = self.reader.take() {
+            reader.wake();
+        }
+    }
+
+    fn abandon(&mut self) {
+        self.abandoned = true;
+        if let Some(writer) = self.writer.take() {
+            writer.wake();
+        }
+    }
+}
+
+/// Returns two connected in-memory streams, the bytes written to one being read from the other.
+///
+/// Each direction buffers up to `capacity` bytes, writes wait once it is full. Shutting down or
+/// dropping a stream ends the reads of the other one, whose writes then fail with
+/// [`io::ErrorKind::BrokenPipe`].
+pub fn duplex(capacity: usize) -> (DuplexStream, DuplexStream) {
+    let (a, b) = (Pipe::new(capacity), Pipe::new(capacity));
+    let one = DuplexStream {
+        read: a.clone(),
+        write: b.clone(),
+    };
+    let other = DuplexStream { read: b, write: a };
+    (one, other)
+}
+
+/// One end of an in-memory stream created with [`duplex`].
+#[derive(Debug)]
+pub struct DuplexStream {
+    read: Rc<RefCell<Pipe>>,
+    write: Rc<RefCell<Pipe>>,
+}
+
+impl Drop for DuplexStream {
+    fn drop(&mut self) {
+        self.write.borrow_mut().close();
+        self.read.borrow_mut().abandon();
+    }
+}
+
+impl TransportConnMetadata for DuplexStream {
+    type Metadata = TransportConnMeta;
+
+    fn get_conn_metadata(&self) -> Self::Metadata {
+        TransportConnMeta::default()
+    }
+}
+
+impl AsyncReadRent for DuplexStream {
+    async fn read<T: IoBufMut>(&mut self, mut buf: T) -> BufResult<usize, T> {
+        poll_fn(|cx| self.read.borrow_mut().poll_readable(cx)).await;
+        // SAFETY: the buffer is valid for `bytes_total` bytes, `n` of which are written.
+        unsafe {
+            let n = self
+                .read
+                .borrow_mut()
+                .take(buf.write_ptr(), buf.bytes_total());
+            buf.set_init(n);
+            (Ok(n), buf)
+        }
+    }
+
+    async fn readv<T: IoVecBufMut>(&mut self, mut buf: T) -> BufResult<usize, T> {
+        poll_fn(|cx| self.read.borrow_mut().poll_readable(cx)).await;
+        let mut pipe = self.read.borrow_mut();
+        let mut n = 0;
+        // SAFETY: the iovecs are valid, and written up to `n` bytes in order.
+        unsafe {
+            let iovecs = std::slice::from_raw_parts(buf.write_iovec_ptr(), buf.write_iovec_len());
+            for iovec in iovecs {
+                let read = pipe.take(iovec.iov_base.cast(), iovec.iov_len);
+                n += read;
+                if read < iovec.iov_len {
+                    break;
+                }
+            }
+            buf.set_init(n);
+        }
+        (Ok(n), buf)
+    }
+}
+
+impl AsyncWriteRent for DuplexStream {
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        if let Err(e) = poll_fn(|cx| self.write.borrow_mut().poll_writable(cx)).await {
+            return (Err(e), buf);
+        }
+        // SAFETY: the buffer has `bytes_init` initialized bytes.
+        let src = unsafe { std::slice::from_raw_parts(buf.read_ptr(), buf.bytes_init()) };
+        let n = self.write.borrow_mut().put(src);
+        (Ok(n), buf)
+    }
+
+    async fn writev<T: IoVecBuf>(&mut self, buf_vec: T) -> BufResult<usize, T> {
+        if let Err(e) = poll_fn(|cx| self.write.borrow_mut().poll_writable(cx)).await {
+            return (Err(e), buf_vec);
+        }
+        let mut pipe = self.write.borrow_mut();
+        let mut n = 0;
+        // SAFETY: the iovecs are valid and initialized.
+        unsafe {
+            let iovecs =
+                std::slice::from_raw_parts(buf_vec.read_iovec_ptr(), buf_vec.read_iovec_len());
+            for iovec in iovecs {
+                let src = std::slice::from_raw_parts(iovec.iov_base as *const u8, iovec.iov_len);
+                let written = pipe.put(src);
+                n += written;
+                if written < src.len() {
+                    break;
+                }
+            }
+        }
+        (Ok(n), buf_vec)
+    }
+
+    #[inline]
+    async fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> io::Result<()> {
+        self.write.borrow_mut().close();
+        Ok(())
+    }
+}
+
+// The halves use distinct pipes, borrowed only for the time of a copy.
+unsafe impl Split for DuplexStream {}
+
+type Handler = Rc<dyn Fn(DuplexStream) -> Pin<Box<dyn Future<Output = ()>>>>;
+
+#[derive(Clone)]
+enum Route {
+    Serve(Handler),
+    Fail(io::ErrorKind),
+}
+
+/// A connector to scripted in-memory servers, to test clients without binding sockets.
+///
+/// Each connection to a key set with [`with_handler`](Self::with_handler) spawns its handler on
+/// the current runtime with the server end of a [`duplex`] stream, and
+/// [`with_responses`](Self::with_responses) replays raw responses. Connections to keys set with
+/// [`with_error`](Self::with_error) fail with that error, and to other keys with
+/// [`io::ErrorKind::ConnectionRefused`].
+///
+/// Clones share the counts of [`connects`](Self::connects).
+#[derive(Clone)]
+pub struct MockConnector<K> {
+    routes: HashMap<K, Route>,
+    capacity: usize,
+    connects: Rc<RefCell<HashMap<K, usize>>>,
+}
+
+impl<K> std::fmt::Debug for MockConnector<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockConnector")
+            .field("capacity", &self.capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K> Default for MockConnector<K> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            routes: HashMap::new(),
+            capacity: DEFAULT_CAPACITY,
+            connects: Default::default(),
+        }
+    }
+}
+
+impl<K: Hash + Eq> MockConnector<K> {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves each connection to `key` with `handler`, replacing any previous script for it.
+    pub fn with_handler<F, Fut>(mut self, key: K, handler: F) -> Self
+    where
+        F: Fn(DuplexStream) -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let handler: Handler = Rc::new(move |stream| Box::pin(handler(stream)));
+        self.routes.insert(key, Route::Serve(handler));
+        self
+    }
+
+    /// Answers the HTTP/1.1 requests sent to `key` with `responses`, raw responses sent in turn
+    /// once each request was read, across connections. A connection is closed once they are
+    /// exhausted.
+    ///
+    /// Requests are read up to the end of their head and their `Content-Length` body, chunked
+    /// bodies need a handler.
+    pub fn with_responses<I>(self, key: K, responses: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Bytes>,
+    {
+        let responses: Rc<RefCell<VecDeque<Bytes>>> = Rc::new(RefCell::new(
+            responses.into_iter().map(Into::into).collect(),
+        ));
+        self.with_handler(key, move |stream| {
+            serve_responses(stream, responses.clone())
+        })
+    }
+
+    /// Fails the connections to `key` with `kind`.
+    #[inline]
+    pub fn with_error(mut self, key: K, kind: io::ErrorKind) -> Self {
+        self.routes.insert(key, Route::Fail(kind));
+        self
+    }
+
+    /// Sets the bytes buffered in each direction of the streams, 64 KiB by default.
+    #[inline]
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Returns how many connections to `key` were attempted.
+    pub fn connects(&self, key: &K) -> usize {
+        self.connects.borrow().get(key).copied().unwrap_or(0)
+    }
+}
+
+impl<K: Hash + Eq + Clone> Connector<K> for MockConnector<K> {
+    type Connection = DuplexStream;
+    type Error = io::Error;
+
+    async fn connect(&self, key: K) -> Result<Self::Connection, Self::Error> {
+        *self.connects.borrow_mut().entry(key.clone()).or_default() += 1;
+        match self.routes.get(&key) {
+            Some(Route::Serve(handler)) => {
+                let (client, server) = duplex(self.capacity);
+                monoio::spawn(handler(server));
+                Ok(client)
+            }
+            Some(Route::Fail(kind)) => Err((*kind).into()),
+            None => Err(io::ErrorKind::ConnectionRefused.into()),
+        }
+    }
+}
+
+/// Sends the next of `responses` after each request read from `stream`.
+async fn serve_responses(mut stream: DuplexStream, responses: Rc<RefCell<VecDeque<Bytes>>>) {
+    let mut pending = Vec::new();
+    loop {
+        let Some(len) = request_len(&pending) else {
+            let (res, buf) = stream.read(vec![0; 4096]).await;
+            match res {
+                Ok(n) if n > 0 => pending.extend_from_slice(&buf[..n]),
+                _ => return,
+            }
+            continue;
+        };
+        if pending.len() < len {
+            // The head is complete, the body is not.
+            let (res, buf) = stream.read(vec![0; 4096]).await;
+            match res {
+                Ok(n) if n > 0 => pending.extend_from_slice(&buf[..n]),
+                _ => return,
+            }
+            continue;
+        }
+        pending.drain(..len);
+        let Some(response) = responses.borrow_mut().pop_front() else {
+            return;
+        };
+        if stream.write_all(response).await.0.is_err() {
+            return;
+        }
+    }
+}
+
+/// Returns the length of the request starting `buf`, head and `Content-Length` body, once its
+/// head is complete.
+pub(super) fn request_len(buf: &[u8]) -> Option<usize> {
+    let head = buf.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+    let content_length = buf[..head]
+        .split(|&b| b == b'\n')
+        .filter_map(|line| {
+            let line = std::str::from_utf8(line).ok()?;
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("content-length")
+                .then(|| value.trim().parse::<usize>().ok())?
+        })
+        .next()
+        .unwrap_or(0);
+    Some(head + content_length)
+}
+
+#[cfg(test)]
+mod tests {
+    use monoio::io::AsyncReadRentExt;
+
+    use super::*;
+
+    #[monoio::test(enable_timer = true)]
+    async fn streams_both_ways() {
+        let (mut client, mut server) = duplex(4);
+        let writer = monoio::spawn(async move {
+            // Waits for the reader once 4 bytes are buffered.
+            client.write_all(b"hello world".to_vec()).await.0.unwrap();
+            let (res, buf) = client.read_exact(vec![0; 3]).await;
+            res.unwrap();
+            // The server end was dropped.
+            assert_eq!(client.read(vec![0; 1]).await.0.unwrap(), 0);
+            assert!(client.write(b"?".to_vec()).await.0.is_err());
+            buf
+        });
+        let (res, buf) = server.read_exact(vec![0; 11]).await;
+        res.unwrap();
+        assert_eq!(buf, b"hello world");
+        server.write_all(b"bye".to_vec()).await.0.unwrap();
+        drop(server);
+        assert_eq!(writer.await, b"bye");
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn scripts_responses_per_key() {
+        use http::request;
+        use monoio_http::common::body::HttpBody;
+
+        use crate::http::{response::ResponseExt, HttpConnector};
+
+        let connector = MockConnector::new()
+            .with_responses(
+                "api",
+                [
+                    "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nfirst",
+                    "HTTP/1.1 201 Created\r\ncontent-length: 6\r\n\r\nsecond",
+                ],
+            )
+            .with_error("down", io::ErrorKind::TimedOut);
+        let mut http = HttpConnector::new(connector.clone());
+        http.set_http1_only();
+        let request = || {
+            request::Builder::new()
+                .uri("/")
+                .header("Host", "localhost")
+                .header("Content-Length", "4")
+                .body(HttpBody::Ready(Some("ping".into())))
+                .unwrap()
+        };
+
+        let first = http.request("api", request).await.unwrap();
+        assert_eq!(first.status(), 200);
+        assert_eq!(first.bytes().await.unwrap(), "first");
+        let second = http.request("api", request).await.unwrap();
+        assert_eq!(second.status(), 201);
+        assert_eq!(second.bytes().await.unwrap(), "second");
+        assert_eq!(connector.connects(&"api"), 1);
+
+        assert!(http.request("down", request).await.is_err());
+        assert!(http.request("unknown", request).await.is_err());
+        assert_eq!(connector.connects(&"unknown"), 1);
+    }
+}
@@ -4,16 +4,64 @@
 //!
 //! - The [`Connector`] trait for establishing connections
 //! - The [`ConnectorExt`] trait for adding timeout functionality
+//! - The [`layer`] module for stacking wrapping connectors
 //! - The [`TransportConnMetadata`] trait for retrieving connection metadata
+//! - [`TlsConfig`] for configuring the TLS backend of a [`TlsConnector`], including certificate
+//!   pinning with [`SpkiPin`]
+//! - [`CircuitBreakerConnector`] for failing fast on keys whose connections keep failing
+//! - [`FromFdConnector`] for using sockets connected before being handed to the process
+//! - [`ProxyProtocolConnector`] for sending a PROXY protocol header to L4 load balancers
+//! - [`FaultConnector`] for injecting connect delays and failures, resets and throttling
+//! - [`ThrottleConnector`] for limiting the bandwidth of connections with token buckets
+//! - [`CassetteConnector`] for recording HTTP/1.1 exchanges and replaying them offline
+//! - [`MockConnector`] for testing clients against scripted in-memory servers, over [`duplex`]
+//!   streams
+//! - `VsockConnector` for `AF_VSOCK` connections between virtual machines and their host, on Linux
+//! - Proxy tunneling connectors such as `HttpTunnelConnector` (requires the `proxy` feature)
+#[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+compile_error!("a TLS backend is required, enable either the `rustls` or the `native-tls` feature");
+
+mod cassette;
+mod circuit_breaker;
+mod fault;
+mod fd;
+#[cfg(all(feature = "ktls", target_os = "linux", not(feature = "native-tls")))]
+mod ktls;
 mod l4_connector;
+pub mod layer;
+mod mock;
 #[cfg(feature = "hyper")]
 pub mod pollio;
+#[cfg(feature = "proxy")]
+mod proxy;
+mod proxy_protocol;
+mod socket;
+mod throttle;
+mod tls_config;
 mod tls_connector;
+mod tls_pin;
+#[cfg(target_os = "linux")]
+mod vsock;
 
 use std::{future::Future, time::Duration};
 
+pub use cassette::*;
+pub use circuit_breaker::*;
+pub use fault::*;
+pub use fd::*;
+#[cfg(all(feature = "ktls", target_os = "linux", not(feature = "native-tls")))]
+pub use ktls::*;
 pub use l4_connector::*;
+pub use mock::*;
+#[cfg(feature = "proxy")]
+pub use proxy::*;
+pub use proxy_protocol::*;
+pub use throttle::*;
+pub use tls_config::*;
 pub use tls_connector::*;
+pub use tls_pin::*;
+#[cfg(target_os = "linux")]
+pub use vsock::*;
 
 /// The [`Connector`] trait defines an interface for establishing connections.
 /// This trait is designed to be composable, allowing for the creation of modular
@@ -103,6 +151,20 @@ pub trait Connector<K> {
     /// A `Future` that resolves to a `Result` containing either the established
     /// connection or an error.
     fn connect(&self, key: K) -> impl Future<Output = Result<Self::Connection, Self::Error>>;
+
+    /// Connects to `key`, sending `data` as TLS 1.3 early data when the connection allows it.
+    ///
+    /// Returns whether the server accepted `data` as early data. Otherwise none of it was
+    /// delivered, and it is up to the caller to send it over the connection. Only the rustls
+    /// [`TlsConnector`] sends early data, the default implementation connects and returns false.
+    fn connect_early(
+        &self,
+        key: K,
+        data: &[u8],
+    ) -> impl Future<Output = Result<(Self::Connection, bool), Self::Error>> {
+        let _ = data;
+        async move { Ok((self.connect(key).await?, false)) }
+    }
 }
 
 /// Extends the `Connector` trait with timeout functionality.
@@ -144,7 +206,7 @@ pub trait TransportConnMetadata {
 }
 
 /// Represents the Application-Layer Protocol Negotiation (ALPN) protocol.
-#[derive(Default, Copy, Clone)]
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Alpn {
     /// HTTP/2 protocol
     HTTP2,
@@ -155,12 +217,40 @@ pub enum Alpn {
     None,
 }
 
-/// Holds metadata for a transport connection.
-///
-/// Currently only holds the ALPN protocol information.
-#[derive(Default, Copy, Clone)]
+impl Alpn {
+    /// Returns the protocol matching the ALPN identifier `protocol`, `None` for unknown ones.
+    pub fn from_protocol(protocol: Option<&[u8]>) -> Self {
+        match protocol {
+            Some(b"h2") => Alpn::HTTP2,
+            Some(b"http/1.1") => Alpn::HTTP11,
+            _ => Alpn::None,
+        }
+    }
+
+    /// Returns the ALPN identifier of the protocol.
+    pub fn protocol(&self) -> Option<&'static str> {
+        match self {
+            Alpn::HTTP2 => Some("h2"),
+            Alpn::HTTP11 => Some("http/1.1"),
+            Alpn::None => None,
+        }
+    }
+}
+
+/// Holds metadata for a transport connection: the protocol negotiated with ALPN, the socket
+/// addresses of TCP connections, the paths of Unix ones and the parameters of TLS ones, each
+/// when the connection reports it.
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
 pub struct TransportConnMeta {
     alpn: Alpn,
+    local_addr: Option<std::net::SocketAddr>,
+    peer_addr: Option<std::net::SocketAddr>,
+    local_path: Option<std::path::PathBuf>,
+    peer_path: Option<std::path::PathBuf>,
+    server_name: Option<smol_str::SmolStr>,
+    tls_version: Option<&'static str>,
+    cipher_suite: Option<&'static str>,
+    peer_certificate_sha256: Option<[u8; 32]>,
 }
 
 impl TransportConnMeta {
@@ -172,12 +262,16 @@ impl TransportConnMeta {
     ///
     /// # Notes
     ///
-    /// Sets `Alpn::HTTP2` if the vector contains "h2", otherwise sets `Alpn::None`.
+    /// Sets `Alpn::HTTP2` if the vector contains "h2", `Alpn::HTTP11` if it contains
+    /// "http/1.1", otherwise sets `Alpn::None`.
     pub fn set_alpn(&mut self, alpn: Option<Vec<u8>>) {
-        self.alpn = match alpn {
-            Some(p) if p == b"h2" => Alpn::HTTP2,
-            _ => Alpn::None,
-        }
+        self.alpn = Alpn::from_protocol(alpn.as_deref());
+    }
+
+    /// Returns the protocol negotiated with ALPN, `Alpn::None` if none was.
+    #[inline]
+    pub fn alpn(&self) -> Alpn {
+        self.alpn
     }
 
     /// Checks if the ALPN protocol is set to HTTP/2.
@@ -188,4 +282,82 @@ impl TransportConnMeta {
     pub fn is_alpn_h2(&self) -> bool {
         matches!(self.alpn, Alpn::HTTP2)
     }
+
+    /// Sets the local and peer addresses of the connection.
+    #[inline]
+    pub fn set_addrs(&mut self, local: std::net::SocketAddr, peer: std::net::SocketAddr) {
+        self.local_addr = Some(local);
+        self.peer_addr = Some(peer);
+    }
+
+    #[inline]
+    pub fn local_addr(&self) -> Option<std::net::SocketAddr> {
+        self.local_addr
+    }
+
+    #[inline]
+    pub fn peer_addr(&self) -> Option<std::net::SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Sets the paths of a Unix connection, `None` for unnamed sockets.
+    #[inline]
+    pub fn set_paths(
+        &mut self,
+        local: Option<std::path::PathBuf>,
+        peer: Option<std::path::PathBuf>,
+    ) {
+        self.local_path = local;
+        self.peer_path = peer;
+    }
+
+    #[inline]
+    pub fn local_path(&self) -> Option<&std::path::Path> {
+        self.local_path.as_deref()
+    }
+
+    #[inline]
+    pub fn peer_path(&self) -> Option<&std::path::Path> {
+        self.peer_path.as_deref()
+    }
+
+    /// Sets the server name sent with SNI and verified against the certificate.
+    #[inline]
+    pub fn set_server_name(&mut self, server_name: Option<smol_str::SmolStr>) {
+        self.server_name = server_name;
+    }
+
+    #[inline]
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// Sets the negotiated TLS version and cipher suite, such as `TLSv1_3` and
+    /// `TLS13_AES_128_GCM_SHA256`.
+    #[inline]
+    pub fn set_tls(&mut self, version: Option<&'static str>, cipher_suite: Option<&'static str>) {
+        self.tls_version = version;
+        self.cipher_suite = cipher_suite;
+    }
+
+    #[inline]
+    pub fn tls_version(&self) -> Option<&'static str> {
+        self.tls_version
+    }
+
+    #[inline]
+    pub fn cipher_suite(&self) -> Option<&'static str> {
+        self.cipher_suite
+    }
+
+    /// Sets the SHA-256 digest of the DER encoded certificate presented by the server.
+    #[inline]
+    pub fn set_peer_certificate_sha256(&mut self, digest: Option<[u8; 32]>) {
+        self.peer_certificate_sha256 = digest;
+    }
+
+    #[inline]
+    pub fn peer_certificate_sha256(&self) -> Option<&[u8; 32]> {
+        self.peer_certificate_sha256.as_ref()
+    }
 }
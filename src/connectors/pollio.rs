@@ -46,6 +46,12 @@ impl IntoPollIo for super::UnifiedL4Stream {
                 Ok(io) => Ok(UnifiedL4StreamPoll::Unix(io)),
                 Err((e, io)) => Err((e, super::UnifiedL4Stream::Unix(io))),
             },
+            // Vsock streams are served by a TCP stream of monoio.
+            #[cfg(target_os = "linux")]
+            super::UnifiedL4Stream::Vsock(inner) => match inner.try_into_poll_io() {
+                Ok(io) => Ok(UnifiedL4StreamPoll::Tcp(io)),
+                Err((e, io)) => Err((e, super::UnifiedL4Stream::Vsock(io))),
+            },
         }
     }
 }
@@ -0,0 +1,254 @@
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+};
+
+use monoio::{
+    io::{AsyncWriteRent, AsyncWriteRentExt},
+    net::{TcpStream, UnixStream},
+};
+
+use super::{Connector, UnifiedL4Stream};
+
+/// The signature starting PROXY protocol v2 headers.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The version of the PROXY protocol headers sent.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProxyProtocolVersion {
+    /// The human readable header, `PROXY TCP4 ...\r\n`.
+    V1,
+    /// The binary header.
+    #[default]
+    V2,
+}
+
+/// Exposes the TCP addresses of a connection, announced in PROXY protocol headers.
+pub trait TcpAddrs {
+    /// Returns the local and peer addresses of the connection, `None` if it is not over TCP.
+    fn tcp_addrs(&self) -> Option<(SocketAddr, SocketAddr)>;
+}
+
+impl TcpAddrs for TcpStream {
+    #[inline]
+    fn tcp_addrs(&self) -> Option<(SocketAddr, SocketAddr)> {
+        Some((self.local_addr().ok()?, self.peer_addr().ok()?))
+    }
+}
+
+impl TcpAddrs for UnixStream {
+    #[inline]
+    fn tcp_addrs(&self) -> Option<(SocketAddr, SocketAddr)> {
+        None
+    }
+}
+
+impl TcpAddrs for UnifiedL4Stream {
+    #[inline]
+    fn tcp_addrs(&self) -> Option<(SocketAddr, SocketAddr)> {
+        match self {
+            UnifiedL4Stream::Tcp(inner) => inner.tcp_addrs(),
+            _ => None,
+        }
+    }
+}
+
+/// Returns `addr` with an IPv4-mapped IPv6 address.
+fn to_ipv6(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) => SocketAddr::new(ip.to_ipv6_mapped().into(), addr.port()),
+        IpAddr::V6(_) => addr,
+    }
+}
+
+fn ipv6_octets(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
+
+/// Encodes the PROXY protocol header announcing a connection from `source` to `destination`, or
+/// a connection of unknown or local origin when `None`.
+pub fn encode_proxy_header(
+    version: ProxyProtocolVersion,
+    addrs: Option<(SocketAddr, SocketAddr)>,
+) -> Vec<u8> {
+    // Both addresses must be of the same family.
+    let addrs = addrs.map(|(source, destination)| match (source, destination) {
+        (SocketAddr::V4(_), SocketAddr::V4(_)) | (SocketAddr::V6(_), SocketAddr::V6(_)) => {
+            (source, destination)
+        }
+        _ => (to_ipv6(source), to_ipv6(destination)),
+    });
+    match version {
+        ProxyProtocolVersion::V1 => match addrs {
+            Some((source, destination)) => {
+                let family = if source.is_ipv4() { "TCP4" } else { "TCP6" };
+                format!(
+                    "PROXY {family} {} {} {} {}\r\n",
+                    source.ip(),
+                    destination.ip(),
+                    source.port(),
+                    destination.port()
+                )
+                .into_bytes()
+            }
+            None => b"PROXY UNKNOWN\r\n".to_vec(),
+        },
+        ProxyProtocolVersion::V2 => {
+            let mut buf = V2_SIGNATURE.to_vec();
+            let Some((source, destination)) = addrs else {
+                // LOCAL command, unspecified family and no address.
+                buf.extend_from_slice(&[0x20, 0x00, 0, 0]);
+                return buf;
+            };
+            let mut body = Vec::with_capacity(36);
+            let family = match (source.ip(), destination.ip()) {
+                (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                    body.extend_from_slice(&src.octets());
+                    body.extend_from_slice(&dst.octets());
+                    0x11
+                }
+                (src, dst) => {
+                    body.extend_from_slice(&ipv6_octets(src));
+                    body.extend_from_slice(&ipv6_octets(dst));
+                    0x21
+                }
+            };
+            body.extend_from_slice(&source.port().to_be_bytes());
+            body.extend_from_slice(&destination.port().to_be_bytes());
+            // PROXY command over TCP.
+            buf.extend_from_slice(&[0x21, family]);
+            buf.extend_from_slice(&(body.len() as u16).to_be_bytes());
+            buf.extend_from_slice(&body);
+            buf
+        }
+    }
+}
+
+/// A connector sending a PROXY protocol header right after connecting, for upstreams behind L4
+/// load balancers that require one.
+///
+/// The header announces the addresses set with [`with_addresses`](Self::with_addresses), or by
+/// default the local and peer addresses of the connection itself. Connections that are not over
+/// TCP are announced as `UNKNOWN` in v1 and `LOCAL` in v2.
+#[derive(Debug, Clone)]
+pub struct ProxyProtocolConnector<C> {
+    inner_connector: C,
+    version: ProxyProtocolVersion,
+    addrs: Option<(SocketAddr, SocketAddr)>,
+}
+
+impl<C> ProxyProtocolConnector<C> {
+    #[inline]
+    pub fn new(inner_connector: C, version: ProxyProtocolVersion) -> Self {
+        Self {
+            inner_connector,
+            version,
+            addrs: None,
+        }
+    }
+
+    /// Announces connections from `source` to `destination`, such as the addresses of the
+    /// client this one is relaying.
+    #[inline]
+    pub fn with_addresses(mut self, source: SocketAddr, destination: SocketAddr) -> Self {
+        self.addrs = Some((source, destination));
+        self
+    }
+
+    #[inline]
+    pub fn inner_connector(&self) -> &C {
+        &self.inner_connector
+    }
+
+    #[inline]
+    pub fn version(&self) -> ProxyProtocolVersion {
+        self.version
+    }
+}
+
+impl<C, K> Connector<K> for ProxyProtocolConnector<C>
+where
+    C: Connector<K>,
+    C::Connection: AsyncWriteRent + TcpAddrs,
+    C::Error: From<io::Error>,
+{
+    type Connection = C::Connection;
+    type Error = C::Error;
+
+    async fn connect(&self, key: K) -> Result<Self::Connection, Self::Error> {
+        let mut conn = self.inner_connector.connect(key).await?;
+        let addrs = self.addrs.or_else(|| conn.tcp_addrs());
+        let (res, _) = conn
+            .write_all(encode_proxy_header(self.version, addrs))
+            .await;
+        res?;
+        Ok(conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use monoio::{io::AsyncReadRent, net::TcpListener};
+
+    use super::*;
+    use crate::connectors::TcpConnector;
+
+    #[test]
+    fn encodes_headers() {
+        let source: SocketAddr = "192.0.2.1:56324".parse().unwrap();
+        let destination: SocketAddr = "198.51.100.2:443".parse().unwrap();
+        let header = |version, addrs| encode_proxy_header(version, addrs);
+        assert_eq!(
+            header(ProxyProtocolVersion::V1, Some((source, destination))),
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\n"
+        );
+        assert_eq!(header(ProxyProtocolVersion::V1, None), b"PROXY UNKNOWN\r\n");
+        let v6: SocketAddr = "[2001:db8::1]:443".parse().unwrap();
+        assert_eq!(
+            header(ProxyProtocolVersion::V1, Some((source, v6))),
+            b"PROXY TCP6 ::ffff:192.0.2.1 2001:db8::1 56324 443\r\n"
+        );
+
+        let v2 = header(ProxyProtocolVersion::V2, Some((source, destination)));
+        assert_eq!(v2[..12], V2_SIGNATURE);
+        assert_eq!(
+            v2[12..],
+            [0x21, 0x11, 0, 12, 192, 0, 2, 1, 198, 51, 100, 2, 0xdc, 0x04, 0x01, 0xbb]
+        );
+        let v2 = header(ProxyProtocolVersion::V2, Some((source, v6)));
+        assert_eq!(v2[12..16], [0x21, 0x21, 0, 36]);
+        assert_eq!(v2.len(), 16 + 36);
+        assert_eq!(
+            header(ProxyProtocolVersion::V2, None)[12..],
+            [0x20, 0x00, 0, 0]
+        );
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn sends_header_after_connecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = monoio::spawn(async move {
+            let (mut conn, peer) = listener.accept().await.unwrap();
+            let (res, buf) = conn.read(vec![0; 64]).await;
+            let n = res.unwrap();
+            (String::from_utf8(buf[..n].to_vec()).unwrap(), peer)
+        });
+
+        let connector =
+            ProxyProtocolConnector::new(TcpConnector::default(), ProxyProtocolVersion::V1);
+        let _conn = connector.connect(addr).await.unwrap();
+        let (header, peer) = server.await;
+        assert_eq!(
+            header,
+            format!(
+                "PROXY TCP4 127.0.0.1 127.0.0.1 {} {}\r\n",
+                peer.port(),
+                addr.port()
+            )
+        );
+    }
+}
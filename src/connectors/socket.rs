@@ -0,0 +1,64 @@
+use std::{
+    io,
+    mem::ManuallyDrop,
+    os::fd::{AsRawFd, FromRawFd, IntoRawFd},
+};
+
+use monoio::net::TcpStream;
+use socket2::{SockAddr, Socket};
+
+/// Returns a handle to set options on the socket of `io`, which keeps owning it.
+pub(crate) fn socket_of<S: AsRawFd>(io: &S) -> ManuallyDrop<Socket> {
+    ManuallyDrop::new(unsafe { Socket::from_raw_fd(io.as_raw_fd()) })
+}
+
+/// Makes `connect` on `socket` return right away, the SYN being sent with the first bytes
+/// written if the server granted a TCP Fast Open cookie.
+#[cfg(target_os = "linux")]
+pub(crate) fn set_fast_open_connect(socket: &Socket) -> io::Result<()> {
+    let enabled: libc::c_int = 1;
+    let res = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_FASTOPEN_CONNECT,
+            &enabled as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    match res {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Returns true if the runtime of the current thread drives its IO with io_uring, which expects
+/// blocking sockets, rather than epoll.
+pub(crate) fn is_uring_driver() -> io::Result<bool> {
+    // monoio only creates non blocking sockets for its legacy driver.
+    let probe = monoio::net::UnixDatagram::unbound()?;
+    Ok(!socket_of(&probe).nonblocking()?)
+}
+
+/// Connects `socket`, created and configured by the caller, to `addr`.
+///
+/// monoio creates the sockets it connects itself, so options needed before `connect` cannot be
+/// set on them. The returned stream serves any stream socket, whatever its family.
+pub(crate) async fn connect_socket(socket: Socket, addr: &SockAddr) -> io::Result<TcpStream> {
+    socket.set_nonblocking(true)?;
+    match socket.connect(addr) {
+        Err(e) if e.raw_os_error() != Some(libc::EINPROGRESS) => return Err(e),
+        _ => {}
+    }
+    let stream = unsafe { std::net::TcpStream::from_raw_fd(socket.into_raw_fd()) };
+    let stream = TcpStream::from_std(stream)?;
+    stream.writable(false).await?;
+    let socket = socket_of(&stream);
+    if let Some(e) = socket.take_error()? {
+        return Err(e);
+    }
+    if is_uring_driver()? {
+        socket.set_nonblocking(false)?;
+    }
+    Ok(stream)
+}
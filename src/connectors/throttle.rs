@@ -0,0 +1,383 @@
+use std::{
+    cell::Cell,
+    io,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use monoio::{
+    buf::{IoBuf, IoBufMut, IoVecBuf, IoVecBufMut},
+    io::{AsyncReadRent, AsyncWriteRent, Split},
+    BufResult,
+};
+
+use super::{layer::ConnectorLayer, Connector, TransportConnMetadata};
+
+#[derive(Debug)]
+struct Bucket {
+    rate: f64,
+    burst: f64,
+    // Negative once bytes were taken on credit, which their consumers wait for.
+    tokens: Cell<f64>,
+    refilled: Cell<Instant>,
+}
+
+/// A token bucket limiting the bytes per second going through it.
+///
+/// Bytes are taken after being transferred, and a transfer exceeding the tokens left waits until
+/// the bucket refills, so concurrent consumers are served in turn. Up to the burst size is
+/// transferred at full speed after a pause. Clones share the same bucket, e.g. to give all the
+/// bulk transfers of a client one budget; it cannot be sent to other threads.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: Rc<Bucket>,
+}
+
+impl RateLimiter {
+    /// Limits transfers to `bytes_per_second`, with a burst size of one second of transfer.
+    #[inline]
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self::with_burst(bytes_per_second, bytes_per_second)
+    }
+
+    /// Limits transfers to `bytes_per_second`, transferring up to `burst` bytes at full speed.
+    pub fn with_burst(bytes_per_second: u64, burst: u64) -> Self {
+        let burst = burst.max(1) as f64;
+        Self {
+            bucket: Rc::new(Bucket {
+                rate: bytes_per_second.max(1) as f64,
+                burst,
+                tokens: Cell::new(burst),
+                refilled: Cell::new(Instant::now()),
+            }),
+        }
+    }
+
+    #[inline]
+    pub fn rate(&self) -> u64 {
+        self.bucket.rate as u64
+    }
+
+    #[inline]
+    pub fn burst(&self) -> u64 {
+        self.bucket.burst as u64
+    }
+
+    /// Takes `n` bytes from the bucket, waiting until it holds enough.
+    pub async fn consume(&self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let bucket = &self.bucket;
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled.replace(now));
+        let tokens = (bucket.tokens.get() + elapsed.as_secs_f64() * bucket.rate).min(bucket.burst);
+        let tokens = tokens - n as f64;
+        bucket.tokens.set(tokens);
+        if tokens < 0.0 {
+            monoio::time::sleep(Duration::from_secs_f64(-tokens / bucket.rate)).await;
+        }
+    }
+}
+
+/// The bandwidth a [`ThrottleConnector`] allows its connections in each direction, unlimited by
+/// default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bandwidth {
+    read: Option<u64>,
+    write: Option<u64>,
+    burst: Option<u64>,
+}
+
+impl Bandwidth {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits reads to `bytes_per_second`.
+    #[inline]
+    pub fn with_read_rate(mut self, bytes_per_second: u64) -> Self {
+        self.read = Some(bytes_per_second);
+        self
+    }
+
+    /// Limits writes to `bytes_per_second`.
+    #[inline]
+    pub fn with_write_rate(mut self, bytes_per_second: u64) -> Self {
+        self.write = Some(bytes_per_second);
+        self
+    }
+
+    /// Sets the bytes transferred at full speed in each direction, one second of transfer by
+    /// default.
+    #[inline]
+    pub fn with_burst(mut self, burst: u64) -> Self {
+        self.burst = Some(burst);
+        self
+    }
+
+    #[inline]
+    pub fn read_rate(&self) -> Option<u64> {
+        self.read
+    }
+
+    #[inline]
+    pub fn write_rate(&self) -> Option<u64> {
+        self.write
+    }
+
+    #[inline]
+    pub fn burst(&self) -> Option<u64> {
+        self.burst
+    }
+
+    fn limiter(&self, rate: Option<u64>) -> Option<RateLimiter> {
+        rate.map(|rate| RateLimiter::with_burst(rate, self.burst.unwrap_or(rate)))
+    }
+
+    fn limiters(&self) -> Limiters {
+        Limiters {
+            read: self.limiter(self.read),
+            write: self.limiter(self.write),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Limiters {
+    read: Option<RateLimiter>,
+    write: Option<RateLimiter>,
+}
+
+/// A layer wrapping connectors into a [`ThrottleConnector`].
+#[derive(Debug, Clone)]
+pub struct ThrottleLayer {
+    bandwidth: Bandwidth,
+    shared: bool,
+}
+
+impl ThrottleLayer {
+    /// Throttles each connection on its own, see [`ThrottleConnector::new`].
+    #[inline]
+    pub fn new(bandwidth: Bandwidth) -> Self {
+        Self {
+            bandwidth,
+            shared: false,
+        }
+    }
+
+    /// Throttles the connections of each wrapped connector together, see
+    /// [`ThrottleConnector::shared`].
+    #[inline]
+    pub fn shared(bandwidth: Bandwidth) -> Self {
+        Self {
+            bandwidth,
+            shared: true,
+        }
+    }
+}
+
+impl<C> ConnectorLayer<C> for ThrottleLayer {
+    type Connector = ThrottleConnector<C>;
+
+    #[inline]
+    fn layer(&self, inner: C) -> Self::Connector {
+        match self.shared {
+            true => ThrottleConnector::shared(inner, self.bandwidth),
+            false => ThrottleConnector::new(inner, self.bandwidth),
+        }
+    }
+}
+
+/// A connector limiting the bandwidth of its connections, so bulk transfers do not starve
+/// latency-sensitive traffic to the same hosts. Requires the monoio timer driver.
+///
+/// Reads and writes wait for a [`RateLimiter`] after the inner stream transferred their bytes.
+/// Single requests are throttled with [`ThrottledBody`](crate::http::body::ThrottledBody)
+/// instead, as HTTP/2 multiplexes requests on connections.
+#[derive(Debug, Clone)]
+pub struct ThrottleConnector<C> {
+    inner_connector: C,
+    bandwidth: Bandwidth,
+    // The limiters of all connections when shared.
+    shared: Option<Limiters>,
+}
+
+impl<C> ThrottleConnector<C> {
+    /// Limits each connection to `bandwidth`.
+    #[inline]
+    pub fn new(inner_connector: C, bandwidth: Bandwidth) -> Self {
+        Self {
+            inner_connector,
+            bandwidth,
+            shared: None,
+        }
+    }
+
+    /// Limits all connections together to `bandwidth`, including the ones of clones of the
+    /// connector.
+    #[inline]
+    pub fn shared(inner_connector: C, bandwidth: Bandwidth) -> Self {
+        Self {
+            inner_connector,
+            bandwidth,
+            shared: Some(bandwidth.limiters()),
+        }
+    }
+
+    #[inline]
+    pub fn inner_connector(&self) -> &C {
+        &self.inner_connector
+    }
+
+    #[inline]
+    pub fn bandwidth(&self) -> &Bandwidth {
+        &self.bandwidth
+    }
+}
+
+impl<C, K> Connector<K> for ThrottleConnector<C>
+where
+    C: Connector<K>,
+{
+    type Connection = ThrottledStream<C::Connection>;
+    type Error = C::Error;
+
+    async fn connect(&self, key: K) -> Result<Self::Connection, Self::Error> {
+        let inner = self.inner_connector.connect(key).await?;
+        let limiters = match &self.shared {
+            Some(limiters) => limiters.clone(),
+            None => self.bandwidth.limiters(),
+        };
+        Ok(ThrottledStream {
+            inner,
+            read: limiters.read,
+            write: limiters.write,
+        })
+    }
+}
+
+/// A connection of a [`ThrottleConnector`].
+#[derive(Debug)]
+pub struct ThrottledStream<S> {
+    inner: S,
+    read: Option<RateLimiter>,
+    write: Option<RateLimiter>,
+}
+
+impl<S> ThrottledStream<S> {
+    #[inline]
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+/// Waits for `limiter` to allow the bytes transferred by `res`.
+async fn throttle(limiter: &Option<RateLimiter>, res: &io::Result<usize>) {
+    if let (Some(limiter), Ok(n)) = (limiter, res) {
+        limiter.consume(*n).await;
+    }
+}
+
+impl<S: TransportConnMetadata> TransportConnMetadata for ThrottledStream<S> {
+    type Metadata = S::Metadata;
+
+    #[inline]
+    fn get_conn_metadata(&self) -> Self::Metadata {
+        self.inner.get_conn_metadata()
+    }
+}
+
+impl<S: AsyncReadRent> AsyncReadRent for ThrottledStream<S> {
+    async fn read<T: IoBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        let (res, buf) = self.inner.read(buf).await;
+        throttle(&self.read, &res).await;
+        (res, buf)
+    }
+
+    async fn readv<T: IoVecBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        let (res, buf) = self.inner.readv(buf).await;
+        throttle(&self.read, &res).await;
+        (res, buf)
+    }
+}
+
+impl<S: AsyncWriteRent> AsyncWriteRent for ThrottledStream<S> {
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        let (res, buf) = self.inner.write(buf).await;
+        throttle(&self.write, &res).await;
+        (res, buf)
+    }
+
+    async fn writev<T: IoVecBuf>(&mut self, buf_vec: T) -> BufResult<usize, T> {
+        let (res, buf_vec) = self.inner.writev(buf_vec).await;
+        throttle(&self.write, &res).await;
+        (res, buf_vec)
+    }
+
+    #[inline]
+    async fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush().await
+    }
+
+    #[inline]
+    async fn shutdown(&mut self) -> io::Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+unsafe impl<S: Split> Split for ThrottledStream<S> {}
+
+#[cfg(test)]
+mod tests {
+    use monoio::io::{AsyncReadRentExt, AsyncWriteRentExt};
+
+    use super::*;
+    use crate::connectors::MockConnector;
+
+    /// Answers every connection to `"bulk"` with 100 bytes, once 100 bytes were read.
+    fn server() -> MockConnector<&'static str> {
+        MockConnector::new().with_handler("bulk", |mut stream| async move {
+            let (res, _) = stream.read_exact(vec![0; 100]).await;
+            if res.is_ok() {
+                let _ = stream.write_all(vec![0; 100]).await;
+            }
+        })
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn throttles_connections() {
+        // Bursts of 50 bytes at 1000 bytes per second.
+        let bandwidth = Bandwidth::new()
+            .with_read_rate(1000)
+            .with_write_rate(1000)
+            .with_burst(50);
+        let connector = ThrottleConnector::new(server(), bandwidth);
+        let mut conn = connector.connect("bulk").await.unwrap();
+        let start = Instant::now();
+        conn.write_all(vec![0; 100]).await.0.unwrap();
+        conn.read_exact(vec![0; 100]).await.0.unwrap();
+        // 50 bytes on credit in each direction.
+        assert!(start.elapsed() >= Duration::from_millis(90));
+
+        // Connections have budgets of their own, unless shared.
+        let mut conn = connector.connect("bulk").await.unwrap();
+        let start = Instant::now();
+        conn.write_all(vec![0; 50]).await.0.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(40));
+
+        let connector = ThrottleConnector::shared(server(), bandwidth);
+        let mut conn = connector.connect("bulk").await.unwrap();
+        conn.write_all(vec![0; 100]).await.0.unwrap();
+        let mut conn = connector.connect("bulk").await.unwrap();
+        let start = Instant::now();
+        conn.write_all(vec![0; 50]).await.0.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}
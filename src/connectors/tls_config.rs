@@ -0,0 +1,1235 @@
+use base64::Engine;
+use thiserror::Error as ThisError;
+
+use super::{MonoioTlsConnector, PinFailure, Pins, SpkiPin};
+
+/// TLS client settings shared by both backends.
+///
+/// The same configuration builds a rustls connector, or a native-tls one when the `native-tls`
+/// feature is enabled, so code configuring TLS does not depend on the backend. See
+/// [`TlsConnector::with_config`](super::TlsConnector::with_config).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsConfig {
+    alpn: Vec<String>,
+    identity: Option<Identity>,
+    root_store: RootStore,
+    // PEM bundles trusted in addition to `root_store`.
+    extra_roots: Vec<Vec<u8>>,
+    accept_invalid_certs: bool,
+    accept_invalid_hostnames: bool,
+    pins: Pins,
+    session_cache_size: Option<usize>,
+    early_data: bool,
+    ktls: bool,
+    key_log: KeyLog,
+}
+
+/// The certificate authorities trusted to verify servers, besides the roots added to a
+/// [`TlsConfig`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RootStore {
+    /// The webpki roots bundled with rustls, the platform store with native-tls.
+    #[default]
+    Builtin,
+    /// The certificates of the platform. rustls reads the system bundle where OpenSSL would,
+    /// honoring `SSL_CERT_FILE` and `SSL_CERT_DIR`, when the connector is built.
+    Platform,
+    /// Only the roots added to the configuration.
+    CustomOnly,
+}
+
+/// Where the secrets of TLS connections are logged, as
+/// [NSS key log](https://firefox-source-docs.mozilla.org/security/nss/legacy/key_log_format/index.html)
+/// lines that Wireshark reads to decrypt captures.
+///
+/// Anyone reading the log can decrypt the connections, so it is meant for debugging only. Only
+/// supported by the rustls backend.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum KeyLog {
+    #[default]
+    Disabled,
+    /// Appends to the file named by the `SSLKEYLOGFILE` environment variable when it is set,
+    /// like curl and browsers do.
+    Env,
+    /// Appends to this file.
+    File(std::path::PathBuf),
+}
+
+/// A client certificate chain and its private key, presented for mutual TLS.
+///
+/// PKCS#12 archives are only supported by the native-tls backend.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Identity(IdentityFormat);
+
+#[derive(Clone, PartialEq, Eq)]
+enum IdentityFormat {
+    Pem { chain: Vec<u8>, key: Vec<u8> },
+    Pkcs12 { der: Vec<u8>, password: String },
+}
+
+impl std::fmt::Debug for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format = match self.0 {
+            IdentityFormat::Pem { .. } => "pem",
+            IdentityFormat::Pkcs12 { .. } => "pkcs12",
+        };
+        f.debug_struct("Identity")
+            .field("format", &format)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Identity {
+    /// Uses the PEM certificates in `chain`, leaf first, and the PEM private key in `key`.
+    ///
+    /// The key must be PKCS#8 with native-tls; rustls also reads PKCS#1 and SEC1 keys.
+    pub fn from_pem(chain: impl Into<Vec<u8>>, key: impl Into<Vec<u8>>) -> Self {
+        Identity(IdentityFormat::Pem {
+            chain: chain.into(),
+            key: key.into(),
+        })
+    }
+
+    /// Uses the DER certificates in `chain`, leaf first, and the DER PKCS#8 private key in
+    /// `key`.
+    pub fn from_der<I, D>(chain: I, key: &[u8]) -> Self
+    where
+        I: IntoIterator<Item = D>,
+        D: AsRef<[u8]>,
+    {
+        let mut pem = Vec::new();
+        for cert in chain {
+            pem_encode("CERTIFICATE", cert.as_ref(), &mut pem);
+        }
+        let mut key_pem = Vec::new();
+        pem_encode("PRIVATE KEY", key, &mut key_pem);
+        Self::from_pem(pem, key_pem)
+    }
+
+    /// Uses a DER PKCS#12 archive holding the chain and the key, encrypted with `password`.
+    pub fn from_pkcs12(der: impl Into<Vec<u8>>, password: impl Into<String>) -> Self {
+        Identity(IdentityFormat::Pkcs12 {
+            der: der.into(),
+            password: password.into(),
+        })
+    }
+
+    #[cfg(not(feature = "native-tls"))]
+    fn to_rustls(
+        &self,
+    ) -> Result<
+        (
+            Vec<rustls::pki_types::CertificateDer<'static>>,
+            rustls::pki_types::PrivateKeyDer<'static>,
+        ),
+        TlsConfigError,
+    > {
+        use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+
+        let IdentityFormat::Pem { chain, key } = &self.0 else {
+            return Err(TlsConfigError::Unsupported(
+                "PKCS#12 identities with rustls",
+            ));
+        };
+        let chain = CertificateDer::pem_slice_iter(chain)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| TlsConfigError::InvalidIdentity(e.to_string()))?;
+        if chain.is_empty() {
+            return Err(TlsConfigError::InvalidIdentity(
+                "no certificate found".to_string(),
+            ));
+        }
+        let key = PrivateKeyDer::from_pem_slice(key)
+            .map_err(|e| TlsConfigError::InvalidIdentity(e.to_string()))?;
+        Ok((chain, key))
+    }
+
+    #[cfg(feature = "native-tls")]
+    fn to_native_tls(&self) -> Result<native_tls::Identity, TlsConfigError> {
+        Ok(match &self.0 {
+            IdentityFormat::Pem { chain, key } => native_tls::Identity::from_pkcs8(chain, key)?,
+            IdentityFormat::Pkcs12 { der, password } => {
+                native_tls::Identity::from_pkcs12(der, password)?
+            }
+        })
+    }
+}
+
+/// Verifies servers like `inner`, ignoring name mismatches, or only checks the handshake
+/// signatures when `inner` is `None`.
+#[cfg(not(feature = "native-tls"))]
+#[derive(Debug)]
+struct DangerousVerifier {
+    inner: Option<std::sync::Arc<rustls::client::WebPkiServerVerifier>>,
+    provider: std::sync::Arc<rustls::crypto::CryptoProvider>,
+}
+
+#[cfg(not(feature = "native-tls"))]
+impl rustls::client::danger::ServerCertVerifier for DangerousVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::pki_types::CertificateDer<'_>,
+        intermediates: &[rustls::pki_types::CertificateDer<'_>],
+        server_name: &rustls::pki_types::ServerName<'_>,
+        ocsp_response: &[u8],
+        now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        use rustls::{client::danger::ServerCertVerified, CertificateError, Error};
+
+        let Some(inner) = &self.inner else {
+            return Ok(ServerCertVerified::assertion());
+        };
+        // The name is checked once the chain is verified.
+        match inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now) {
+            Err(Error::InvalidCertificate(
+                CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. },
+            )) => Ok(ServerCertVerified::assertion()),
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Appends NSS key log lines to a file, ignoring write errors like rustls does.
+#[cfg(not(feature = "native-tls"))]
+#[derive(Debug)]
+struct KeyLogWriter(std::sync::Mutex<std::fs::File>);
+
+#[cfg(not(feature = "native-tls"))]
+impl rustls::KeyLog for KeyLogWriter {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        use std::fmt::Write;
+
+        let mut line =
+            String::with_capacity(label.len() + 2 * (client_random.len() + secret.len()) + 3);
+        line.push_str(label);
+        for part in [client_random, secret] {
+            line.push(' ');
+            for b in part {
+                let _ = write!(line, "{b:02x}");
+            }
+        }
+        line.push('\n');
+        if let Ok(mut file) = self.0.lock() {
+            let _ = std::io::Write::write_all(&mut *file, line.as_bytes());
+        }
+    }
+}
+
+/// Adds the certificates of the platform bundle, or of the certificate directories when no
+/// bundle is found. Unreadable files and invalid certificates are skipped.
+#[cfg(not(feature = "native-tls"))]
+fn add_platform_roots(root_store: &mut rustls::RootCertStore) {
+    use rustls::pki_types::{pem::PemObject, CertificateDer};
+
+    let probe = openssl_probe::probe();
+    let files: Vec<_> = match probe.cert_file {
+        Some(file) => vec![file],
+        None => probe
+            .cert_dir
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .collect(),
+    };
+    for file in files {
+        if let Ok(pem) = std::fs::read(file) {
+            root_store.add_parsable_certificates(
+                CertificateDer::pem_slice_iter(&pem).filter_map(Result::ok),
+            );
+        }
+    }
+}
+
+/// Returns the PEM blocks labeled `label` in `pem`, markers included.
+#[cfg(feature = "native-tls")]
+fn pem_blocks<'a>(pem: &'a [u8], label: &str) -> impl Iterator<Item = &'a [u8]> {
+    let begin = format!("-----BEGIN {label}-----");
+    let end = format!("-----END {label}-----");
+    let mut rest = pem;
+    std::iter::from_fn(move || {
+        let start = find(rest, begin.as_bytes())?;
+        let stop = start + find(&rest[start..], end.as_bytes())? + end.len();
+        let block = &rest[start..stop];
+        rest = &rest[stop..];
+        Some(block)
+    })
+}
+
+#[cfg(feature = "native-tls")]
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn pem_encode(label: &str, der: &[u8], out: &mut Vec<u8>) {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(der);
+    out.extend_from_slice(format!("-----BEGIN {label}-----\n").as_bytes());
+    for line in encoded.as_bytes().chunks(64) {
+        out.extend_from_slice(line);
+        out.push(b'\n');
+    }
+    out.extend_from_slice(format!("-----END {label}-----\n").as_bytes());
+}
+
+/// A TLS configuration built once and shared by the connectors using it.
+///
+/// Building a [`TlsConfig`] parses its root certificates and client identity, while cloning
+/// this is cheap, so many connectors on a thread can share one with
+/// [`TlsConnector::with_shared_config`](super::TlsConnector::with_shared_config). With rustls,
+/// a `ClientConfig` built by hand can also be shared with [`from_rustls`](Self::from_rustls);
+/// connectors sharing one also share its session cache.
+#[derive(Clone)]
+pub struct SharedTlsConfig {
+    tls_connector: MonoioTlsConnector,
+    #[cfg(not(feature = "native-tls"))]
+    client_config: std::sync::Arc<rustls::ClientConfig>,
+}
+
+impl SharedTlsConfig {
+    /// Shares a rustls configuration built outside of a [`TlsConfig`].
+    #[cfg(not(feature = "native-tls"))]
+    pub fn from_rustls(client_config: std::sync::Arc<rustls::ClientConfig>) -> Self {
+        Self {
+            tls_connector: client_config.clone().into(),
+            client_config,
+        }
+    }
+
+    #[inline]
+    pub fn tls_connector(&self) -> &MonoioTlsConnector {
+        &self.tls_connector
+    }
+
+    #[cfg(not(feature = "native-tls"))]
+    #[inline]
+    pub fn client_config(&self) -> &std::sync::Arc<rustls::ClientConfig> {
+        &self.client_config
+    }
+
+    /// Returns the default configuration offering `alpn`, built once per thread.
+    pub(crate) fn default_for(alpn: Vec<&str>) -> Self {
+        use std::cell::RefCell;
+
+        thread_local! {
+            static DEFAULTS: RefCell<Vec<(Vec<String>, SharedTlsConfig)>> = const {
+                RefCell::new(Vec::new())
+            };
+        }
+        DEFAULTS.with_borrow_mut(|defaults| {
+            if let Some((_, config)) = defaults.iter().find(|(protocols, _)| *protocols == alpn) {
+                return config.clone();
+            }
+            let config = TlsConfig::new().with_alpn(alpn);
+            let shared = config
+                .build_shared()
+                .expect("the default TLS configuration is valid");
+            defaults.push((config.alpn, shared.clone()));
+            shared
+        })
+    }
+}
+
+impl std::fmt::Debug for SharedTlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedTlsConfig")
+    }
+}
+
+/// An error building a TLS connector from a [`TlsConfig`].
+#[derive(ThisError, Debug)]
+pub enum TlsConfigError {
+    #[cfg(not(feature = "native-tls"))]
+    #[error("rustls error {0}")]
+    Rustls(#[from] rustls::Error),
+    #[cfg(feature = "native-tls")]
+    #[error("native-tls error {0}")]
+    NativeTls(#[from] native_tls::Error),
+    #[error("invalid root certificate: {0}")]
+    InvalidRootCertificate(String),
+    #[error("invalid pin: {0}")]
+    InvalidPin(String),
+    #[error("invalid client identity: {0}")]
+    InvalidIdentity(String),
+    #[error("unsupported by the TLS backend: {0}")]
+    Unsupported(&'static str),
+    #[error("cannot open key log file: {0}")]
+    KeyLogFile(std::io::Error),
+}
+
+impl TlsConfig {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the protocols advertised with ALPN, in order of preference.
+    pub fn with_alpn<I, P>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<String>,
+    {
+        self.alpn = protocols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    #[inline]
+    pub fn alpn(&self) -> &[String] {
+        &self.alpn
+    }
+
+    /// Presents `identity` to servers requesting a client certificate.
+    pub fn with_identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    #[inline]
+    pub fn identity(&self) -> Option<&Identity> {
+        self.identity.as_ref()
+    }
+
+    /// Sets the store of trusted certificate authorities.
+    pub fn with_root_store(mut self, root_store: RootStore) -> Self {
+        self.root_store = root_store;
+        self
+    }
+
+    #[inline]
+    pub fn root_store(&self) -> RootStore {
+        self.root_store
+    }
+
+    /// Also trusts the certificates of the PEM bundle `pem`, e.g. the CA of internal services.
+    pub fn with_root_certificates_pem(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.extra_roots.push(pem.into());
+        self
+    }
+
+    /// Also trusts the DER certificate `der`.
+    pub fn with_root_certificate_der(mut self, der: &[u8]) -> Self {
+        let mut pem = Vec::new();
+        pem_encode("CERTIFICATE", der, &mut pem);
+        self.extra_roots.push(pem);
+        self
+    }
+
+    /// Accepts any server certificate, including expired, self-signed and mismatched ones.
+    ///
+    /// This disables the authentication of servers and must only be used for testing.
+    pub fn with_danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    #[inline]
+    pub fn danger_accept_invalid_certs(&self) -> bool {
+        self.accept_invalid_certs
+    }
+
+    /// Accepts server certificates issued for other names, still requiring them to be signed by
+    /// a trusted root.
+    ///
+    /// Any server with a valid certificate can then impersonate another one, so this must only
+    /// be used for testing.
+    pub fn with_danger_accept_invalid_hostnames(mut self, accept: bool) -> Self {
+        self.accept_invalid_hostnames = accept;
+        self
+    }
+
+    #[inline]
+    pub fn danger_accept_invalid_hostnames(&self) -> bool {
+        self.accept_invalid_hostnames
+    }
+
+    /// Only accepts `server_name` if one of the certificates it presents has a key in `pins`,
+    /// once the certificates are otherwise verified. Replaces the previous pins of the name.
+    ///
+    /// Pinning is only supported by the rustls backend.
+    pub fn with_pins<I>(mut self, server_name: impl Into<String>, pins: I) -> Self
+    where
+        I: IntoIterator<Item = SpkiPin>,
+    {
+        self.pins
+            .set(server_name.into(), pins.into_iter().collect());
+        self
+    }
+
+    /// Returns the pins of `server_name`, if it is pinned.
+    #[inline]
+    pub fn pins(&self, server_name: &str) -> Option<&[SpkiPin]> {
+        self.pins.get(server_name)
+    }
+
+    /// Calls `callback` whenever a pinned server fails verification because of its pins, e.g.
+    /// to report an intercepting middlebox.
+    pub fn with_pin_failure_callback(
+        mut self,
+        callback: impl Fn(&PinFailure) + Send + Sync + 'static,
+    ) -> Self {
+        self.pins.set_on_failure(callback);
+        self
+    }
+
+    /// Keeps up to `size` TLS sessions to resume later connections to the same servers, with
+    /// TLS 1.3 tickets or TLS 1.2 session IDs and tickets. A size of 0 disables resumption.
+    ///
+    /// The cache is shared by every connection of the built connector and its clones. rustls
+    /// keeps 256 sessions by default, native-tls does not resume sessions and only accepts 0.
+    pub fn with_session_cache_size(mut self, size: usize) -> Self {
+        self.session_cache_size = Some(size);
+        self
+    }
+
+    #[inline]
+    pub fn session_cache_size(&self) -> Option<usize> {
+        self.session_cache_size
+    }
+
+    /// Allows sending TLS 1.3 early data when resuming a session, see
+    /// [`TlsConnector::connect_with_early_data`](super::TlsConnector::connect_with_early_data).
+    /// `connect` never sends early data: only the bytes given to that method are, and the
+    /// requests of an [`HttpConnector`](crate::http::HttpConnector) set with `set_early_data`.
+    ///
+    /// Only supported by the rustls backend.
+    pub fn with_early_data(mut self, enabled: bool) -> Self {
+        self.early_data = enabled;
+        self
+    }
+
+    #[inline]
+    pub fn early_data(&self) -> bool {
+        self.early_data
+    }
+
+    /// Allows handing the encryption of connections to the kernel once their handshake
+    /// completed, see [`TlsConnector::connect_ktls`](super::TlsConnector::connect_ktls).
+    ///
+    /// Only supported by the rustls backend on Linux, with the kernel `tls` module loaded.
+    #[cfg(feature = "ktls")]
+    pub fn with_ktls(mut self, enabled: bool) -> Self {
+        self.ktls = enabled;
+        self
+    }
+
+    #[cfg(feature = "ktls")]
+    #[inline]
+    pub fn ktls(&self) -> bool {
+        self.ktls
+    }
+
+    /// Logs the secrets of connections to `key_log`, see [`KeyLog`].
+    pub fn with_key_log(mut self, key_log: KeyLog) -> Self {
+        self.key_log = key_log;
+        self
+    }
+
+    #[inline]
+    pub fn key_log(&self) -> &KeyLog {
+        &self.key_log
+    }
+
+    /// Builds a connector of the enabled backend.
+    #[cfg(not(feature = "native-tls"))]
+    pub fn build(&self) -> Result<MonoioTlsConnector, TlsConfigError> {
+        Ok(self.client_config()?.into())
+    }
+
+    /// Builds the configuration once to share it between connectors, see [`SharedTlsConfig`].
+    #[cfg(not(feature = "native-tls"))]
+    #[inline]
+    pub fn build_shared(&self) -> Result<SharedTlsConfig, TlsConfigError> {
+        self.client_config().map(SharedTlsConfig::from_rustls)
+    }
+
+    /// Builds the configuration once to share it between connectors, see [`SharedTlsConfig`].
+    #[cfg(feature = "native-tls")]
+    #[inline]
+    pub fn build_shared(&self) -> Result<SharedTlsConfig, TlsConfigError> {
+        Ok(SharedTlsConfig {
+            tls_connector: self.build()?,
+        })
+    }
+
+    #[cfg(not(feature = "native-tls"))]
+    pub(crate) fn client_config(
+        &self,
+    ) -> Result<std::sync::Arc<rustls::ClientConfig>, TlsConfigError> {
+        use rustls::pki_types::{pem::PemObject, CertificateDer};
+
+        let mut root_store = rustls::RootCertStore::empty();
+        match self.root_store {
+            RootStore::Builtin => {
+                root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            }
+            RootStore::Platform => add_platform_roots(&mut root_store),
+            RootStore::CustomOnly => {}
+        }
+        for pem in &self.extra_roots {
+            let certs = CertificateDer::pem_slice_iter(pem)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| TlsConfigError::InvalidRootCertificate(e.to_string()))?;
+            if certs.is_empty() {
+                return Err(TlsConfigError::InvalidRootCertificate(
+                    "no certificate found".to_string(),
+                ));
+            }
+            for cert in certs {
+                root_store.add(cert)?;
+            }
+        }
+        let root_store = std::sync::Arc::new(root_store);
+
+        let builder = rustls::ClientConfig::builder().with_root_certificates(root_store.clone());
+        let mut cfg = match &self.identity {
+            Some(identity) => {
+                let (chain, key) = identity.to_rustls()?;
+                builder.with_client_auth_cert(chain, key)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let dangerous = self.accept_invalid_certs || self.accept_invalid_hostnames;
+        if dangerous || !self.pins.is_empty() {
+            use rustls::client::{danger::ServerCertVerifier, WebPkiServerVerifier};
+
+            let provider = cfg.crypto_provider().clone();
+            let webpki = || {
+                WebPkiServerVerifier::builder_with_provider(root_store.clone(), provider.clone())
+                    .build()
+                    .map_err(|e| TlsConfigError::InvalidRootCertificate(e.to_string()))
+            };
+            let mut verifier: std::sync::Arc<dyn ServerCertVerifier> = match dangerous {
+                true => std::sync::Arc::new(DangerousVerifier {
+                    inner: match self.accept_invalid_certs {
+                        true => None,
+                        false => Some(webpki()?),
+                    },
+                    provider: provider.clone(),
+                }),
+                false => webpki()?,
+            };
+            if !self.pins.is_empty() {
+                verifier = std::sync::Arc::new(self.pins.verifier(verifier, &provider)?);
+            }
+            cfg.dangerous().set_certificate_verifier(verifier);
+        }
+        match self.session_cache_size {
+            Some(0) => cfg.resumption = rustls::client::Resumption::disabled(),
+            Some(size) => cfg.resumption = rustls::client::Resumption::in_memory_sessions(size),
+            None => {}
+        }
+        cfg.enable_early_data = self.early_data;
+        cfg.enable_secret_extraction = self.ktls;
+        match &self.key_log {
+            KeyLog::Disabled => {}
+            KeyLog::Env => cfg.key_log = std::sync::Arc::new(rustls::KeyLogFile::new()),
+            KeyLog::File(path) => {
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(TlsConfigError::KeyLogFile)?;
+                cfg.key_log = std::sync::Arc::new(KeyLogWriter(std::sync::Mutex::new(file)));
+            }
+        }
+        cfg.alpn_protocols = self.alpn.iter().map(|a| a.as_bytes().to_vec()).collect();
+        Ok(std::sync::Arc::new(cfg))
+    }
+
+    /// Builds a connector of the enabled backend.
+    #[cfg(feature = "native-tls")]
+    pub fn build(&self) -> Result<MonoioTlsConnector, TlsConfigError> {
+        if !self.pins.is_empty() {
+            return Err(TlsConfigError::Unsupported(
+                "certificate pinning with native-tls",
+            ));
+        }
+        if self.early_data {
+            return Err(TlsConfigError::Unsupported("early data with native-tls"));
+        }
+        if self.ktls {
+            return Err(TlsConfigError::Unsupported("kTLS with native-tls"));
+        }
+        if self.key_log != KeyLog::Disabled {
+            return Err(TlsConfigError::Unsupported("key logging with native-tls"));
+        }
+        if self.session_cache_size.is_some_and(|size| size > 0) {
+            return Err(TlsConfigError::Unsupported(
+                "session resumption with native-tls",
+            ));
+        }
+        let mut builder = native_tls::TlsConnector::builder();
+        if self.root_store == RootStore::CustomOnly {
+            builder.disable_built_in_roots(true);
+        }
+        builder
+            .danger_accept_invalid_certs(self.accept_invalid_certs)
+            .danger_accept_invalid_hostnames(self.accept_invalid_hostnames);
+        for pem in &self.extra_roots {
+            let mut found = false;
+            for cert in pem_blocks(pem, "CERTIFICATE") {
+                builder.add_root_certificate(native_tls::Certificate::from_pem(cert)?);
+                found = true;
+            }
+            if !found {
+                return Err(TlsConfigError::InvalidRootCertificate(
+                    "no certificate found".to_string(),
+                ));
+            }
+        }
+        if !self.alpn.is_empty() {
+            let alpn: Vec<&str> = self.alpn.iter().map(String::as_str).collect();
+            builder.request_alpns(&alpn);
+        }
+        if let Some(identity) = &self.identity {
+            builder.identity(identity.to_native_tls()?);
+        }
+        Ok(builder.build()?.into())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::connectors::{ServerName, TcpConnector, TcpTlsAddr, TlsConnector};
+
+    #[test]
+    fn builds_connector_for_the_enabled_backend() {
+        let config = TlsConfig::new().with_alpn(["h2", "http/1.1"]);
+        assert_eq!(config.alpn(), ["h2", "http/1.1"]);
+        assert!(TlsConnector::with_config(TcpConnector::default(), &config).is_ok());
+        assert!(TlsConfig::new().build().is_ok());
+    }
+
+    #[test]
+    fn shares_built_configs() {
+        let shared = TlsConfig::new().with_alpn(["h2"]).build_shared().unwrap();
+        let _ = TlsConnector::with_shared_config(TcpConnector::default(), &shared);
+        let _ = TlsConnector::with_shared_config(TcpConnector::default(), &shared.clone());
+
+        // Default configurations are built once per thread.
+        let one = SharedTlsConfig::default_for(vec!["h2", "http/1.1"]);
+        let other = SharedTlsConfig::default_for(vec!["h2", "http/1.1"]);
+        #[cfg(not(feature = "native-tls"))]
+        {
+            assert!(std::sync::Arc::ptr_eq(
+                one.client_config(),
+                other.client_config()
+            ));
+            let http1 = SharedTlsConfig::default_for(vec!["http/1.1"]);
+            assert!(!std::sync::Arc::ptr_eq(
+                one.client_config(),
+                http1.client_config()
+            ));
+            assert_eq!(http1.client_config().alpn_protocols, [b"http/1.1"]);
+        }
+        let _ = (one, other);
+    }
+
+    pub(crate) fn server_name(host: &str) -> ServerName<'static> {
+        let uri = http::Uri::try_from(format!("https://{host}")).unwrap();
+        TcpTlsAddr::try_from(uri).unwrap().sn
+    }
+
+    #[test]
+    fn builds_connectors_with_client_identities() {
+        let identity = Identity::from_pem(
+            include_str!("testdata/client.pem"),
+            include_str!("testdata/client.key"),
+        );
+        let config = TlsConfig::new().with_identity(identity);
+        assert!(config.build().is_ok());
+        let invalid = TlsConfig::new().with_identity(Identity::from_pem("", "garbage"));
+        assert!(invalid.build().is_err());
+
+        let internal = server_name("internal.example");
+        let connector = TlsConnector::new_with_tls_default(TcpConnector::default(), None)
+            .with_config_for(internal.clone(), &config)
+            .unwrap();
+        let default = connector.tls_connector() as *const _;
+        assert_ne!(connector.tls_connector_for(&internal) as *const _, default);
+        let other = server_name("other.example");
+        assert_eq!(connector.tls_connector_for(&other) as *const _, default);
+    }
+
+    #[cfg(feature = "rustls")]
+    type Served = monoio::task::JoinHandle<Result<Vec<rustls::HandshakeKind>, String>>;
+
+    #[cfg(feature = "rustls")]
+    fn serve_once(client_auth: bool) -> (std::net::SocketAddr, Served) {
+        serve(client_auth, 1)
+    }
+
+    /// Serves `connections` TLS connections one after the other, answering `ping` with `pong`,
+    /// as `localhost` with the test CA and offering `h2` and `http/1.1` with ALPN. A client
+    /// certificate signed by the same CA is requested when `client_auth` is set. Returns the
+    /// kind of each handshake.
+    #[cfg(feature = "rustls")]
+    fn serve(client_auth: bool, connections: usize) -> (std::net::SocketAddr, Served) {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        let acceptor = monoio_rustls::TlsAcceptor::from(server_config(client_auth));
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = monoio::spawn(async move {
+            let mut handshakes = Vec::new();
+            for _ in 0..connections {
+                let (tcp, _) = listener.accept().await.map_err(|e| e.to_string())?;
+                let mut tls = acceptor.accept(tcp).await.map_err(|e| e.to_string())?;
+                let (res, _) = tls.read(vec![0; 4]).await;
+                res.map_err(|e| e.to_string())?;
+                tls.write_all(b"pong".to_vec())
+                    .await
+                    .0
+                    .map_err(|e| e.to_string())?;
+                handshakes.extend(tls.into_parts().1.handshake_kind());
+            }
+            Ok(handshakes)
+        });
+        (addr, server)
+    }
+
+    #[cfg(feature = "rustls")]
+    pub(crate) fn server_config(client_auth: bool) -> rustls::ServerConfig {
+        use rustls::{
+            pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+            server::WebPkiClientVerifier,
+        };
+
+        let chain = CertificateDer::pem_slice_iter(include_bytes!("testdata/server.pem"))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_slice(include_bytes!("testdata/server.key")).unwrap();
+        let builder = rustls::ServerConfig::builder();
+        let builder = if client_auth {
+            let mut roots = rustls::RootCertStore::empty();
+            roots
+                .add(CertificateDer::from_pem_slice(include_bytes!("testdata/ca.pem")).unwrap())
+                .unwrap();
+            builder.with_client_cert_verifier(
+                WebPkiClientVerifier::builder(roots.into()).build().unwrap(),
+            )
+        } else {
+            builder.with_no_client_auth()
+        };
+        let mut config = builder.with_single_cert(chain, key).unwrap();
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        config
+    }
+
+    #[cfg(feature = "rustls")]
+    async fn ping(
+        config: &TlsConfig,
+        addr: std::net::SocketAddr,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let connector = TlsConnector::with_config(TcpConnector::default(), config)?;
+        ping_as(&connector, "localhost", addr).await
+    }
+
+    /// Sends `ping` to `addr` through `connector`, with `name` as the server name of the key.
+    #[cfg(feature = "rustls")]
+    async fn ping_as(
+        connector: &TlsConnector<TcpConnector>,
+        name: &str,
+        addr: std::net::SocketAddr,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        use crate::connectors::Connector;
+
+        let key = TcpTlsAddr {
+            host: "127.0.0.1".into(),
+            port: addr.port(),
+            sn: server_name(name),
+        };
+        let mut stream = connector.connect(key).await?;
+        stream.write_all(b"ping".to_vec()).await.0?;
+        let (res, buf) = stream.read(vec![0; 4]).await;
+        Ok(buf[..res?].to_vec())
+    }
+
+    #[cfg(feature = "rustls")]
+    #[monoio::test(enable_timer = true)]
+    async fn verifies_servers_with_custom_roots() {
+        let custom = TlsConfig::new()
+            .with_root_store(RootStore::CustomOnly)
+            .with_root_certificates_pem(include_str!("testdata/ca.pem"));
+
+        let (addr, server) = serve_once(false);
+        assert!(ping(&TlsConfig::new(), addr).await.is_err());
+        assert!(server.await.is_err());
+
+        let (addr, server) = serve_once(false);
+        assert_eq!(ping(&custom, addr).await.unwrap(), b"pong");
+        server.await.unwrap();
+
+        let (addr, server) = serve_once(true);
+        let _ = ping(&custom, addr).await;
+        assert!(server.await.is_err());
+
+        let (addr, server) = serve_once(true);
+        let identity = Identity::from_pem(
+            include_str!("testdata/client.pem"),
+            include_str!("testdata/client.key"),
+        );
+        let mutual = custom.with_identity(identity);
+        assert_eq!(ping(&mutual, addr).await.unwrap(), b"pong");
+        server.await.unwrap();
+
+        assert!(TlsConfig::new()
+            .with_root_certificates_pem("not a certificate")
+            .build()
+            .is_err());
+    }
+
+    #[cfg(feature = "rustls")]
+    #[monoio::test(enable_timer = true)]
+    async fn accepts_invalid_servers_when_asked() {
+        let (addr, server) = serve_once(false);
+        let insecure = TlsConfig::new().with_danger_accept_invalid_certs(true);
+        assert_eq!(ping(&insecure, addr).await.unwrap(), b"pong");
+        server.await.unwrap();
+
+        // Certificates must still be trusted when only host names are not checked.
+        let (addr, server) = serve_once(false);
+        let any_name = TlsConfig::new().with_danger_accept_invalid_hostnames(true);
+        assert!(ping(&any_name, addr).await.is_err());
+        assert!(server.await.is_err());
+
+        let custom = TlsConfig::new()
+            .with_root_store(RootStore::CustomOnly)
+            .with_root_certificates_pem(include_str!("testdata/ca.pem"));
+        let connector = TlsConnector::with_config(TcpConnector::default(), &custom).unwrap();
+        let (addr, server) = serve_once(false);
+        assert!(ping_as(&connector, "wrong.example", addr).await.is_err());
+        assert!(server.await.is_err());
+
+        let (addr, server) = serve_once(false);
+        let any_name = custom.with_danger_accept_invalid_hostnames(true);
+        let relaxed = TlsConnector::with_config(TcpConnector::default(), &any_name).unwrap();
+        assert_eq!(
+            ping_as(&relaxed, "wrong.example", addr).await.unwrap(),
+            b"pong"
+        );
+        server.await.unwrap();
+
+        let (addr, server) = serve_once(false);
+        let connector = connector.with_server_name_override(Some(server_name("localhost")));
+        assert_eq!(
+            ping_as(&connector, "wrong.example", addr).await.unwrap(),
+            b"pong"
+        );
+        server.await.unwrap();
+    }
+
+    #[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+    #[monoio::test(enable_timer = true)]
+    async fn checks_pinned_keys() {
+        use std::sync::{Arc, Mutex};
+
+        let server_pin =
+            SpkiPin::from_base64("flS8kZmDPKudlCa0gFrx2RY1fTLsQZCK8D/lXj8pXlw=").unwrap();
+        let ca_pin = SpkiPin::from_base64("8Sy01tga0zCGd85k+dlzVOlIbVbyY//mZxbNjofw9o8=").unwrap();
+        let custom = TlsConfig::new()
+            .with_root_store(RootStore::CustomOnly)
+            .with_root_certificates_pem(include_str!("testdata/ca.pem"));
+
+        let (addr, server) = serve_once(false);
+        let pinned = custom.clone().with_pins("localhost", [ca_pin, server_pin]);
+        assert_eq!(ping(&pinned, addr).await.unwrap(), b"pong");
+        server.await.unwrap();
+
+        // Pins also accept servers that are not otherwise verified.
+        let (addr, server) = serve_once(false);
+        let self_signed = TlsConfig::new()
+            .with_danger_accept_invalid_certs(true)
+            .with_pins("localhost", [server_pin]);
+        assert_eq!(ping(&self_signed, addr).await.unwrap(), b"pong");
+        server.await.unwrap();
+
+        let (addr, server) = serve_once(false);
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let reported = failures.clone();
+        let mismatched = custom
+            .with_pins("localhost", [ca_pin])
+            .with_pin_failure_callback(move |failure| {
+                reported.lock().unwrap().push(failure.clone())
+            });
+        assert!(ping(&mismatched, addr).await.is_err());
+        assert!(server.await.is_err());
+        assert_eq!(
+            *failures.lock().unwrap(),
+            [PinFailure {
+                server_name: "localhost".to_string(),
+                presented: vec![server_pin],
+            }]
+        );
+    }
+
+    #[cfg(feature = "rustls")]
+    #[monoio::test(enable_timer = true)]
+    async fn reports_negotiated_protocol() {
+        use crate::connectors::{Alpn, Connector, TransportConnMetadata};
+
+        let custom = TlsConfig::new()
+            .with_root_store(RootStore::CustomOnly)
+            .with_root_certificates_pem(include_str!("testdata/ca.pem"));
+        for (offered, negotiated) in [
+            (vec!["h2", "http/1.1"], Alpn::HTTP2),
+            (vec!["http/1.1"], Alpn::HTTP11),
+            (vec![], Alpn::None),
+        ] {
+            let (addr, server) = serve_once(false);
+            let config = custom.clone().with_alpn(offered);
+            let connector = TlsConnector::with_config(TcpConnector::default(), &config).unwrap();
+            let key = TcpTlsAddr {
+                host: "127.0.0.1".into(),
+                port: addr.port(),
+                sn: server_name("localhost"),
+            };
+            let stream = connector.connect(key).await.unwrap();
+            assert_eq!(stream.get_conn_metadata().alpn(), negotiated);
+            drop(stream);
+            let _ = server.await;
+        }
+    }
+
+    #[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+    #[monoio::test(enable_timer = true)]
+    async fn reports_connection_parameters() {
+        use rustls::pki_types::{pem::PemObject, CertificateDer};
+
+        use crate::connectors::{Connector, TransportConnMetadata};
+
+        let config = TlsConfig::new()
+            .with_root_store(RootStore::CustomOnly)
+            .with_root_certificates_pem(include_str!("testdata/ca.pem"));
+        let (addr, server) = serve_once(false);
+        let connector = TlsConnector::with_config(TcpConnector::default(), &config).unwrap();
+        let key = TcpTlsAddr {
+            host: "127.0.0.1".into(),
+            port: addr.port(),
+            sn: server_name("localhost"),
+        };
+        let meta = connector.connect(key).await.unwrap().get_conn_metadata();
+        let _ = server.await;
+
+        assert_eq!(meta.peer_addr(), Some(addr));
+        assert!(meta.local_addr().is_some());
+        assert_eq!(meta.server_name(), Some("localhost"));
+        assert_eq!(meta.tls_version(), Some("TLSv1_3"));
+        assert!(meta.cipher_suite().unwrap().starts_with("TLS13_"));
+        let leaf = CertificateDer::from_pem_slice(include_bytes!("testdata/server.pem")).unwrap();
+        let client_config = config.client_config().unwrap();
+        let sha256 = crate::connectors::tls_pin::sha256(client_config.crypto_provider()).unwrap();
+        assert_eq!(
+            meta.peer_certificate_sha256().map(|d| &d[..]),
+            Some(sha256.hash(&leaf).as_ref())
+        );
+    }
+
+    #[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+    #[monoio::test(enable_timer = true)]
+    async fn resumes_sessions() {
+        use rustls::HandshakeKind;
+
+        let custom = TlsConfig::new()
+            .with_root_store(RootStore::CustomOnly)
+            .with_root_certificates_pem(include_str!("testdata/ca.pem"));
+        for (config, second) in [
+            (custom.clone(), HandshakeKind::Resumed),
+            (custom.with_session_cache_size(0), HandshakeKind::Full),
+        ] {
+            let (addr, server) = serve(false, 2);
+            let connector = TlsConnector::with_config(TcpConnector::default(), &config).unwrap();
+            // Clones share the cache.
+            for connector in [connector.clone(), connector] {
+                assert_eq!(
+                    ping_as(&connector, "localhost", addr).await.unwrap(),
+                    b"pong"
+                );
+            }
+            assert_eq!(server.await.unwrap(), [HandshakeKind::Full, second]);
+        }
+    }
+
+    #[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+    #[monoio::test(enable_timer = true)]
+    async fn sends_early_data_on_resumption() {
+        use std::sync::Arc;
+
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        use crate::connectors::EarlyData;
+
+        let early_data_config = || {
+            let mut config = server_config(false);
+            config.max_early_data_size = 1024;
+            Arc::new(config)
+        };
+        let resuming = early_data_config();
+        // A server with another session store cannot resume, and rejects early data.
+        let configs = [resuming.clone(), resuming, early_data_config()];
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = monoio::spawn(async move {
+            let mut early_data_sizes = Vec::new();
+            for config in configs {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let acceptor = monoio_rustls::TlsAcceptor::from(config);
+                let (io, mut session) = acceptor.accept(tcp).await.unwrap().into_parts();
+                let mut early_data = Vec::new();
+                if let Some(mut data) = session.early_data() {
+                    std::io::Read::read_to_end(&mut data, &mut early_data).unwrap();
+                }
+                early_data_sizes.push(early_data.len());
+                let mut tls = monoio_rustls::ServerTlsStream::new(io, session);
+                if early_data.is_empty() {
+                    tls.read(vec![0; 4]).await.0.unwrap();
+                }
+                tls.write_all(b"pong".to_vec()).await.0.unwrap();
+            }
+            early_data_sizes
+        });
+
+        let config = TlsConfig::new()
+            .with_root_store(RootStore::CustomOnly)
+            .with_root_certificates_pem(include_str!("testdata/ca.pem"))
+            .with_early_data(true);
+        let connector = TlsConnector::with_config(TcpConnector::default(), &config).unwrap();
+        let key = TcpTlsAddr {
+            host: "127.0.0.1".into(),
+            port: addr.port(),
+            sn: server_name("localhost"),
+        };
+        for expected in [EarlyData::NotSent, EarlyData::Accepted, EarlyData::Rejected] {
+            let (mut stream, early_data) = connector
+                .connect_with_early_data(key.clone(), b"ping")
+                .await
+                .unwrap();
+            assert_eq!(early_data, expected);
+            let (res, buf) = stream.read(vec![0; 4]).await;
+            assert_eq!(&buf[..res.unwrap()], b"pong");
+        }
+        assert_eq!(server.await, [0, 4, 0]);
+    }
+
+    #[cfg(feature = "rustls")]
+    #[monoio::test(enable_timer = true)]
+    async fn connects_over_unix_sockets() {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        use crate::connectors::{Connector, TransportConnMetadata, UnixConnector, UnixTlsAddr};
+
+        let path = std::env::temp_dir().join(format!("monoio-tls-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        // Unix sockets do not support `SO_REUSEPORT`, which monoio sets by default.
+        let opts = monoio::net::ListenerOpts::new().reuse_port(false);
+        let listener = monoio::net::UnixListener::bind_with_config(&path, &opts).unwrap();
+        let acceptor = monoio_rustls::TlsAcceptor::from(server_config(false));
+        let server = monoio::spawn(async move {
+            let (unix, _) = listener.accept().await.unwrap();
+            let mut tls = acceptor.accept(unix).await.unwrap();
+            tls.read(vec![0; 4]).await.0.unwrap();
+            tls.write_all(b"pong".to_vec()).await.0.unwrap();
+        });
+
+        let config = TlsConfig::new()
+            .with_root_store(RootStore::CustomOnly)
+            .with_root_certificates_pem(include_str!("testdata/ca.pem"));
+        let connector = TlsConnector::with_config(UnixConnector, &config).unwrap();
+        let key = UnixTlsAddr::new(&path, server_name("localhost"));
+        let mut stream = connector.connect(key).await.unwrap();
+        assert_eq!(stream.get_conn_metadata().peer_path(), Some(path.as_path()));
+        stream.write_all(b"ping".to_vec()).await.0.unwrap();
+        let (res, buf) = stream.read(vec![0; 4]).await;
+        assert_eq!(&buf[..res.unwrap()], b"pong");
+        server.await;
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+    #[monoio::test(enable_timer = true)]
+    async fn logs_session_keys() {
+        let path = std::env::temp_dir().join(format!("monoio-keylog-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = TlsConfig::new()
+            .with_root_store(RootStore::CustomOnly)
+            .with_root_certificates_pem(include_str!("testdata/ca.pem"))
+            .with_key_log(KeyLog::File(path.clone()));
+        let (addr, server) = serve_once(false);
+        assert_eq!(ping(&config, addr).await.unwrap(), b"pong");
+        server.await.unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let line = log
+            .lines()
+            .find(|line| line.starts_with("CLIENT_TRAFFIC_SECRET_0 "))
+            .unwrap();
+        let fields: Vec<_> = line.split(' ').collect();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields[1].len(), 64);
+        assert!(fields[2].bytes().all(|b| b.is_ascii_hexdigit()));
+    }
+
+    /// Works whether the kernel offloads the connection or not, the sandboxes running the tests
+    /// usually lack the `tls` module.
+    #[cfg(all(feature = "ktls", target_os = "linux", not(feature = "native-tls")))]
+    #[monoio::test(enable_timer = true)]
+    async fn connects_with_ktls() {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        let config = TlsConfig::new()
+            .with_root_store(RootStore::CustomOnly)
+            .with_root_certificates_pem(include_str!("testdata/ca.pem"))
+            .with_alpn(["http/1.1"])
+            .with_ktls(true);
+        let connector = TlsConnector::with_config(TcpConnector::default(), &config).unwrap();
+        let (addr, server) = serve_once(false);
+        let key = TcpTlsAddr {
+            host: "127.0.0.1".into(),
+            port: addr.port(),
+            sn: server_name("localhost"),
+        };
+        let mut stream = connector.connect_ktls(key).await.unwrap();
+        assert_eq!(stream.alpn_protocol().as_deref(), Some(&b"http/1.1"[..]));
+        stream.write_all(b"ping".to_vec()).await.0.unwrap();
+        let (res, buf) = stream.read(vec![0; 4]).await;
+        assert_eq!(&buf[..res.unwrap()], b"pong");
+        server.await.unwrap();
+    }
+}
@@ -1,18 +1,20 @@
-use std::{fmt::Debug, net::ToSocketAddrs};
+use std::{fmt::Debug, net::ToSocketAddrs, time::Duration};
 
 use http::Uri;
 use monoio::io::{AsyncReadRent, AsyncWriteRent, Split};
 use service_async::Param;
 use thiserror::Error as ThisError;
 
-use super::{Connector, TransportConnMeta, TransportConnMetadata};
-use crate::FromUriError;
+use super::{
+    Connector, SharedTlsConfig, TlsConfig, TlsConfigError, TransportConnMeta, TransportConnMetadata,
+};
+use crate::{metrics::ClientMetrics, FromUriError};
 
 #[cfg(not(feature = "native-tls"))]
-pub type TlsStream<C> = monoio_rustls::ClientTlsStream<C>;
+type BackendStream<C> = monoio_rustls::ClientTlsStream<C>;
 
 #[cfg(feature = "native-tls")]
-pub type TlsStream<C> = monoio_native_tls::TlsStream<C>;
+type BackendStream<C> = monoio_native_tls::TlsStream<C>;
 
 #[cfg(feature = "native-tls")]
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -40,25 +42,165 @@ impl<T: Into<smol_str::SmolStr>> From<T> for ServerName<'static> {
     }
 }
 
+/// A TLS connection established by a [`TlsConnector`].
+///
+/// Its [`TransportConnMetadata`] holds the one of the inner connection along with the server
+/// name sent and the protocol negotiated with ALPN. With rustls, connections using the
+/// configuration of [`TlsConnector::with_config`] also report the TLS version, the cipher suite
+/// and the SHA-256 digest of the certificate of the server.
+#[derive(Debug)]
+pub struct TlsStream<C> {
+    inner: BackendStream<C>,
+    meta: TransportConnMeta,
+}
+
+impl<C> TlsStream<C> {
+    /// Wraps `inner`, adding its ALPN protocol to `meta`.
+    pub(crate) fn new(inner: BackendStream<C>, mut meta: TransportConnMeta) -> Self {
+        meta.set_alpn(inner.alpn_protocol());
+        Self { inner, meta }
+    }
+
+    #[inline]
+    pub fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        self.inner.alpn_protocol()
+    }
+
+    /// Returns the stream of the TLS implementation.
+    #[inline]
+    pub fn get_ref(&self) -> &BackendStream<C> {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> BackendStream<C> {
+        self.inner
+    }
+}
+
+#[cfg(not(feature = "native-tls"))]
+impl<C> TlsStream<C> {
+    /// Wraps `session` of `config`, whose handshake completed over `io`, adding its parameters
+    /// to `meta`.
+    pub(crate) fn from_session(
+        io: C,
+        session: rustls::ClientConnection,
+        config: &rustls::ClientConfig,
+        mut meta: TransportConnMeta,
+    ) -> Self {
+        set_session_meta(&mut meta, &session, config);
+        Self::new(BackendStream::new(io, session), meta)
+    }
+}
+
+/// Adds the TLS version, the cipher suite and the digest of the certificate negotiated by
+/// `session` of `config` to `meta`.
+#[cfg(not(feature = "native-tls"))]
+pub(crate) fn set_session_meta(
+    meta: &mut TransportConnMeta,
+    session: &rustls::ClientConnection,
+    config: &rustls::ClientConfig,
+) {
+    meta.set_tls(
+        session.protocol_version().and_then(|v| v.as_str()),
+        session
+            .negotiated_cipher_suite()
+            .and_then(|suite| suite.suite().as_str()),
+    );
+    let digest = session
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .zip(super::tls_pin::sha256(config.crypto_provider()))
+        .and_then(|(cert, sha256)| sha256.hash(cert).as_ref().try_into().ok());
+    meta.set_peer_certificate_sha256(digest);
+}
+
 impl<S> TransportConnMetadata for TlsStream<S> {
     type Metadata = TransportConnMeta;
 
+    #[inline]
     fn get_conn_metadata(&self) -> Self::Metadata {
-        let mut meta = TransportConnMeta::default();
-        meta.set_alpn(self.alpn_protocol());
-        meta
+        self.meta.clone()
+    }
+}
+
+impl<S: AsyncReadRent + AsyncWriteRent> AsyncReadRent for TlsStream<S> {
+    #[inline]
+    async fn read<T: monoio::buf::IoBufMut>(&mut self, buf: T) -> monoio::BufResult<usize, T> {
+        self.inner.read(buf).await
+    }
+
+    #[inline]
+    async fn readv<T: monoio::buf::IoVecBufMut>(&mut self, buf: T) -> monoio::BufResult<usize, T> {
+        self.inner.readv(buf).await
+    }
+}
+
+impl<S: AsyncReadRent + AsyncWriteRent> AsyncWriteRent for TlsStream<S> {
+    #[inline]
+    async fn write<T: monoio::buf::IoBuf>(&mut self, buf: T) -> monoio::BufResult<usize, T> {
+        self.inner.write(buf).await
+    }
+
+    #[inline]
+    async fn writev<T: monoio::buf::IoVecBuf>(
+        &mut self,
+        buf_vec: T,
+    ) -> monoio::BufResult<usize, T> {
+        self.inner.writev(buf_vec).await
+    }
+
+    #[inline]
+    async fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush().await
+    }
+
+    #[inline]
+    async fn shutdown(&mut self) -> std::io::Result<()> {
+        self.inner.shutdown().await
     }
 }
+
+unsafe impl<S: Split> Split for TlsStream<S> {}
 /// A connector for establishing TLS connections over an inner connector.
 ///
 /// This connector wraps another connector (typically a TCP or Unix socket connector)
-/// and adds TLS encryption to the connection. The underlying TLS implentation
-/// can be either `rustls` or `native-tls` depending on the feature flags. Set th
-/// `native-tls` feature to use the `native-tls` implementation.
+/// and adds TLS encryption to the connection. Any connector accepting a reference to the key
+/// works, e.g. [`UnixConnector`](super::UnixConnector) with [`UnixTlsAddr`] for TLS over a Unix
+/// socket, or [`UnifiedL4Connector`](super::UnifiedL4Connector) with [`UnifiedTlsAddr`]. The
+/// underlying TLS implentation can be either `rustls` or `native-tls` depending on the feature
+/// flags. Set th `native-tls` feature to use the `native-tls` implementation, which relies on the
+/// platform library (OpenSSL on Linux, or a vendored copy with `native-tls-vendored`) and its
+/// certificate store. [`TlsConfig`] configures either
+/// of them, see [`with_config`](Self::with_config), and a [`SharedTlsConfig`] built from it once
+/// can be shared by many connectors, see [`with_shared_config`](Self::with_shared_config).
+///
+/// The protocols offered with ALPN are set with [`TlsConfig::with_alpn`], e.g. `["h2",
+/// "http/1.1"]` or only `["http/1.1"]`, and the negotiated one is reported by the
+/// [`TransportConnMetadata`] of the connections.
+///
+/// Connections to some server names can use their own TLS settings, for example to present a
+/// different client [`Identity`](super::Identity) to each upstream, see
+/// [`with_config_for`](Self::with_config_for).
+///
+/// With the `ktls` feature on Linux, `connect_ktls` hands the encryption of established
+/// connections to the kernel.
+///
+/// A handshake timeout can be set with [`with_handshake_timeout`](Self::with_handshake_timeout);
+/// it is surfaced as [`TransportError::TlsHandshakeTimeout`](crate::TransportError) once
+/// converted.
 #[derive(Clone)]
 pub struct TlsConnector<C> {
     inner_connector: C,
     tls_connector: MonoioTlsConnector,
+    // Connectors used instead of `tls_connector` for some server names.
+    overrides: Vec<(ServerName<'static>, MonoioTlsConnector)>,
+    server_name_override: Option<ServerName<'static>>,
+    // The configuration of `tls_connector` when built from a `TlsConfig`.
+    #[cfg(not(feature = "native-tls"))]
+    client_config: Option<std::sync::Arc<rustls::ClientConfig>>,
+    handshake_timeout: Option<Duration>,
+    metrics: Option<std::sync::Arc<dyn ClientMetrics>>,
 }
 
 impl<C: Debug> std::fmt::Debug for TlsConnector<C> {
@@ -72,38 +214,128 @@ impl<C> TlsConnector<C> {
         Self {
             inner_connector,
             tls_connector,
+            overrides: Vec::new(),
+            server_name_override: None,
+            #[cfg(not(feature = "native-tls"))]
+            client_config: None,
+            handshake_timeout: None,
+            metrics: None,
         }
     }
 
-    // Create a new `TlsConnector` with custom ALPN protocols.
-    #[cfg(not(feature = "native-tls"))]
+    /// Sets the time allowed for the TLS handshake, once the inner connection is established.
+    /// Requires the monoio timer driver.
     #[inline]
-    pub fn new_with_tls_default(inner_connector: C, alpn: Option<Vec<&str>>) -> Self {
-        let mut root_store = rustls::RootCertStore::empty();
-        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    pub fn with_handshake_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
 
-        let mut cfg = rustls::ClientConfig::builder()
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
+    #[inline]
+    pub fn handshake_timeout(&self) -> Option<Duration> {
+        self.handshake_timeout
+    }
 
-        // Set ALPN from client side
-        if let Some(alpn) = alpn {
-            let alpn: Vec<Vec<u8>> = alpn.iter().map(|a| a.as_bytes().to_vec()).collect();
-            cfg.alpn_protocols = alpn;
+    /// Reports the duration of successful handshakes to `metrics`, see
+    /// [`metrics`](crate::metrics).
+    #[inline]
+    pub fn with_metrics(mut self, metrics: std::sync::Arc<dyn ClientMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Bounds `handshake` with the handshake timeout, and reports its duration once it
+    /// succeeded.
+    async fn complete_handshake<T>(
+        &self,
+        handshake: impl std::future::Future<Output = Result<T, TlsError>>,
+    ) -> Result<T, TlsError> {
+        let start = std::time::Instant::now();
+        let res = match self.handshake_timeout {
+            Some(timeout) => monoio::time::timeout(timeout, handshake)
+                .await
+                .unwrap_or_else(|_| Err(TlsError::Io(crate::error::Elapsed::TlsHandshake.into()))),
+            None => handshake.await,
+        };
+        if let (Ok(_), Some(metrics)) = (&res, &self.metrics) {
+            metrics.on_handshake(start.elapsed());
         }
+        res
+    }
+
+    /// Completes the handshake with `server_name` over `stream` within the handshake timeout.
+    async fn handshake_over<CN>(
+        &self,
+        stream: CN,
+        server_name: &ServerName<'static>,
+    ) -> Result<TlsStream<CN>, TlsError>
+    where
+        CN: AsyncReadRent + AsyncWriteRent + TransportConnMetadata<Metadata = TransportConnMeta>,
+    {
+        let meta = stream_meta(&stream, server_name);
+        let handshake = async move {
+            // The session is only exposed when the handshake is driven here.
+            #[cfg(not(feature = "native-tls"))]
+            if let Some(config) = self.session_config(server_name) {
+                let mut io = stream;
+                let mut session =
+                    rustls::ClientConnection::new(config.clone(), server_name.clone())?;
+                drive_handshake(&mut io, &mut session).await?;
+                return Ok(TlsStream::from_session(io, session, config, meta));
+            }
+            let tls_connector = self.tls_connector_for(server_name);
+            #[cfg(not(feature = "native-tls"))]
+            let inner = tls_connector.connect(server_name.clone(), stream).await?;
+            #[cfg(feature = "native-tls")]
+            let inner = tls_connector.connect(&server_name.0, stream).await?;
+            Ok(TlsStream::new(inner, meta))
+        };
+        #[cfg(feature = "tracing")]
+        let handshake = tracing::Instrument::instrument(
+            handshake,
+            tracing::debug_span!("tls_handshake", server_name = ?server_name),
+        );
+        self.complete_handshake(handshake).await
+    }
 
-        TlsConnector::new(inner_connector, cfg.into())
+    /// Returns the configuration of [`with_config`](Self::with_config) when it is used for
+    /// connections to `server_name`.
+    #[cfg(not(feature = "native-tls"))]
+    fn session_config(
+        &self,
+        server_name: &ServerName<'static>,
+    ) -> Option<&std::sync::Arc<rustls::ClientConfig>> {
+        let overridden = self.overrides.iter().any(|(name, _)| name == server_name);
+        self.client_config.as_ref().filter(|_| !overridden)
     }
 
-    // Create a new `TlsConnector` with custom ALPN protocols.
-    #[cfg(feature = "native-tls")]
+    /// Creates a `TlsConnector` with the TLS connector built from `config`.
+    #[inline]
+    pub fn with_config(inner_connector: C, config: &TlsConfig) -> Result<Self, TlsConfigError> {
+        Ok(Self::with_shared_config(
+            inner_connector,
+            &config.build_shared()?,
+        ))
+    }
+
+    /// Creates a `TlsConnector` using a configuration built beforehand, without building it
+    /// again.
+    pub fn with_shared_config(inner_connector: C, config: &SharedTlsConfig) -> Self {
+        let connector = TlsConnector::new(inner_connector, config.tls_connector().clone());
+        #[cfg(not(feature = "native-tls"))]
+        let connector = TlsConnector {
+            client_config: Some(config.client_config().clone()),
+            ..connector
+        };
+        connector
+    }
+
+    /// Creates a `TlsConnector` offering the `alpn` protocols with the default settings. The
+    /// configuration is built once per thread for each list of protocols.
     #[inline]
     pub fn new_with_tls_default(inner_connector: C, alpn: Option<Vec<&str>>) -> Self {
-        let mut tls_connector = native_tls::TlsConnector::builder();
-        if let Some(alpn) = alpn {
-            tls_connector.request_alpns(&alpn);
-        }
-        TlsConnector::new(inner_connector, tls_connector.build().unwrap().into())
+        let config = SharedTlsConfig::default_for(alpn.unwrap_or_default());
+        Self::with_shared_config(inner_connector, &config)
     }
 
     #[inline]
@@ -115,6 +347,293 @@ impl<C> TlsConnector<C> {
     pub fn tls_connector(&self) -> &MonoioTlsConnector {
         &self.tls_connector
     }
+
+    /// Returns the same TLS settings over `inner_connector`.
+    pub fn with_inner_connector<D>(self, inner_connector: D) -> TlsConnector<D> {
+        TlsConnector {
+            inner_connector,
+            tls_connector: self.tls_connector,
+            overrides: self.overrides,
+            server_name_override: self.server_name_override,
+            #[cfg(not(feature = "native-tls"))]
+            client_config: self.client_config,
+            handshake_timeout: self.handshake_timeout,
+            metrics: self.metrics,
+        }
+    }
+
+    /// Uses a TLS connector built from `config` for connections to `server_name`, replacing any
+    /// previous one for that name. Early data is only sent with the configuration of
+    /// [`with_config`](Self::with_config).
+    pub fn with_config_for(
+        self,
+        server_name: ServerName<'static>,
+        config: &TlsConfig,
+    ) -> Result<Self, TlsConfigError> {
+        Ok(self.with_tls_connector_for(server_name, config.build()?))
+    }
+
+    /// Uses `tls_connector` for connections to `server_name`, replacing any previous one for
+    /// that name.
+    pub fn with_tls_connector_for(
+        mut self,
+        server_name: ServerName<'static>,
+        tls_connector: MonoioTlsConnector,
+    ) -> Self {
+        self.overrides.retain(|(name, _)| *name != server_name);
+        self.overrides.push((server_name, tls_connector));
+        self
+    }
+
+    /// Sends and verifies `server_name` for every connection instead of the name of the key,
+    /// e.g. to connect to an IP address and verify the certificate of a host name.
+    ///
+    /// Per server name settings are looked up with this name too.
+    #[inline]
+    pub fn with_server_name_override(mut self, server_name: Option<ServerName<'static>>) -> Self {
+        self.server_name_override = server_name;
+        self
+    }
+
+    #[inline]
+    pub fn server_name_override(&self) -> Option<&ServerName<'static>> {
+        self.server_name_override.as_ref()
+    }
+
+    // Returns the name sent and verified for connections to `server_name`.
+    #[inline]
+    fn effective_server_name<'a>(
+        &'a self,
+        server_name: &'a ServerName<'static>,
+    ) -> &'a ServerName<'static> {
+        self.server_name_override.as_ref().unwrap_or(server_name)
+    }
+
+    /// Returns the TLS connector used for connections to `server_name`.
+    pub fn tls_connector_for(&self, server_name: &ServerName<'static>) -> &MonoioTlsConnector {
+        self.overrides
+            .iter()
+            .find(|(name, _)| name == server_name)
+            .map_or(&self.tls_connector, |(_, connector)| connector)
+    }
+}
+
+/// A layer wrapping connectors with a [`TlsConnector`] of the given settings.
+#[derive(Clone, Debug)]
+pub struct TlsLayer(TlsConnector<()>);
+
+impl TlsLayer {
+    /// Uses the TLS settings of `connector`, whatever its inner connector.
+    #[inline]
+    pub fn new<C>(connector: TlsConnector<C>) -> Self {
+        Self(connector.with_inner_connector(()))
+    }
+
+    #[inline]
+    pub fn with_config(config: &TlsConfig) -> Result<Self, TlsConfigError> {
+        TlsConnector::with_config((), config).map(Self)
+    }
+
+    #[inline]
+    pub fn with_shared_config(config: &SharedTlsConfig) -> Self {
+        Self(TlsConnector::with_shared_config((), config))
+    }
+}
+
+impl<C> super::layer::ConnectorLayer<C> for TlsLayer {
+    type Connector = TlsConnector<C>;
+
+    #[inline]
+    fn layer(&self, inner: C) -> Self::Connector {
+        self.0.clone().with_inner_connector(inner)
+    }
+}
+
+/// Whether the data given to [`TlsConnector::connect_with_early_data`] was sent as TLS 1.3
+/// early data.
+#[cfg(not(feature = "native-tls"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EarlyData {
+    /// The server accepted the early data, and may have started to process it before the end
+    /// of the handshake.
+    Accepted,
+    /// The server rejected the early data without processing it, it was sent again once the
+    /// handshake completed.
+    Rejected,
+    /// No session allowed early data, the data was sent once the handshake completed.
+    NotSent,
+}
+
+#[cfg(not(feature = "native-tls"))]
+impl<C> TlsConnector<C> {
+    /// Connects to `key` and sends `data`, as 0-RTT early data when a resumed session allows it.
+    ///
+    /// Early data can be replayed by an attacker, so `data` must be safe to process twice, such
+    /// as an idempotent request. When the server rejects it, `data` is sent again once the
+    /// handshake completed, so it is always delivered once. Early data must be enabled with
+    /// [`TlsConfig::with_early_data`], otherwise this connects and then writes `data`.
+    ///
+    /// This is a raw-bytes primitive: `data` is written as given and the stream is returned for
+    /// the caller to read the reply. [`HttpConnector`](crate::http::HttpConnector) sends
+    /// idempotent HTTP/1.1 requests early through [`Connector::connect_early`] once enabled with
+    /// `set_early_data`.
+    pub async fn connect_with_early_data<T, CN>(
+        &self,
+        key: T,
+        data: &[u8],
+    ) -> Result<(TlsStream<CN>, EarlyData), TlsError>
+    where
+        T: AsRef<ServerName<'static>>,
+        for<'a> C: Connector<&'a T, Error = std::io::Error, Connection = CN>,
+        CN: AsyncReadRent + AsyncWriteRent + TransportConnMetadata<Metadata = TransportConnMeta>,
+    {
+        use monoio::io::AsyncWriteRentExt;
+
+        let (mut stream, early_data) = self.connect_early_data(key, data).await?;
+        if early_data != EarlyData::Accepted {
+            stream.write_all(data.to_vec()).await.0?;
+        }
+        Ok((stream, early_data))
+    }
+
+    /// Connects like [`connect_with_early_data`](Self::connect_with_early_data), without
+    /// sending `data` once the handshake completed when it was not accepted as early data.
+    async fn connect_early_data<T, CN>(
+        &self,
+        key: T,
+        data: &[u8],
+    ) -> Result<(TlsStream<CN>, EarlyData), TlsError>
+    where
+        T: AsRef<ServerName<'static>>,
+        for<'a> C: Connector<&'a T, Error = std::io::Error, Connection = CN>,
+        CN: AsyncReadRent + AsyncWriteRent + TransportConnMetadata<Metadata = TransportConnMeta>,
+    {
+        use monoio::io::AsyncWriteRentExt;
+
+        let server_name = self.effective_server_name(key.as_ref());
+        let config = match self.session_config(server_name) {
+            Some(config) if config.enable_early_data => config,
+            _ => return Ok((self.connect(key).await?, EarlyData::NotSent)),
+        };
+        let mut io = self.inner_connector.connect(&key).await?;
+        let meta = stream_meta(&io, server_name);
+        let mut session = rustls::ClientConnection::new(config.clone(), server_name.clone())?;
+        let sent = match session.early_data() {
+            Some(mut early_data) => std::io::Write::write(&mut early_data, data)?,
+            None => 0,
+        };
+        self.handshake(&mut io, &mut session).await?;
+        let early_data = match (sent, session.is_early_data_accepted()) {
+            (0, _) => EarlyData::NotSent,
+            (_, true) => EarlyData::Accepted,
+            (_, false) => EarlyData::Rejected,
+        };
+        let mut stream = TlsStream::from_session(io, session, config, meta);
+        // The data that did not fit in the early data allowed by the server follows.
+        if early_data == EarlyData::Accepted && sent < data.len() {
+            stream.write_all(data[sent..].to_vec()).await.0?;
+        }
+        Ok((stream, early_data))
+    }
+
+    /// Completes the handshake of `session` over `io` within the handshake timeout.
+    async fn handshake<IO: AsyncReadRent + AsyncWriteRent>(
+        &self,
+        io: &mut IO,
+        session: &mut rustls::ClientConnection,
+    ) -> Result<(), TlsError> {
+        self.complete_handshake(drive_handshake(io, session)).await
+    }
+}
+
+#[cfg(all(feature = "ktls", target_os = "linux", not(feature = "native-tls")))]
+impl<C> TlsConnector<C> {
+    /// Connects to `key` and hands the encryption of the connection to the kernel with kTLS
+    /// once the handshake completed.
+    ///
+    /// The connection stays in userspace when kTLS is not enabled with
+    /// [`TlsConfig::with_ktls`], when the kernel lacks the `tls` module or the negotiated
+    /// cipher, or when `key` uses the settings of [`with_config_for`](Self::with_config_for).
+    /// [`KtlsStream::is_kernel`](super::KtlsStream::is_kernel) tells which one is used.
+    pub async fn connect_ktls<T, CN>(&self, key: T) -> Result<super::KtlsStream<CN>, TlsError>
+    where
+        T: AsRef<ServerName<'static>>,
+        for<'a> C: Connector<&'a T, Error = std::io::Error, Connection = CN>,
+        CN: AsyncReadRent
+            + AsyncWriteRent
+            + TransportConnMetadata<Metadata = TransportConnMeta>
+            + std::os::fd::AsRawFd,
+    {
+        let server_name = self.effective_server_name(key.as_ref());
+        let config = match self.session_config(server_name) {
+            Some(config) if config.enable_secret_extraction => config,
+            _ => return Ok(super::KtlsStream::from(self.connect(key).await?)),
+        };
+        let mut io = self.inner_connector.connect(&key).await?;
+        let mut meta = stream_meta(&io, server_name);
+        let mut session = rustls::ClientConnection::new(config.clone(), server_name.clone())?;
+        self.handshake(&mut io, &mut session).await?;
+        set_session_meta(&mut meta, &session, config);
+        Ok(super::KtlsStream::offload(io, session, meta)?)
+    }
+}
+
+/// Returns the metadata of `stream`, over which a TLS connection to `server_name` is
+/// established.
+fn stream_meta<CN>(stream: &CN, server_name: &ServerName<'static>) -> TransportConnMeta
+where
+    CN: TransportConnMetadata<Metadata = TransportConnMeta>,
+{
+    let mut meta = stream.get_conn_metadata();
+    #[cfg(not(feature = "native-tls"))]
+    meta.set_server_name(Some(server_name.to_str().into()));
+    #[cfg(feature = "native-tls")]
+    meta.set_server_name(Some(server_name.0.clone()));
+    meta
+}
+
+/// Completes the handshake of `session` over `io`, sending its buffered early data along.
+///
+/// Records read past the handshake are kept by `session` and read from the stream later.
+#[cfg(not(feature = "native-tls"))]
+async fn drive_handshake<IO: AsyncReadRent + AsyncWriteRent>(
+    io: &mut IO,
+    session: &mut rustls::ClientConnection,
+) -> Result<(), TlsError> {
+    use monoio::io::AsyncWriteRentExt;
+
+    let mut buf = Vec::with_capacity(16 * 1024);
+    loop {
+        while session.wants_write() {
+            let mut records = Vec::new();
+            session.write_tls(&mut records)?;
+            io.write_all(records).await.0?;
+        }
+        if !session.is_handshaking() {
+            return Ok(());
+        }
+        buf.clear();
+        let (res, read) = io.read(buf).await;
+        buf = read;
+        if res? == 0 {
+            return Err(TlsError::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "tls handshake eof",
+            )));
+        }
+        let mut input = buf.as_slice();
+        while !input.is_empty() {
+            session.read_tls(&mut input)?;
+            if let Err(e) = session.process_new_packets() {
+                // Sends the alert describing the error.
+                let mut records = Vec::new();
+                if session.write_tls(&mut records).is_ok() {
+                    let _ = io.write_all(records).await;
+                }
+                return Err(e.into());
+            }
+        }
+    }
 }
 
 impl<C: Default> Default for TlsConnector<C> {
@@ -131,7 +650,7 @@ impl<C, T, CN> Connector<T> for TlsConnector<C>
 where
     T: AsRef<ServerName<'static>>,
     for<'a> C: Connector<&'a T, Error = std::io::Error, Connection = CN>,
-    CN: AsyncReadRent + AsyncWriteRent,
+    CN: AsyncReadRent + AsyncWriteRent + TransportConnMetadata<Metadata = TransportConnMeta>,
 {
     type Connection = TlsStream<CN>;
     type Error = TlsError;
@@ -139,15 +658,20 @@ where
     #[inline]
     async fn connect(&self, key: T) -> Result<Self::Connection, Self::Error> {
         let stream = self.inner_connector.connect(&key).await?;
-        let server_name = key.as_ref();
-        #[cfg(not(feature = "native-tls"))]
-        let tls_stream = self
-            .tls_connector
-            .connect(server_name.clone(), stream)
-            .await?;
-        #[cfg(feature = "native-tls")]
-        let tls_stream = self.tls_connector.connect(&server_name.0, stream).await?;
-        Ok(tls_stream)
+        let server_name = self.effective_server_name(key.as_ref());
+        self.handshake_over(stream, server_name).await
+    }
+
+    /// Sends `data` as early data when it is enabled with [`TlsConfig::with_early_data`] and a
+    /// resumed session allows it.
+    #[cfg(not(feature = "native-tls"))]
+    async fn connect_early(
+        &self,
+        key: T,
+        data: &[u8],
+    ) -> Result<(Self::Connection, bool), Self::Error> {
+        let (stream, early_data) = self.connect_early_data(key, data).await?;
+        Ok((stream, early_data == EarlyData::Accepted))
     }
 }
 
@@ -186,6 +710,44 @@ impl AsRef<super::UnifiedL4Addr> for UnifiedTlsAddr {
     }
 }
 
+/// The address of a TLS server listening on a Unix socket, verified as `sn`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UnixTlsAddr {
+    pub path: std::path::PathBuf,
+    pub sn: ServerName<'static>,
+}
+
+impl UnixTlsAddr {
+    #[inline]
+    pub fn new(path: impl Into<std::path::PathBuf>, sn: ServerName<'static>) -> Self {
+        Self {
+            path: path.into(),
+            sn,
+        }
+    }
+}
+
+impl Param<ServerName<'static>> for UnixTlsAddr {
+    #[inline]
+    fn param(&self) -> ServerName<'static> {
+        self.sn.clone()
+    }
+}
+
+impl AsRef<ServerName<'static>> for UnixTlsAddr {
+    #[inline]
+    fn as_ref(&self) -> &ServerName<'static> {
+        &self.sn
+    }
+}
+
+impl AsRef<std::path::Path> for UnixTlsAddr {
+    #[inline]
+    fn as_ref(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct TcpTlsAddr {
     pub host: smol_str::SmolStr,
@@ -216,6 +778,23 @@ impl ToSocketAddrs for TcpTlsAddr {
     }
 }
 
+impl super::HostPort for TcpTlsAddr {
+    #[inline]
+    fn host(&self) -> &str {
+        &self.host
+    }
+
+    #[inline]
+    fn port(&self) -> u16 {
+        self.port
+    }
+
+    #[inline]
+    fn is_tls(&self) -> bool {
+        true
+    }
+}
+
 impl TryFrom<&Uri> for TcpTlsAddr {
     type Error = FromUriError;
 
@@ -234,7 +813,8 @@ impl TryFrom<&Uri> for TcpTlsAddr {
         if !tls {
             return Err(FromUriError::UnsupportScheme);
         }
-        let host = smol_str::SmolStr::from(host);
+        // Hosts are case insensitive, lowercasing them lets every spelling share connections.
+        let host = smol_str::SmolStr::from(host.to_ascii_lowercase());
         let port = uri.port_u16().unwrap_or(default_port);
 
         let sn = {
@@ -261,6 +841,12 @@ impl TryFrom<Uri> for TcpTlsAddr {
     }
 }
 
+/// A connector routing each [`UnifiedAddr`] by the scheme it was derived from: `https` uris are
+/// connected with TLS, `http` and `http+unix` ones in plain text, over TCP or a Unix socket.
+///
+/// One [`HttpConnector`](crate::http::HttpConnector) then serves every scheme, see
+/// [`HttpConnector::build_unified`](crate::http::HttpConnector::build_unified), and picks
+/// HTTP/2 when the TLS handshake negotiates it with ALPN.
 #[derive(Debug, Clone)]
 pub struct UnifiedConnector(pub TlsConnector<super::UnifiedL4Connector>);
 
@@ -272,6 +858,24 @@ impl UnifiedConnector {
         Self(TlsConnector::new(inner_connector, tls_connector))
     }
 
+    /// Creates a `UnifiedConnector` with the TLS connector built from `config`.
+    #[inline]
+    pub fn with_config(
+        inner_connector: super::UnifiedL4Connector,
+        config: &TlsConfig,
+    ) -> Result<Self, TlsConfigError> {
+        TlsConnector::with_config(inner_connector, config).map(Self)
+    }
+
+    /// Creates a `UnifiedConnector` using a configuration built beforehand.
+    #[inline]
+    pub fn with_shared_config(
+        inner_connector: super::UnifiedL4Connector,
+        config: &SharedTlsConfig,
+    ) -> Self {
+        Self(TlsConnector::with_shared_config(inner_connector, config))
+    }
+
     #[inline]
     pub fn inner_connector(&self) -> &super::UnifiedL4Connector {
         &self.0.inner_connector
@@ -283,24 +887,29 @@ impl UnifiedConnector {
     }
 }
 
+impl Default for UnifiedConnector {
+    /// Offers `h2` and `http/1.1` with ALPN, like the default [`TlsConnector`].
+    #[inline]
+    fn default() -> Self {
+        Self(TlsConnector::default())
+    }
+}
+
 impl<'a> Connector<&'a UnifiedTlsAddr> for UnifiedConnector {
     type Connection = TlsStream<super::UnifiedL4Stream>;
     type Error = TlsError;
 
     #[inline]
     async fn connect(&self, key: &'a UnifiedTlsAddr) -> Result<Self::Connection, Self::Error> {
-        let sn = &key.sn;
+        let sn = self.0.effective_server_name(&key.sn);
         let addr = &key.addr;
         let stream = self.0.inner_connector.connect(addr).await?;
-        #[cfg(not(feature = "native-tls"))]
-        let tls_stream = self.0.tls_connector.connect(sn.clone(), stream).await?;
-        #[cfg(feature = "native-tls")]
-        let tls_stream = self.0.tls_connector.connect(&sn.0, stream).await?;
-        Ok(tls_stream)
+        self.0.handshake_over(stream, sn).await
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// The address of a server of any scheme, with the name to verify when it is reached with TLS.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct UnifiedAddr {
     pub addr: super::UnifiedL4Addr,
     pub sn: Option<ServerName<'static>>,
@@ -334,44 +943,46 @@ impl AsRef<super::UnifiedL4Addr> for UnifiedAddr {
     }
 }
 
-impl TryFrom<&Uri> for UnifiedAddr {
-    type Error = FromUriError;
+impl UnifiedAddr {
+    /// Converts `uri` into an address like `TryFrom<&Uri>` does, but resolves the host with
+    /// `resolver` instead of blocking on the system resolver.
+    pub async fn resolve_uri<R: crate::dns::Resolve>(
+        uri: &Uri,
+        resolver: &R,
+    ) -> Result<Self, FromUriError> {
+        let sn = Self::server_name(uri)?;
+        let addr = super::UnifiedL4Addr::resolve_uri(uri, resolver).await?;
+        Ok(UnifiedAddr { addr, sn })
+    }
 
-    #[inline]
-    fn try_from(uri: &Uri) -> Result<Self, Self::Error> {
+    /// Returns the server name to verify for `https` uris.
+    fn server_name(uri: &Uri) -> Result<Option<ServerName<'static>>, FromUriError> {
+        if uri.scheme() != Some(&http::uri::Scheme::HTTPS) {
+            return Ok(None);
+        }
         let host = match uri.host() {
-            Some(a) => a.to_string(),
+            Some(a) => a.to_ascii_lowercase(),
             None => return Err(FromUriError::NoAuthority),
         };
+        #[cfg(feature = "native-tls")]
+        {
+            Ok(Some(ServerName::from(host)))
+        }
+        #[cfg(not(feature = "native-tls"))]
+        {
+            Ok(Some(ServerName::try_from(host)?))
+        }
+    }
+}
 
-        let (tls, default_port) = match uri.scheme() {
-            Some(scheme) if scheme == &http::uri::Scheme::HTTP => (false, 80),
-            Some(scheme) if scheme == &http::uri::Scheme::HTTPS => (true, 443),
-            _ => (false, 0),
-        };
-        let port = uri.port_u16().unwrap_or(default_port);
-
-        let l4_addr = super::UnifiedL4Addr::Tcp(
-            (host.to_string(), port)
-                .to_socket_addrs()?
-                .next()
-                .ok_or(crate::FromUriError::NoResolve)?,
-        );
-
-        let sn = if tls {
-            #[cfg(feature = "native-tls")]
-            {
-                Some(ServerName::from(host))
-            }
-            #[cfg(not(feature = "native-tls"))]
-            {
-                Some(ServerName::try_from(host.to_string())?)
-            }
-        } else {
-            None
-        };
+impl TryFrom<&Uri> for UnifiedAddr {
+    type Error = FromUriError;
 
-        Ok(UnifiedAddr { addr: l4_addr, sn })
+    #[inline]
+    fn try_from(uri: &Uri) -> Result<Self, Self::Error> {
+        let sn = Self::server_name(uri)?;
+        let addr = super::UnifiedL4Addr::try_from(uri)?;
+        Ok(UnifiedAddr { addr, sn })
     }
 }
 
@@ -385,6 +996,7 @@ impl TryFrom<Uri> for UnifiedAddr {
 }
 
 /// A unified stream that can be either a L4 or TLS stream.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum UnifiedStream {
     L4(super::UnifiedL4Stream),
@@ -407,6 +1019,7 @@ impl<'a> Connector<&'a UnifiedAddr> for UnifiedConnector {
     async fn connect(&self, key: &'a UnifiedAddr) -> Result<Self::Connection, Self::Error> {
         match &key.sn {
             Some(sn) => {
+                let sn = self.0.effective_server_name(sn);
                 let addr = &key.addr;
                 let stream = self
                     .0
@@ -414,18 +1027,9 @@ impl<'a> Connector<&'a UnifiedAddr> for UnifiedConnector {
                     .connect(addr)
                     .await
                     .map_err(|e| UnifiedError::Tls(TlsError::from(e)))?;
-                #[cfg(not(feature = "native-tls"))]
-                let tls_stream = self
-                    .0
-                    .tls_connector
-                    .connect(sn.clone(), stream)
-                    .await
-                    .map_err(UnifiedError::Tls)?;
-                #[cfg(feature = "native-tls")]
                 let tls_stream = self
                     .0
-                    .tls_connector
-                    .connect(&sn.0, stream)
+                    .handshake_over(stream, sn)
                     .await
                     .map_err(UnifiedError::Tls)?;
                 Ok(UnifiedStream::Tls(tls_stream))
@@ -444,6 +1048,27 @@ impl<'a> Connector<&'a UnifiedAddr> for UnifiedConnector {
     }
 }
 
+impl Connector<UnifiedAddr> for UnifiedConnector {
+    type Connection = UnifiedStream;
+    type Error = UnifiedError;
+
+    #[inline]
+    async fn connect(&self, key: UnifiedAddr) -> Result<Self::Connection, Self::Error> {
+        <Self as Connector<&UnifiedAddr>>::connect(self, &key).await
+    }
+}
+
+impl TransportConnMetadata for UnifiedStream {
+    type Metadata = TransportConnMeta;
+
+    fn get_conn_metadata(&self) -> Self::Metadata {
+        match self {
+            UnifiedStream::L4(inner) => inner.get_conn_metadata(),
+            UnifiedStream::Tls(inner) => inner.get_conn_metadata(),
+        }
+    }
+}
+
 impl AsyncReadRent for UnifiedStream {
     #[inline]
     async fn read<T: monoio::buf::IoBufMut>(&mut self, buf: T) -> monoio::BufResult<usize, T> {
@@ -500,3 +1125,105 @@ impl AsyncWriteRent for UnifiedStream {
 }
 
 unsafe impl Split for UnifiedStream {}
+
+// Early data is only supported by rustls.
+#[cfg(all(test, feature = "rustls", not(feature = "native-tls")))]
+mod tests {
+    use super::*;
+
+    #[monoio::test(enable_timer = true)]
+    async fn sends_idempotent_requests_early() {
+        use std::sync::Arc;
+
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+        use monoio_http::common::body::HttpBody;
+
+        use crate::{
+            connectors::{
+                tls_config::tests::{server_config, server_name},
+                RootStore, TcpConnector, TcpTlsAddr, TlsConfig,
+            },
+            http::{response::ResponseExt, HttpConnector},
+        };
+
+        let early_data_config = || {
+            let mut config = server_config(false);
+            config.max_early_data_size = 1024;
+            Arc::new(config)
+        };
+        let resuming = early_data_config();
+        // A server with another session store cannot resume, and rejects early data.
+        let configs = [
+            resuming.clone(),
+            resuming.clone(),
+            resuming,
+            early_data_config(),
+        ];
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Answers each request with the size of the early data received.
+        let server = monoio::spawn(async move {
+            for config in configs {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let acceptor = monoio_rustls::TlsAcceptor::from(config);
+                let (io, mut session) = acceptor.accept(tcp).await.unwrap().into_parts();
+                let mut early_data = Vec::new();
+                if let Some(mut data) = session.early_data() {
+                    std::io::Read::read_to_end(&mut data, &mut early_data).unwrap();
+                }
+                let mut tls = monoio_rustls::ServerTlsStream::new(io, session);
+                let mut head = early_data.clone();
+                while !head.ends_with(b"\r\n\r\n") {
+                    let (res, buf) = tls.read(vec![0; 1024]).await;
+                    head.extend_from_slice(&buf[..res.unwrap()]);
+                }
+                let body = early_data.len().to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{body}",
+                    body.len()
+                );
+                tls.write_all(response.into_bytes()).await.0.unwrap();
+            }
+        });
+
+        let config = TlsConfig::new()
+            .with_root_store(RootStore::CustomOnly)
+            .with_root_certificates_pem(include_str!("testdata/ca.pem"))
+            .with_alpn(["http/1.1"])
+            .with_early_data(true);
+        let connector = TlsConnector::with_config(TcpConnector::default(), &config).unwrap();
+        let mut connector: HttpConnector<_, TcpTlsAddr, _> = HttpConnector::new(connector);
+        connector.set_early_data(true);
+        connector.set_max_requests_per_connection(Some(1));
+        let key = TcpTlsAddr {
+            host: "127.0.0.1".into(),
+            port: addr.port(),
+            sn: server_name("localhost"),
+        };
+        let mut early_data_sizes = Vec::new();
+        for method in [
+            http::Method::GET,
+            http::Method::POST,
+            http::Method::GET,
+            http::Method::GET,
+        ] {
+            let response = connector
+                .request(key.clone(), || {
+                    http::Request::builder()
+                        .method(method.clone())
+                        .uri("https://localhost/")
+                        .body(HttpBody::Ready(None))
+                        .unwrap()
+                })
+                .await
+                .unwrap();
+            early_data_sizes.push(response.text().await.unwrap().parse::<usize>().unwrap());
+        }
+        server.await;
+        // The first connection has no session to resume, `POST`s are not sent early, and the
+        // last server rejects early data: the request is sent again over the connection.
+        assert_eq!(early_data_sizes[..2], [0, 0]);
+        assert!(early_data_sizes[2] > 0);
+        assert_eq!(early_data_sizes[3], 0);
+    }
+}
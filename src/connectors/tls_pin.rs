@@ -0,0 +1,282 @@
+use std::sync::Arc;
+
+use base64::Engine;
+
+use super::TlsConfigError;
+
+/// The SHA-256 hash of a DER subject public key info, computed like HPKP pins.
+///
+/// Pinning the key rather than the certificate keeps pins valid across renewals that reuse the
+/// key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpkiPin([u8; 32]);
+
+impl SpkiPin {
+    #[inline]
+    pub const fn from_sha256(hash: [u8; 32]) -> Self {
+        Self(hash)
+    }
+
+    /// Parses a base64 hash, optionally prefixed with `sha256/`.
+    pub fn from_base64(pin: &str) -> Result<Self, TlsConfigError> {
+        let encoded = pin.strip_prefix("sha256/").unwrap_or(pin);
+        let hash = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| TlsConfigError::InvalidPin(e.to_string()))?;
+        let hash = hash
+            .try_into()
+            .map_err(|_| TlsConfigError::InvalidPin("expected a SHA-256 hash".to_string()))?;
+        Ok(Self(hash))
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for SpkiPin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(self.0);
+        write!(f, "sha256/{encoded}")
+    }
+}
+
+/// A server whose certificates matched none of the pins of its name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinFailure {
+    pub server_name: String,
+    /// The pins of the certificates presented by the server, leaf first.
+    pub presented: Vec<SpkiPin>,
+}
+
+#[derive(Clone)]
+struct PinFailureCallback(Arc<dyn Fn(&PinFailure) + Send + Sync>);
+
+impl std::fmt::Debug for PinFailureCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PinFailureCallback")
+    }
+}
+
+impl PartialEq for PinFailureCallback {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for PinFailureCallback {}
+
+/// The pin sets of a [`TlsConfig`](super::TlsConfig), by lowercase server name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Pins {
+    sets: Vec<(String, Vec<SpkiPin>)>,
+    on_failure: Option<PinFailureCallback>,
+}
+
+impl Pins {
+    pub(crate) fn set(&mut self, server_name: String, pins: Vec<SpkiPin>) {
+        let server_name = server_name.to_ascii_lowercase();
+        self.sets.retain(|(name, _)| *name != server_name);
+        self.sets.push((server_name, pins));
+    }
+
+    pub(crate) fn get(&self, server_name: &str) -> Option<&[SpkiPin]> {
+        self.sets
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(server_name))
+            .map(|(_, pins)| pins.as_slice())
+    }
+
+    pub(crate) fn set_on_failure(
+        &mut self,
+        callback: impl Fn(&PinFailure) + Send + Sync + 'static,
+    ) {
+        self.on_failure = Some(PinFailureCallback(Arc::new(callback)));
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+
+    /// Wraps `inner`, checking the pins of servers once `inner` accepted them.
+    #[cfg(not(feature = "native-tls"))]
+    pub(crate) fn verifier(
+        &self,
+        inner: Arc<dyn rustls::client::danger::ServerCertVerifier>,
+        provider: &rustls::crypto::CryptoProvider,
+    ) -> Result<PinningVerifier, TlsConfigError> {
+        let sha256 = sha256(provider).ok_or(TlsConfigError::Unsupported(
+            "certificate pinning without a SHA-256 cipher suite",
+        ))?;
+        Ok(PinningVerifier {
+            inner,
+            pins: self.clone(),
+            sha256,
+        })
+    }
+}
+
+/// Returns the SHA-256 implementation of `provider`, the hash of one of its cipher suites.
+#[cfg(not(feature = "native-tls"))]
+pub(crate) fn sha256(
+    provider: &rustls::crypto::CryptoProvider,
+) -> Option<&'static dyn rustls::crypto::hash::Hash> {
+    provider
+        .cipher_suites
+        .iter()
+        .filter_map(|suite| Some(suite.tls13()?.common.hash_provider))
+        .find(|hash| hash.algorithm() == rustls::crypto::hash::HashAlgorithm::SHA256)
+}
+
+#[cfg(not(feature = "native-tls"))]
+pub(crate) struct PinningVerifier {
+    inner: Arc<dyn rustls::client::danger::ServerCertVerifier>,
+    pins: Pins,
+    sha256: &'static dyn rustls::crypto::hash::Hash,
+}
+
+#[cfg(not(feature = "native-tls"))]
+impl std::fmt::Debug for PinningVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PinningVerifier")
+            .field("inner", &self.inner)
+            .field("pins", &self.pins)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(not(feature = "native-tls"))]
+impl rustls::client::danger::ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::pki_types::CertificateDer<'_>,
+        intermediates: &[rustls::pki_types::CertificateDer<'_>],
+        server_name: &rustls::pki_types::ServerName<'_>,
+        ocsp_response: &[u8],
+        now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        use rustls::{CertificateError, Error};
+
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        let server_name = server_name.to_str();
+        let Some(pins) = self.pins.get(&server_name) else {
+            return Ok(verified);
+        };
+        let mut presented = Vec::with_capacity(1 + intermediates.len());
+        for cert in std::iter::once(end_entity).chain(intermediates) {
+            let spki = subject_public_key_info(cert)
+                .ok_or(Error::InvalidCertificate(CertificateError::BadEncoding))?;
+            let hash = self.sha256.hash(spki);
+            let pin = hash
+                .as_ref()
+                .try_into()
+                .map(SpkiPin)
+                .expect("SHA-256 hashes are 32 bytes");
+            if pins.contains(&pin) {
+                return Ok(verified);
+            }
+            presented.push(pin);
+        }
+        if let Some(PinFailureCallback(callback)) = &self.pins.on_failure {
+            callback(&PinFailure {
+                server_name: server_name.into_owned(),
+                presented,
+            });
+        }
+        Err(Error::InvalidCertificate(
+            CertificateError::ApplicationVerificationFailure,
+        ))
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &rustls::pki_types::CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Splits the DER element at the start of `input` into the whole element, its content and the
+/// rest of `input`.
+#[cfg(not(feature = "native-tls"))]
+fn der_element(input: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let (_tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = match first {
+        0..=0x7f => (first as usize, rest),
+        0x81..=0x84 => {
+            let n = (first & 0x7f) as usize;
+            let (len, rest) = rest.split_at_checked(n)?;
+            (len.iter().fold(0, |acc, &b| acc << 8 | b as usize), rest)
+        }
+        _ => return None,
+    };
+    let (content, rest) = rest.split_at_checked(len)?;
+    let element = &input[..input.len() - rest.len()];
+    Some((element, content, rest))
+}
+
+/// Returns the DER subject public key info of the DER certificate `cert`.
+#[cfg(not(feature = "native-tls"))]
+fn subject_public_key_info(cert: &[u8]) -> Option<&[u8]> {
+    let (_, cert, _) = der_element(cert)?;
+    let (_, tbs, _) = der_element(cert)?;
+    let mut rest = tbs;
+    // The explicitly tagged version is optional.
+    if rest.first() == Some(&0xa0) {
+        rest = der_element(rest)?.2;
+    }
+    // Skips the serial number, signature algorithm, issuer, validity and subject.
+    for _ in 0..5 {
+        rest = der_element(rest)?.2;
+    }
+    Some(der_element(rest)?.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pins() {
+        let pin =
+            SpkiPin::from_base64("sha256/flS8kZmDPKudlCa0gFrx2RY1fTLsQZCK8D/lXj8pXlw=").unwrap();
+        assert_eq!(
+            pin.to_string(),
+            "sha256/flS8kZmDPKudlCa0gFrx2RY1fTLsQZCK8D/lXj8pXlw="
+        );
+        assert_eq!(
+            SpkiPin::from_base64("flS8kZmDPKudlCa0gFrx2RY1fTLsQZCK8D/lXj8pXlw=").unwrap(),
+            pin
+        );
+        assert!(SpkiPin::from_base64("c2hvcnQ=").is_err());
+
+        let mut pins = Pins::default();
+        pins.set("Example.COM".to_string(), vec![pin]);
+        assert_eq!(pins.get("example.com"), Some([pin].as_slice()));
+        assert_eq!(pins.get("other.example"), None);
+    }
+}
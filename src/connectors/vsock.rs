@@ -0,0 +1,178 @@
+use std::io;
+
+use monoio::{
+    io::{AsyncReadRent, AsyncWriteRent, Split},
+    net::TcpStream,
+};
+use socket2::{Domain, SockAddr, Socket, Type};
+
+use super::{socket::connect_socket, Connector, TransportConnMeta, TransportConnMetadata};
+
+/// The context id of the host, as seen from a virtual machine.
+pub const VMADDR_CID_HOST: u32 = 2;
+
+/// A `AF_VSOCK` address, the context id of a virtual machine or of the host and a port.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VsockAddr {
+    pub cid: u32,
+    pub port: u32,
+}
+
+impl VsockAddr {
+    #[inline]
+    pub const fn new(cid: u32, port: u32) -> Self {
+        Self { cid, port }
+    }
+
+    /// Returns the address of `port` on the host of the virtual machine.
+    #[inline]
+    pub const fn host(port: u32) -> Self {
+        Self::new(VMADDR_CID_HOST, port)
+    }
+}
+
+/// A connector for establishing `AF_VSOCK` connections between virtual machines and their host,
+/// as offered by Firecracker or cloud-hypervisor.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct VsockConnector;
+
+impl Connector<VsockAddr> for VsockConnector {
+    type Connection = VsockStream;
+    type Error = io::Error;
+
+    async fn connect(&self, key: VsockAddr) -> Result<Self::Connection, Self::Error> {
+        let socket = Socket::new(Domain::VSOCK, Type::STREAM.cloexec(), None)?;
+        let stream = connect_socket(socket, &SockAddr::vsock(key.cid, key.port)).await?;
+        Ok(VsockStream {
+            inner: stream,
+            addr: key,
+        })
+    }
+}
+
+/// A `AF_VSOCK` stream.
+#[derive(Debug)]
+pub struct VsockStream {
+    // The stream of any socket, its TCP specific methods must not be used.
+    inner: TcpStream,
+    addr: VsockAddr,
+}
+
+impl VsockStream {
+    /// Returns the address the stream is connected to.
+    #[inline]
+    pub fn peer_addr(&self) -> VsockAddr {
+        self.addr
+    }
+}
+
+impl TransportConnMetadata for VsockStream {
+    type Metadata = TransportConnMeta;
+
+    fn get_conn_metadata(&self) -> Self::Metadata {
+        TransportConnMeta::default()
+    }
+}
+
+impl AsyncReadRent for VsockStream {
+    #[inline]
+    async fn read<T: monoio::buf::IoBufMut>(&mut self, buf: T) -> monoio::BufResult<usize, T> {
+        self.inner.read(buf).await
+    }
+
+    #[inline]
+    async fn readv<T: monoio::buf::IoVecBufMut>(&mut self, buf: T) -> monoio::BufResult<usize, T> {
+        self.inner.readv(buf).await
+    }
+}
+
+impl AsyncWriteRent for VsockStream {
+    #[inline]
+    async fn write<T: monoio::buf::IoBuf>(&mut self, buf: T) -> monoio::BufResult<usize, T> {
+        self.inner.write(buf).await
+    }
+
+    #[inline]
+    async fn writev<T: monoio::buf::IoVecBuf>(
+        &mut self,
+        buf_vec: T,
+    ) -> monoio::BufResult<usize, T> {
+        self.inner.writev(buf_vec).await
+    }
+
+    #[inline]
+    async fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush().await
+    }
+
+    #[inline]
+    async fn shutdown(&mut self) -> std::io::Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+unsafe impl Split for VsockStream {}
+
+impl super::TcpAddrs for VsockStream {
+    #[inline]
+    fn tcp_addrs(&self) -> Option<(std::net::SocketAddr, std::net::SocketAddr)> {
+        None
+    }
+}
+
+#[cfg(feature = "hyper")]
+impl monoio::io::IntoPollIo for VsockStream {
+    type PollIo = monoio::net::tcp::stream_poll::TcpStreamPoll;
+
+    fn try_into_poll_io(self) -> Result<Self::PollIo, (io::Error, Self)> {
+        let addr = self.addr;
+        self.inner
+            .try_into_poll_io()
+            .map_err(|(e, inner)| (e, Self { inner, addr }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The context id of the local host, when the kernel offers vsock loopback.
+    const VMADDR_CID_LOCAL: u32 = 1;
+
+    #[monoio::test(enable_timer = true)]
+    async fn connects_over_loopback() {
+        let listener = match Socket::new(Domain::VSOCK, Type::STREAM, None) {
+            Ok(listener) => listener,
+            // No vsock transport in this kernel, connecting fails as well.
+            Err(_) => {
+                let addr = VsockAddr::new(VMADDR_CID_LOCAL, 5000);
+                assert!(VsockConnector.connect(addr).await.is_err());
+                return;
+            }
+        };
+        // VMADDR_PORT_ANY
+        if listener
+            .bind(&SockAddr::vsock(VMADDR_CID_LOCAL, u32::MAX))
+            .is_err()
+        {
+            return;
+        }
+        listener.listen(1).unwrap();
+        let port = listener.local_addr().unwrap().as_vsock_address().unwrap().1;
+        let server = std::thread::spawn(move || {
+            let (conn, _) = listener.accept().unwrap();
+            conn.send(b"hello").unwrap();
+        });
+
+        let addr = VsockAddr::new(VMADDR_CID_LOCAL, port);
+        let mut stream = VsockConnector.connect(addr).await.unwrap();
+        assert_eq!(stream.peer_addr(), addr);
+        let (res, buf) = stream.read(vec![0; 5]).await;
+        assert_eq!(res.unwrap(), 5);
+        assert_eq!(buf, b"hello");
+        server.join().unwrap();
+
+        let refused = VsockConnector.connect(VsockAddr::new(VMADDR_CID_LOCAL, port + 1));
+        assert!(refused.await.is_err());
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use smol_str::SmolStr;
+
+use super::{parse_ip_literal, Lookup, Resolve};
+
+const DEFAULT_MIN_TTL: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_TTL: Duration = Duration::from_secs(300);
+const DEFAULT_TTL: Duration = Duration::from_secs(30);
+const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_ENTRIES: usize = 1024;
+
+enum CachedResult {
+    Found(Vec<std::net::SocketAddr>),
+    Failed(io::ErrorKind, String),
+}
+
+struct CacheEntry {
+    result: CachedResult,
+    expires: Instant,
+}
+
+/// A resolver caching the results of another one, keyed by host and port.
+///
+/// Positive results live for the TTL reported by the inner resolver clamped to
+/// `[min_ttl, max_ttl]`, or for `default_ttl` when it reports none. Failures are cached for
+/// `negative_ttl`. Clones share the same cache.
+#[derive(Clone)]
+pub struct CachingResolver<R> {
+    inner: R,
+    cache: Rc<RefCell<HashMap<(SmolStr, u16), CacheEntry>>>,
+    min_ttl: Duration,
+    max_ttl: Duration,
+    default_ttl: Duration,
+    negative_ttl: Duration,
+    max_entries: usize,
+}
+
+impl<R> CachingResolver<R> {
+    /// Creates a new caching resolver in front of `inner`.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            cache: Rc::new(RefCell::new(HashMap::new())),
+            min_ttl: DEFAULT_MIN_TTL,
+            max_ttl: DEFAULT_MAX_TTL,
+            default_ttl: DEFAULT_TTL,
+            negative_ttl: DEFAULT_NEGATIVE_TTL,
+            max_entries: DEFAULT_MAX_ENTRIES,
+        }
+    }
+
+    /// Sets the lower bound for the TTL of positive results.
+    #[inline]
+    pub fn with_min_ttl(mut self, ttl: Duration) -> Self {
+        self.min_ttl = ttl;
+        self
+    }
+
+    /// Sets the upper bound for the TTL of positive results.
+    #[inline]
+    pub fn with_max_ttl(mut self, ttl: Duration) -> Self {
+        self.max_ttl = ttl;
+        self
+    }
+
+    /// Sets the TTL used when the inner resolver does not report one.
+    #[inline]
+    pub fn with_default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// Sets how long failed lookups are cached. `Duration::ZERO` disables negative caching.
+    #[inline]
+    pub fn with_negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = ttl;
+        self
+    }
+
+    /// Sets the maximum number of cached entries.
+    #[inline]
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Returns a reference to the inner resolver.
+    #[inline]
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns the number of cached entries, including expired ones not yet evicted.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.cache.borrow().len()
+    }
+
+    /// Returns true if nothing is cached.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.cache.borrow().is_empty()
+    }
+
+    /// Drops all cached entries.
+    #[inline]
+    pub fn clear(&self) {
+        self.cache.borrow_mut().clear();
+    }
+
+    fn insert(&self, key: (SmolStr, u16), entry: CacheEntry) {
+        let mut cache = self.cache.borrow_mut();
+        if cache.len() >= self.max_entries && !cache.contains_key(&key) {
+            let now = Instant::now();
+            cache.retain(|_, entry| entry.expires > now);
+            if cache.len() >= self.max_entries {
+                // Still full of live entries: make room by dropping the one expiring first.
+                let oldest = cache
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    cache.remove(&oldest);
+                }
+            }
+        }
+        if self.max_entries > 0 {
+            cache.insert(key, entry);
+        }
+    }
+}
+
+impl<R> std::fmt::Debug for CachingResolver<R>
+where
+    R: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CachingResolver")
+            .field("inner", &self.inner)
+            .field("entries", &self.len())
+            .field("min_ttl", &self.min_ttl)
+            .field("max_ttl", &self.max_ttl)
+            .field("default_ttl", &self.default_ttl)
+            .field("negative_ttl", &self.negative_ttl)
+            .finish()
+    }
+}
+
+impl<R: Resolve> Resolve for CachingResolver<R> {
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Lookup> {
+        if let Some(addr) = parse_ip_literal(host, port) {
+            return Ok(vec![addr].into());
+        }
+
+        let key = (SmolStr::new(host.to_ascii_lowercase()), port);
+        let now = Instant::now();
+        if let Some(entry) = self.cache.borrow().get(&key) {
+            if entry.expires > now {
+                let ttl = Some(entry.expires - now);
+                return match &entry.result {
+                    CachedResult::Found(addrs) => Ok(Lookup {
+                        addrs: addrs.clone(),
+                        ttl,
+                    }),
+                    CachedResult::Failed(kind, msg) => Err(io::Error::new(*kind, msg.clone())),
+                };
+            }
+        }
+
+        match self.inner.resolve(host, port).await {
+            Ok(lookup) => {
+                let ttl = lookup
+                    .ttl
+                    .unwrap_or(self.default_ttl)
+                    .clamp(self.min_ttl, self.max_ttl.max(self.min_ttl));
+                let entry = CacheEntry {
+                    result: CachedResult::Found(lookup.addrs.clone()),
+                    expires: Instant::now() + ttl,
+                };
+                self.insert(key, entry);
+                Ok(Lookup {
+                    addrs: lookup.addrs,
+                    ttl: Some(ttl),
+                })
+            }
+            Err(e) => {
+                if !self.negative_ttl.is_zero() {
+                    let entry = CacheEntry {
+                        result: CachedResult::Failed(e.kind(), e.to_string()),
+                        expires: Instant::now() + self.negative_ttl,
+                    };
+                    self.insert(key, entry);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, net::SocketAddr};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct CountingResolver {
+        calls: Cell<usize>,
+        ttl: Option<Duration>,
+    }
+
+    impl Resolve for CountingResolver {
+        async fn resolve(&self, host: &str, port: u16) -> io::Result<Lookup> {
+            self.calls.set(self.calls.get() + 1);
+            if host == "missing.test" {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "no such host"));
+            }
+            Ok(Lookup {
+                addrs: vec![SocketAddr::from(([127, 0, 0, 1], port))],
+                ttl: self.ttl,
+            })
+        }
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn caches_positive_and_negative_results() {
+        let resolver = CachingResolver::new(CountingResolver::default());
+        for _ in 0..3 {
+            resolver.resolve("Example.TEST", 80).await.unwrap();
+            let err = resolver.resolve("missing.test", 80).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        }
+        assert_eq!(resolver.inner().calls.get(), 2);
+
+        // A different port is a different key, and IP literals skip the cache entirely.
+        resolver.resolve("example.test", 443).await.unwrap();
+        resolver.resolve("127.0.0.1", 80).await.unwrap();
+        assert_eq!(resolver.inner().calls.get(), 3);
+        assert_eq!(resolver.len(), 3);
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn clamps_ttl_and_expires() {
+        let inner = CountingResolver {
+            ttl: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        let resolver = CachingResolver::new(inner)
+            .with_min_ttl(Duration::ZERO)
+            .with_max_ttl(Duration::from_millis(20));
+        let lookup = resolver.resolve("example.test", 80).await.unwrap();
+        assert_eq!(lookup.ttl, Some(Duration::from_millis(20)));
+
+        monoio::time::sleep(Duration::from_millis(30)).await;
+        resolver.resolve("example.test", 80).await.unwrap();
+        assert_eq!(resolver.inner().calls.get(), 2);
+    }
+}
@@ -0,0 +1,322 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use http::{header, Method, StatusCode, Uri};
+use monoio::io::{sink::Sink, AsyncReadRent, AsyncWriteRent};
+use monoio_http::{
+    common::{
+        body::{BodyExt, HttpBody},
+        error::HttpError,
+        request::Request,
+    },
+    h1::codec::ClientCodec,
+};
+
+use super::{parse_ip_literal, Lookup, Resolve};
+use crate::{connectors::Connector, http::HttpConnection, pool::Key, FromUriError};
+
+/// The media type of DNS wire format messages (RFC 8484).
+pub const DNS_MESSAGE_MIME: &str = "application/dns-message";
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+const RCODE_NXDOMAIN: u16 = 3;
+
+/// A resolver querying a DNS-over-HTTPS endpoint (RFC 8484).
+///
+/// Queries are sent as `POST` requests with an `application/dns-message` body through an
+/// [`HttpConnector`](crate::http::HttpConnector), so they share its connection pooling and
+/// HTTP/2 multiplexing. A and AAAA queries are issued concurrently, and the smallest record TTL is
+/// reported, which lets [`CachingResolver`](super::CachingResolver) cache the answers.
+///
+/// The endpoint itself is reached through the connector, so it should either be an IP literal
+/// (e.g. `https://1.1.1.1/dns-query`) or be resolved by a connector that does not depend on this
+/// resolver.
+#[derive(Clone, Debug)]
+pub struct DohResolver<C, K> {
+    connector: C,
+    endpoint: Uri,
+    key: K,
+}
+
+impl<C, K> DohResolver<C, K> {
+    /// Creates a resolver sending queries to `endpoint` through `connector`.
+    pub fn new(connector: C, endpoint: Uri) -> Result<Self, FromUriError>
+    where
+        for<'a> K: TryFrom<&'a Uri, Error = FromUriError>,
+    {
+        let key = K::try_from(&endpoint)?;
+        Ok(Self {
+            connector,
+            endpoint,
+            key,
+        })
+    }
+
+    /// Returns the endpoint queries are sent to.
+    #[inline]
+    pub fn endpoint(&self) -> &Uri {
+        &self.endpoint
+    }
+
+    /// Returns a reference to the connector.
+    #[inline]
+    pub fn connector(&self) -> &C {
+        &self.connector
+    }
+}
+
+impl<C, K, IO> DohResolver<C, K>
+where
+    C: Connector<K, Connection = HttpConnection<K, IO>>,
+    C::Error: std::error::Error + Send + Sync + 'static,
+    K: Key,
+    IO: AsyncReadRent + AsyncWriteRent,
+    ClientCodec<IO>: Sink<Request<HttpBody>>,
+    <ClientCodec<IO> as Sink<Request<HttpBody>>>::Error: std::fmt::Debug + Into<HttpError>,
+{
+    async fn query(&self, host: &str, qtype: u16, port: u16) -> io::Result<Lookup> {
+        let message = encode_query(host, qtype)?;
+        let authority = self.endpoint.authority().map(|a| a.as_str()).unwrap_or("");
+        let request = http::Request::builder()
+            .method(Method::POST)
+            .uri(self.endpoint.path_and_query().map_or("/", |pq| pq.as_str()))
+            .header(header::HOST, authority)
+            .header(header::CONTENT_TYPE, DNS_MESSAGE_MIME)
+            .header(header::ACCEPT, DNS_MESSAGE_MIME)
+            .header(header::CONTENT_LENGTH, message.len())
+            .body(HttpBody::Ready(Some(message)))
+            .map_err(io::Error::other)?;
+
+        let mut conn = self
+            .connector
+            .connect(self.key.clone())
+            .await
+            .map_err(io::Error::other)?;
+        let (res, _) = conn.send_request(request).await;
+        let response = res.map_err(io::Error::other)?;
+        if response.status() != StatusCode::OK {
+            return Err(io::Error::other(format!(
+                "doh endpoint responded with status {}",
+                response.status()
+            )));
+        }
+        let body = response
+            .into_body()
+            .bytes()
+            .await
+            .map_err(io::Error::other)?;
+        parse_response(&body, port)
+    }
+}
+
+impl<C, K, IO> Resolve for DohResolver<C, K>
+where
+    C: Connector<K, Connection = HttpConnection<K, IO>>,
+    C::Error: std::error::Error + Send + Sync + 'static,
+    K: Key,
+    IO: AsyncReadRent + AsyncWriteRent,
+    ClientCodec<IO>: Sink<Request<HttpBody>>,
+    <ClientCodec<IO> as Sink<Request<HttpBody>>>::Error: std::fmt::Debug + Into<HttpError>,
+{
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Lookup> {
+        if let Some(addr) = parse_ip_literal(host, port) {
+            return Ok(vec![addr].into());
+        }
+        let (v6, v4) = monoio::join!(
+            self.query(host, TYPE_AAAA, port),
+            self.query(host, TYPE_A, port)
+        );
+        let (v6, v4) = match (v6, v4) {
+            (Err(e), Err(_)) => return Err(e),
+            (v6, v4) => (v6.unwrap_or_default(), v4.unwrap_or_default()),
+        };
+        let ttl = v6.ttl.into_iter().chain(v4.ttl).min();
+        let addrs: Vec<_> = v6.addrs.into_iter().chain(v4.addrs).collect();
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no address resolved for {host}"),
+            ));
+        }
+        Ok(Lookup { addrs, ttl })
+    }
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Encodes a recursive query for `host` in DNS wire format.
+fn encode_query(host: &str, qtype: u16) -> io::Result<Bytes> {
+    let name = host.trim_end_matches('.');
+    if name.is_empty() || name.len() > 253 || !name.is_ascii() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid dns name",
+        ));
+    }
+    let mut buf = BytesMut::with_capacity(18 + name.len());
+    // ID 0 keeps queries cacheable by HTTP caches, as RFC 8484 recommends.
+    buf.put_u16(0);
+    // Standard query with recursion desired.
+    buf.put_u16(0x0100);
+    buf.put_u16(1);
+    buf.put_u16(0);
+    buf.put_u16(0);
+    buf.put_u16(0);
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid dns name",
+            ));
+        }
+        buf.put_u8(label.len() as u8);
+        buf.put_slice(label.as_bytes());
+    }
+    buf.put_u8(0);
+    buf.put_u16(qtype);
+    buf.put_u16(CLASS_IN);
+    Ok(buf.freeze())
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| invalid_data("truncated dns message"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        self.take(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Skips a possibly compressed domain name.
+    fn skip_name(&mut self) -> io::Result<()> {
+        loop {
+            let len = self.take(1)?[0];
+            match len {
+                0 => return Ok(()),
+                // A compression pointer ends the name.
+                l if l & 0xc0 == 0xc0 => return self.take(1).map(drop),
+                l => drop(self.take(l as usize)?),
+            }
+        }
+    }
+}
+
+/// Extracts the addresses and the smallest TTL from a DNS response.
+fn parse_response(message: &[u8], port: u16) -> io::Result<Lookup> {
+    let mut reader = Reader {
+        buf: message,
+        pos: 0,
+    };
+    let _id = reader.u16()?;
+    let flags = reader.u16()?;
+    if flags & 0x8000 == 0 {
+        return Err(invalid_data("dns message is not a response"));
+    }
+    match flags & 0x000f {
+        0 => {}
+        RCODE_NXDOMAIN => {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no such domain"));
+        }
+        _ => {
+            return Err(io::Error::other(format!(
+                "dns error code {}",
+                flags & 0x000f
+            )))
+        }
+    }
+    let questions = reader.u16()?;
+    let answers = reader.u16()?;
+    reader.take(4)?;
+    for _ in 0..questions {
+        reader.skip_name()?;
+        reader.take(4)?;
+    }
+
+    let mut lookup = Lookup::default();
+    for _ in 0..answers {
+        reader.skip_name()?;
+        let rtype = reader.u16()?;
+        let class = reader.u16()?;
+        let ttl = Duration::from_secs(reader.u32()? as u64);
+        let len = reader.u16()? as usize;
+        let data = reader.take(len)?;
+        let ip = match (rtype, class, len) {
+            (TYPE_A, CLASS_IN, 4) => IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3])),
+            (TYPE_AAAA, CLASS_IN, 16) => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            // CNAMEs and other records are skipped, the final address records are included in
+            // the answer section.
+            _ => continue,
+        };
+        lookup.addrs.push(SocketAddr::new(ip, port));
+        lookup.ttl = Some(lookup.ttl.map_or(ttl, |min| min.min(ttl)));
+    }
+    Ok(lookup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_dns_query() {
+        let query = encode_query("example.com.", TYPE_AAAA).unwrap();
+        assert_eq!(
+            &query[..],
+            b"\x00\x00\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+              \x07example\x03com\x00\x00\x1c\x00\x01"
+        );
+        assert!(encode_query("bad..name", TYPE_A).is_err());
+    }
+
+    #[test]
+    fn parse_dns_response() {
+        let mut message = BytesMut::new();
+        message.put_slice(b"\x00\x00\x81\x80\x00\x01\x00\x03\x00\x00\x00\x00");
+        message.put_slice(b"\x03www\x07example\x03com\x00\x00\x01\x00\x01");
+        // www.example.com CNAME example.com, using a compression pointer to the question.
+        message.put_slice(b"\xc0\x0c\x00\x05\x00\x01\x00\x00\x0e\x10\x00\x02\xc0\x10");
+        message.put_slice(b"\xc0\x10\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\x5d\xb8\xd7\x0e");
+        message.put_slice(b"\xc0\x10\x00\x01\x00\x01\x00\x00\x00\x1e\x00\x04\x5d\xb8\xd7\x0f");
+
+        let lookup = parse_response(&message, 443).unwrap();
+        assert_eq!(
+            lookup.addrs,
+            [
+                "93.184.215.14:443".parse().unwrap(),
+                "93.184.215.15:443".parse().unwrap()
+            ]
+        );
+        assert_eq!(lookup.ttl, Some(Duration::from_secs(30)));
+
+        let nxdomain = b"\x00\x00\x81\x83\x00\x00\x00\x00\x00\x00\x00\x00";
+        let err = parse_response(nxdomain, 443).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,174 @@
+use std::{io, net::SocketAddr, time::Instant};
+
+use hickory_resolver::{
+    config::{ResolverConfig, ResolverOpts},
+    error::{ResolveError, ResolveErrorKind},
+    lookup::SrvLookup,
+    lookup_ip::LookupIp,
+    TokioAsyncResolver,
+};
+use tokio::sync::mpsc;
+
+use super::{completion, parse_ip_literal, Completer, Lookup, Resolve};
+
+enum Query {
+    Ip(String),
+    Srv(String),
+}
+
+enum Answer {
+    Ip(LookupIp),
+    Srv(SrvLookup),
+}
+
+type Request = (Query, Completer<Result<Answer, ResolveError>>);
+
+/// A service record returned by [`HickoryResolver::lookup_srv`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+/// A resolver backed by hickory-dns, speaking DNS over UDP and TCP itself instead of going
+/// through `getaddrinfo`.
+///
+/// hickory runs on tokio, so queries are served by a background thread driving a
+/// current-thread tokio runtime. The thread exits once every clone of the resolver is dropped.
+/// TTLs from the answers are reported in [`Lookup::ttl`], so it pairs well with
+/// [`CachingResolver`](super::CachingResolver).
+#[derive(Clone, Debug)]
+pub struct HickoryResolver {
+    requests: mpsc::UnboundedSender<Request>,
+}
+
+impl HickoryResolver {
+    /// Creates a resolver configured from the system, e.g. `/etc/resolv.conf`.
+    pub fn from_system_conf() -> io::Result<Self> {
+        Self::spawn(TokioAsyncResolver::tokio_from_system_conf)
+    }
+
+    /// Creates a resolver with an explicit configuration.
+    pub fn new(config: ResolverConfig, opts: ResolverOpts) -> io::Result<Self> {
+        Self::spawn(move || Ok(TokioAsyncResolver::tokio(config, opts)))
+    }
+
+    fn spawn<F>(build: F) -> io::Result<Self>
+    where
+        F: FnOnce() -> Result<TokioAsyncResolver, ResolveError> + Send + 'static,
+    {
+        let (requests, mut rx) = mpsc::unbounded_channel::<Request>();
+        let (init_tx, init_rx) = std::sync::mpsc::sync_channel(1);
+        std::thread::Builder::new()
+            .name("monoio-transports-hickory".into())
+            .spawn(move || {
+                let rt = match tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                {
+                    Ok(rt) => rt,
+                    Err(e) => {
+                        let _ = init_tx.send(Err(e));
+                        return;
+                    }
+                };
+                rt.block_on(async move {
+                    let resolver = match build() {
+                        Ok(resolver) => resolver,
+                        Err(e) => {
+                            let _ = init_tx.send(Err(e.into()));
+                            return;
+                        }
+                    };
+                    let _ = init_tx.send(Ok(()));
+                    while let Some((query, completer)) = rx.recv().await {
+                        let resolver = resolver.clone();
+                        tokio::spawn(async move {
+                            let answer = match query {
+                                Query::Ip(host) => resolver.lookup_ip(host).await.map(Answer::Ip),
+                                Query::Srv(name) => {
+                                    resolver.srv_lookup(name).await.map(Answer::Srv)
+                                }
+                            };
+                            completer.complete(answer);
+                        });
+                    }
+                });
+            })?;
+        init_rx
+            .recv()
+            .map_err(|_| io::Error::other("hickory resolver worker exited"))??;
+        Ok(Self { requests })
+    }
+
+    async fn query(&self, query: Query) -> io::Result<Answer> {
+        let (completer, completion) = completion()?;
+        self.requests
+            .send((query, completer))
+            .map_err(|_| io::Error::other("hickory resolver worker exited"))?;
+        completion.wait().await?.map_err(into_io_error)
+    }
+
+    /// Looks up the SRV records of `name`, ordered by priority.
+    pub async fn lookup_srv(&self, name: &str) -> io::Result<Vec<SrvRecord>> {
+        let Answer::Srv(lookup) = self.query(Query::Srv(name.to_owned())).await? else {
+            unreachable!("srv query answered with another record type");
+        };
+        let mut records: Vec<_> = lookup
+            .iter()
+            .map(|srv| SrvRecord {
+                priority: srv.priority(),
+                weight: srv.weight(),
+                port: srv.port(),
+                target: srv.target().to_utf8().trim_end_matches('.').to_owned(),
+            })
+            .collect();
+        records.sort_by_key(|record| record.priority);
+        Ok(records)
+    }
+}
+
+fn into_io_error(e: ResolveError) -> io::Error {
+    match e.kind() {
+        ResolveErrorKind::NoRecordsFound { .. } => io::Error::new(io::ErrorKind::NotFound, e),
+        _ => e.into(),
+    }
+}
+
+impl Resolve for HickoryResolver {
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Lookup> {
+        if let Some(addr) = parse_ip_literal(host, port) {
+            return Ok(vec![addr].into());
+        }
+        let Answer::Ip(lookup) = self.query(Query::Ip(host.to_owned())).await? else {
+            unreachable!("ip query answered with another record type");
+        };
+        Ok(Lookup {
+            addrs: lookup.iter().map(|ip| SocketAddr::new(ip, port)).collect(),
+            ttl: Some(
+                lookup
+                    .valid_until()
+                    .saturating_duration_since(Instant::now()),
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[monoio::test(enable_timer = true)]
+    async fn resolves_ip_literals_and_hosts_file() {
+        let resolver =
+            HickoryResolver::new(ResolverConfig::default(), ResolverOpts::default()).unwrap();
+        let lookup = resolver.resolve("127.0.0.1", 80).await.unwrap();
+        assert_eq!(lookup.addrs, ["127.0.0.1:80".parse().unwrap()]);
+
+        // `localhost` is answered from the hosts file without network access.
+        let lookup = resolver.resolve("localhost", 80).await.unwrap();
+        assert!(lookup.addrs.iter().all(|addr| addr.ip().is_loopback()));
+    }
+}
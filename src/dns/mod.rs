@@ -0,0 +1,320 @@
+//! Asynchronous name resolution for the connector stack.
+//!
+//! - [`Resolve`]: The trait implemented by resolvers.
+//! - [`GaiResolver`]: The default resolver, running the system's `getaddrinfo` off the reactor
+//!   thread.
+//! - [`HickoryResolver`]: A hickory-dns backed resolver (requires the `hickory-dns` feature).
+//! - [`DohResolver`]: A DNS-over-HTTPS resolver built on the crate's own HTTP connector.
+//! - [`CachingResolver`]: A TTL aware cache in front of another resolver.
+//! - [`FamilyResolver`]: Applies an [`IpPreference`] to the results of another resolver.
+//! - [`OverrideResolver`]: Pins host names to fixed addresses, like curl's `--resolve`.
+//! - [`DnsConnector`]: A connector that resolves its key before handing the addresses to an L4
+//!   connector such as [`TcpConnector`](crate::connectors::TcpConnector).
+mod cache;
+mod doh;
+#[cfg(feature = "hickory-dns")]
+mod hickory;
+mod overrides;
+mod workers;
+
+use std::{
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+pub use cache::CachingResolver;
+pub use doh::{DohResolver, DNS_MESSAGE_MIME};
+#[cfg(feature = "hickory-dns")]
+pub use hickory::{HickoryResolver, SrvRecord};
+use monoio::io::AsyncReadRent;
+pub use overrides::OverrideResolver;
+
+use crate::connectors::{Connector, HostPort, IpPreference};
+
+/// The result of resolving a host name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Lookup {
+    /// The resolved addresses, in the order they should be tried.
+    pub addrs: Vec<SocketAddr>,
+    /// How long the result may be reused, if the resolver knows it.
+    pub ttl: Option<Duration>,
+}
+
+impl From<Vec<SocketAddr>> for Lookup {
+    #[inline]
+    fn from(addrs: Vec<SocketAddr>) -> Self {
+        Self { addrs, ttl: None }
+    }
+}
+
+/// The [`Resolve`] trait defines an interface for asynchronously resolving host names.
+///
+/// Resolvers must not block the reactor thread. Errors are reported as [`io::Error`] so they
+/// compose with the L4 connectors.
+pub trait Resolve {
+    /// Resolves `host` to the socket addresses to connect to on `port`.
+    fn resolve(&self, host: &str, port: u16) -> impl Future<Output = io::Result<Lookup>>;
+}
+
+impl<R: Resolve + ?Sized> Resolve for &R {
+    #[inline]
+    fn resolve(&self, host: &str, port: u16) -> impl Future<Output = io::Result<Lookup>> {
+        (**self).resolve(host, port)
+    }
+}
+
+impl<R: Resolve + ?Sized> Resolve for Rc<R> {
+    #[inline]
+    fn resolve(&self, host: &str, port: u16) -> impl Future<Output = io::Result<Lookup>> {
+        (**self).resolve(host, port)
+    }
+}
+
+/// Returns the address directly if `host` is an IP literal, bracketed or not.
+#[inline]
+pub(crate) fn parse_ip_literal(host: &str, port: u16) -> Option<SocketAddr> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.parse::<IpAddr>()
+        .ok()
+        .map(|ip| SocketAddr::new(ip, port))
+}
+
+/// The sending half of a one-shot result passed from a worker thread to the reactor.
+pub(crate) struct Completer<T> {
+    slot: Arc<Mutex<Option<T>>>,
+    _notify: std::os::unix::net::UnixStream,
+}
+
+impl<T> Completer<T> {
+    /// Stores the result and wakes up the waiting side.
+    pub(crate) fn complete(self, value: T) {
+        *self.slot.lock().unwrap() = Some(value);
+        // Dropping the notify socket with `self` signals the completion.
+    }
+}
+
+/// The receiving half of a one-shot result, awaited on the reactor.
+///
+/// Completion is signalled by closing one end of a socket pair, so no cross-thread waker
+/// support is required from the runtime. If the worker drops the [`Completer`] without a result,
+/// the wait fails instead of hanging.
+pub(crate) struct Completion<T> {
+    slot: Arc<Mutex<Option<T>>>,
+    wait: std::os::unix::net::UnixStream,
+}
+
+impl<T> Completion<T> {
+    pub(crate) async fn wait(self) -> io::Result<T> {
+        let mut wait = monoio::net::UnixStream::from_std(self.wait)?;
+        let (res, _) = wait.read(vec![0; 1]).await;
+        res?;
+        let value = self.slot.lock().unwrap().take();
+        value.ok_or_else(|| io::Error::other("resolver worker exited"))
+    }
+}
+
+pub(crate) fn completion<T>() -> io::Result<(Completer<T>, Completion<T>)> {
+    let (notify, wait) = std::os::unix::net::UnixStream::pair()?;
+    wait.set_nonblocking(true)?;
+    let slot = Arc::new(Mutex::new(None));
+    Ok((
+        Completer {
+            slot: slot.clone(),
+            _notify: notify,
+        },
+        Completion { slot, wait },
+    ))
+}
+
+/// The threads running the lookups of [`GaiResolver`].
+static GAI_WORKERS: workers::Workers = workers::Workers::new(4);
+
+/// Resolves names with the system resolver (`getaddrinfo`).
+///
+/// Lookups run on a pool of at most four threads shared by the process, so the reactor is never
+/// blocked; further lookups wait for one of them. The threads are started on demand and exit
+/// after being idle for a while. IP literals are returned without a thread.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct GaiResolver;
+
+impl Resolve for GaiResolver {
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Lookup> {
+        if let Some(addr) = parse_ip_literal(host, port) {
+            return Ok(vec![addr].into());
+        }
+
+        let (completer, completion) = completion()?;
+        let name = host.to_owned();
+        GAI_WORKERS.run(move || {
+            let addrs = (name.as_str(), port)
+                .to_socket_addrs()
+                .map(|addrs| addrs.collect::<Vec<_>>());
+            completer.complete(addrs);
+        })?;
+
+        let addrs = completion.wait().await??;
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no address resolved for {host}"),
+            ));
+        }
+        #[cfg(feature = "logging")]
+        tracing::debug!("resolved {host} to {addrs:?}");
+        Ok(addrs.into())
+    }
+}
+
+/// A resolver reordering or filtering the addresses of another one by family.
+///
+/// This is the resolver side counterpart of `TcpConnector::ip_preference`, useful when the
+/// results are cached or consumed by something other than
+/// [`TcpConnector`](crate::connectors::TcpConnector).
+#[derive(Default, Clone, Copy, Debug)]
+pub struct FamilyResolver<R> {
+    inner: R,
+    preference: IpPreference,
+}
+
+impl<R> FamilyResolver<R> {
+    /// Creates a new resolver applying `preference` to the results of `inner`.
+    #[inline]
+    pub fn new(inner: R, preference: IpPreference) -> Self {
+        Self { inner, preference }
+    }
+
+    /// Returns the applied preference.
+    #[inline]
+    pub fn preference(&self) -> IpPreference {
+        self.preference
+    }
+
+    /// Returns a reference to the inner resolver.
+    #[inline]
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: Resolve> Resolve for FamilyResolver<R> {
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Lookup> {
+        let lookup = self.inner.resolve(host, port).await?;
+        Ok(Lookup {
+            addrs: self.preference.apply(lookup.addrs)?,
+            ttl: lookup.ttl,
+        })
+    }
+}
+
+/// A connector that resolves its key with a [`Resolve`] implementation.
+///
+/// The key only needs to provide its host and port through [`HostPort`]. All resolved
+/// addresses are handed to the inner connector, so
+/// [`TcpConnector`](crate::connectors::TcpConnector) can race them.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use monoio_transports::{
+///     connectors::{Connector, TcpConnector},
+///     dns::DnsConnector,
+/// };
+///
+/// #[monoio::main(timer_enabled = true)]
+/// async fn main() -> std::io::Result<()> {
+///     let connector = DnsConnector::new(TcpConnector::default());
+///     let stream = connector.connect(("example.com", 80)).await?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct DnsConnector<C, R = GaiResolver> {
+    inner_connector: C,
+    resolver: R,
+}
+
+impl<C> DnsConnector<C> {
+    /// Creates a new connector resolving with [`GaiResolver`].
+    #[inline]
+    pub fn new(inner_connector: C) -> Self {
+        Self::with_resolver(inner_connector, GaiResolver)
+    }
+}
+
+impl<C, R> DnsConnector<C, R> {
+    /// Creates a new connector resolving with `resolver`.
+    #[inline]
+    pub fn with_resolver(inner_connector: C, resolver: R) -> Self {
+        Self {
+            inner_connector,
+            resolver,
+        }
+    }
+
+    /// Returns a reference to the inner connector.
+    #[inline]
+    pub fn inner_connector(&self) -> &C {
+        &self.inner_connector
+    }
+
+    /// Returns a reference to the resolver.
+    #[inline]
+    pub fn resolver(&self) -> &R {
+        &self.resolver
+    }
+}
+
+impl<C, R, T, CN> Connector<T> for DnsConnector<C, R>
+where
+    T: HostPort,
+    R: Resolve,
+    for<'a> C: Connector<&'a [SocketAddr], Error = io::Error, Connection = CN>,
+{
+    type Connection = CN;
+    type Error = io::Error;
+
+    #[inline]
+    async fn connect(&self, key: T) -> Result<Self::Connection, Self::Error> {
+        let resolve = self.resolver.resolve(key.host(), key.port());
+        #[cfg(feature = "tracing")]
+        let resolve = tracing::Instrument::instrument(
+            resolve,
+            tracing::debug_span!("dns", host = key.host(), port = key.port()),
+        );
+        let lookup = resolve.await?;
+        self.inner_connector.connect(lookup.addrs.as_slice()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use monoio::net::TcpListener;
+
+    use super::*;
+    use crate::connectors::TcpConnector;
+
+    #[monoio::test(enable_timer = true)]
+    async fn gai_resolves_without_blocking() {
+        let lookup = GaiResolver.resolve("[::1]", 80).await.unwrap();
+        assert_eq!(lookup.addrs, ["[::1]:80".parse().unwrap()]);
+
+        let lookup = GaiResolver.resolve("localhost", 80).await.unwrap();
+        assert!(lookup.addrs.iter().all(|addr| addr.ip().is_loopback()));
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn dns_connector_connects() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        monoio::spawn(async move {
+            let _conn = listener.accept().await.unwrap();
+        });
+        let connector = DnsConnector::new(TcpConnector::default());
+        let stream = connector.connect(("127.0.0.1", port)).await.unwrap();
+        assert_eq!(stream.peer_addr().unwrap().port(), port);
+    }
+}
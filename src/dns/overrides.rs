@@ -0,0 +1,104 @@
+use std::{collections::HashMap, io, net::SocketAddr};
+
+use smol_str::SmolStr;
+
+use super::{Lookup, Resolve};
+
+/// A resolver answering pinned host names from a static table and delegating everything else,
+/// like curl's `--resolve`.
+///
+/// Overridden names resolve to exactly the configured addresses, including their ports, so a
+/// host can be pointed at a test server listening on an ephemeral port. Matching is case
+/// insensitive and ignores a trailing dot.
+///
+/// # Examples
+///
+/// ```rust
+/// use monoio_transports::{
+///     connectors::TcpConnector,
+///     dns::{DnsConnector, GaiResolver, OverrideResolver},
+/// };
+///
+/// let resolver = OverrideResolver::new(GaiResolver)
+///     .resolve_to("api.example.com", "10.0.0.7:443".parse().unwrap());
+/// let connector = DnsConnector::with_resolver(TcpConnector::default(), resolver);
+/// ```
+#[derive(Clone, Debug)]
+pub struct OverrideResolver<R> {
+    inner: R,
+    overrides: HashMap<SmolStr, Vec<SocketAddr>>,
+}
+
+impl<R> OverrideResolver<R> {
+    /// Creates a new resolver with no overrides in front of `inner`.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            overrides: HashMap::new(),
+        }
+    }
+
+    /// Pins `host` to `addr`. Calling it again for the same host adds another address.
+    pub fn resolve_to(mut self, host: &str, addr: SocketAddr) -> Self {
+        self.insert(host, addr);
+        self
+    }
+
+    /// Pins `host` to `addr` in place. See [`resolve_to`](Self::resolve_to).
+    pub fn insert(&mut self, host: &str, addr: SocketAddr) {
+        self.overrides
+            .entry(normalize(host))
+            .or_default()
+            .push(addr);
+    }
+
+    /// Removes the overrides for `host`, returning the addresses it was pinned to.
+    pub fn remove(&mut self, host: &str) -> Option<Vec<SocketAddr>> {
+        self.overrides.remove(&normalize(host))
+    }
+
+    /// Returns the addresses `host` is pinned to, if any.
+    #[inline]
+    pub fn get(&self, host: &str) -> Option<&[SocketAddr]> {
+        self.overrides.get(&normalize(host)).map(Vec::as_slice)
+    }
+
+    /// Returns a reference to the inner resolver.
+    #[inline]
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+}
+
+fn normalize(host: &str) -> SmolStr {
+    SmolStr::new(host.trim_end_matches('.').to_ascii_lowercase())
+}
+
+impl<R: Resolve> Resolve for OverrideResolver<R> {
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Lookup> {
+        if let Some(addrs) = self.get(host) {
+            #[cfg(feature = "logging")]
+            tracing::debug!("resolved {host} to {addrs:?} from overrides");
+            return Ok(addrs.to_vec().into());
+        }
+        self.inner.resolve(host, port).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::GaiResolver;
+
+    #[monoio::test(enable_timer = true)]
+    async fn overrides_take_precedence() {
+        let pinned: SocketAddr = "127.0.0.2:8443".parse().unwrap();
+        let resolver = OverrideResolver::new(GaiResolver).resolve_to("Pinned.Test.", pinned);
+
+        let lookup = resolver.resolve("pinned.test", 443).await.unwrap();
+        assert_eq!(lookup.addrs, [pinned]);
+
+        let lookup = resolver.resolve("127.0.0.1", 443).await.unwrap();
+        assert_eq!(lookup.addrs, ["127.0.0.1:443".parse().unwrap()]);
+    }
+}
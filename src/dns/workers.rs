@@ -0,0 +1,124 @@
+//! A bounded pool of threads running blocking lookups off the reactor.
+use std::{
+    collections::VecDeque,
+    io,
+    panic::AssertUnwindSafe,
+    sync::{Condvar, Mutex},
+    time::Duration,
+};
+
+/// How long an idle worker waits for a job before exiting.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct State {
+    jobs: VecDeque<Job>,
+    workers: usize,
+    idle: usize,
+}
+
+/// Threads shared by the runtimes of the process, started on demand up to `max` and reused
+/// until they have been idle for [`IDLE_TIMEOUT`]. Jobs wait in a queue while all are busy.
+pub(crate) struct Workers {
+    state: Mutex<State>,
+    available: Condvar,
+    max: usize,
+}
+
+impl Workers {
+    pub(crate) const fn new(max: usize) -> Self {
+        Self {
+            state: Mutex::new(State {
+                jobs: VecDeque::new(),
+                workers: 0,
+                idle: 0,
+            }),
+            available: Condvar::new(),
+            max,
+        }
+    }
+
+    /// Runs `job` on a worker. Fails only when no worker is running and none can be started.
+    pub(crate) fn run(&'static self, job: impl FnOnce() + Send + 'static) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.jobs.push_back(Box::new(job));
+        if state.jobs.len() <= state.idle {
+            self.available.notify_one();
+            return Ok(());
+        }
+        if state.workers < self.max {
+            let spawned = std::thread::Builder::new()
+                .name("monoio-transports-dns".into())
+                .spawn(move || self.work());
+            match spawned {
+                Ok(_) => state.workers += 1,
+                // The running workers take the job once they are done with theirs.
+                Err(_) if state.workers > 0 => self.available.notify_one(),
+                Err(e) => {
+                    state.jobs.pop_back();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn work(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                drop(state);
+                // A panicking job drops its completer, failing the lookup, not the worker.
+                let _ = std::panic::catch_unwind(AssertUnwindSafe(job));
+                state = self.state.lock().unwrap();
+                continue;
+            }
+            state.idle += 1;
+            let (next, wait) = self.available.wait_timeout(state, IDLE_TIMEOUT).unwrap();
+            state = next;
+            state.idle -= 1;
+            if wait.timed_out() && state.jobs.is_empty() {
+                state.workers -= 1;
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    };
+
+    use super::*;
+
+    #[test]
+    fn bounds_and_reuses_workers() {
+        static WORKERS: Workers = Workers::new(2);
+        static RUNNING: AtomicUsize = AtomicUsize::new(0);
+        static MAX_RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..8 {
+            let tx = tx.clone();
+            WORKERS
+                .run(move || {
+                    let running = RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+                    MAX_RUNNING.fetch_max(running, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(20));
+                    RUNNING.fetch_sub(1, Ordering::SeqCst);
+                    tx.send(std::thread::current().id()).unwrap();
+                })
+                .unwrap();
+        }
+        let mut threads = (0..8).map(|_| rx.recv().unwrap()).collect::<Vec<_>>();
+        threads.sort_unstable_by_key(|id| format!("{id:?}"));
+        threads.dedup();
+        assert!(threads.len() <= 2);
+        assert!(MAX_RUNNING.load(Ordering::SeqCst) <= 2);
+        assert!(WORKERS.state.lock().unwrap().workers <= 2);
+    }
+}
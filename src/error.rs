@@ -9,13 +9,38 @@ pub enum TransportError {
     #[error("decode error {0}")]
     H1Decode(#[from] monoio_http::h1::codec::decoder::DecodeError),
     #[error("io error {0}")]
-    Io(#[from] std::io::Error),
+    Io(std::io::Error),
     #[cfg(not(feature = "native-tls"))]
     #[error("rustls error {0}")]
-    Rustls(#[from] monoio_rustls::TlsError),
+    Rustls(monoio_rustls::TlsError),
     #[cfg(feature = "native-tls")]
     #[error("native-tls error {0}")]
-    NativeTls(#[from] monoio_native_tls::TlsError),
+    NativeTls(monoio_native_tls::TlsError),
+    #[error("connect timed out")]
+    ConnectTimeout,
+    #[error("tls handshake timed out")]
+    TlsHandshakeTimeout,
+    #[error("response timed out")]
+    ResponseTimeout,
+    #[error("response headers exceed the limit of {0} bytes")]
+    HeadersTooLarge(usize),
+    #[error("response body exceeds the limit of {0} bytes")]
+    BodyTooLarge(usize),
+    #[error("too many redirects, the limit is {0}")]
+    TooManyRedirects(usize),
+    #[error("unexpected response status {0}")]
+    UnexpectedStatus(http::StatusCode),
+    #[error("request cancelled")]
+    Cancelled,
+    #[error("client is shut down")]
+    Shutdown,
+    #[error("{0:?} is not available for the request")]
+    VersionUnavailable(http::Version),
+    #[error("{0}")]
+    CircuitOpen(#[from] crate::connectors::CircuitOpen),
+    #[error("{0}")]
+    QueueTimeout(#[from] crate::pool::QueueTimeout),
+    #[cfg(feature = "serde")]
     #[error("serde_json error {0}")]
     Json(#[from] serde_json::Error),
     #[error("H2 error {0}")]
@@ -27,7 +52,7 @@ pub enum TransportError {
     #[error("Conn Manager marked this conn for close")]
     ClosePooledConnection,
     #[error("Http crate error {0}")]
-    HttpError(#[from] monoio_http::common::error::HttpError),
+    HttpError(monoio_http::common::error::HttpError),
     #[error("Codec missing from PooledConnection")]
     MissingCodec,
     #[error("Validation error {0}")]
@@ -38,14 +63,138 @@ pub enum TransportError {
 
 pub type Result<T> = std::result::Result<T, TransportError>;
 
+/// Marks an [`std::io::Error`] raised by an elapsed connector timeout, so that the phase survives
+/// the conversions between the error types of stacked connectors.
+#[derive(ThisError, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Elapsed {
+    #[error("connect timed out")]
+    Connect,
+    #[error("tls handshake timed out")]
+    TlsHandshake,
+}
+
+impl From<Elapsed> for std::io::Error {
+    #[inline]
+    fn from(elapsed: Elapsed) -> Self {
+        std::io::Error::new(std::io::ErrorKind::TimedOut, elapsed)
+    }
+}
+
+/// Marks an [`std::io::Error`] raised when a response exceeds a configured size limit, so that
+/// it can be told apart once wrapped in an `HttpError`.
+#[derive(ThisError, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LimitExceeded {
+    #[error("response headers exceed the limit of {0} bytes")]
+    Headers(usize),
+    #[error("response body exceeds the limit of {0} bytes")]
+    Body(usize),
+}
+
+impl From<LimitExceeded> for std::io::Error {
+    #[inline]
+    fn from(limit: LimitExceeded) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, limit)
+    }
+}
+
+impl From<LimitExceeded> for TransportError {
+    #[inline]
+    fn from(limit: LimitExceeded) -> Self {
+        match limit {
+            LimitExceeded::Headers(max) => TransportError::HeadersTooLarge(max),
+            LimitExceeded::Body(max) => TransportError::BodyTooLarge(max),
+        }
+    }
+}
+
+impl From<monoio_http::common::error::HttpError> for TransportError {
+    fn from(e: monoio_http::common::error::HttpError) -> Self {
+        if let monoio_http::common::error::HttpError::IOError(io) = &e {
+            if let Some(limit) = io.get_ref().and_then(|i| i.downcast_ref::<LimitExceeded>()) {
+                return (*limit).into();
+            }
+        }
+        TransportError::HttpError(e)
+    }
+}
+
+impl From<std::io::Error> for TransportError {
+    fn from(e: std::io::Error) -> Self {
+        let Some(inner) = e.get_ref() else {
+            return TransportError::Io(e);
+        };
+        if let Some(open) = inner.downcast_ref::<crate::connectors::CircuitOpen>() {
+            return TransportError::CircuitOpen(*open);
+        }
+        if let Some(timeout) = inner.downcast_ref::<crate::pool::QueueTimeout>() {
+            return TransportError::QueueTimeout(*timeout);
+        }
+        if let Some(limit) = inner.downcast_ref::<LimitExceeded>() {
+            return (*limit).into();
+        }
+        match inner.downcast_ref::<Elapsed>() {
+            Some(Elapsed::Connect) => TransportError::ConnectTimeout,
+            Some(Elapsed::TlsHandshake) => TransportError::TlsHandshakeTimeout,
+            None => TransportError::Io(e),
+        }
+    }
+}
+
+impl From<crate::connectors::UnifiedError> for TransportError {
+    fn from(e: crate::connectors::UnifiedError) -> Self {
+        match e {
+            crate::connectors::UnifiedError::L4(e) => e.into(),
+            crate::connectors::UnifiedError::Tls(e) => e.into(),
+        }
+    }
+}
+
+#[cfg(not(feature = "native-tls"))]
+impl From<monoio_rustls::TlsError> for TransportError {
+    fn from(e: monoio_rustls::TlsError) -> Self {
+        match e {
+            monoio_rustls::TlsError::Io(e) => e.into(),
+            e => TransportError::Rustls(e),
+        }
+    }
+}
+
+#[cfg(feature = "native-tls")]
+impl From<monoio_native_tls::TlsError> for TransportError {
+    fn from(e: monoio_native_tls::TlsError) -> Self {
+        match e {
+            monoio_native_tls::TlsError::Io(e) => e.into(),
+            e => TransportError::NativeTls(e),
+        }
+    }
+}
+
+impl TransportError {
+    /// Returns true if the error was caused by an elapsed timeout, in any phase.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            TransportError::ConnectTimeout
+            | TransportError::TlsHandshakeTimeout
+            | TransportError::ResponseTimeout => true,
+            TransportError::Io(e) => e.kind() == std::io::ErrorKind::TimedOut,
+            _ => false,
+        }
+    }
+}
+
 #[derive(ThisError, Debug)]
 pub enum FromUriError {
+    #[cfg(feature = "rustls")]
     #[error("Invalid dns name {0}")]
     InvalidDnsName(#[from] rustls::pki_types::InvalidDnsNameError),
     #[error("Scheme not supported")]
     UnsupportScheme,
+    #[error("Invalid uri {0}")]
+    InvalidUri(#[from] http::uri::InvalidUri),
     #[error("Missing authority in uri")]
     NoAuthority,
+    #[error("Invalid unix socket path in uri")]
+    InvalidUnixPath,
     #[error("resolve error {0}")]
     Resolve(#[from] std::io::Error),
     #[error("no resolve result")]
@@ -0,0 +1,146 @@
+//! Cancelling requests in flight.
+//!
+//! An [`AbortHandle`] is given to
+//! [`HttpConnector::request_abortable`](super::HttpConnector::request_abortable) or
+//! [`HttpConnection::send_request_abortable`](super::HttpConnection::send_request_abortable),
+//! and a clone of it kept elsewhere, e.g. by a UI or a supervising task, cancels the request with
+//! [`AbortHandle::abort`]. The request then fails with [`TransportError::Cancelled`] as soon as
+//! the runtime polls it again, whatever it was waiting for.
+//!
+//! An HTTP/1.1 connection whose request was cancelled is left in an unknown state, so it is
+//! closed instead of returning to the pool. Over HTTP/2 the stream of the request is reset and
+//! the connection stays open for the other streams multiplexed on it.
+//!
+//! [`TransportError::Cancelled`]: crate::TransportError::Cancelled
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    future::{poll_fn, Future},
+    pin::pin,
+    rc::Rc,
+    task::{Poll, Waker},
+};
+
+#[derive(Debug, Default)]
+struct State {
+    aborted: Cell<bool>,
+    // The tasks waiting for the abort, by the slot of each wait.
+    wakers: RefCell<HashMap<u64, Waker>>,
+    next_slot: Cell<u64>,
+}
+
+/// Removes the waker of a wait once it ends, so a handle reused across requests does not
+/// accumulate them.
+struct Slot<'a> {
+    state: &'a State,
+    id: u64,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.state.wakers.borrow_mut().remove(&self.id);
+    }
+}
+
+/// A handle cancelling the requests it is given to. Clones share the same state, so aborting
+/// any of them cancels all those requests. It cannot be sent to other threads.
+#[derive(Debug, Clone, Default)]
+pub struct AbortHandle {
+    state: Rc<State>,
+}
+
+impl AbortHandle {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the requests in flight with this handle, and the ones sent with it later.
+    pub fn abort(&self) {
+        self.state.aborted.set(true);
+        for waker in self.state.wakers.take().into_values() {
+            waker.wake();
+        }
+    }
+
+    #[inline]
+    pub fn is_aborted(&self) -> bool {
+        self.state.aborted.get()
+    }
+
+    /// Waits until the handle is aborted.
+    pub async fn aborted(&self) {
+        let id = self.state.next_slot.get();
+        self.state.next_slot.set(id + 1);
+        let slot = Slot {
+            state: &self.state,
+            id,
+        };
+        poll_fn(|cx| {
+            if self.is_aborted() {
+                return Poll::Ready(());
+            }
+            let mut wakers = slot.state.wakers.borrow_mut();
+            match wakers.get_mut(&slot.id) {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                Some(waker) => waker.clone_from(cx.waker()),
+                None => {
+                    wakers.insert(slot.id, cx.waker().clone());
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Runs `future` until it completes, or drops it once the handle is aborted and returns
+    /// `None`.
+    pub async fn run<F: Future>(&self, future: F) -> Option<F::Output> {
+        let mut future = pin!(future);
+        let mut aborted = pin!(self.aborted());
+        poll_fn(|cx| {
+            if aborted.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(None);
+            }
+            future.as_mut().poll(cx).map(Some)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[monoio::test(enable_timer = true)]
+    async fn aborts_pending_futures() {
+        let handle = AbortHandle::new();
+        assert_eq!(handle.run(async { 1 }).await, Some(1));
+
+        let aborter = handle.clone();
+        monoio::spawn(async move {
+            monoio::time::sleep(Duration::from_millis(10)).await;
+            aborter.abort();
+        });
+        let slow = monoio::time::sleep(Duration::from_secs(10));
+        assert_eq!(handle.run(slow).await, None);
+        assert!(handle.is_aborted());
+        // Aborted handles cancel futures right away.
+        assert_eq!(handle.run(async { 1 }).await, None);
+
+        // Completed runs no longer wait for the handle.
+        let handle = AbortHandle::new();
+        for _ in 0..3 {
+            let handle = handle.clone();
+            monoio::spawn(async move {
+                let sleep = monoio::time::sleep(Duration::from_millis(1));
+                handle.run(sleep).await
+            })
+            .await
+            .unwrap();
+        }
+        assert!(handle.state.wakers.borrow().is_empty());
+    }
+}
@@ -0,0 +1,183 @@
+//! `Authorization` headers.
+//!
+//! [`Credentials`] encodes Basic and Bearer credentials. They can be set on a single request
+//! with [`AuthRequestExt`], or on every request sent through
+//! [`HttpConnector::request`](super::HttpConnector::request) with
+//! [`HttpConnector::set_default_auth`](super::HttpConnector::set_default_auth). Header values are
+//! marked sensitive so they are not logged.
+use base64::Engine;
+use http::{header, HeaderValue};
+
+/// Credentials sent in the `Authorization` header.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Credentials {
+    /// RFC 7617 Basic credentials, a password of `None` is sent as empty.
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    /// RFC 6750 Bearer token.
+    Bearer(String),
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Credentials::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .finish_non_exhaustive(),
+            Credentials::Bearer(_) => f.write_str("Bearer(..)"),
+        }
+    }
+}
+
+impl Credentials {
+    #[inline]
+    pub fn basic(username: impl Into<String>, password: Option<impl Into<String>>) -> Self {
+        Credentials::Basic {
+            username: username.into(),
+            password: password.map(Into::into),
+        }
+    }
+
+    #[inline]
+    pub fn bearer(token: impl Into<String>) -> Self {
+        Credentials::Bearer(token.into())
+    }
+
+    /// Encodes the credentials as an `Authorization` value.
+    ///
+    /// Fails if a Bearer token contains characters not allowed in a header.
+    pub fn header_value(&self) -> Result<HeaderValue, http::Error> {
+        let mut value = HeaderValue::try_from(self.encode())?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+
+    fn encode(&self) -> String {
+        match self {
+            Credentials::Basic { username, password } => {
+                let token = base64::engine::general_purpose::STANDARD.encode(format!(
+                    "{username}:{}",
+                    password.as_deref().unwrap_or_default()
+                ));
+                format!("Basic {token}")
+            }
+            Credentials::Bearer(token) => format!("Bearer {token}"),
+        }
+    }
+}
+
+/// Sets credentials on request builders.
+pub trait AuthRequestExt: Sized {
+    /// Sets Basic credentials, replacing any previous `Authorization` header.
+    fn basic_auth(self, username: &str, password: Option<&str>) -> Self;
+
+    /// Sets a Bearer token, replacing any previous `Authorization` header.
+    fn bearer_auth(self, token: &str) -> Self;
+}
+
+impl AuthRequestExt for http::request::Builder {
+    fn basic_auth(self, username: &str, password: Option<&str>) -> Self {
+        set_credentials(self, Credentials::basic(username, password))
+    }
+
+    fn bearer_auth(self, token: &str) -> Self {
+        set_credentials(self, Credentials::bearer(token))
+    }
+}
+
+fn set_credentials(
+    mut builder: http::request::Builder,
+    credentials: Credentials,
+) -> http::request::Builder {
+    if let Some(headers) = builder.headers_mut() {
+        headers.remove(header::AUTHORIZATION);
+    }
+    // An invalid value is kept as the error of the builder.
+    let mut builder = builder.header(header::AUTHORIZATION, credentials.encode());
+    if let Some(value) = builder
+        .headers_mut()
+        .and_then(|headers| headers.get_mut(header::AUTHORIZATION))
+    {
+        value.set_sensitive(true);
+    }
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+    use monoio_http::{common::body::HttpBody, h1::payload::Payload};
+
+    use super::*;
+    use crate::{connectors::TcpConnector, http::HttpConnector};
+
+    #[test]
+    fn encodes_credentials() {
+        let request = http::Request::get("/")
+            .basic_auth("Aladdin", Some("open sesame"))
+            .body(())
+            .unwrap();
+        let value = &request.headers()[header::AUTHORIZATION];
+        assert_eq!(value, "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==");
+        assert!(value.is_sensitive());
+
+        let request = http::Request::get("/")
+            .bearer_auth("abc.def")
+            .body(())
+            .unwrap();
+        assert_eq!(request.headers()[header::AUTHORIZATION], "Bearer abc.def");
+        assert!(http::Request::get("/")
+            .bearer_auth("bad\ntoken")
+            .body(())
+            .is_err());
+        assert_eq!(format!("{:?}", Credentials::bearer("secret")), "Bearer(..)");
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn applies_default_credentials() {
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = monoio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            for _ in 0..2 {
+                let (res, buf) = conn.read(vec![0; 1024]).await;
+                let n = res.unwrap();
+                received.push(String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase());
+                conn.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n".as_slice())
+                    .await
+                    .0
+                    .unwrap();
+            }
+            received
+        });
+
+        let mut connector: HttpConnector<TcpConnector, SocketAddr, _> =
+            HttpConnector::build_tcp_http1_only();
+        connector
+            .set_default_auth(Some(Credentials::basic("user", None::<String>)))
+            .unwrap();
+        let request = |token: Option<&str>| {
+            let builder = http::Request::get("/").header(header::HOST, "localhost");
+            let builder = match token {
+                Some(token) => builder.bearer_auth(token),
+                None => builder,
+            };
+            builder.body(HttpBody::H1(Payload::None)).unwrap()
+        };
+        connector.request(addr, || request(None)).await.unwrap();
+        connector
+            .request(addr, || request(Some("mine")))
+            .await
+            .unwrap();
+
+        let received = server.await;
+        assert!(received[0].contains("authorization: basic dxnlcjo=\r\n"));
+        assert!(received[1].contains("authorization: bearer mine\r\n"));
+    }
+}
@@ -0,0 +1,736 @@
+//! Bodies produced or consumed incrementally.
+//!
+//! - [`IntoBody`]: Conversion of the body of a request sent with
+//!   [`HttpConnector::request`](super::HttpConnector::request), so a `Request<Bytes>`,
+//!   `Request<String>` or `Request<&'static str>` is sent as is. Responses are received as
+//!   [`HttpBody`] and mapped into other body types with [`http::Response::map`], e.g.
+//!   `response.map(BodyReader::new)`.
+//! - [`StreamBody`]: A body pulling its chunks from a [`Stream`], sent with chunked transfer
+//!   encoding over HTTP/1.1 and as data frames over HTTP/2.
+//! - [`FileBody`]: A file, or a range of it, read with io_uring in large chunks that are written to
+//!   the connection without being copied again.
+//! - [`channel`]: A bounded channel whose receiving half is a [`StreamBody`], so a producer task
+//!   can push an upload while the request is being sent. Once `capacity` chunks are buffered,
+//!   [`BodySender::send`] waits for the connection to write them, which bounds memory use.
+//! - [`BodyReader`]: An [`AsyncReadRent`] adapter over any body, to pipe a response returned by
+//!   [`HttpConnection::send_request_streaming`](super::HttpConnection::send_request_streaming) into
+//!   a file or socket without buffering it.
+//! - [`ThrottledBody`]: A body paced by a [`RateLimiter`], to throttle a single upload, or a
+//!   download once the response is mapped into it, whatever the connections it shares.
+use std::{io, path::Path};
+
+use bytes::{Bytes, BytesMut};
+use local_sync::mpsc::bounded;
+use monoio::{
+    buf::{IoBufMut, IoVecBufMut, IoVecWrapperMut},
+    fs::File,
+    io::{stream::Stream, AsyncReadRent},
+    BufResult,
+};
+use monoio_http::{
+    common::{
+        body::{Body, HttpBody, StreamHint},
+        error::HttpError,
+    },
+    h1::payload::Payload,
+};
+
+use crate::connectors::RateLimiter;
+
+/// Conversion into a request body.
+///
+/// Implemented by the body types of this crate and of `monoio-http`, and by byte buffers sent
+/// with a `Content-Length`.
+pub trait IntoBody {
+    /// The body sent.
+    type Body: Body<Data = Bytes, Error = HttpError>;
+
+    fn into_body(self) -> Self::Body;
+}
+
+impl IntoBody for HttpBody {
+    type Body = Self;
+
+    #[inline]
+    fn into_body(self) -> Self::Body {
+        self
+    }
+}
+
+impl IntoBody for Payload {
+    type Body = HttpBody;
+
+    #[inline]
+    fn into_body(self) -> Self::Body {
+        HttpBody::H1(self)
+    }
+}
+
+impl IntoBody for () {
+    type Body = HttpBody;
+
+    #[inline]
+    fn into_body(self) -> Self::Body {
+        HttpBody::Ready(None)
+    }
+}
+
+macro_rules! bytes_into_body {
+    ($($ty:ty),*) => {
+        $(impl IntoBody for $ty {
+            type Body = HttpBody;
+
+            #[inline]
+            fn into_body(self) -> Self::Body {
+                HttpBody::Ready(Some(Bytes::from(self)))
+            }
+        })*
+    };
+}
+
+bytes_into_body!(Bytes, Vec<u8>, String, &'static str, &'static [u8]);
+
+impl<S, E> IntoBody for StreamBody<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    HttpError: From<E>,
+{
+    type Body = Self;
+
+    #[inline]
+    fn into_body(self) -> Self::Body {
+        self
+    }
+}
+
+/// A body streaming the chunks yielded by a [`Stream`].
+///
+/// The body ends when the stream does, and a stream error aborts the request. A body created with
+/// [`with_length`](Self::with_length) fails the request if the stream yields a different number of
+/// bytes. The `Content-Length` header is left to the caller: HTTP/2 sends it as is, while the
+/// HTTP/1.1 codec always frames streamed bodies as chunked and drops it.
+#[derive(Debug)]
+pub struct StreamBody<S> {
+    stream: S,
+    // The bytes still expected when the length is known.
+    remaining: Option<u64>,
+    finished: bool,
+}
+
+impl<S> StreamBody<S> {
+    #[inline]
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            remaining: None,
+            finished: false,
+        }
+    }
+
+    /// Declares the total length of the body, which the stream must produce exactly.
+    #[inline]
+    pub fn with_length(mut self, length: u64) -> Self {
+        self.remaining = Some(length);
+        self
+    }
+
+    /// Returns the declared length of the body minus what was already read, if declared.
+    #[inline]
+    pub fn remaining(&self) -> Option<u64> {
+        self.remaining
+    }
+}
+
+impl<S, E> Body for StreamBody<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    HttpError: From<E>,
+{
+    type Data = Bytes;
+    type Error = HttpError;
+
+    async fn next_data(&mut self) -> Option<Result<Self::Data, Self::Error>> {
+        if self.finished {
+            return None;
+        }
+        let chunk = match self.stream.next().await {
+            Some(Ok(chunk)) => chunk,
+            Some(Err(e)) => {
+                self.finished = true;
+                return Some(Err(e.into()));
+            }
+            None => {
+                self.finished = true;
+                return match self.remaining {
+                    Some(n) if n > 0 => Some(Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("body ended {n} bytes before its declared length"),
+                    )
+                    .into())),
+                    _ => None,
+                };
+            }
+        };
+        if let Some(remaining) = self.remaining.as_mut() {
+            match remaining.checked_sub(chunk.len() as u64) {
+                Some(left) => *remaining = left,
+                None => {
+                    self.finished = true;
+                    return Some(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "body is longer than its declared length",
+                    )
+                    .into()));
+                }
+            }
+        }
+        Some(Ok(chunk))
+    }
+
+    #[inline]
+    fn stream_hint(&self) -> StreamHint {
+        StreamHint::Stream
+    }
+}
+
+/// The sending half of a body [`channel`].
+///
+/// Dropping it ends the body.
+pub struct BodySender {
+    tx: bounded::Tx<Result<Bytes, HttpError>>,
+}
+
+impl std::fmt::Debug for BodySender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BodySender").finish_non_exhaustive()
+    }
+}
+
+impl BodySender {
+    /// Sends a chunk, waiting while the channel is full.
+    ///
+    /// Fails once the request was sent or dropped.
+    pub async fn send(&self, chunk: Bytes) -> io::Result<()> {
+        self.tx
+            .send(Ok(chunk))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "request body dropped"))
+    }
+
+    /// Aborts the body, failing the request with `err`.
+    pub async fn abort(self, err: io::Error) {
+        let _ = self.tx.send(Err(err.into())).await;
+    }
+}
+
+/// The receiving half of a body [`channel`].
+pub struct BodyReceiver {
+    rx: bounded::Rx<Result<Bytes, HttpError>>,
+}
+
+impl std::fmt::Debug for BodyReceiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BodyReceiver").finish_non_exhaustive()
+    }
+}
+
+impl Stream for BodyReceiver {
+    type Item = Result<Bytes, HttpError>;
+
+    #[inline]
+    async fn next(&mut self) -> Option<Self::Item> {
+        self.rx.recv().await
+    }
+}
+
+/// The size of the chunks read by a [`FileBody`] by default.
+const FILE_CHUNK_SIZE: usize = 256 * 1024;
+
+/// A body reading a file from disk.
+///
+/// The file is read with positioned io_uring reads into a fresh buffer per chunk, and the codecs
+/// write chunks of more than 8 KiB straight from that buffer, so the data is copied once from the
+/// page cache and never re-buffered. `splice` and `sendfile` are not used: bodies pass through the
+/// HTTP codecs, and TLS connections need the data in user space anyway.
+///
+/// A body whose length is known, as with [`open`](Self::open) or [`with_range`](Self::with_range),
+/// that fits in one chunk is sent with a `Content-Length`; larger ones and files of unknown length
+/// are chunked over HTTP/1.1 like a [`StreamBody`]. A file shorter than its declared length fails
+/// the request.
+#[derive(Debug)]
+pub struct FileBody {
+    file: File,
+    offset: u64,
+    // The bytes still to read when the length is known.
+    remaining: Option<u64>,
+    chunk_size: usize,
+    started: bool,
+    finished: bool,
+}
+
+impl FileBody {
+    /// Reads `file` from its beginning to its end, its length being unknown.
+    #[inline]
+    pub fn new(file: File) -> Self {
+        Self {
+            file,
+            offset: 0,
+            remaining: None,
+            chunk_size: FILE_CHUNK_SIZE,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Opens the file at `path` to read it whole, with its current length.
+    pub async fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path).await?;
+        let len = file.metadata().await?.len();
+        Ok(Self::new(file).with_range(0, len))
+    }
+
+    /// Reads `len` bytes of the file starting at `offset` instead.
+    #[inline]
+    pub fn with_range(mut self, offset: u64, len: u64) -> Self {
+        self.offset = offset;
+        self.remaining = Some(len);
+        self
+    }
+
+    /// Sets the size of the chunks read from the file, 256 KiB by default.
+    #[inline]
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Returns the bytes still to be read when the length is known.
+    #[inline]
+    pub fn remaining(&self) -> Option<u64> {
+        self.remaining
+    }
+}
+
+impl IntoBody for FileBody {
+    type Body = Self;
+
+    #[inline]
+    fn into_body(self) -> Self::Body {
+        self
+    }
+}
+
+impl Body for FileBody {
+    type Data = Bytes;
+    type Error = HttpError;
+
+    async fn next_data(&mut self) -> Option<Result<Self::Data, Self::Error>> {
+        if self.finished {
+            return None;
+        }
+        // A fixed body is returned at once.
+        let fixed = self.stream_hint() == StreamHint::Fixed;
+        self.started = true;
+        let want = match self.remaining {
+            Some(n) => n.min(self.chunk_size as u64) as usize,
+            None => self.chunk_size,
+        };
+        if want == 0 {
+            self.finished = true;
+            return fixed.then(|| Ok(Bytes::new()));
+        }
+        let buf = BytesMut::with_capacity(want);
+        let (res, buf) = if fixed {
+            let (res, buf) = self.file.read_exact_at(buf, self.offset).await;
+            (res.map(|_| want), buf)
+        } else {
+            self.file.read_at(buf, self.offset).await
+        };
+        match res {
+            Ok(0) if self.remaining.is_none() => {
+                self.finished = true;
+                None
+            }
+            Ok(0) => {
+                self.finished = true;
+                Some(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "file ended before the end of the body",
+                )
+                .into()))
+            }
+            Ok(n) => {
+                self.offset += n as u64;
+                if let Some(remaining) = self.remaining.as_mut() {
+                    *remaining -= n as u64;
+                }
+                self.finished = fixed;
+                Some(Ok(buf.freeze()))
+            }
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e.into()))
+            }
+        }
+    }
+
+    fn stream_hint(&self) -> StreamHint {
+        match self.remaining {
+            Some(n) if !self.started && n <= self.chunk_size as u64 => StreamHint::Fixed,
+            _ => StreamHint::Stream,
+        }
+    }
+}
+
+/// Creates a body fed through a channel buffering up to `capacity` chunks.
+pub fn channel(capacity: usize) -> (BodySender, StreamBody<BodyReceiver>) {
+    let (tx, rx) = bounded::channel(capacity.max(1));
+    (BodySender { tx }, StreamBody::new(BodyReceiver { rx }))
+}
+
+/// Reads a body as a byte stream.
+///
+/// Chunks are copied into the read buffers as they arrive, a chunk larger than the buffer is
+/// kept for the next reads. A body error is returned as an [`io::Error`] and ends the reader.
+#[derive(Debug)]
+pub struct BodyReader<B> {
+    body: B,
+    pending: Bytes,
+    finished: bool,
+}
+
+impl<B> BodyReader<B> {
+    #[inline]
+    pub fn new(body: B) -> Self {
+        Self {
+            body,
+            pending: Bytes::new(),
+            finished: false,
+        }
+    }
+
+    /// Returns the body, dropping any data read from it but not yet returned.
+    #[inline]
+    pub fn into_inner(self) -> B {
+        self.body
+    }
+}
+
+impl<B> AsyncReadRent for BodyReader<B>
+where
+    B: Body<Data = Bytes>,
+    B::Error: Into<HttpError>,
+{
+    async fn read<T: IoBufMut>(&mut self, mut buf: T) -> BufResult<usize, T> {
+        while self.pending.is_empty() && !self.finished {
+            match self.body.next_data().await {
+                Some(Ok(chunk)) => self.pending = chunk,
+                Some(Err(e)) => {
+                    self.finished = true;
+                    return (Err(io::Error::other(e.into())), buf);
+                }
+                None => self.finished = true,
+            }
+        }
+        let n = self.pending.len().min(buf.bytes_total());
+        // Safety: `n` is within both the remaining capacity of `buf` and the pending chunk.
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.pending.as_ptr(), buf.write_ptr(), n);
+            buf.set_init(n);
+        }
+        let _ = self.pending.split_to(n);
+        (Ok(n), buf)
+    }
+
+    async fn readv<T: IoVecBufMut>(&mut self, mut buf: T) -> BufResult<usize, T> {
+        let slice = match IoVecWrapperMut::new(buf) {
+            Ok(slice) => slice,
+            Err(buf) => return (Ok(0), buf),
+        };
+        let (result, slice) = self.read(slice).await;
+        buf = slice.into_inner();
+        if let Ok(n) = result {
+            unsafe { buf.set_init(n) };
+        }
+        (result, buf)
+    }
+}
+
+/// A body yielding the data of another one at the rate allowed by a [`RateLimiter`].
+///
+/// Streamed chunks are split into pieces of at most the burst size of the limiter, each yielded
+/// once the limiter allowed its bytes, so a throttled upload does not send large chunks at full
+/// speed. A body of a fixed length waits for all of its bytes at once. Requires the monoio timer
+/// driver.
+#[derive(Debug)]
+pub struct ThrottledBody<B> {
+    body: B,
+    limiter: RateLimiter,
+    pending: Bytes,
+}
+
+impl<B> ThrottledBody<B> {
+    #[inline]
+    pub fn new(body: B, limiter: RateLimiter) -> Self {
+        Self {
+            body,
+            limiter,
+            pending: Bytes::new(),
+        }
+    }
+
+    /// Returns the body, dropping any data read from it but not yet returned.
+    #[inline]
+    pub fn into_inner(self) -> B {
+        self.body
+    }
+}
+
+impl<B: IntoBody> IntoBody for ThrottledBody<B> {
+    type Body = ThrottledBody<B::Body>;
+
+    #[inline]
+    fn into_body(self) -> Self::Body {
+        ThrottledBody {
+            body: self.body.into_body(),
+            limiter: self.limiter,
+            pending: self.pending,
+        }
+    }
+}
+
+impl<B: Body<Data = Bytes>> Body for ThrottledBody<B> {
+    type Data = Bytes;
+    type Error = B::Error;
+
+    async fn next_data(&mut self) -> Option<Result<Self::Data, Self::Error>> {
+        if self.pending.is_empty() {
+            match self.body.next_data().await? {
+                Ok(chunk) => self.pending = chunk,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        let n = match self.body.stream_hint() {
+            StreamHint::Stream => self.pending.len().min(self.limiter.burst() as usize),
+            _ => self.pending.len(),
+        };
+        self.limiter.consume(n).await;
+        Some(Ok(self.pending.split_to(n)))
+    }
+
+    #[inline]
+    fn stream_hint(&self) -> StreamHint {
+        self.body.stream_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, net::SocketAddr, rc::Rc};
+
+    use http::header;
+    use monoio::io::AsyncWriteRentExt;
+    use monoio_http::{common::body::HttpBody, h1::payload::Payload};
+
+    use super::*;
+    use crate::{
+        connectors::{Connector, TcpConnector},
+        http::HttpConnector,
+    };
+
+    #[monoio::test(enable_timer = true)]
+    async fn streams_chunked_upload() {
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = monoio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            while !received.ends_with(b"0\r\n\r\n") {
+                let (res, buf) = conn.read(Vec::with_capacity(1024)).await;
+                assert!(res.unwrap() > 0);
+                received.extend_from_slice(&buf);
+            }
+            let _ = conn
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n".as_slice())
+                .await;
+            String::from_utf8(received).unwrap()
+        });
+
+        let (sender, body) = channel(1);
+        monoio::spawn(async move {
+            for chunk in ["hello", " ", "world"] {
+                sender
+                    .send(Bytes::from_static(chunk.as_bytes()))
+                    .await
+                    .unwrap();
+            }
+        });
+        let connector: HttpConnector<TcpConnector, SocketAddr, _> =
+            HttpConnector::build_tcp_http1_only();
+        let mut conn = connector.connect(addr).await.unwrap();
+        let request = http::Request::post("/upload")
+            .header(header::HOST, "localhost")
+            .body(body)
+            .unwrap();
+        let response = conn.send_request(request).await.0.unwrap();
+        assert_eq!(response.status(), 200);
+
+        let received = server.await.to_ascii_lowercase();
+        assert!(received.contains("transfer-encoding: chunked"));
+        assert!(received.ends_with("\r\n\r\n5\r\nhello\r\n1\r\n \r\n5\r\nworld\r\n0\r\n\r\n"));
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn sends_plain_bodies() {
+        use crate::connectors::MockConnector;
+
+        let received = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let log = received.clone();
+        let server = MockConnector::new().with_handler("api", move |mut stream| {
+            let log = log.clone();
+            async move {
+                while !log.borrow().ends_with(b"ping") {
+                    let (res, buf) = stream.read(vec![0; 1024]).await;
+                    log.borrow_mut().extend_from_slice(&buf[..res.unwrap()]);
+                }
+                let response = b"HTTP/1.1 204 No Content\r\n\r\n";
+                stream.write_all(response.to_vec()).await.0.unwrap();
+            }
+        });
+        let mut connector = HttpConnector::new(server);
+        connector.set_http1_only();
+        let response = connector
+            .request("api", || {
+                http::Request::post("/")
+                    .header(header::HOST, "localhost")
+                    .body("ping")
+                    .unwrap()
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 204);
+        let received = String::from_utf8(received.take())
+            .unwrap()
+            .to_ascii_lowercase();
+        assert!(received.contains("content-length: 4\r\n"));
+        assert!(received.ends_with("\r\n\r\nping"));
+    }
+
+    #[monoio::test]
+    async fn reads_file_ranges() {
+        let path = std::env::temp_dir().join(format!("file-body-{}.txt", std::process::id()));
+        std::fs::write(&path, "hello world").unwrap();
+
+        let mut whole = FileBody::open(&path).await.unwrap();
+        assert_eq!(whole.stream_hint(), StreamHint::Fixed);
+        assert_eq!(whole.next_data().await.unwrap().unwrap(), "hello world");
+        assert!(whole.next_data().await.is_none());
+
+        let file = File::open(&path).await.unwrap();
+        let mut range = FileBody::new(file).with_range(6, 5).with_chunk_size(2);
+        assert_eq!(range.stream_hint(), StreamHint::Stream);
+        let mut chunks = Vec::new();
+        while let Some(chunk) = range.next_data().await {
+            chunks.push(chunk.unwrap());
+        }
+        assert_eq!(chunks, ["wo", "rl", "d"]);
+        assert_eq!(range.remaining(), Some(0));
+
+        let file = File::open(&path).await.unwrap();
+        let mut truncated = FileBody::new(file).with_range(6, 10);
+        assert!(truncated.next_data().await.unwrap().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[monoio::test]
+    async fn enforces_declared_length() {
+        let (sender, body) = channel(4);
+        let mut body = body.with_length(4);
+        sender.send(Bytes::from_static(b"abc")).await.unwrap();
+        drop(sender);
+        assert_eq!(body.next_data().await.unwrap().unwrap(), "abc");
+        assert!(body.next_data().await.unwrap().is_err());
+        assert!(body.next_data().await.is_none());
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn reads_streaming_responses() {
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Rc::new(Cell::new(0));
+        let count = accepted.clone();
+        monoio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            count.set(count.get() + 1);
+            let responses: [&[u8]; 2] = [
+                b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n\
+                  5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n",
+                b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\ndone",
+            ];
+            for response in responses {
+                let (res, _) = conn.read(vec![0; 1024]).await;
+                assert!(res.unwrap() > 0);
+                conn.write_all(response).await.0.unwrap();
+            }
+        });
+
+        let connector: HttpConnector<TcpConnector, SocketAddr, _> =
+            HttpConnector::build_tcp_http1_only();
+        let request = || {
+            http::Request::get("/")
+                .header(header::HOST, "localhost")
+                .body(HttpBody::H1(Payload::None))
+                .unwrap()
+        };
+
+        let conn = connector.connect(addr).await.unwrap();
+        let response = conn.send_request_streaming(request()).await.unwrap();
+        let mut reader = BodyReader::new(response.into_body());
+        let mut received = Vec::new();
+        loop {
+            let (res, buf) = reader.read(Vec::<u8>::with_capacity(4)).await;
+            if res.unwrap() == 0 {
+                break;
+            }
+            received.extend_from_slice(&buf);
+        }
+        assert_eq!(received, b"hello world");
+        assert!(reader.into_inner().is_end());
+
+        // The connection went back to the pool once the body was read.
+        let conn = connector.connect(addr).await.unwrap();
+        let mut body = conn
+            .send_request_streaming(request())
+            .await
+            .unwrap()
+            .into_body();
+        assert_eq!(body.next().await.unwrap().unwrap(), "done");
+        assert!(body.next().await.is_none());
+        assert_eq!(accepted.get(), 1);
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn throttles_bodies() {
+        use std::time::{Duration, Instant};
+
+        // Bursts of 10 bytes at 1000 bytes per second.
+        let limiter = RateLimiter::with_burst(1000, 10);
+        let (tx, body) = channel(1);
+        monoio::spawn(async move { tx.send(Bytes::from(vec![0; 30])).await });
+        let mut body = ThrottledBody::new(body, limiter.clone()).into_body();
+        let start = Instant::now();
+        let mut pieces = Vec::new();
+        while let Some(piece) = body.next_data().await {
+            pieces.push(piece.unwrap().len());
+        }
+        assert_eq!(pieces, [10, 10, 10]);
+        assert!(start.elapsed() >= Duration::from_millis(15));
+
+        // Fixed bodies are not split, and clones of the limiter share its budget.
+        let mut body = ThrottledBody::new(HttpBody::Ready(Some(Bytes::from(vec![0; 20]))), limiter);
+        let start = Instant::now();
+        assert_eq!(body.next_data().await.unwrap().unwrap().len(), 20);
+        assert!(body.next_data().await.is_none());
+        assert!(start.elapsed() >= Duration::from_millis(15));
+    }
+}
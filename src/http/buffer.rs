@@ -0,0 +1,181 @@
+//! Recycling of connection buffers.
+//!
+//! A [`BufferPool`] set with
+//! [`HttpConnector::set_buffer_pool`](super::HttpConnector::set_buffer_pool) provides the read
+//! buffer of each new HTTP/1.1 connection, taken back when the connection is dropped, and the
+//! buffers request heads are encoded into when they bypass the codec, i.e. with
+//! `Expect: 100-continue`, trailers or a header casing. Busy clients then stop allocating and
+//! freeing these buffers for every connection and request.
+//!
+//! Clones of a pool share its buffers, so connectors of one thread can share a pool; it cannot be
+//! sent to other threads. The write buffer of the codec is private to `monoio-http` and is still
+//! allocated per connection.
+use std::{cell::RefCell, rc::Rc};
+
+use bytes::BytesMut;
+
+/// The capacity of new buffers by default, the one of the codec's own read buffers.
+const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// A pool of byte buffers recycled across requests and connections.
+#[derive(Debug, Clone)]
+pub struct BufferPool {
+    free: Rc<RefCell<Vec<BytesMut>>>,
+    buffer_size: usize,
+    max_buffers: usize,
+    max_capacity: usize,
+}
+
+impl Default for BufferPool {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            free: Default::default(),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            max_buffers: 256,
+            max_capacity: 64 * 1024,
+        }
+    }
+}
+
+impl BufferPool {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the capacity of new buffers, 8 KiB by default.
+    #[inline]
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Sets how many idle buffers are kept, 256 by default. Buffers returned once it is reached
+    /// are freed.
+    #[inline]
+    pub fn with_max_buffers(mut self, max_buffers: usize) -> Self {
+        self.max_buffers = max_buffers;
+        self
+    }
+
+    /// Sets the largest capacity of the buffers kept, 64 KiB by default. Buffers that grew
+    /// beyond it, e.g. to read large response heads, are freed when returned.
+    #[inline]
+    pub fn with_max_capacity(mut self, max_capacity: usize) -> Self {
+        self.max_capacity = max_capacity;
+        self
+    }
+
+    #[inline]
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    #[inline]
+    pub fn max_buffers(&self) -> usize {
+        self.max_buffers
+    }
+
+    #[inline]
+    pub fn max_capacity(&self) -> usize {
+        self.max_capacity
+    }
+
+    /// Returns the number of idle buffers.
+    #[inline]
+    pub fn idle(&self) -> usize {
+        self.free.borrow().len()
+    }
+
+    /// Takes an empty buffer, allocating one when none is idle.
+    pub fn get(&self) -> BytesMut {
+        self.free
+            .borrow_mut()
+            .pop()
+            .unwrap_or_else(|| BytesMut::with_capacity(self.buffer_size))
+    }
+
+    /// Returns a buffer to the pool, emptying it.
+    ///
+    /// The space of a buffer whose front was split off is reclaimed once nothing else refers to
+    /// it, otherwise a new allocation is made.
+    pub fn put(&self, mut buf: BytesMut) {
+        let mut free = self.free.borrow_mut();
+        if free.len() >= self.max_buffers {
+            return;
+        }
+        buf.clear();
+        buf.reserve(self.buffer_size);
+        if buf.capacity() <= self.max_capacity {
+            free.push(buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recycles_buffers() {
+        let pool = BufferPool::new()
+            .with_buffer_size(16)
+            .with_max_buffers(1)
+            .with_max_capacity(64);
+        let mut buf = pool.get();
+        assert!(buf.capacity() >= 16);
+        buf.extend_from_slice(b"data");
+        let ptr = buf.as_ptr();
+        pool.put(buf);
+        assert_eq!(pool.idle(), 1);
+
+        let buf = pool.get();
+        assert!(buf.is_empty());
+        assert_eq!(buf.as_ptr(), ptr);
+        pool.put(buf);
+        // The pool is full.
+        pool.put(BytesMut::with_capacity(16));
+        assert_eq!(pool.idle(), 1);
+
+        // Buffers that grew too large are freed, small ones grow.
+        let pool = pool.clone();
+        pool.get();
+        pool.put(BytesMut::with_capacity(128));
+        assert_eq!(pool.idle(), 0);
+        pool.put(BytesMut::with_capacity(8));
+        assert!(pool.get().capacity() >= 16);
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn lends_buffers_to_connections() {
+        use monoio_http::common::body::HttpBody;
+
+        use crate::{
+            connectors::MockConnector,
+            http::{header_case::HeaderCase, response::ResponseExt, HttpConnector},
+        };
+
+        let server = MockConnector::new()
+            .with_responses("api", ["HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok"; 2]);
+        let pool = BufferPool::new();
+        let mut connector = HttpConnector::new(server);
+        connector.set_http1_only();
+        connector.set_header_case(HeaderCase::Title);
+        connector.set_buffer_pool(pool.clone());
+        let request = || {
+            http::Request::get("/")
+                .header(http::header::HOST, "localhost")
+                .body(HttpBody::Ready(None))
+                .unwrap()
+        };
+        for _ in 0..2 {
+            let response = connector.request("api", request).await.unwrap();
+            assert_eq!(response.bytes().await.unwrap(), "ok");
+            // The head buffer is back, the read buffer is held by the pooled connection.
+            assert_eq!(pool.idle(), 1);
+        }
+        drop(connector);
+        assert_eq!(pool.idle(), 2);
+    }
+}
@@ -0,0 +1,478 @@
+//! Capture of the exchanges of an [`HttpConnector`](super::HttpConnector), to debug interop
+//! problems with third-party APIs.
+//!
+//! A [`Capture`] set with [`HttpConnector::set_capture`](super::HttpConnector::set_capture)
+//! records each request sent by [`HttpConnector::request`](super::HttpConnector::request) as
+//! written, once default credentials and interceptors applied, and its response as received,
+//! before response interceptors run, along with timings. Response bodies are recorded up to the
+//! size set with [`Capture::with_bodies`], which buffers them in memory; request bodies are not,
+//! they may be streamed. Requests failing once built are recorded with their error, connection
+//! failures are not.
+//!
+//! Exchanges are kept in memory, see [`Capture::with_max_exchanges`], handed to the callback of
+//! [`Capture::with_callback`], and exported as a HAR 1.2 log with [`Capture::har`] behind the
+//! `serde` feature:
+//!
+//! ```rust
+//! use monoio_transports::http::{capture::Capture, HttpConnector};
+//!
+//! let capture = Capture::new().with_bodies(64 * 1024);
+//! let mut connector: HttpConnector<_, std::net::SocketAddr, monoio::net::TcpStream> =
+//!     HttpConnector::build_tcp_http1_only();
+//! connector.set_capture(capture.clone());
+//! // Requests sent with `connector.request(..)` are then listed by `capture.exchanges()`.
+//! ```
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    fmt,
+    rc::Rc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use bytes::Bytes;
+use http::{HeaderMap, Method, Request, Response, StatusCode, Uri, Version};
+use monoio_http::common::body::HttpBody;
+
+use super::ConnectionInfo;
+use crate::TransportError;
+
+/// A request and its response, or the error it failed with.
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    /// When the request was issued, before its connection was checked out.
+    pub started: SystemTime,
+    pub method: Method,
+    pub uri: Uri,
+    pub version: Version,
+    pub request_headers: HeaderMap,
+    /// The connection the request was sent on, when established by the connector.
+    pub connection: Option<ConnectionInfo>,
+    pub response: Option<CapturedResponse>,
+    /// The error the request failed with, `None` when a response was received.
+    pub error: Option<String>,
+    pub timings: Timings,
+}
+
+/// A response recorded by a [`Capture`].
+#[derive(Debug, Clone)]
+pub struct CapturedResponse {
+    pub status: StatusCode,
+    pub version: Version,
+    pub headers: HeaderMap,
+    /// The start of the body, up to the size set with [`Capture::with_bodies`].
+    pub body: Option<Bytes>,
+    /// The whole size of the body, when it was read.
+    pub body_size: Option<usize>,
+}
+
+impl CapturedResponse {
+    /// Whether the body was recorded only partially.
+    #[inline]
+    pub fn is_truncated(&self) -> bool {
+        matches!((&self.body, self.body_size), (Some(body), Some(size)) if body.len() < size)
+    }
+}
+
+/// The phases of an [`Exchange`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// Getting a connection, established or reused from the pool.
+    pub checkout: Duration,
+    /// From writing the request until the head of its response was received, HTTP/1.1 bodies
+    /// included.
+    pub wait: Duration,
+    /// Reading the rest of the response body, when recorded.
+    pub receive: Duration,
+}
+
+impl Timings {
+    #[inline]
+    pub fn total(&self) -> Duration {
+        self.checkout + self.wait + self.receive
+    }
+}
+
+type Callback = Rc<dyn Fn(&Exchange)>;
+
+/// Records the exchanges of the connectors it is set on. Clones share the same records.
+#[derive(Clone, Default)]
+pub struct Capture {
+    max_body_size: Option<usize>,
+    max_exchanges: Option<usize>,
+    callback: Option<Callback>,
+    exchanges: Rc<RefCell<VecDeque<Exchange>>>,
+}
+
+impl fmt::Debug for Capture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Capture")
+            .field("max_body_size", &self.max_body_size)
+            .field("max_exchanges", &self.max_exchanges)
+            .field("exchanges", &self.exchanges.borrow().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Capture {
+    /// Records headers and timings, without bodies.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records response bodies up to `max_size` bytes. Responses are then buffered in memory
+    /// before being returned.
+    #[inline]
+    pub fn with_bodies(mut self, max_size: usize) -> Self {
+        self.max_body_size = Some(max_size);
+        self
+    }
+
+    /// Keeps the last `max` exchanges only, none with `0` to only feed the callback.
+    #[inline]
+    pub fn with_max_exchanges(mut self, max: usize) -> Self {
+        self.max_exchanges = Some(max);
+        self
+    }
+
+    /// Calls `callback` with every exchange once it completed.
+    #[inline]
+    pub fn with_callback(mut self, callback: impl Fn(&Exchange) + 'static) -> Self {
+        self.callback = Some(Rc::new(callback));
+        self
+    }
+
+    /// Returns the exchanges kept, oldest first.
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        self.exchanges.borrow().iter().cloned().collect()
+    }
+
+    #[inline]
+    pub fn clear(&self) {
+        self.exchanges.borrow_mut().clear();
+    }
+
+    pub(crate) fn recorder(&self) -> Recorder {
+        Recorder {
+            started: SystemTime::now(),
+            start: Instant::now(),
+            max_body_size: self.max_body_size,
+            connected: Cell::new(None),
+            sent: Cell::new(None),
+            receive: Cell::new(Duration::ZERO),
+            connection: RefCell::new(None),
+            request: RefCell::new(None),
+            response: RefCell::new(None),
+        }
+    }
+
+    /// Records the exchange of `recorder`, which failed with `error` if any.
+    pub(crate) fn record(&self, recorder: Recorder, error: Option<&TransportError>) {
+        let Some(exchange) = recorder.finish(error) else {
+            return;
+        };
+        if let Some(callback) = &self.callback {
+            callback(&exchange);
+        }
+        if self.max_exchanges == Some(0) {
+            return;
+        }
+        let mut exchanges = self.exchanges.borrow_mut();
+        if Some(exchanges.len()) == self.max_exchanges {
+            exchanges.pop_front();
+        }
+        exchanges.push_back(exchange);
+    }
+}
+
+type RequestHead = (Method, Uri, Version, HeaderMap);
+
+/// The state of an exchange in flight.
+pub(crate) struct Recorder {
+    started: SystemTime,
+    start: Instant,
+    max_body_size: Option<usize>,
+    connected: Cell<Option<Instant>>,
+    sent: Cell<Option<Instant>>,
+    receive: Cell<Duration>,
+    connection: RefCell<Option<ConnectionInfo>>,
+    request: RefCell<Option<RequestHead>>,
+    response: RefCell<Option<CapturedResponse>>,
+}
+
+impl Recorder {
+    /// A connection, described by `info`, was checked out.
+    pub(crate) fn on_connected(&self, info: Option<ConnectionInfo>) {
+        self.connected.set(Some(Instant::now()));
+        *self.connection.borrow_mut() = info;
+    }
+
+    /// `request` is about to be written, again when it is sent on a fresh connection.
+    pub(crate) fn on_request<B>(&self, request: &Request<B>) {
+        *self.request.borrow_mut() = Some((
+            request.method().clone(),
+            request.uri().clone(),
+            request.version(),
+            request.headers().clone(),
+        ));
+        self.sent.set(Some(Instant::now()));
+    }
+
+    /// `response` was received; its body is buffered when bodies are recorded.
+    pub(crate) async fn on_response(
+        &self,
+        response: &mut Response<HttpBody>,
+    ) -> Result<(), TransportError> {
+        let received = Instant::now();
+        let (body, body_size) = match self.max_body_size {
+            Some(max_size) => {
+                let body = std::mem::replace(response.body_mut(), HttpBody::Ready(None));
+                let data = super::response::collect(body, usize::MAX).await?;
+                *response.body_mut() = HttpBody::Ready(Some(data.clone()));
+                self.receive.set(received.elapsed());
+                let size = data.len();
+                (Some(data.slice(..size.min(max_size))), Some(size))
+            }
+            None => (None, None),
+        };
+        *self.response.borrow_mut() = Some(CapturedResponse {
+            status: response.status(),
+            version: response.version(),
+            headers: response.headers().clone(),
+            body,
+            body_size,
+        });
+        Ok(())
+    }
+
+    fn finish(self, error: Option<&TransportError>) -> Option<Exchange> {
+        let (method, uri, version, request_headers) = self.request.into_inner()?;
+        let connected = self.connected.get().unwrap_or(self.start);
+        let response = self.response.into_inner();
+        let wait = match (self.sent.get(), &response) {
+            (Some(sent), Some(_)) => sent.elapsed().saturating_sub(self.receive.get()),
+            (Some(sent), None) => sent.elapsed(),
+            (None, _) => Duration::ZERO,
+        };
+        Some(Exchange {
+            started: self.started,
+            method,
+            uri,
+            version,
+            request_headers,
+            connection: self.connection.into_inner(),
+            response,
+            error: error.map(ToString::to_string),
+            timings: Timings {
+                checkout: connected - self.start,
+                wait,
+                receive: self.receive.get(),
+            },
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Capture {
+    /// Returns the exchanges kept as a HAR 1.2 log, to be written to a `.har` file and opened
+    /// in browser developer tools or HAR viewers.
+    ///
+    /// Failed exchanges have a status of `0` and their error in the `_error` field.
+    pub fn har(&self) -> serde_json::Value {
+        use serde_json::json;
+
+        let headers = |headers: &HeaderMap| {
+            headers
+                .iter()
+                .map(|(name, value)| {
+                    json!({
+                        "name": name.as_str(),
+                        "value": String::from_utf8_lossy(value.as_bytes()),
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        let millis = |d: Duration| d.as_secs_f64() * 1000.0;
+        let entries = self
+            .exchanges
+            .borrow()
+            .iter()
+            .map(|exchange| {
+                let query = exchange
+                    .uri
+                    .query()
+                    .into_iter()
+                    .flat_map(|query| query.split('&'))
+                    .filter(|pair| !pair.is_empty())
+                    .map(|pair| {
+                        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                        json!({ "name": name, "value": value })
+                    })
+                    .collect::<Vec<_>>();
+                let body_size = exchange
+                    .request_headers
+                    .get(http::header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok()?.parse::<i64>().ok())
+                    .unwrap_or(-1);
+                let response = match &exchange.response {
+                    Some(response) => {
+                        let mime_type = response
+                            .headers
+                            .get(http::header::CONTENT_TYPE)
+                            .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+                            .unwrap_or_default();
+                        let mut content = json!({
+                            "size": response.body_size.map_or(-1, |size| size as i64),
+                            "mimeType": mime_type,
+                        });
+                        if let Some(body) = &response.body {
+                            match std::str::from_utf8(body) {
+                                Ok(text) => content["text"] = text.into(),
+                                Err(_) => {
+                                    use base64::Engine;
+                                    let encoded =
+                                        base64::engine::general_purpose::STANDARD.encode(body);
+                                    content["text"] = encoded.into();
+                                    content["encoding"] = "base64".into();
+                                }
+                            }
+                        }
+                        json!({
+                            "status": response.status.as_u16(),
+                            "statusText": response.status.canonical_reason().unwrap_or(""),
+                            "httpVersion": format!("{:?}", response.version),
+                            "cookies": [],
+                            "headers": headers(&response.headers),
+                            "content": content,
+                            "redirectURL": response
+                                .headers
+                                .get(http::header::LOCATION)
+                                .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+                                .unwrap_or_default(),
+                            "headersSize": -1,
+                            "bodySize": response.body_size.map_or(-1, |size| size as i64),
+                        })
+                    }
+                    None => json!({
+                        "status": 0,
+                        "statusText": "",
+                        "httpVersion": "",
+                        "cookies": [],
+                        "headers": [],
+                        "content": { "size": 0, "mimeType": "" },
+                        "redirectURL": "",
+                        "headersSize": -1,
+                        "bodySize": -1,
+                    }),
+                };
+                let timings = &exchange.timings;
+                let reused = exchange.connection.as_ref().is_none_or(|c| c.is_reused());
+                let (blocked, connect) = match reused {
+                    true => (millis(timings.checkout), -1.0),
+                    false => (0.0, millis(timings.checkout)),
+                };
+                let mut entry = json!({
+                    "startedDateTime": rfc3339(exchange.started),
+                    "time": millis(timings.total()),
+                    "request": {
+                        "method": exchange.method.as_str(),
+                        "url": exchange.uri.to_string(),
+                        "httpVersion": format!("{:?}", exchange.version),
+                        "cookies": [],
+                        "headers": headers(&exchange.request_headers),
+                        "queryString": query,
+                        "headersSize": -1,
+                        "bodySize": body_size,
+                    },
+                    "response": response,
+                    "cache": {},
+                    "timings": {
+                        "blocked": blocked,
+                        "dns": -1,
+                        "connect": connect,
+                        "send": 0,
+                        "wait": millis(timings.wait),
+                        "receive": millis(timings.receive),
+                    },
+                });
+                if let Some(addr) = exchange.connection.as_ref().and_then(|c| c.peer_addr()) {
+                    entry["serverIPAddress"] = addr.ip().to_string().into();
+                }
+                if let Some(error) = &exchange.error {
+                    entry["_error"] = error.as_str().into();
+                }
+                entry
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": entries,
+            }
+        })
+    }
+}
+
+/// Formats `time` as an RFC 3339 UTC date with milliseconds, as HAR expects.
+#[cfg(feature = "serde")]
+fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = since_epoch.as_secs() as i64;
+    // The proleptic Gregorian date of a day since the epoch, from Howard Hinnant's algorithm.
+    let z = secs.div_euclid(86400) + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let rem = secs.rem_euclid(86400);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn formats_dates() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_millis(784_111_777_042);
+        assert_eq!(rfc3339(time), "1994-11-06T08:49:37.042Z");
+        assert_eq!(rfc3339(SystemTime::UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn keeps_the_last_exchanges() {
+        let seen = Rc::new(Cell::new(0));
+        let counter = seen.clone();
+        let capture = Capture::new()
+            .with_max_exchanges(2)
+            .with_callback(move |_| counter.set(counter.get() + 1));
+        for path in ["/a", "/b", "/c"] {
+            let recorder = capture.recorder();
+            recorder.on_request(&Request::get(path).body(()).unwrap());
+            capture.record(recorder, None);
+        }
+        // Nothing is recorded for requests that were never built.
+        capture.record(capture.recorder(), None);
+        let paths: Vec<_> = capture.exchanges().iter().map(|e| e.uri.clone()).collect();
+        assert_eq!(paths, ["/b", "/c"]);
+        assert_eq!(seen.get(), 3);
+    }
+}
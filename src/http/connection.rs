@@ -1,4 +1,6 @@
-use bytes::Bytes;
+use std::{net::SocketAddr, time::Duration};
+
+use bytes::{BufMut, Bytes, BytesMut};
 use http::Response;
 use monoio::io::{
     sink::{Sink, SinkExt},
@@ -7,28 +9,61 @@ use monoio::io::{
 };
 use monoio_http::{
     common::{
-        body::{Body, HttpBody},
+        body::{Body, HttpBody, StreamHint},
         error::HttpError,
         request::{Request, RequestHead},
         IntoParts,
     },
     h1::{
         codec::{
-            decoder::{DecodeError, PayloadDecoder},
+            decoder::{
+                ChunkedBodyDecoder, DecodeError, DirectHeadDecoder, FixedBodyDecoder,
+                InvalidRequestError, PayloadDecoder,
+            },
             ClientCodec,
         },
         payload::{fixed_payload_pair, stream_payload_pair, Payload},
+        BorrowFramedRead,
     },
     h2::client::SendRequest,
 };
 
-use crate::pool::{Key, Poolable, Pooled};
+use super::{
+    abort::AbortHandle,
+    buffer::BufferPool,
+    forward::ProxyForward,
+    header_case::{write_name, HeaderCase, OriginalHeaderCase},
+    trailers::{encode_last_chunk, Chunk, ChunkedDecoder, RequestTrailers, ResponseTrailers},
+};
+use crate::{
+    connectors::{Alpn, TransportConnMeta},
+    error::LimitExceeded,
+    pool::{Key, Poolable, Pooled},
+    TransportError,
+};
 
 /// A HTTP/1.1 connection.
 pub struct Http1Connection<IO: AsyncWriteRent> {
     framed: ClientCodec<IO>,
     using: bool,
     open: bool,
+    // Whether the last request failed before any response was received.
+    head_failed: bool,
+    limits: ResponseLimits,
+    // The read timeout of the codec, which is bypassed to enforce the header limit.
+    read_timeout: Option<Duration>,
+    keep_alive: KeepAlive,
+    expect_continue: Option<ExpectContinue>,
+    header_case: HeaderCase,
+    // Responses received, and what the last `Keep-Alive` header allows.
+    served: usize,
+    server_max: Option<usize>,
+    server_timeout: Option<Duration>,
+    info: Option<ConnectionInfo>,
+    buffers: Option<BufferPool>,
+    // When the request in flight started, for the timings of its events.
+    #[cfg(feature = "tracing")]
+    started: std::time::Instant,
 }
 
 impl<IO: AsyncWriteRent> Http1Connection<IO> {
@@ -37,17 +72,393 @@ impl<IO: AsyncWriteRent> Http1Connection<IO> {
             framed,
             using: false,
             open: true,
+            head_failed: false,
+            limits: ResponseLimits::default(),
+            read_timeout: None,
+            keep_alive: KeepAlive::default(),
+            expect_continue: None,
+            header_case: HeaderCase::Lower,
+            served: 0,
+            server_max: None,
+            server_timeout: None,
+            info: None,
+            buffers: None,
+            #[cfg(feature = "tracing")]
+            started: std::time::Instant::now(),
+        }
+    }
+
+    /// Reads into a buffer of `pool`, returned to it once the connection is dropped, and encodes
+    /// heads into its buffers.
+    pub(crate) fn with_buffer_pool(mut self, pool: Option<BufferPool>) -> Self {
+        if let Some(pool) = &pool {
+            *self.framed.framed_mut().read_buffer_mut() = pool.get();
+        }
+        self.buffers = pool;
+        self
+    }
+
+    /// Enforces `limits` on responses, `read_timeout` must be the one of the codec.
+    pub(crate) fn with_limits(
+        mut self,
+        limits: ResponseLimits,
+        read_timeout: Option<Duration>,
+    ) -> Self {
+        self.limits = limits;
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    #[inline]
+    pub(crate) fn with_keep_alive(mut self, keep_alive: KeepAlive) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    #[inline]
+    pub(crate) fn with_expect_continue(mut self, expect: Option<ExpectContinue>) -> Self {
+        self.expect_continue = expect;
+        self
+    }
+
+    #[inline]
+    pub(crate) fn with_header_case(mut self, case: HeaderCase) -> Self {
+        self.header_case = case;
+        self
+    }
+
+    #[inline]
+    pub(crate) fn with_info(mut self, info: ConnectionInfo) -> Self {
+        self.info = Some(info);
+        self
+    }
+
+    /// Marks a request in flight. If it is dropped half way, the connection is never released
+    /// and is discarded.
+    fn begin(&mut self) {
+        self.using = true;
+        #[cfg(feature = "tracing")]
+        {
+            self.started = std::time::Instant::now();
+        }
+    }
+
+    /// Whether the connection served as many requests as it may.
+    fn exhausted(&self) -> bool {
+        self.keep_alive
+            .max_requests
+            .is_some_and(|max| self.served >= max)
+            || self.server_max == Some(0)
+    }
+
+    /// Counts a response, and reads the limits announced in its `Keep-Alive` header.
+    fn on_response(&mut self, headers: &http::HeaderMap) {
+        self.served += 1;
+        if !self.keep_alive.server_hints {
+            return;
+        }
+        self.server_max = self.server_max.map(|max| max.saturating_sub(1));
+        for param in headers
+            .get_all("keep-alive")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+        {
+            let Some((name, value)) = param.split_once('=') else {
+                continue;
+            };
+            let Ok(value) = value.trim().trim_matches('"').parse::<u64>() else {
+                continue;
+            };
+            match name.trim() {
+                n if n.eq_ignore_ascii_case("timeout") => {
+                    self.server_timeout = Some(Duration::from_secs(value))
+                }
+                n if n.eq_ignore_ascii_case("max") => {
+                    self.server_max = Some(usize::try_from(value).unwrap_or(usize::MAX))
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// How long HTTP/1.1 connections are kept alive.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct KeepAlive {
+    pub(crate) max_requests: Option<usize>,
+    pub(crate) server_hints: bool,
+}
+
+/// When HTTP/1.1 requests announce their body with `Expect: 100-continue`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ExpectContinue {
+    pub(crate) min_body_size: usize,
+    pub(crate) timeout: Duration,
+}
+
+/// Consumes the interim responses at the start of the read buffer, returning their status, and
+/// returns the status of the final response without consuming it.
+///
+/// Like [`LimitedHeadDecoder`], fails once more than `max_size` bytes arrived without the end of
+/// a head.
+struct InterimDecoder {
+    max_size: Option<usize>,
+}
+
+impl monoio_codec::Decoder for InterimDecoder {
+    type Item = http::StatusCode;
+    type Error = HttpError;
+
+    fn decode(
+        &mut self,
+        src: &mut bytes::BytesMut,
+    ) -> Result<monoio_codec::Decoded<Self::Item>, Self::Error> {
+        let max_size = self.max_size.unwrap_or(usize::MAX);
+        let scanned = &src[..src.len().min(max_size)];
+        let Some(end) = scanned.windows(4).position(|w| w == b"\r\n\r\n") else {
+            if src.len() >= max_size {
+                return Err(std::io::Error::from(LimitExceeded::Headers(max_size)).into());
+            }
+            return Ok(monoio_codec::Decoded::Insufficient);
+        };
+        let status = parse_status_line(&src[..end])?;
+        if status.is_informational() {
+            let _ = src.split_to(end + 4);
+        }
+        Ok(monoio_codec::Decoded::Some(status))
+    }
+}
+
+/// Parses the status code of the status line starting `head`, `HTTP/1.x <code> [reason]`.
+fn parse_status_line(head: &[u8]) -> Result<http::StatusCode, DecodeError> {
+    let line = head.split(|&b| b == b'\r').next().unwrap_or_default();
+    let invalid = || {
+        DecodeError::Invalid(InvalidRequestError::InvalidStatus(format!(
+            "malformed status line {:?}",
+            String::from_utf8_lossy(line)
+        )))
+    };
+    let mut parts = line.splitn(3, |&b| b == b' ');
+    let version = parts.next().unwrap_or_default();
+    if !matches!(version, b"HTTP/1.0" | b"HTTP/1.1") {
+        return Err(invalid());
+    }
+    let code = parts.next().unwrap_or_default();
+    if code.len() != 3 {
+        return Err(invalid());
+    }
+    Ok(http::StatusCode::from_bytes(code)?)
+}
+
+impl<IO: AsyncWriteRent> Drop for Http1Connection<IO> {
+    fn drop(&mut self) {
+        if let Some(pool) = &self.buffers {
+            pool.put(std::mem::take(self.framed.framed_mut().read_buffer_mut()));
+        }
+    }
+}
+
+/// Encodes the head of `head` into `buf`, with a `Content-Length` of `length` or chunked without
+/// one, and header names in `case` unless the request recorded their original casing. Requests
+/// marked with [`ProxyForward`] are written in absolute form.
+fn encode_head(
+    mut buf: BytesMut,
+    head: &RequestHead,
+    length: Option<usize>,
+    expect_continue: bool,
+    case: HeaderCase,
+) -> BytesMut {
+    use std::io::Write;
+
+    let original = head.extensions.get::<OriginalHeaderCase>();
+    let header = |buf: &mut BytesMut, name: &http::HeaderName, value: &[u8]| {
+        write_name(buf, name, case, original);
+        buf.extend_from_slice(b": ");
+        buf.extend_from_slice(value);
+        buf.extend_from_slice(b"\r\n");
+    };
+    let path = head.uri.path_and_query().map_or("/", |pq| pq.as_str());
+    let version = match head.version {
+        http::Version::HTTP_10 => "HTTP/1.0",
+        _ => "HTTP/1.1",
+    };
+    let forward = head.extensions.get::<ProxyForward>();
+    let _ = write!((&mut buf).writer(), "{} ", head.method);
+    if let Some(authority) = forward.and_then(|_| request_authority(head)) {
+        let scheme = head.uri.scheme_str().unwrap_or("http");
+        let _ = write!((&mut buf).writer(), "{scheme}://{authority}");
+    }
+    let _ = write!((&mut buf).writer(), "{path} {version}\r\n");
+    if let Some(auth) = forward.and_then(|forward| forward.missing_authorization(&head.headers)) {
+        header(
+            &mut buf,
+            &http::header::PROXY_AUTHORIZATION,
+            auth.as_bytes(),
+        );
+    }
+    match length {
+        Some(length) => header(
+            &mut buf,
+            &http::header::CONTENT_LENGTH,
+            length.to_string().as_bytes(),
+        ),
+        None => header(&mut buf, &http::header::TRANSFER_ENCODING, b"chunked"),
+    }
+    if expect_continue {
+        header(&mut buf, &http::header::EXPECT, b"100-continue");
+    }
+    for (name, value) in head.headers.iter().filter(|(name, _)| {
+        !matches!(
+            *name,
+            &http::header::CONTENT_LENGTH | &http::header::TRANSFER_ENCODING
+        ) && !(expect_continue && *name == http::header::EXPECT)
+    }) {
+        header(&mut buf, name, value.as_bytes());
+    }
+    buf.extend_from_slice(b"\r\n");
+    buf
+}
+
+/// Encodes the head of a request without body, sent as TLS early data before the connection
+/// serving it is set up.
+pub(crate) fn encode_early_head(head: &RequestHead, case: HeaderCase) -> BytesMut {
+    encode_head(BytesMut::with_capacity(256), head, Some(0), false, case)
+}
+
+/// Returns the authority of the uri of `head` without user info, or its `Host` header.
+fn request_authority(head: &RequestHead) -> Option<&str> {
+    match head.uri.authority() {
+        Some(authority) => {
+            let authority = authority.as_str();
+            Some(
+                authority
+                    .rsplit_once('@')
+                    .map_or(authority, |(_, host)| host),
+            )
+        }
+        None => head.headers.get(http::header::HOST)?.to_str().ok(),
+    }
+}
+
+/// Buffers written with a single vectored write, so a request head and the body data after it,
+/// or a chunk and its framing, leave in one syscall rather than in as many packets as buffers
+/// with `TCP_NODELAY`.
+#[derive(Default)]
+struct Gather {
+    // The encoded head, returned to the buffer pool once written.
+    head: Option<BytesMut>,
+    bufs: Vec<Bytes>,
+    iovecs: Vec<libc::iovec>,
+}
+
+impl Gather {
+    fn new(head: BytesMut) -> Self {
+        let mut gather = Self::default();
+        gather.iovecs.push(libc::iovec {
+            iov_base: head.as_ptr() as _,
+            iov_len: head.len(),
+        });
+        gather.head = Some(head);
+        gather
+    }
+
+    fn push(&mut self, buf: Bytes) {
+        if buf.is_empty() {
+            return;
+        }
+        // The data of `Bytes` does not move with them.
+        self.iovecs.push(libc::iovec {
+            iov_base: buf.as_ptr() as _,
+            iov_len: buf.len(),
+        });
+        self.bufs.push(buf);
+    }
+
+    /// Writes the buffers to `io`, then returns the head buffer to `pool`.
+    async fn write_to<IO: AsyncWriteRent>(
+        self,
+        io: &mut IO,
+        pool: Option<&BufferPool>,
+    ) -> std::io::Result<()> {
+        use monoio::io::AsyncWriteRentExt;
+
+        let (res, gather) = io.write_vectored_all(self).await;
+        if let (Some(pool), Some(head)) = (pool, gather.head) {
+            pool.put(head);
         }
+        res.map(drop)
     }
 }
 
+// SAFETY: the iovecs point into the buffers owned by the value, whose data does not move.
+unsafe impl monoio::buf::IoVecBuf for Gather {
+    fn read_iovec_ptr(&self) -> *const libc::iovec {
+        self.iovecs.as_ptr()
+    }
+
+    fn read_iovec_len(&self) -> usize {
+        self.iovecs.len()
+    }
+}
+
+/// How long before the idle timeout announced by a server a connection stops being reused, so
+/// requests are not sent while the server closes it.
+const SERVER_TIMEOUT_MARGIN: Duration = Duration::from_secs(1);
+
+/// Size limits on HTTP/1.1 responses.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ResponseLimits {
+    pub(crate) max_header_size: Option<usize>,
+    pub(crate) max_body_size: Option<usize>,
+}
+
+/// Decodes a response head, failing once more than `max_size` bytes arrived without the end of
+/// the head.
+struct LimitedHeadDecoder {
+    inner: DirectHeadDecoder,
+    max_size: usize,
+}
+
+impl monoio_codec::Decoder for LimitedHeadDecoder {
+    type Item = <DirectHeadDecoder as monoio_codec::Decoder>::Item;
+    type Error = HttpError;
+
+    fn decode(
+        &mut self,
+        src: &mut bytes::BytesMut,
+    ) -> Result<monoio_codec::Decoded<Self::Item>, Self::Error> {
+        let scanned = &src[..src.len().min(self.max_size)];
+        if scanned.windows(4).any(|w| w == b"\r\n\r\n") {
+            return self.inner.decode(src);
+        }
+        if src.len() >= self.max_size {
+            return Err(std::io::Error::from(LimitExceeded::Headers(self.max_size)).into());
+        }
+        Ok(monoio_codec::Decoded::Insufficient)
+    }
+}
+
+fn content_length(headers: &http::HeaderMap) -> Option<usize> {
+    headers
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<usize>().ok())
+}
+
 impl<IO: AsyncWriteRent> Poolable for Http1Connection<IO> {
     #[inline]
     fn is_open(&self) -> bool {
         match self {
-            Self { using, open, .. } => *open && !*using,
+            Self { using, open, .. } => *open && !*using && !self.exhausted(),
         }
     }
+
+    #[inline]
+    fn idle_timeout(&self) -> Option<Duration> {
+        self.server_timeout
+            .map(|timeout| timeout.saturating_sub(SERVER_TIMEOUT_MARGIN))
+    }
 }
 
 impl<IO: AsyncReadRent + AsyncWriteRent> Http1Connection<IO> {
@@ -55,82 +466,414 @@ impl<IO: AsyncReadRent + AsyncWriteRent> Http1Connection<IO> {
         &mut self,
         request: R,
     ) -> (Result<Response<HttpBody>, HttpError>, bool)
+    where
+        ClientCodec<IO>: Sink<R, Error = E>,
+        E: std::fmt::Debug + Into<HttpError>,
+    {
+        // While a request is in flight, the connection must not be pooled.
+        self.begin();
+        let result = self.dispatch(request).await;
+        self.using = false;
+        result
+    }
+
+    async fn dispatch<R, E>(&mut self, request: R) -> (Result<Response<HttpBody>, HttpError>, bool)
+    where
+        ClientCodec<IO>: Sink<R, Error = E>,
+        E: std::fmt::Debug + Into<HttpError>,
+    {
+        match self.send_head(request).await {
+            Ok(resp) => self.read_body(resp).await,
+            Err(e) => (Err(e), false),
+        }
+    }
+
+    /// Reads the body of `resp` in full.
+    async fn read_body(
+        &mut self,
+        resp: ResponseWithDecoder,
+    ) -> (Result<Response<HttpBody>, HttpError>, bool) {
+        let result = self.read_payload(resp).await;
+        #[cfg(feature = "tracing")]
+        if result.0.is_ok() {
+            tracing::debug!(elapsed = ?self.started.elapsed(), "response body received");
+        }
+        result
+    }
+
+    async fn read_payload(
+        &mut self,
+        resp: ResponseWithDecoder,
+    ) -> (Result<Response<HttpBody>, HttpError>, bool) {
+        let (parts, payload_decoder) = resp.into_parts();
+        let handle = &mut self.framed;
+        match payload_decoder {
+            PayloadDecoder::None => {
+                let payload = Payload::None;
+                let response = Response::from_parts(parts, payload.into());
+                (Ok(response), false)
+            }
+            PayloadDecoder::Fixed(_) => {
+                if let Some(max) = self.limits.max_body_size {
+                    if content_length(&parts.headers).is_some_and(|len| len > max) {
+                        self.open = false;
+                        return (Err(body_too_large(max)), false);
+                    }
+                }
+                let mut framed_payload = payload_decoder.with_io(handle);
+                let (payload, payload_sender) = fixed_payload_pair();
+                if let Some(data) = framed_payload.next_data().await {
+                    payload_sender.feed(data)
+                }
+                let payload = Payload::Fixed(payload);
+                let response = Response::from_parts(parts, payload.into());
+                (Ok(response), false)
+            }
+            PayloadDecoder::Streamed(_) => {
+                let mut decoder = ChunkedDecoder::new(self.limits.max_header_size);
+                let framed = handle.framed_mut();
+                let (payload, mut payload_sender) = stream_payload_pair();
+                let mut received = 0usize;
+                let trailers = loop {
+                    match framed.next_with(&mut decoder).await {
+                        Some(Ok(Chunk::Data(data))) => {
+                            received = received.saturating_add(data.len());
+                            if let Some(max) = self.limits.max_body_size.filter(|&m| received > m) {
+                                self.open = false;
+                                return (Err(body_too_large(max)), false);
+                            }
+                            payload_sender.feed_data(Some(data))
+                        }
+                        Some(Ok(Chunk::End(trailers))) => {
+                            payload_sender.feed_data(None);
+                            break trailers;
+                        }
+                        Some(Err(e)) => {
+                            #[cfg(feature = "logging")]
+                            tracing::error!("decode upstream response error {:?}", e);
+                            self.open = false;
+                            return (Err(e), false);
+                        }
+                        None => {
+                            self.open = false;
+                            return (Err(DecodeError::UnexpectedEof.into()), false);
+                        }
+                    }
+                };
+                let payload = Payload::Stream(payload);
+                let mut response = Response::from_parts(parts, payload.into());
+                if !trailers.is_empty() {
+                    response.extensions_mut().insert(ResponseTrailers(trailers));
+                }
+                (Ok(response), false)
+            }
+        }
+    }
+
+    /// Sends the request and reads the response head, leaving the body on the wire.
+    async fn send_head<R, E>(&mut self, request: R) -> Result<ResponseWithDecoder, HttpError>
     where
         ClientCodec<IO>: Sink<R, Error = E>,
         E: std::fmt::Debug + Into<HttpError>,
     {
         let handle = &mut self.framed;
+        self.head_failed = false;
 
         if let Err(e) = handle.send_and_flush(request).await {
             #[cfg(feature = "logging")]
             tracing::error!("send upstream request error {:?}", e);
             self.open = false;
-            return (Err(e.into()), false);
+            self.head_failed = true;
+            return Err(e.into());
         }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(elapsed = ?self.started.elapsed(), "request written");
+        self.read_head().await
+    }
 
-        match handle.next().await {
-            Some(Ok(resp)) => {
-                let (parts, payload_decoder) = resp.into_parts();
-                match payload_decoder {
-                    PayloadDecoder::None => {
-                        let payload = Payload::None;
-                        let response = Response::from_parts(parts, payload.into());
-                        (Ok(response), false)
-                    }
-                    PayloadDecoder::Fixed(_) => {
-                        let mut framed_payload = payload_decoder.with_io(handle);
-                        let (payload, payload_sender) = fixed_payload_pair();
-                        if let Some(data) = framed_payload.next_data().await {
-                            payload_sender.feed(data)
-                        }
-                        let payload = Payload::Fixed(payload);
-                        let response = Response::from_parts(parts, payload.into());
-                        (Ok(response), false)
-                    }
-                    PayloadDecoder::Streamed(_) => {
-                        let mut framed_payload = payload_decoder.with_io(handle);
-                        let (payload, mut payload_sender) = stream_payload_pair();
-                        loop {
-                            match framed_payload.next_data().await {
-                                Some(Ok(data)) => payload_sender.feed_data(Some(data)),
-                                Some(Err(e)) => {
-                                    #[cfg(feature = "logging")]
-                                    tracing::error!("decode upstream response error {:?}", e);
-                                    self.open = false;
-                                    return (Err(e), false);
-                                }
-                                None => {
-                                    payload_sender.feed_data(None);
-                                    break;
-                                }
-                            }
-                        }
-                        let payload = Payload::Stream(payload);
-                        let response = Response::from_parts(parts, payload.into());
-                        (Ok(response), false)
-                    }
+    /// Reads the head of the response to the request sent last.
+    async fn read_head(&mut self) -> Result<ResponseWithDecoder, HttpError> {
+        let handle = &mut self.framed;
+        let next = match self.limits.max_header_size {
+            None => handle.next().await,
+            Some(max_size) => {
+                let framed = handle.framed_mut();
+                let ready = match self.read_timeout {
+                    Some(timeout) => match monoio::time::timeout(timeout, framed.peek_data()).await
+                    {
+                        Ok(ready) => ready.map(|_| ()).map_err(HttpError::from),
+                        Err(_) => Err(DecodeError::TimedOut.into()),
+                    },
+                    None => Ok(()),
+                };
+                match ready {
+                    Ok(()) => framed
+                        .next_with(&mut LimitedHeadDecoder {
+                            inner: DirectHeadDecoder::default(),
+                            max_size,
+                        })
+                        .await
+                        .map(|r| r.map(|(head, decoder)| Response::from_parts(head, decoder))),
+                    Err(e) => Some(Err(e)),
                 }
             }
+        };
+        match next {
+            Some(Ok(resp)) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(elapsed = ?self.started.elapsed(), "response head received");
+                self.on_response(resp.headers());
+                Ok(resp)
+            }
             Some(Err(e)) => {
                 #[cfg(feature = "logging")]
                 tracing::error!("decode upstream response error {:?}", e);
                 self.open = false;
-                (Err(e), false)
+                self.head_failed = true;
+                Err(e)
             }
             None => {
                 #[cfg(feature = "logging")]
                 tracing::error!("upstream return eof");
                 self.open = false;
-                (Err(DecodeError::UnexpectedEof.into()), false)
+                self.head_failed = true;
+                Err(DecodeError::UnexpectedEof.into())
             }
         }
     }
 }
 
+impl<IO: AsyncReadRent + AsyncWriteRent> Http1Connection<IO> {
+    /// Reads the response to the request written as TLS early data when the connection was
+    /// established.
+    async fn read_early_response(&mut self) -> (Result<Response<HttpBody>, HttpError>, bool) {
+        self.begin();
+        self.head_failed = false;
+        let result = match self.read_head().await {
+            Ok(resp) => self.read_body(resp).await,
+            Err(e) => (Err(e), false),
+        };
+        self.using = false;
+        result
+    }
+
+    /// Sends a request like [`send_request`](Self::send_request), with the `Expect:
+    /// 100-continue` policy and header casing of the connection, and the [`RequestTrailers`] of
+    /// the request.
+    async fn send_request_parts<B>(
+        &mut self,
+        head: RequestHead,
+        body: B,
+    ) -> (Result<Response<HttpBody>, HttpError>, bool)
+    where
+        B: Body<Data = Bytes, Error = HttpError>,
+    {
+        self.begin();
+        let result = match self.send_parts(head, body).await {
+            Ok(resp) => self.read_body(resp).await,
+            Err(e) => (Err(e), false),
+        };
+        self.using = false;
+        result
+    }
+
+    /// Sends the request and reads the response head, writing the request itself when the codec
+    /// cannot encode it.
+    async fn send_parts<B>(
+        &mut self,
+        head: RequestHead,
+        body: B,
+    ) -> Result<ResponseWithDecoder, HttpError>
+    where
+        B: Body<Data = Bytes, Error = HttpError>,
+    {
+        let trailers = head
+            .extensions
+            .get::<RequestTrailers>()
+            .filter(|_| head.version != http::Version::HTTP_10)
+            .cloned();
+        if self.expect_continue.is_none()
+            && trailers.is_none()
+            && self.header_case == HeaderCase::Lower
+            && head.extensions.get::<OriginalHeaderCase>().is_none()
+            && head.extensions.get::<ProxyForward>().is_none()
+        {
+            return self.send_head(Request::from_parts(head, body)).await;
+        }
+        self.send_raw(head, body, trailers).await
+    }
+
+    /// Sends a request, announcing large bodies with `Expect: 100-continue` and only sending
+    /// them once the server accepted the head, chunking bodies followed by `trailers`, and
+    /// writing header names in their casing and forwarded requests in absolute form.
+    async fn send_raw<B>(
+        &mut self,
+        head: RequestHead,
+        mut body: B,
+        trailers: Option<RequestTrailers>,
+    ) -> Result<ResponseWithDecoder, HttpError>
+    where
+        B: Body<Data = Bytes, Error = HttpError>,
+    {
+        self.head_failed = false;
+        // The size of fixed bodies is only known once they are read.
+        let (first, length) = match body.stream_hint() {
+            StreamHint::None => (None, Some(0)),
+            StreamHint::Fixed => {
+                let data = body.next_data().await.transpose()?.unwrap_or_default();
+                let len = data.len();
+                (Some(data), Some(len))
+            }
+            StreamHint::Stream => (None, None),
+        };
+        // Trailers can only follow a chunked body.
+        let length = length.filter(|_| trailers.is_none());
+        let expect = self.expect_continue;
+        let expecting = expect.is_some_and(|expect| {
+            head.version != http::Version::HTTP_10
+                && length.is_none_or(|len| len > 0 && len >= expect.min_body_size)
+        });
+
+        // The codec writes whole requests, the request is written to its stream directly. It is
+        // idle between requests, so nothing is left in its buffer.
+        let stream = self.framed.framed_mut().get_mut().0.get();
+        // SAFETY: the read and write halves of the codec share the stream through an
+        // `UnsafeCell` owned by `self.framed`, which outlives this call. The write half is not
+        // used while the request is written here, and each reference returned by `io` is only
+        // used for one write or flush, which completes before the read half next reads interim
+        // responses, so no two references to the stream are live at once.
+        let io = || unsafe { &mut *stream };
+        let buffers = self.buffers.clone();
+        let sent: Result<bool, HttpError> = async {
+            let buf = match &buffers {
+                Some(pool) => pool.get(),
+                None => BytesMut::with_capacity(256),
+            };
+            // Unless the server is asked to accept the head first, it is written along with the
+            // first body data.
+            let mut pending = Some(Gather::new(encode_head(
+                buf,
+                &head,
+                length,
+                expecting,
+                self.header_case,
+            )));
+            let mut send_body = true;
+            if let Some(expect) = expect.filter(|_| expecting) {
+                if let Some(gather) = pending.take() {
+                    gather.write_to(io(), buffers.as_ref()).await?;
+                }
+                io().flush().await?;
+                loop {
+                    match monoio::time::timeout(expect.timeout, self.next_interim()).await {
+                        // Servers not answering in time may not support the expectation.
+                        Err(_) => break,
+                        Ok(Some(Ok(http::StatusCode::CONTINUE))) => break,
+                        Ok(Some(Ok(status))) if status.is_informational() => continue,
+                        Ok(Some(Ok(_))) => {
+                            // The final response came first, the body is not sent and the
+                            // connection no longer knows where the server stands.
+                            send_body = false;
+                            self.open = false;
+                            break;
+                        }
+                        Ok(Some(Err(e))) => return Err(e),
+                        Ok(None) => return Err(DecodeError::UnexpectedEof.into()),
+                    }
+                }
+            }
+            if !send_body {
+                return Ok(false);
+            }
+            if length.is_some() {
+                let mut gather = pending.take().unwrap_or_default();
+                if let Some(data) = first {
+                    gather.push(data);
+                }
+                gather.write_to(io(), buffers.as_ref()).await?;
+            } else {
+                let mut first = first.map(Ok);
+                // The head of a streamed body is not held back until its first chunk.
+                if first.is_none() {
+                    if let Some(gather) = pending.take() {
+                        gather.write_to(io(), buffers.as_ref()).await?;
+                        io().flush().await?;
+                    }
+                }
+                while let Some(data) = match first.take() {
+                    Some(data) => Some(data),
+                    None => body.next_data().await,
+                } {
+                    let data = data?;
+                    if data.is_empty() {
+                        continue;
+                    }
+                    let mut gather = pending.take().unwrap_or_default();
+                    gather.push(Bytes::from(format!("{:X}\r\n", data.len())));
+                    gather.push(data);
+                    gather.push(Bytes::from_static(b"\r\n"));
+                    gather.write_to(io(), buffers.as_ref()).await?;
+                }
+                let mut gather = pending.take().unwrap_or_default();
+                gather.push(encode_last_chunk(trailers.as_ref()).into());
+                gather.write_to(io(), buffers.as_ref()).await?;
+            }
+            io().flush().await?;
+            Ok(true)
+        }
+        .await;
+        if let Err(e) = sent {
+            #[cfg(feature = "logging")]
+            tracing::error!("send upstream request error {:?}", e);
+            self.open = false;
+            self.head_failed = true;
+            return Err(e);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(elapsed = ?self.started.elapsed(), "request written");
+
+        // An interim response may still precede the final one.
+        loop {
+            let e = match self.next_interim().await {
+                Some(Ok(status)) if status.is_informational() => continue,
+                Some(Ok(_)) => break,
+                Some(Err(e)) => e,
+                None => DecodeError::UnexpectedEof.into(),
+            };
+            #[cfg(feature = "logging")]
+            tracing::error!("decode upstream response error {:?}", e);
+            self.open = false;
+            self.head_failed = true;
+            return Err(e);
+        }
+        self.read_head().await
+    }
+
+    /// Reads the status of the next interim or final response, within the read timeout and the
+    /// header size limit of the connection.
+    async fn next_interim(&mut self) -> Option<Result<http::StatusCode, HttpError>> {
+        let mut decoder = InterimDecoder {
+            max_size: self.limits.max_header_size,
+        };
+        let next = self.framed.framed_mut().next_with(&mut decoder);
+        match self.read_timeout {
+            Some(timeout) => monoio::time::timeout(timeout, next)
+                .await
+                .unwrap_or_else(|_| Some(Err(DecodeError::TimedOut.into()))),
+            None => next.await,
+        }
+    }
+}
+
+type ResponseWithDecoder = Response<PayloadDecoder<FixedBodyDecoder, ChunkedBodyDecoder>>;
+
 /// A HTTP/2 connection.
 #[derive(Clone, Debug)]
 pub struct Http2Connection {
     tx: SendRequest<Bytes>,
+    info: Option<ConnectionInfo>,
+    // Whether this handle was taken from the pool.
+    reused: bool,
 }
 
 impl Poolable for Http2Connection {
@@ -142,13 +885,26 @@ impl Poolable for Http2Connection {
 
 impl Http2Connection {
     pub fn new(tx: SendRequest<Bytes>) -> Self {
-        Self { tx }
+        Self {
+            tx,
+            info: None,
+            reused: false,
+        }
     }
 
+    #[inline]
+    pub(crate) fn with_info(mut self, info: ConnectionInfo) -> Self {
+        self.info = Some(info);
+        self
+    }
+
+    /// Returns a handle to the connection for a request, once it is pooled.
     #[allow(dead_code)]
     fn to_owned(&self) -> Self {
         Self {
             tx: self.tx.clone(),
+            info: self.info.clone(),
+            reused: true,
         }
     }
 
@@ -166,6 +922,8 @@ impl Http2Connection {
         R: IntoParts<Parts = RequestHead>,
         R::Body: Body<Data = Bytes, Error = HttpError>,
     {
+        #[cfg(feature = "tracing")]
+        let started = std::time::Instant::now();
         let mut client = match self.tx.clone().ready().await {
             Ok(client) => client,
             Err(e) => {
@@ -174,6 +932,7 @@ impl Http2Connection {
         };
 
         let (parts, mut body) = request.into_parts();
+        let trailers = parts.extensions.get::<RequestTrailers>().cloned();
         let h2_request = Request::from_parts(parts, ());
 
         let (response, mut send_stream) = match client.send_request(h2_request, false) {
@@ -200,7 +959,18 @@ impl Http2Connection {
             }
         }
         // Mark end of stream
-        let _ = send_stream.send_data(Bytes::new(), true);
+        match trailers {
+            Some(trailers) => {
+                if let Err(e) = send_stream.send_trailers(trailers.fields()) {
+                    return (Err(e.into()), false);
+                }
+            }
+            None => {
+                let _ = send_stream.send_data(Bytes::new(), true);
+            }
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(elapsed = ?started.elapsed(), "request written");
 
         let response = match response.await {
             Ok(response) => response,
@@ -210,17 +980,100 @@ impl Http2Connection {
                 return (Err(e.into()), false);
             }
         };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(elapsed = ?started.elapsed(), "response head received");
 
         let (parts, body) = response.into_parts();
         (Ok(Response::from_parts(parts, body.into())), true)
     }
 }
 
+/// Details of the connection a response was received on.
+///
+/// [`HttpConnection`]s established by [`HttpConnector`](super::HttpConnector) insert it in the
+/// extensions of their responses, so per-request logs can include transport details:
+///
+/// ```rust
+/// # use monoio_transports::http::ConnectionInfo;
+/// # fn log(response: &http::Response<()>) {
+/// if let Some(info) = response.extensions().get::<ConnectionInfo>() {
+///     println!("{:?} reused={}", info.peer_addr(), info.is_reused());
+/// }
+/// # }
+/// ```
+///
+/// Addresses and TLS parameters are the ones reported by the
+/// [`TransportConnMetadata`](crate::connectors::TransportConnMetadata) of the transport, `None`
+/// when it does not report them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    meta: TransportConnMeta,
+    reused: bool,
+    connect_time: Duration,
+}
+
+impl ConnectionInfo {
+    #[inline]
+    pub(crate) fn new(meta: TransportConnMeta, connect_time: Duration) -> Self {
+        Self {
+            meta,
+            reused: false,
+            connect_time,
+        }
+    }
+
+    #[inline]
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.meta.local_addr()
+    }
+
+    #[inline]
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.meta.peer_addr()
+    }
+
+    /// Whether the connection came from the pool: an HTTP/1.1 one that served requests before,
+    /// or a shared HTTP/2 one.
+    #[inline]
+    pub fn is_reused(&self) -> bool {
+        self.reused
+    }
+
+    /// Returns the protocol negotiated with ALPN.
+    #[inline]
+    pub fn alpn(&self) -> Alpn {
+        self.meta.alpn()
+    }
+
+    #[inline]
+    pub fn tls_version(&self) -> Option<&'static str> {
+        self.meta.tls_version()
+    }
+
+    #[inline]
+    pub fn cipher_suite(&self) -> Option<&'static str> {
+        self.meta.cipher_suite()
+    }
+
+    /// Returns how long establishing the connection took, TLS handshake included.
+    #[inline]
+    pub fn connect_time(&self) -> Duration {
+        self.connect_time
+    }
+
+    /// Returns the metadata reported by the transport.
+    #[inline]
+    pub fn transport(&self) -> &TransportConnMeta {
+        &self.meta
+    }
+}
+
 /// A unified representation of an HTTP connection, supporting both HTTP/1.1 and HTTP/2 protocols.
 ///
 /// This enum is designed to work with monoio's native IO traits, which are optimized for io_uring.
 /// It allows for efficient handling of both HTTP/1.1 and HTTP/2 connections within the same
 /// abstraction.
+#[allow(clippy::large_enum_variant)]
 pub enum HttpConnection<K: Key, IO: AsyncReadRent + AsyncWriteRent> {
     Http1(Pooled<K, Http1Connection<IO>>),
     Http2(Http2Connection),
@@ -256,6 +1109,10 @@ impl<K: Key, IO: AsyncReadRent + AsyncWriteRent> HttpConnection<K, IO> {
     /// This method automatically handles the differences between HTTP/1.1 and HTTP/2,
     /// providing a unified interface for sending requests.
     ///
+    /// With the `tracing` feature, requests are sent within an `http_request` span, emitting
+    /// events once the request is written, once the response head is received and, for
+    /// HTTP/1.1, once its body is received, each with the time `elapsed` since it was sent.
+    ///
     /// # Arguments
     ///
     /// * `request` - The HTTP request to send.
@@ -294,9 +1151,614 @@ impl<K: Key, IO: AsyncReadRent + AsyncWriteRent> HttpConnection<K, IO> {
         R: IntoParts<Parts = RequestHead>,
         R::Body: Body<Data = Bytes, Error = HttpError>,
     {
+        let (head, body) = request.into_parts();
+        let version = self.version();
+        let span = request_span(&head, version);
+        let info = self.info();
+        let send = async move {
+            match self {
+                Self::Http1(conn) => conn.send_request_parts(head, body).await,
+                Self::Http2(conn) => conn.send_request(Request::from_parts(head, body)).await,
+            }
+        };
+        let (mut res, reuse) = instrument(send, span).await;
+        if let (Ok(response), Some(info)) = (&mut res, info) {
+            response.extensions_mut().insert(info);
+        }
+        (res, reuse)
+    }
+
+    /// Reads the response to the request of `head`, written as TLS early data when the
+    /// connection was established, see
+    /// [`HttpConnector::set_early_data`](super::HttpConnector::set_early_data).
+    pub(crate) async fn read_early_response(
+        &mut self,
+        head: &RequestHead,
+    ) -> (Result<Response<HttpBody>, HttpError>, bool) {
+        let span = request_span(head, self.version());
+        let info = self.info();
+        let read = async move {
+            match self {
+                Self::Http1(conn) => conn.read_early_response().await,
+                // Early requests are HTTP/1.1 ones.
+                Self::Http2(_) => (Err(DecodeError::UnexpectedEof.into()), false),
+            }
+        };
+        let (mut res, reuse) = instrument(read, span).await;
+        if let (Ok(response), Some(info)) = (&mut res, info) {
+            response.extensions_mut().insert(info);
+        }
+        (res, reuse)
+    }
+
+    /// Returns the details of this connection inserted in the extensions of its responses,
+    /// `None` when it was not established by an [`HttpConnector`](super::HttpConnector).
+    pub fn info(&self) -> Option<ConnectionInfo> {
+        let (info, reused) = match self {
+            Self::Http1(conn) => (conn.info.clone(), conn.is_reused()),
+            Self::Http2(conn) => (conn.info.clone(), conn.reused),
+        };
+        info.map(|info| ConnectionInfo { reused, ..info })
+    }
+    /// Returns the HTTP version spoken on this connection, as picked from ALPN or the
+    /// connector configuration.
+    #[inline]
+    pub fn version(&self) -> http::Version {
+        match self {
+            Self::Http1(_) => http::Version::HTTP_11,
+            Self::Http2(_) => http::Version::HTTP_2,
+        }
+    }
+
+    /// Returns true if this is a pooled HTTP/1.1 connection that already served a request.
+    #[inline]
+    pub fn is_reused(&self) -> bool {
+        match self {
+            Self::Http1(conn) => conn.is_reused(),
+            Self::Http2(_) => false,
+        }
+    }
+
+    /// Returns true if `err`, returned by the last request on this connection, shows that a
+    /// reused HTTP/1.1 connection had been closed by the server while idle.
+    ///
+    /// That is the case when writing the request or reading the status line hit a reset or an
+    /// EOF. The request was then never processed, so it is safe to send again on a fresh
+    /// connection.
+    pub fn is_stale(&self, err: &HttpError) -> bool {
         match self {
-            Self::Http1(conn) => conn.send_request(request).await,
-            Self::Http2(conn) => conn.send_request(request).await,
+            Self::Http1(conn) => conn.is_reused() && conn.head_failed && is_closed_error(err),
+            Self::Http2(_) => false,
+        }
+    }
+
+    /// Sends an HTTP request like [`send_request`](Self::send_request), failing with
+    /// [`TransportError::ResponseTimeout`] if no response is received within `timeout`.
+    ///
+    /// HTTP/1.1 responses are read in full before returning, so the timeout covers the body as
+    /// well. A timed out HTTP/1.1 connection is left in an unknown state and is not reused.
+    pub async fn send_request_timeout<R, E>(
+        &mut self,
+        request: R,
+        timeout: Duration,
+    ) -> (Result<Response<HttpBody>, TransportError>, bool)
+    where
+        ClientCodec<IO>: Sink<R, Error = E>,
+        E: std::fmt::Debug + Into<HttpError>,
+        R: IntoParts<Parts = RequestHead>,
+        R::Body: Body<Data = Bytes, Error = HttpError>,
+    {
+        match monoio::time::timeout(timeout, self.send_request(request)).await {
+            Ok((res, reuse)) => (res.map_err(Into::into), reuse),
+            Err(_) => {
+                if let Self::Http1(conn) = self {
+                    conn.open = false;
+                }
+                (Err(TransportError::ResponseTimeout), false)
+            }
+        }
+    }
+
+    /// Sends an HTTP request like [`send_request`](Self::send_request), failing with
+    /// [`TransportError::Cancelled`] if `abort` is aborted first, see [`abort`](super::abort).
+    ///
+    /// A cancelled HTTP/1.1 connection is not reused, a cancelled HTTP/2 stream is reset.
+    pub async fn send_request_abortable<R, E>(
+        &mut self,
+        request: R,
+        abort: &AbortHandle,
+    ) -> (Result<Response<HttpBody>, TransportError>, bool)
+    where
+        ClientCodec<IO>: Sink<R, Error = E>,
+        E: std::fmt::Debug + Into<HttpError>,
+        R: IntoParts<Parts = RequestHead>,
+        R::Body: Body<Data = Bytes, Error = HttpError>,
+    {
+        match abort.run(self.send_request(request)).await {
+            Some((res, reuse)) => (res.map_err(Into::into), reuse),
+            None => {
+                if let Self::Http1(conn) = self {
+                    conn.open = false;
+                }
+                (Err(TransportError::Cancelled), false)
+            }
         }
     }
+
+    /// Sends an HTTP request and returns as soon as the response head is received, reading the
+    /// body from the connection while it is consumed.
+    ///
+    /// Unlike [`send_request`](Self::send_request), HTTP/1.1 bodies are not buffered, which suits
+    /// large downloads. The connection is owned by the returned body: it goes back to the pool
+    /// once the body has been read to its end, and is closed if the body is dropped before that
+    /// or fails.
+    pub async fn send_request_streaming<R, E>(
+        self,
+        request: R,
+    ) -> Result<Response<StreamingBody<K, IO>>, TransportError>
+    where
+        ClientCodec<IO>: Sink<R, Error = E>,
+        E: std::fmt::Debug + Into<HttpError>,
+        R: IntoParts<Parts = RequestHead>,
+        R::Body: Body<Data = Bytes, Error = HttpError>,
+    {
+        let (head, body) = request.into_parts();
+        let span = request_span(&head, self.version());
+        let info = self.info();
+        let mut conn = match self {
+            Self::Http1(conn) => conn,
+            Self::Http2(mut conn) => {
+                let send = conn.send_request(Request::from_parts(head, body));
+                let mut response = instrument(send, span).await.0?;
+                if let Some(info) = info {
+                    response.extensions_mut().insert(info);
+                }
+                return Ok(response.map(|body| StreamingBody {
+                    inner: StreamingInner::Ready(body),
+                }));
+            }
+        };
+        // Cleared once the body has been read in full.
+        conn.begin();
+        let (parts, payload_decoder) = instrument(conn.send_parts(head, body), span)
+            .await?
+            .into_parts();
+        let max_body_size = conn.limits.max_body_size;
+        let decoder = match payload_decoder {
+            PayloadDecoder::None => None,
+            PayloadDecoder::Fixed(_) => {
+                let len = content_length(&parts.headers);
+                if let (Some(len), Some(max)) = (len, max_body_size) {
+                    if len > max {
+                        conn.open = false;
+                        return Err(LimitExceeded::Body(max).into());
+                    }
+                }
+                len.filter(|&remaining| remaining > 0)
+                    .map(|remaining| StreamingDecoder::Fixed(PartialFixedDecoder { remaining }))
+            }
+            PayloadDecoder::Streamed(_) => Some(StreamingDecoder::Chunked(ChunkedDecoder::new(
+                conn.limits.max_header_size,
+            ))),
+        };
+        let inner = match decoder {
+            Some(decoder) => StreamingInner::Http1 {
+                conn: Some(conn),
+                decoder,
+                remaining: max_body_size,
+                trailers: None,
+            },
+            None => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(elapsed = ?conn.started.elapsed(), "response body received");
+                conn.using = false;
+                StreamingInner::Ready(HttpBody::H1(Payload::None))
+            }
+        };
+        let mut response = Response::from_parts(parts, StreamingBody { inner });
+        if let Some(info) = info {
+            response.extensions_mut().insert(info);
+        }
+        Ok(response)
+    }
+}
+
+/// Decodes a fixed length body in the chunks it arrives in, rather than in one piece.
+struct PartialFixedDecoder {
+    remaining: usize,
+}
+
+impl monoio_codec::Decoder for PartialFixedDecoder {
+    type Item = Bytes;
+    type Error = DecodeError;
+
+    fn decode(
+        &mut self,
+        src: &mut bytes::BytesMut,
+    ) -> Result<monoio_codec::Decoded<Self::Item>, Self::Error> {
+        if src.is_empty() {
+            return Ok(monoio_codec::Decoded::Insufficient);
+        }
+        let n = src.len().min(self.remaining);
+        self.remaining -= n;
+        Ok(monoio_codec::Decoded::Some(src.split_to(n).freeze()))
+    }
+}
+
+enum StreamingDecoder {
+    Fixed(PartialFixedDecoder),
+    Chunked(ChunkedDecoder),
+}
+
+#[allow(clippy::large_enum_variant)]
+enum StreamingInner<K: Key, IO: AsyncReadRent + AsyncWriteRent> {
+    // The connection is released once the body ended.
+    Http1 {
+        conn: Option<Pooled<K, Http1Connection<IO>>>,
+        decoder: StreamingDecoder,
+        // What is left of the body size limit.
+        remaining: Option<usize>,
+        trailers: Option<http::HeaderMap>,
+    },
+    Ready(HttpBody),
+}
+
+/// A response body returned by
+/// [`HttpConnection::send_request_streaming`], read from the connection as it is consumed.
+///
+/// It is both a [`Body`] and a [`Stream`] of chunks, and can be turned into an
+/// [`AsyncReadRent`] with [`BodyReader`](super::body::BodyReader).
+pub struct StreamingBody<K: Key, IO: AsyncReadRent + AsyncWriteRent> {
+    inner: StreamingInner<K, IO>,
+}
+
+impl<K: Key, IO: AsyncReadRent + AsyncWriteRent> std::fmt::Debug for StreamingBody<K, IO> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let in_progress = matches!(self.inner, StreamingInner::Http1 { conn: Some(_), .. });
+        f.debug_struct("StreamingBody")
+            .field("in_progress", &in_progress)
+            .finish()
+    }
+}
+
+impl<K: Key, IO: AsyncReadRent + AsyncWriteRent> StreamingBody<K, IO> {
+    /// Returns true once the whole body has been read.
+    pub fn is_end(&self) -> bool {
+        match &self.inner {
+            StreamingInner::Http1 { conn, .. } => conn.is_none(),
+            StreamingInner::Ready(body) => body.stream_hint() == StreamHint::None,
+        }
+    }
+
+    /// Returns the trailers of the response once the body has been read, if the server sent
+    /// any.
+    pub async fn trailers(&mut self) -> Result<Option<http::HeaderMap>, HttpError> {
+        match &mut self.inner {
+            StreamingInner::Http1 { trailers, .. } => Ok(trailers.take()),
+            StreamingInner::Ready(HttpBody::H2(body)) => Ok(body.trailers().await?),
+            StreamingInner::Ready(_) => Ok(None),
+        }
+    }
+}
+
+impl<K: Key, IO: AsyncReadRent + AsyncWriteRent> Body for StreamingBody<K, IO> {
+    type Data = Bytes;
+    type Error = HttpError;
+
+    async fn next_data(&mut self) -> Option<Result<Self::Data, Self::Error>> {
+        let (conn, decoder, remaining, trailers) = match &mut self.inner {
+            StreamingInner::Ready(body) => return body.next_data().await,
+            StreamingInner::Http1 {
+                conn,
+                decoder,
+                remaining,
+                trailers,
+            } => (conn, decoder, remaining, trailers),
+        };
+        let active = conn.as_mut()?;
+        let framed = active.framed.framed_mut();
+        let (result, end) = match decoder {
+            StreamingDecoder::Fixed(fixed) => {
+                let result = framed.next_with(fixed).await;
+                (
+                    result.map(|r| r.map(Some).map_err(HttpError::from)),
+                    fixed.remaining == 0,
+                )
+            }
+            StreamingDecoder::Chunked(chunked) => match framed.next_with(chunked).await {
+                Some(Ok(Chunk::Data(data))) => (Some(Ok(Some(data))), false),
+                Some(Ok(Chunk::End(fields))) => {
+                    *trailers = Some(fields).filter(|fields| !fields.is_empty());
+                    (Some(Ok(None)), true)
+                }
+                Some(Err(e)) => (Some(Err(e)), false),
+                None => (None, false),
+            },
+        };
+        match result {
+            Some(Ok(data)) => {
+                let len = data.as_ref().map_or(0, Bytes::len);
+                if let Some(left) = remaining {
+                    match left.checked_sub(len) {
+                        Some(rest) => *left = rest,
+                        None => {
+                            let max = active.limits.max_body_size.unwrap_or_default();
+                            active.open = false;
+                            *conn = None;
+                            return Some(Err(body_too_large(max)));
+                        }
+                    }
+                }
+                if end {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(elapsed = ?active.started.elapsed(), "response body received");
+                    // The connection can serve another request, return it to the pool.
+                    active.using = false;
+                    *conn = None;
+                }
+                data.map(Ok)
+            }
+            Some(Err(e)) => {
+                active.open = false;
+                *conn = None;
+                Some(Err(e))
+            }
+            None => {
+                active.open = false;
+                *conn = None;
+                Some(Err(DecodeError::UnexpectedEof.into()))
+            }
+        }
+    }
+
+    fn stream_hint(&self) -> StreamHint {
+        match &self.inner {
+            StreamingInner::Http1 { conn: Some(_), .. } => StreamHint::Stream,
+            StreamingInner::Http1 { conn: None, .. } => StreamHint::None,
+            StreamingInner::Ready(body) => body.stream_hint(),
+        }
+    }
+}
+
+impl<K: Key, IO: AsyncReadRent + AsyncWriteRent> Stream for StreamingBody<K, IO> {
+    type Item = Result<Bytes, HttpError>;
+
+    #[inline]
+    fn next(&mut self) -> impl std::future::Future<Output = Option<Self::Item>> {
+        self.next_data()
+    }
+}
+
+/// The span requests are sent in with the `tracing` feature.
+#[cfg(feature = "tracing")]
+type RequestSpan = tracing::Span;
+
+#[cfg(not(feature = "tracing"))]
+struct RequestSpan;
+
+#[cfg(feature = "tracing")]
+fn request_span(head: &RequestHead, version: http::Version) -> RequestSpan {
+    tracing::debug_span!(
+        "http_request",
+        method = %head.method,
+        uri = %head.uri,
+        version = ?version
+    )
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline]
+fn request_span(_head: &RequestHead, _version: http::Version) -> RequestSpan {
+    RequestSpan
+}
+
+#[cfg(feature = "tracing")]
+#[inline]
+fn instrument<F: std::future::Future>(
+    send: F,
+    span: RequestSpan,
+) -> tracing::instrument::Instrumented<F> {
+    tracing::Instrument::instrument(send, span)
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline]
+fn instrument<F: std::future::Future>(send: F, _span: RequestSpan) -> F {
+    send
+}
+
+fn body_too_large(max: usize) -> HttpError {
+    std::io::Error::from(LimitExceeded::Body(max)).into()
+}
+
+fn is_closed_error(err: &HttpError) -> bool {
+    fn is_closed_io(e: &std::io::Error) -> bool {
+        use std::io::ErrorKind;
+        matches!(
+            e.kind(),
+            ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::BrokenPipe
+                | ErrorKind::UnexpectedEof
+                | ErrorKind::NotConnected
+        )
+    }
+
+    match err {
+        HttpError::IOError(e) => is_closed_io(e),
+        HttpError::H1EncodeError(monoio_http::h1::codec::encoder::EncodeError::Io(e)) => {
+            is_closed_io(e)
+        }
+        HttpError::H1DecodeError(DecodeError::Io(e)) => is_closed_io(e),
+        HttpError::H1DecodeError(DecodeError::UnexpectedEof) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use monoio::{
+        buf::{IoBuf, IoBufMut, IoVecBuf, IoVecBufMut},
+        io::{AsyncReadRent, AsyncWriteRentExt, Split},
+        BufResult,
+    };
+    use monoio_http::common::body::HttpBody;
+
+    use super::*;
+    use crate::connectors::{duplex, DuplexStream};
+
+    /// Counts the writes made to a stream.
+    struct Counted(DuplexStream, Rc<Cell<usize>>);
+
+    impl AsyncReadRent for Counted {
+        async fn read<T: IoBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+            self.0.read(buf).await
+        }
+
+        async fn readv<T: IoVecBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+            self.0.readv(buf).await
+        }
+    }
+
+    impl AsyncWriteRent for Counted {
+        async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+            self.1.set(self.1.get() + 1);
+            self.0.write(buf).await
+        }
+
+        async fn writev<T: IoVecBuf>(&mut self, buf_vec: T) -> BufResult<usize, T> {
+            self.1.set(self.1.get() + 1);
+            self.0.writev(buf_vec).await
+        }
+
+        async fn flush(&mut self) -> std::io::Result<()> {
+            self.0.flush().await
+        }
+
+        async fn shutdown(&mut self) -> std::io::Result<()> {
+            self.0.shutdown().await
+        }
+    }
+
+    unsafe impl Split for Counted {}
+
+    #[monoio::test(enable_timer = true)]
+    async fn gathers_heads_and_bodies() {
+        let (client, mut server) = duplex(64 * 1024);
+        monoio::spawn(async move {
+            let response = b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n";
+            for _ in 0..2 {
+                let mut request = Vec::new();
+                while !request.ends_with(b"hello") && !request.ends_with(b"0\r\n\r\n") {
+                    let (res, buf) = server.read(vec![0; 1024]).await;
+                    let Ok(n @ 1..) = res else { return };
+                    request.extend_from_slice(&buf[..n]);
+                }
+                server.write_all(response.as_slice()).await.0.unwrap();
+            }
+        });
+        let writes = Rc::new(Cell::new(0));
+        let mut conn = Http1Connection::new(ClientCodec::new(Counted(client, writes.clone())))
+            .with_header_case(HeaderCase::Title);
+        let request = |body: HttpBody| {
+            http::Request::post("/")
+                .header(http::header::HOST, "localhost")
+                .body(body)
+                .unwrap()
+                .into_parts()
+        };
+
+        let (head, body) = request(HttpBody::Ready(Some(Bytes::from_static(b"hello"))));
+        let (res, _) = conn.send_request_parts(head, body).await;
+        assert_eq!(res.unwrap().status(), 200);
+        // The head and the body in one write.
+        assert_eq!(writes.get(), 1);
+
+        let (mut head, body) = request(HttpBody::Ready(Some(Bytes::from_static(b"hello"))));
+        head.extensions
+            .insert(RequestTrailers::from(http::HeaderMap::new()));
+        let (res, _) = conn.send_request_parts(head, body).await;
+        assert_eq!(res.unwrap().status(), 200);
+        // The head and the only chunk, then the last chunk.
+        assert_eq!(writes.get(), 3);
+    }
+
+    #[test]
+    fn parses_status_lines() {
+        let status = |line: &[u8]| parse_status_line(line).ok();
+        assert_eq!(
+            status(b"HTTP/1.1 100 Continue"),
+            Some(http::StatusCode::CONTINUE)
+        );
+        assert_eq!(
+            status(b"HTTP/1.0 103\r\nlink: </a>"),
+            Some(http::StatusCode::from_u16(103).unwrap())
+        );
+        assert_eq!(status(b"HTTP/1.1 1000 Continue"), None);
+        assert_eq!(status(b"HTTP/1.1  100 Continue"), None);
+        assert_eq!(status(b"ICY 200 OK"), None);
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn bounds_interim_responses() {
+        let request = || {
+            http::Request::post("/")
+                .header(http::header::HOST, "localhost")
+                .body(HttpBody::Ready(Some(Bytes::from_static(b"hello"))))
+                .unwrap()
+                .into_parts()
+        };
+        // Writes `response` once the request arrived, then stalls.
+        let server = |mut server: DuplexStream, response: Vec<u8>| async move {
+            let mut request = Vec::new();
+            while !request.ends_with(b"hello") {
+                let (res, buf) = server.read(vec![0; 1024]).await;
+                let Ok(n @ 1..) = res else { return };
+                request.extend_from_slice(&buf[..n]);
+            }
+            server.write_all(response).await.0.unwrap();
+            monoio::time::sleep(Duration::from_secs(5)).await;
+        };
+
+        let (client, stream) = duplex(64 * 1024);
+        monoio::spawn(server(
+            stream,
+            b"HTTP/1.1 103 Early Hints\r\nlink: ".to_vec(),
+        ));
+        let mut conn = Http1Connection::new(ClientCodec::new(client))
+            .with_header_case(HeaderCase::Title)
+            .with_limits(ResponseLimits::default(), Some(Duration::from_millis(50)));
+        let (head, body) = request();
+        let (res, _) =
+            monoio::time::timeout(Duration::from_secs(1), conn.send_request_parts(head, body))
+                .await
+                .expect("the read timeout should fail the request");
+        assert!(matches!(
+            res,
+            Err(HttpError::H1DecodeError(DecodeError::TimedOut))
+        ));
+
+        let (client, stream) = duplex(64 * 1024);
+        let mut response = b"HTTP/1.1 103 Early Hints\r\nlink: ".to_vec();
+        response.resize(256, b'a');
+        monoio::spawn(server(stream, response));
+        let limits = ResponseLimits {
+            max_header_size: Some(64),
+            max_body_size: None,
+        };
+        let mut conn = Http1Connection::new(ClientCodec::new(client))
+            .with_header_case(HeaderCase::Title)
+            .with_limits(limits, None);
+        let (head, body) = request();
+        let (res, _) =
+            monoio::time::timeout(Duration::from_secs(1), conn.send_request_parts(head, body))
+                .await
+                .expect("the header limit should fail the request");
+        let Err(HttpError::IOError(e)) = res else {
+            panic!("expected the header limit error, got {res:?}");
+        };
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    }
 }
@@ -1,12 +1,48 @@
-use std::{cell::UnsafeCell, collections::HashMap, rc::Rc, time::Duration};
+use std::{
+    cell::{Cell, RefCell, UnsafeCell},
+    collections::HashMap,
+    future::poll_fn,
+    rc::Rc,
+    sync::Arc,
+    task::{Poll, Waker},
+    time::Duration,
+};
 
-use monoio::io::{AsyncReadRent, AsyncWriteRent, Split};
-use monoio_http::{h1::codec::ClientCodec, h2::client::Builder as MonoioH2Builder};
+use http::{HeaderValue, Response};
+use monoio::io::{sink::Sink, AsyncReadRent, AsyncWriteRent, Split};
+use monoio_http::{
+    common::{
+        body::{Body, HttpBody, StreamHint},
+        error::HttpError,
+        request::{Request, RequestHead},
+        IntoParts,
+    },
+    h1::codec::ClientCodec,
+    h2::client::Builder as MonoioH2Builder,
+};
 
-use super::connection::{Http1Connection, Http2Connection, HttpConnection};
+use super::{
+    abort::AbortHandle,
+    auth::Credentials,
+    body::IntoBody,
+    buffer::BufferPool,
+    capture::{Capture, Recorder},
+    connection::{
+        encode_early_head, ConnectionInfo, ExpectContinue, Http1Connection, Http2Connection,
+        HttpConnection, KeepAlive, ResponseLimits,
+    },
+    header_case::HeaderCase,
+    interceptor::Interceptor,
+    trailers::RequestTrailers,
+    version::RequiredVersion,
+};
 use crate::{
-    connectors::{Connector, TcpConnector, TlsConnector, TransportConnMeta, TransportConnMetadata},
-    pool::{ConnectionPool, Key, Pooled},
+    connectors::{
+        Alpn, Connector, TcpConnector, TlsConnector, TransportConnMeta, TransportConnMetadata,
+        UnifiedAddr, UnifiedConnector, UnifiedStream,
+    },
+    metrics::ClientMetrics,
+    pool::{ConnectionPool, Key, KeyStats, PoolConfig, PoolStats, Pooled, Reservation},
 };
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
@@ -42,24 +78,130 @@ enum Protocol {
 /// when used within a monoio-based application.
 pub struct HttpConnector<C, K, IO: AsyncWriteRent> {
     connector: C,
-    protocol: Protocol, // User configured protocol
+    http1_connector: Option<C>,
     h1_pool: Option<ConnectionPool<K, Http1Connection<IO>>>,
     h2_pool: ConnectionPool<K, Http2Connection>,
     connecting: UnsafeCell<HashMap<K, Rc<local_sync::semaphore::Semaphore>>>,
-    h2_builder: MonoioH2Builder,
     pub read_timeout: Option<Duration>,
+    config: HttpConnectorConfig,
+    lifecycle: Rc<Lifecycle>,
+}
+
+/// The settings of an [`HttpConnector`], set with its `set_*` methods and cloned along with it.
+#[derive(Clone)]
+struct HttpConnectorConfig {
+    protocol: Protocol, // User configured protocol
+    h2_builder: MonoioH2Builder,
+    default_auth: Option<HeaderValue>,
+    limits: ResponseLimits,
+    keep_alive: KeepAlive,
+    expect_continue: Option<ExpectContinue>,
+    early_data: bool,
+    header_case: HeaderCase,
+    interceptors: Vec<Rc<dyn Interceptor>>,
+    metrics: Option<Arc<dyn ClientMetrics>>,
+    capture: Option<Capture>,
+    buffer_pool: Option<BufferPool>,
+    #[cfg(feature = "cookie")]
+    cookie_jar: Option<super::cookie::CookieJar>,
+    #[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+    decompress: bool,
+}
+
+impl Default for HttpConnectorConfig {
+    fn default() -> Self {
+        Self {
+            protocol: Protocol::default(),
+            h2_builder: MonoioH2Builder::default(),
+            default_auth: None,
+            limits: ResponseLimits::default(),
+            keep_alive: KeepAlive::default(),
+            expect_continue: None,
+            early_data: false,
+            header_case: HeaderCase::Lower,
+            interceptors: Vec::new(),
+            metrics: None,
+            capture: None,
+            buffer_pool: None,
+            #[cfg(feature = "cookie")]
+            cookie_jar: None,
+            #[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+            decompress: true,
+        }
+    }
+}
+
+impl HttpConnectorConfig {
+    /// Returns whether the connections established with `self` behave as the ones established
+    /// with `other`: the protocol and the settings of new HTTP/1.1 connections match.
+    ///
+    /// The HTTP/2 settings of `h2_builder` cannot be compared, and are not.
+    fn same_connections(&self, other: &Self) -> bool {
+        self.protocol == other.protocol
+            && self.limits == other.limits
+            && self.keep_alive == other.keep_alive
+            && self.expect_continue == other.expect_continue
+            && self.header_case == other.header_case
+    }
+}
+
+/// Whether a connector and its clones are shutting down, and their requests in flight.
+#[derive(Debug, Default)]
+struct Lifecycle {
+    shut_down: Cell<bool>,
+    in_flight: Cell<usize>,
+    // The tasks waiting for the requests in flight to finish.
+    waiters: RefCell<Vec<Waker>>,
+}
+
+impl Lifecycle {
+    /// Counts a request in flight until the returned guard is dropped.
+    fn begin(&self) -> InFlight<'_> {
+        self.in_flight.set(self.in_flight.get() + 1);
+        InFlight(self)
+    }
+
+    /// Waits until no request is in flight.
+    async fn drained(&self) {
+        poll_fn(|cx| {
+            if self.in_flight.get() == 0 {
+                return Poll::Ready(());
+            }
+            let mut waiters = self.waiters.borrow_mut();
+            if !waiters.iter().any(|waker| waker.will_wake(cx.waker())) {
+                waiters.push(cx.waker().clone());
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+struct InFlight<'a>(&'a Lifecycle);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        let in_flight = self.0.in_flight.get() - 1;
+        self.0.in_flight.set(in_flight);
+        if in_flight == 0 {
+            for waker in self.0.waiters.take() {
+                waker.wake();
+            }
+        }
+    }
 }
 
 impl<C: Clone, K, IO: AsyncWriteRent> Clone for HttpConnector<C, K, IO> {
     fn clone(&self) -> Self {
         Self {
             connector: self.connector.clone(),
+            http1_connector: self.http1_connector.clone(),
             h1_pool: self.h1_pool.clone(),
             h2_pool: self.h2_pool.clone(),
-            protocol: self.protocol,
             connecting: UnsafeCell::new(HashMap::new()),
             read_timeout: self.read_timeout,
-            h2_builder: self.h2_builder.clone(),
+            config: self.config.clone(),
+            lifecycle: self.lifecycle.clone(),
         }
     }
 }
@@ -67,14 +209,19 @@ impl<C: Clone, K, IO: AsyncWriteRent> Clone for HttpConnector<C, K, IO> {
 impl<C, K: 'static, IO: AsyncWriteRent + 'static> HttpConnector<C, K, IO> {
     #[inline]
     pub fn new(connector: C) -> Self {
+        Self::with_config(connector, HttpConnectorConfig::default())
+    }
+
+    fn with_config(connector: C, config: HttpConnectorConfig) -> Self {
         Self {
             connector,
-            protocol: Protocol::default(),
+            http1_connector: None,
             h1_pool: Some(ConnectionPool::default()),
             h2_pool: ConnectionPool::new(None),
             connecting: UnsafeCell::new(HashMap::new()),
-            h2_builder: MonoioH2Builder::default(),
             read_timeout: None,
+            config,
+            lifecycle: Default::default(),
         }
     }
 
@@ -99,7 +246,7 @@ impl<C, K: 'static, IO: AsyncWriteRent + 'static> HttpConnector<C, K, IO> {
     /// connector.set_http1_only();
     /// ```
     pub fn set_http1_only(&mut self) {
-        self.protocol = Protocol::HTTP11
+        self.config.protocol = Protocol::HTTP11
     }
 
     /// Sets the protocol of the `HttpConnector` to HTTP/2 only.
@@ -114,31 +261,230 @@ impl<C, K: 'static, IO: AsyncWriteRent + 'static> HttpConnector<C, K, IO> {
     /// connector.set_http2_only();
     /// ```
     pub fn set_http2_only(&mut self) {
-        self.protocol = Protocol::HTTP2
+        self.config.protocol = Protocol::HTTP2
+    }
+
+    /// Sets credentials sent by [`request`](Self::request) when a request has no
+    /// `Authorization` header of its own.
+    ///
+    /// Fails if the credentials cannot be encoded as a header value.
+    pub fn set_default_auth(
+        &mut self,
+        credentials: Option<Credentials>,
+    ) -> Result<(), http::Error> {
+        self.config.default_auth = credentials.map(|c| c.header_value()).transpose()?;
+        Ok(())
+    }
+
+    /// Sets the maximum size of HTTP/1.1 response heads, status line included.
+    ///
+    /// A larger head fails the request with [`TransportError::HeadersTooLarge`] and closes the
+    /// connection.
+    ///
+    /// [`TransportError::HeadersTooLarge`]: crate::TransportError::HeadersTooLarge
+    #[inline]
+    pub fn set_max_response_header_size(&mut self, max_size: Option<usize>) {
+        self.config.limits.max_header_size = max_size;
+    }
+
+    /// Sets the maximum size of HTTP/1.1 response bodies.
+    ///
+    /// Reading stops as soon as a body exceeds it, failing the request with
+    /// [`TransportError::BodyTooLarge`] and closing the connection instead of returning it to the
+    /// pool. A `Content-Length` above the limit fails before the body is read. HTTP/2 bodies are
+    /// read by the caller, see [`ResponseExt::bytes_with_limit`](super::response::ResponseExt).
+    ///
+    /// [`TransportError::BodyTooLarge`]: crate::TransportError::BodyTooLarge
+    #[inline]
+    pub fn set_max_response_body_size(&mut self, max_size: Option<usize>) {
+        self.config.limits.max_body_size = max_size;
+    }
+
+    /// Sets how many requests an HTTP/1.1 connection serves before it is closed instead of going
+    /// back to the pool.
+    #[inline]
+    pub fn set_max_requests_per_connection(&mut self, max: Option<usize>) {
+        self.config.keep_alive.max_requests = max;
+    }
+
+    /// Sets whether the `Keep-Alive` header of HTTP/1.1 responses is respected. Connections then
+    /// stop being reused a second before the `timeout` announced by the server closes them, and
+    /// once they served the `max` requests it allows. Disabled by default.
+    #[inline]
+    pub fn set_keep_alive_hints(&mut self, enabled: bool) {
+        self.config.keep_alive.server_hints = enabled;
+    }
+
+    /// Sends HTTP/1.1 request bodies of at least `min_body_size` bytes, or streamed, only after
+    /// the server accepted their head, announcing them with `Expect: 100-continue`. A final
+    /// response received instead, like a `401`, is returned without sending the body, and the
+    /// connection is closed. The body is sent anyway if the server does not answer within
+    /// `timeout`.
+    ///
+    /// Applies to [`HttpConnection::send_request`] and the methods built on it.
+    #[inline]
+    pub fn set_expect_continue(&mut self, min_body_size: Option<usize>, timeout: Duration) {
+        self.config.expect_continue = min_body_size.map(|min_body_size| ExpectContinue {
+            min_body_size,
+            timeout,
+        });
+    }
+
+    /// Sends the head of idempotent HTTP/1.1 requests without a body, like `GET`s, as TLS early
+    /// data when a new connection resumes a session, saving a round trip. Requests rejected as
+    /// early data by the server are sent again over the established connection. Early data can
+    /// be replayed by an attacker, so only enable it for requests safe to repeat. Requires
+    /// connections established with
+    /// [`TlsConfig::with_early_data`](crate::connectors::TlsConfig::with_early_data), other
+    /// connections send requests as usual. Disabled by default.
+    #[inline]
+    pub fn set_early_data(&mut self, enabled: bool) {
+        self.config.early_data = enabled;
+    }
+
+    /// Sets the casing of HTTP/1.1 header names without an
+    /// [`OriginalHeaderCase`](super::header_case::OriginalHeaderCase), for servers rejecting
+    /// lowercase names. Lowercase by default.
+    #[inline]
+    pub fn set_header_case(&mut self, case: HeaderCase) {
+        self.config.header_case = case;
+    }
+
+    /// Adds an interceptor run on the requests and responses of [`request`](Self::request), after
+    /// the ones added before, see [`interceptor`](super::interceptor).
+    #[inline]
+    pub fn add_interceptor(&mut self, interceptor: impl Interceptor + 'static) {
+        self.config.interceptors.push(Rc::new(interceptor));
+    }
+
+    /// Reports pool checkouts, and the latencies and failures of [`request`](Self::request) to
+    /// `metrics`, see [`metrics`](crate::metrics).
+    #[inline]
+    pub fn set_metrics(&mut self, metrics: Arc<dyn ClientMetrics>) {
+        self.config.metrics = Some(metrics);
+    }
+
+    /// Records the exchanges of [`request`](Self::request) into `capture`, see
+    /// [`capture`](super::capture).
+    #[inline]
+    pub fn set_capture(&mut self, capture: Capture) {
+        self.config.capture = Some(capture);
+    }
+
+    /// Recycles the buffers of HTTP/1.1 connections through `pool`, shared with its clones, see
+    /// [`buffer`](super::buffer).
+    #[inline]
+    pub fn set_buffer_pool(&mut self, pool: BufferPool) {
+        self.config.buffer_pool = Some(pool);
+    }
+
+    /// Sends the matching cookies of `jar` with the requests of [`request`](Self::request) that
+    /// have no `Cookie` header, and stores the cookies set by their responses, see
+    /// [`cookie`](super::cookie). Requests with an origin-form uri are matched as `https` on TLS
+    /// connections and `http` otherwise, with the authority of their `Host` header.
+    #[cfg(feature = "cookie")]
+    #[inline]
+    pub fn set_cookie_jar(&mut self, jar: super::cookie::CookieJar) {
+        self.config.cookie_jar = Some(jar);
+    }
+
+    /// Sets whether [`request`](Self::request) advertises the enabled codings in `Accept-Encoding`
+    /// and decodes response bodies, see [`encoding`](super::encoding). Enabled by default, single
+    /// requests opt out with
+    /// [`DecompressRequestExt`](super::encoding::DecompressRequestExt::no_decompress).
+    #[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+    #[inline]
+    pub fn set_decompress(&mut self, enabled: bool) {
+        self.config.decompress = enabled;
+    }
+
+    /// Establishes the new connections of requests requiring HTTP/1.x with `connector`, e.g. a
+    /// `TlsConnector` offering only `http/1.1`, see [`version`](super::version).
+    #[inline]
+    pub fn set_http1_connector(&mut self, connector: C) {
+        self.http1_connector = Some(connector);
+    }
+
+    /// Returns a snapshot of the HTTP/1.1 connection pool, whose waits are the time taken to
+    /// get a connection, reused or new.
+    pub fn pool_stats(&self) -> Option<PoolStats<K>>
+    where
+        K: Key,
+    {
+        self.h1_pool.as_ref().map(ConnectionPool::stats)
+    }
+
+    /// Returns the keys with pooled connections. HTTP/2 connections are shared by all their
+    /// requests, they are counted as idle.
+    pub fn pool_keys(&self) -> Vec<KeyStats<K>>
+    where
+        K: Key,
+    {
+        let mut keys = self
+            .h1_pool
+            .as_ref()
+            .map_or_else(Vec::new, ConnectionPool::keys);
+        for h2 in self.h2_pool.keys() {
+            match keys.iter_mut().find(|stats| stats.key == h2.key) {
+                Some(stats) => stats.idle += h2.idle,
+                None => keys.push(h2),
+            }
+        }
+        keys
+    }
+
+    /// Closes the pooled connections of `key`, like [`ConnectionPool::evict`], so the next
+    /// requests to `key` connect again. Requests already sent on HTTP/2 connections of `key`
+    /// are left to complete. Returns how many idle connections were closed.
+    pub fn evict(&self, key: &K) -> usize
+    where
+        K: Key,
+    {
+        let h1 = self.h1_pool.as_ref().map_or(0, |pool| pool.evict(key));
+        h1 + self.h2_pool.evict(key)
+    }
+
+    /// Replaces the HTTP/1.1 connection pool with an empty one limited by `config`.
+    ///
+    /// HTTP/2 connections are multiplexed and kept one per key regardless of `config`.
+    pub fn set_pool_config(&mut self, config: PoolConfig) {
+        self.h1_pool = Some(ConnectionPool::with_config(config));
     }
 
     #[inline]
     pub fn h2_builder(&mut self) -> &mut MonoioH2Builder {
-        &mut self.h2_builder
+        &mut self.config.h2_builder
     }
 
     fn is_config_h2(&self) -> bool {
-        matches!(self.protocol, Protocol::HTTP2)
+        matches!(self.config.protocol, Protocol::HTTP2)
     }
 
     fn is_config_h1(&self) -> bool {
-        matches!(self.protocol, Protocol::HTTP11)
+        matches!(self.config.protocol, Protocol::HTTP11)
+    }
+
+    /// Whether `request` may be sent as early data, see [`set_early_data`](Self::set_early_data).
+    fn is_early_data<Bd: Body>(&self, request: &Request<Bd>) -> bool {
+        let version = request.extensions().get::<RequiredVersion>();
+        self.config.early_data
+            && !self.is_config_h2()
+            && version.is_none_or(|version| version.is_http1())
+            && request.method().is_idempotent()
+            && request.body().stream_hint() == StreamHint::None
+            && request.extensions().get::<RequestTrailers>().is_none()
     }
 
     fn is_config_auto(&self) -> bool {
-        matches!(self.protocol, Protocol::Auto)
+        matches!(self.config.protocol, Protocol::Auto)
     }
 
     /// Transfers the connection pool from an old `HttpConnector` instance to a new one.
     ///
-    /// This function checks if the protocol and read timeout settings of the old and new
-    /// `HttpConnector` instances match. If they do, it clones the connection pools from the
-    /// old instance to the new instance.
+    /// This function checks if the protocol, read timeout and the settings of new HTTP/1.1
+    /// connections (response limits, keep-alive, `Expect: 100-continue` and header case) of the
+    /// old and new `HttpConnector` instances match. If they do, it clones the connection pools
+    /// from the old instance to the new instance.
     ///
     /// # Parameters
     ///
@@ -148,20 +494,22 @@ impl<C, K: 'static, IO: AsyncWriteRent + 'static> HttpConnector<C, K, IO> {
     /// # Returns
     ///
     /// - `Ok(())` if the pool transfer was successful.
-    /// - `Err(&'static str)` if the pool transfer failed due to mismatched protocol or read timeout
-    ///   settings.
+    /// - `Err(&'static str)` if the pool transfer failed due to mismatched settings.
     ///
     /// # Notes
     ///
-    /// - If the protocol or read timeout settings do not match between the old and new instances,
-    ///   the function will return early without transferring the connection pools.
+    /// - If the settings do not match between the old and new instances, the function will return
+    ///   early without transferring the connection pools.
     pub fn transfer_pool(old: &Self, new: &mut Self) -> Result<(), &'static str> {
-        if old.protocol != new.protocol {
+        if old.config.protocol != new.config.protocol {
             return Err("Protocols do not match");
         }
         if old.read_timeout != new.read_timeout {
             return Err("Read timeouts do not match");
         }
+        if !old.config.same_connections(&new.config) {
+            return Err("Connection settings do not match");
+        }
 
         new.h1_pool = old.h1_pool.clone();
         new.h2_pool = old.h2_pool.clone();
@@ -181,15 +529,11 @@ impl<K: 'static, IO: AsyncWriteRent + 'static> HttpConnector<TcpConnector, K, IO
     /// let connector = HttpConnector::build_tcp_http1_only();
     /// ```
     pub fn build_tcp_http1_only() -> Self {
-        Self {
-            connector: TcpConnector::default(),
+        let config = HttpConnectorConfig {
             protocol: Protocol::HTTP11,
-            h1_pool: Some(ConnectionPool::default()),
-            h2_pool: ConnectionPool::new(None),
-            connecting: UnsafeCell::new(HashMap::new()),
-            h2_builder: MonoioH2Builder::default(),
-            read_timeout: None,
-        }
+            ..Default::default()
+        };
+        Self::with_config(TcpConnector::default(), config)
     }
 
     /// Builds a new `HttpConnector` with a `TcpConnector` that supports only HTTP/2.
@@ -202,15 +546,11 @@ impl<K: 'static, IO: AsyncWriteRent + 'static> HttpConnector<TcpConnector, K, IO
     /// let connector = HttpConnector::build_tcp_http2_only();
     /// ```
     pub fn build_tcp_http2_only() -> Self {
-        Self {
-            connector: TcpConnector::default(),
+        let config = HttpConnectorConfig {
             protocol: Protocol::HTTP2,
-            h1_pool: Some(ConnectionPool::default()),
-            h2_pool: ConnectionPool::new(None),
-            connecting: UnsafeCell::new(HashMap::new()),
-            h2_builder: MonoioH2Builder::default(),
-            read_timeout: None,
-        }
+            ..Default::default()
+        };
+        Self::with_config(TcpConnector::default(), config)
     }
 }
 
@@ -227,15 +567,7 @@ impl<C: Default, K: 'static, IO: AsyncWriteRent + 'static> HttpConnector<TlsConn
     pub fn build_tls_http1_only() -> Self {
         let alpn = vec!["http/1.1"];
         let tls_connector = TlsConnector::new_with_tls_default(C::default(), Some(alpn));
-        Self {
-            connector: tls_connector,
-            protocol: Protocol::default(),
-            h1_pool: Some(ConnectionPool::default()),
-            h2_pool: ConnectionPool::new(None),
-            connecting: UnsafeCell::new(HashMap::new()),
-            h2_builder: MonoioH2Builder::default(),
-            read_timeout: None,
-        }
+        Self::new(tls_connector)
     }
 
     /// Builds a new `HttpConnector` with a `TlsConnector` that supports only HTTP/2.
@@ -250,15 +582,26 @@ impl<C: Default, K: 'static, IO: AsyncWriteRent + 'static> HttpConnector<TlsConn
     pub fn build_tls_http2_only() -> Self {
         let alpn = vec!["h2"];
         let tls_connector = TlsConnector::new_with_tls_default(C::default(), Some(alpn));
-        Self {
-            connector: tls_connector,
-            protocol: Protocol::default(),
-            h1_pool: Some(ConnectionPool::default()),
-            h2_pool: ConnectionPool::new(None),
-            connecting: UnsafeCell::new(HashMap::new()),
-            h2_builder: MonoioH2Builder::default(),
-            read_timeout: None,
-        }
+        Self::new(tls_connector)
+    }
+}
+
+impl HttpConnector<UnifiedConnector, UnifiedAddr, UnifiedStream> {
+    /// Builds a new `HttpConnector` serving `http`, `https` and `http+unix` uris alike, routed by
+    /// the scheme of their [`UnifiedAddr`], e.g. derived with `UnifiedAddr::try_from(&uri)`.
+    ///
+    /// Plain text connections speak HTTP/1.1, TLS ones the protocol negotiated with ALPN.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use monoio_transports::http::HttpConnector;
+    ///
+    /// let connector = HttpConnector::build_unified();
+    /// ```
+    #[inline]
+    pub fn build_unified() -> Self {
+        Self::new(UnifiedConnector::default())
     }
 }
 
@@ -301,26 +644,166 @@ where
     type Connection = HttpConnection<K, IO>;
     type Error = crate::TransportError;
 
+    #[inline]
     async fn connect(&self, key: K) -> Result<Self::Connection, Self::Error> {
-        if self.is_config_auto() || self.is_config_h2() {
+        self.connect_as(key, None).await
+    }
+}
+
+impl<C, K: Key, IO> HttpConnector<C, K, IO>
+where
+    C: Connector<K, Connection = IO>,
+    C::Connection: TransportConnMetadata<Metadata = TransportConnMeta>,
+    crate::TransportError: From<C::Error>,
+    IO: AsyncReadRent + AsyncWriteRent + Split + Unpin + 'static,
+{
+    /// Connects to `key` with a connection serving `version`, or any connection when `None`.
+    async fn connect_as(
+        &self,
+        key: K,
+        version: Option<RequiredVersion>,
+    ) -> Result<HttpConnection<K, IO>, crate::TransportError> {
+        Ok(self.connect_early(key, version, None).await?.0)
+    }
+
+    /// Like [`connect_as`](Self::connect_as), sending `early` as early data if a new HTTP/1.1
+    /// connection is established. Returns whether the server accepted it.
+    async fn connect_early(
+        &self,
+        key: K,
+        version: Option<RequiredVersion>,
+        early: Option<&[u8]>,
+    ) -> Result<(HttpConnection<K, IO>, bool), crate::TransportError> {
+        if self.lifecycle.shut_down.get() {
+            return Err(crate::TransportError::Shutdown);
+        }
+        let (h1, h2) = match version {
+            None => (
+                self.is_config_auto() || self.is_config_h1(),
+                self.is_config_auto() || self.is_config_h2(),
+            ),
+            Some(version) if version.is_http1() => (true, false),
+            Some(version) if version.is_http2() => (false, true),
+            Some(RequiredVersion(version)) => {
+                return Err(crate::TransportError::VersionUnavailable(version))
+            }
+        };
+        if h2 {
             if let Some(conn) = try_get!(self, h2_pool, key) {
-                return Ok(conn.into());
+                #[cfg(feature = "tracing")]
+                tracing::debug!(version = ?http::Version::HTTP_2, "pool checkout hit");
+                self.on_checkout(true);
+                return Ok((conn.into(), false));
             }
         }
 
-        if self.is_config_auto() || self.is_config_h1() {
+        let start = std::time::Instant::now();
+        let reservation = match h1 {
+            true => self.reserve(&key).await?,
+            false => Reservation::default(),
+        };
+        if h1 {
             if let Some(h1_pool) = &self.h1_pool {
-                if let Some(h1_pooled) = h1_pool.get(&key) {
-                    return Ok(h1_pooled.into());
+                if let Some(mut h1_pooled) = h1_pool.get(&key) {
+                    h1_pool.record_wait(start.elapsed());
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        version = ?http::Version::HTTP_11,
+                        wait = ?start.elapsed(),
+                        "pool checkout hit"
+                    );
+                    self.on_checkout(true);
+                    h1_pooled.hold(reservation);
+                    return Ok((h1_pooled.into(), false));
                 }
             }
         }
 
+        #[cfg(feature = "tracing")]
+        tracing::debug!("pool checkout miss");
+        self.on_checkout(false);
+        let (conn, sent) = self
+            .connect_reserved(key, reservation, version, early)
+            .await?;
+        if let (Some(h1_pool), HttpConnection::Http1(_)) = (&self.h1_pool, &conn) {
+            h1_pool.record_wait(start.elapsed());
+        }
+        Ok((conn, sent))
+    }
+
+    #[inline]
+    fn on_checkout(&self, reused: bool) {
+        if let Some(metrics) = &self.config.metrics {
+            metrics.on_checkout(reused);
+        }
+    }
+
+    /// Reserves a slot for an HTTP/1.1 connection to `key`, see
+    /// [`PoolConfig::with_max_connections_per_key`].
+    async fn reserve(&self, key: &K) -> Result<Reservation, crate::TransportError> {
+        match &self.h1_pool {
+            Some(pool) => Ok(pool.reserve(key).await?),
+            None => Ok(Reservation::default()),
+        }
+    }
+
+    /// Establishes a new connection to `key` serving `version`, without looking for an idle
+    /// HTTP/1.1 one.
+    async fn connect_fresh(
+        &self,
+        key: K,
+        version: Option<RequiredVersion>,
+    ) -> Result<HttpConnection<K, IO>, crate::TransportError> {
+        if self.lifecycle.shut_down.get() {
+            return Err(crate::TransportError::Shutdown);
+        }
+        self.on_checkout(false);
+        let reservation = match version {
+            Some(version) if version.is_http2() => Reservation::default(),
+            _ => self.reserve(&key).await?,
+        };
+        Ok(self
+            .connect_reserved(key, reservation, version, None)
+            .await?
+            .0)
+    }
+
+    /// Establishes a new connection to `key` serving `version`, an HTTP/1.1 one holds
+    /// `reservation`. Sends `early` as early data, returning whether the server accepted it.
+    async fn connect_reserved(
+        &self,
+        key: K,
+        reservation: Reservation,
+        version: Option<RequiredVersion>,
+        mut early: Option<&[u8]>,
+    ) -> Result<(HttpConnection<K, IO>, bool), crate::TransportError> {
+        let connector = match (version, &self.http1_connector) {
+            (Some(version), Some(connector)) if version.is_http1() => connector,
+            _ => &self.connector,
+        };
         // We use ALPN to determine if connector should use HTTP/2 codecs or HTTP/1.1
-        let transport_conn = self.connector.connect(key.clone()).await?;
+        let start = std::time::Instant::now();
+        let (transport_conn, sent) = loop {
+            let (conn, sent) = match early.take() {
+                Some(data) => connector.connect_early(key.clone(), data).await?,
+                None => (connector.connect(key.clone()).await?, false),
+            };
+            // The HTTP/1.1 request sent early cannot be answered over HTTP/2, connect again.
+            if !(sent && conn.get_conn_metadata().is_alpn_h2()) {
+                break (conn, sent);
+            }
+        };
         let conn_meta = transport_conn.get_conn_metadata();
-
-        let connect_to_h2 = self.is_config_h2() || conn_meta.is_alpn_h2();
+        let connect_to_h2 = match version {
+            None => self.is_config_h2() || conn_meta.is_alpn_h2(),
+            // Without ALPN, HTTP/2 is spoken with prior knowledge.
+            Some(version) if version.is_http2() && conn_meta.alpn() != Alpn::HTTP11 => true,
+            Some(version) if version.is_http1() && !conn_meta.is_alpn_h2() => false,
+            Some(RequiredVersion(version)) => {
+                return Err(crate::TransportError::VersionUnavailable(version))
+            }
+        };
+        let info = ConnectionInfo::new(conn_meta, start.elapsed());
 
         if connect_to_h2 {
             let lock = {
@@ -334,27 +817,323 @@ where
             // get lock and try again
             let _guard = lock.acquire().await?;
             if let Some(conn) = try_get!(self, h2_pool, key) {
-                return Ok(conn.into());
+                return Ok((conn.into(), false));
             }
 
-            let (tx, conn) = self.h2_builder.handshake(transport_conn).await?;
+            let (tx, conn) = self.config.h2_builder.handshake(transport_conn).await?;
             monoio::spawn(conn);
-            self.h2_pool.put(key, Http2Connection::new(tx.clone()));
-            Ok(Http2Connection::new(tx.clone()).into())
+            let conn = Http2Connection::new(tx).with_info(info);
+            self.h2_pool.put(key, conn.clone());
+            Ok((conn.into(), false))
         } else {
             let client_codec = if let Some(timeout) = self.read_timeout {
                 ClientCodec::new_with_timeout(transport_conn, timeout)
             } else {
                 ClientCodec::new(transport_conn)
             };
-            let http_conn = Http1Connection::new(client_codec);
+            let http_conn = Http1Connection::new(client_codec)
+                .with_limits(self.config.limits, self.read_timeout)
+                .with_keep_alive(self.config.keep_alive)
+                .with_expect_continue(self.config.expect_continue)
+                .with_header_case(self.config.header_case)
+                .with_buffer_pool(self.config.buffer_pool.clone())
+                .with_info(info);
             let pooled = if let Some(pool) = &self.h1_pool {
-                pool.link(key, http_conn)
+                let mut pooled = pool.link(key, http_conn);
+                pooled.hold(reservation);
+                pooled
             } else {
                 Pooled::unpooled(http_conn)
             };
-            Ok(pooled.into())
+            Ok((pooled.into(), sent))
+        }
+    }
+
+    /// Shuts this connector and its clones down gracefully, e.g. before a service exits.
+    ///
+    /// New requests and connections fail with
+    /// [`TransportError::Shutdown`](crate::TransportError::Shutdown) right away, while the
+    /// requests already sent with [`request`](Self::request) get up to `timeout` to complete,
+    /// their bodies included over HTTP/1.1. The pooled connections are then closed, and the ones
+    /// still in use once they are released. Returns whether all requests completed within
+    /// `timeout`; the others keep running. Requires the monoio timer driver.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.lifecycle.shut_down.set(true);
+        let drained = monoio::time::timeout(timeout, self.lifecycle.drained())
+            .await
+            .is_ok();
+        if let Some(pool) = &self.h1_pool {
+            pool.evict_all();
+        }
+        self.h2_pool.evict_all();
+        drained
+    }
+
+    /// Whether [`shutdown`](Self::shutdown) was called on this connector or one of its clones.
+    #[inline]
+    pub fn is_shut_down(&self) -> bool {
+        self.lifecycle.shut_down.get()
+    }
+
+    /// Establishes connections to `key` ahead of traffic, handshakes included, and pools them
+    /// so the first requests after a deploy do not pay for them.
+    ///
+    /// Up to `n` HTTP/1.1 connections are kept idle, within the limits of the pool and no more
+    /// than [`PoolConfig::with_max_connections_per_key`]: warming up stops once the slots of `key`
+    /// are all reserved, e.g. by connections in use, instead of waiting for one to be released. A
+    /// single connection is enough when the server negotiates HTTP/2. Returns how many connections
+    /// were established; the first failure is returned instead, the connections already
+    /// established stay pooled.
+    pub async fn warm_up(&self, key: K, n: usize) -> Result<usize, crate::TransportError> {
+        let n = self
+            .h1_pool
+            .as_ref()
+            .and_then(|pool| pool.config().max_connections_per_key())
+            .map_or(n, |max| n.min(max));
+        // Connections are held until all are established, otherwise they would be reused.
+        let mut conns = Vec::with_capacity(n);
+        while conns.len() < n {
+            if self.lifecycle.shut_down.get() {
+                return Err(crate::TransportError::Shutdown);
+            }
+            let reservation = match &self.h1_pool {
+                Some(pool) => match pool.try_reserve(&key) {
+                    Some(reservation) => reservation,
+                    None => break,
+                },
+                None => Reservation::default(),
+            };
+            self.on_checkout(false);
+            let conn = self
+                .connect_reserved(key.clone(), reservation, None, None)
+                .await?
+                .0;
+            let is_h2 = matches!(conn, HttpConnection::Http2(_));
+            conns.push(conn);
+            if is_h2 {
+                break;
+            }
+        }
+        Ok(conns.len())
+    }
+
+    /// Connects to `key` and sends the request built by `make_request`, whose body is any
+    /// [`IntoBody`], e.g. `Bytes`, a `String` or a [`StreamBody`](super::body::StreamBody).
+    ///
+    /// If a reused HTTP/1.1 connection turns out to have been closed by the server while idle
+    /// (see [`HttpConnection::is_stale`]), it is discarded and the request is rebuilt and sent
+    /// once more on a fresh connection before the error is surfaced.
+    ///
+    /// With the `gzip`, `brotli` or `zstd` features, the response body is decoded according to
+    /// its `Content-Encoding`, unless turned off with `set_decompress`.
+    pub async fn request<B, E, F>(
+        &self,
+        key: K,
+        make_request: F,
+    ) -> Result<Response<HttpBody>, crate::TransportError>
+    where
+        F: FnMut() -> Request<B>,
+        B: IntoBody,
+        ClientCodec<IO>: Sink<Request<B::Body>, Error = E>,
+        E: std::fmt::Debug + Into<HttpError>,
+        Request<B::Body>: IntoParts<Parts = RequestHead, Body = B::Body>,
+    {
+        self.request_with(key, make_request, None).await
+    }
+
+    /// Sends a request like [`request`](Self::request), failing with
+    /// [`TransportError::Cancelled`](crate::TransportError::Cancelled) once `abort` is aborted,
+    /// whether the request is connecting, being sent or waiting for its response. See
+    /// [`abort`](super::abort).
+    pub async fn request_abortable<B, E, F>(
+        &self,
+        key: K,
+        abort: &AbortHandle,
+        make_request: F,
+    ) -> Result<Response<HttpBody>, crate::TransportError>
+    where
+        F: FnMut() -> Request<B>,
+        B: IntoBody,
+        ClientCodec<IO>: Sink<Request<B::Body>, Error = E>,
+        E: std::fmt::Debug + Into<HttpError>,
+        Request<B::Body>: IntoParts<Parts = RequestHead, Body = B::Body>,
+    {
+        self.request_with(key, make_request, Some(abort)).await
+    }
+
+    async fn request_with<B, E, F>(
+        &self,
+        key: K,
+        make_request: F,
+        abort: Option<&AbortHandle>,
+    ) -> Result<Response<HttpBody>, crate::TransportError>
+    where
+        F: FnMut() -> Request<B>,
+        B: IntoBody,
+        ClientCodec<IO>: Sink<Request<B::Body>, Error = E>,
+        E: std::fmt::Debug + Into<HttpError>,
+        Request<B::Body>: IntoParts<Parts = RequestHead, Body = B::Body>,
+    {
+        let _in_flight = self.lifecycle.begin();
+        let start = std::time::Instant::now();
+        let recorder = self.config.capture.as_ref().map(Capture::recorder);
+        let send = self.send(key, make_request, recorder.as_ref());
+        // Dropping the request closes its HTTP/1.1 connection, which is not pooled while in use.
+        let result = match abort {
+            Some(abort) => abort
+                .run(send)
+                .await
+                .unwrap_or(Err(crate::TransportError::Cancelled)),
+            None => send.await,
+        };
+        if let (Some(capture), Some(recorder)) = (&self.config.capture, recorder) {
+            capture.record(recorder, result.as_ref().err());
+        }
+        if let Some(metrics) = &self.config.metrics {
+            match &result {
+                Ok(response) => {
+                    metrics.on_request(response.version(), response.status(), start.elapsed())
+                }
+                Err(e) => metrics.on_error(e),
+            }
         }
+        result
+    }
+
+    async fn send<B, E, F>(
+        &self,
+        key: K,
+        mut make_request: F,
+        recorder: Option<&Recorder>,
+    ) -> Result<Response<HttpBody>, crate::TransportError>
+    where
+        F: FnMut() -> Request<B>,
+        B: IntoBody,
+        ClientCodec<IO>: Sink<Request<B::Body>, Error = E>,
+        E: std::fmt::Debug + Into<HttpError>,
+        Request<B::Body>: IntoParts<Parts = RequestHead, Body = B::Body>,
+    {
+        #[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+        let mut decode = false;
+        let mut make_request = || {
+            let mut request = make_request().map(IntoBody::into_body);
+            #[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+            if self.config.decompress {
+                decode = super::encoding::accept_encoding(&mut request);
+            }
+            if let Some(auth) = &self.config.default_auth {
+                request
+                    .headers_mut()
+                    .entry(http::header::AUTHORIZATION)
+                    .or_insert_with(|| auth.clone());
+            }
+            if !self.config.interceptors.is_empty() {
+                let (mut parts, body) = request.into_parts();
+                for interceptor in &self.config.interceptors {
+                    interceptor.on_request(&mut parts)?;
+                }
+                request = Request::from_parts(parts, body);
+            }
+            if let Some(recorder) = recorder {
+                recorder.on_request(&request);
+            }
+            Ok::<_, crate::TransportError>(request)
+        };
+        let on_connected = |conn: &HttpConnection<K, IO>| {
+            if let Some(recorder) = recorder {
+                recorder.on_connected(conn.info());
+            }
+        };
+        #[cfg(feature = "cookie")]
+        let is_tls = |conn: &HttpConnection<K, IO>| {
+            conn.info().is_some_and(|info| info.tls_version().is_some())
+        };
+        // Returns the uri the cookies of the response are stored for.
+        #[cfg(feature = "cookie")]
+        let add_cookies = |request: &mut Request<B::Body>, tls: bool| {
+            let jar = self.config.cookie_jar.as_ref()?;
+            let uri = super::cookie::request_uri(request.uri(), request.headers(), tls)?;
+            if let Some(cookies) = jar.header_value(&uri) {
+                request
+                    .headers_mut()
+                    .entry(http::header::COOKIE)
+                    .or_insert(cookies);
+            }
+            Some(uri)
+        };
+        #[allow(unused_mut)]
+        let mut request = make_request()?;
+        let version = request.extensions().get::<RequiredVersion>().copied();
+        #[cfg(feature = "cookie")]
+        let had_cookies = request.headers().contains_key(http::header::COOKIE);
+        #[cfg(feature = "cookie")]
+        let mut cookie_uri = None;
+        let early = match self.is_early_data(&request) {
+            true => {
+                // Early data is only accepted over TLS.
+                #[cfg(feature = "cookie")]
+                {
+                    cookie_uri = add_cookies(&mut request, true);
+                }
+                let (head, body) = request.into_parts();
+                let data = encode_early_head(&head, self.config.header_case);
+                request = Request::from_parts(head, body);
+                Some(data)
+            }
+            false => None,
+        };
+        let (mut conn, sent) = self
+            .connect_early(key.clone(), version, early.as_deref())
+            .await?;
+        on_connected(&conn);
+        #[cfg(feature = "cookie")]
+        if !sent {
+            // The connection may not be a TLS one after all.
+            if early.is_some() && !had_cookies {
+                request.headers_mut().remove(http::header::COOKIE);
+            }
+            cookie_uri = add_cookies(&mut request, is_tls(&conn));
+        }
+        let result = match sent {
+            true => {
+                let (head, _) = request.into_parts();
+                conn.read_early_response(&head).await.0
+            }
+            false => conn.send_request(request).await.0,
+        };
+        let mut response = match result {
+            Ok(response) => response,
+            Err(_e) if conn.is_stale(&_e) => {
+                #[cfg(feature = "logging")]
+                tracing::debug!("pooled connection was stale ({_e}), retrying on a fresh one");
+                drop(conn);
+                let mut conn = self.connect_fresh(key, version).await?;
+                on_connected(&conn);
+                #[allow(unused_mut)]
+                let mut request = make_request()?;
+                #[cfg(feature = "cookie")]
+                {
+                    cookie_uri = add_cookies(&mut request, is_tls(&conn));
+                }
+                conn.send_request(request).await.0?
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(recorder) = recorder {
+            recorder.on_response(&mut response).await?;
+        }
+        #[cfg(feature = "cookie")]
+        if let (Some(jar), Some(uri)) = (&self.config.cookie_jar, &cookie_uri) {
+            jar.store(uri, response.headers());
+        }
+        #[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+        if decode {
+            super::encoding::decode_in_place(&mut response)?;
+        }
+        for interceptor in self.config.interceptors.iter().rev() {
+            interceptor.on_response(&mut response)?;
+        }
+        Ok(response)
     }
 }
 
@@ -466,13 +1245,15 @@ where
 
 #[cfg(test)]
 mod tests {
-    use std::net::ToSocketAddrs;
-
+    use bytes::Bytes;
     use http::{request, Uri};
-    use monoio_http::{common::body::HttpBody, h1::payload::Payload};
+    use monoio_http::{
+        common::body::{Body, HttpBody},
+        h1::payload::Payload,
+    };
 
     use super::*;
-    use crate::connectors::{TcpConnector, TcpTlsAddr};
+    use crate::connectors::{HostPort, TcpConnector, TcpTlsAddr};
 
     #[monoio::test(enable_timer = true)]
     async fn test_default_https_connector() -> Result<(), crate::TransportError> {
@@ -563,10 +1344,13 @@ mod tests {
             host: String,
             port: u16,
         }
-        impl ToSocketAddrs for Key {
-            type Iter = std::vec::IntoIter<std::net::SocketAddr>;
-            fn to_socket_addrs(&self) -> std::io::Result<Self::Iter> {
-                (self.host.as_str(), self.port).to_socket_addrs()
+        impl HostPort for Key {
+            fn host(&self) -> &str {
+                &self.host
+            }
+
+            fn port(&self) -> u16 {
+                self.port
             }
         }
 
@@ -598,4 +1382,842 @@ mod tests {
         Ok(())
     }
     // See http_with_tcp for plain text HTTP/2 example
+
+    /// Spawns a server accepting connections and reading from them without ever answering.
+    fn silent_server() -> std::net::SocketAddr {
+        use monoio::io::AsyncReadRent;
+
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                monoio::spawn(async move {
+                    let mut buf = vec![0; 1024];
+                    loop {
+                        let (res, b) = conn.read(buf).await;
+                        buf = b;
+                        if !matches!(res, Ok(n) if n > 0) {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn test_stale_connection_retry() {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        // Answers a single request per connection, then closes it without announcing it.
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                let (res, _) = conn.read(vec![0; 1024]).await;
+                if res.is_ok() {
+                    let _ = conn
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok".as_slice())
+                        .await;
+                }
+            }
+        });
+
+        let connector = HttpConnector::build_tcp_http1_only();
+        let req = || {
+            request::Builder::new()
+                .uri("/")
+                .header("Host", "localhost")
+                .body(HttpBody::H1(Payload::None))
+                .unwrap()
+        };
+        for _ in 0..3 {
+            let resp = connector.request(addr, req).await.unwrap();
+            assert_eq!(resp.status(), 200);
+        }
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn runs_interceptors_in_order() {
+        use std::cell::RefCell;
+
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        use crate::http::interceptor::Interceptor;
+
+        struct Tagger(&'static str, Rc<RefCell<Vec<String>>>);
+
+        impl Interceptor for Tagger {
+            fn on_request(
+                &self,
+                request: &mut http::request::Parts,
+            ) -> Result<(), crate::TransportError> {
+                let tags = request.headers.get_all("x-tag").iter().count();
+                self.1
+                    .borrow_mut()
+                    .push(format!("request {} after {tags}", self.0));
+                request
+                    .headers
+                    .append("x-tag", http::HeaderValue::from_static(self.0));
+                Ok(())
+            }
+
+            fn on_response(
+                &self,
+                response: &mut Response<HttpBody>,
+            ) -> Result<(), crate::TransportError> {
+                self.1.borrow_mut().push(format!("response {}", self.0));
+                response
+                    .headers_mut()
+                    .insert("x-last", http::HeaderValue::from_static(self.0));
+                Ok(())
+            }
+        }
+
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = monoio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let (res, buf) = conn.read(vec![0; 1024]).await;
+            let head = String::from_utf8_lossy(&buf[..res.unwrap()]).into_owned();
+            let response = b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n";
+            let _ = conn.write_all(response.as_slice()).await;
+            head
+        });
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut connector = HttpConnector::build_tcp_http1_only();
+        connector.add_interceptor(Tagger("a", log.clone()));
+        connector.add_interceptor(Tagger("b", log.clone()));
+        let request = || {
+            request::Builder::new()
+                .uri("/")
+                .header("Host", "localhost")
+                .body(HttpBody::H1(Payload::None))
+                .unwrap()
+        };
+        let response = connector.request(addr, request).await.unwrap();
+        assert_eq!(response.headers()["x-last"], "a");
+        assert_eq!(
+            *log.borrow(),
+            [
+                "request a after 0",
+                "request b after 1",
+                "response b",
+                "response a"
+            ]
+        );
+        let head = server.await;
+        assert!(head.contains("x-tag: a\r\nx-tag: b\r\n"), "{head}");
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn transfers_pools_between_matching_connectors() {
+        let addr = silent_server();
+        let old = HttpConnector::build_tcp_http1_only();
+        drop(old.connect(addr).await.unwrap());
+        let mut new = HttpConnector::build_tcp_http1_only();
+        assert_eq!(HttpConnector::transfer_pool(&old, &mut new), Ok(()));
+        assert!(new.connect(addr).await.unwrap().is_reused());
+
+        let mut limited = HttpConnector::build_tcp_http1_only();
+        limited.set_max_response_header_size(Some(1024));
+        assert!(HttpConnector::transfer_pool(&old, &mut limited).is_err());
+        let mut titled = HttpConnector::build_tcp_http1_only();
+        titled.set_header_case(HeaderCase::Title);
+        assert!(HttpConnector::transfer_pool(&old, &mut titled).is_err());
+        assert!(!titled.connect(addr).await.unwrap().is_reused());
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn warms_up_connections() {
+        let addr = silent_server();
+        let connector = HttpConnector::build_tcp_http1_only();
+        assert_eq!(connector.warm_up(addr, 3).await.unwrap(), 3);
+        let stats = connector.pool_stats().unwrap();
+        assert_eq!(stats.created, 3);
+        assert_eq!(stats.keys[0].idle, 3);
+        assert!(connector.connect(addr).await.unwrap().is_reused());
+
+        // Slots in use are not waited for.
+        let mut connector = HttpConnector::build_tcp_http1_only();
+        connector.set_pool_config(PoolConfig::new().with_max_connections_per_key(Some(2)));
+        let in_use = connector.connect(addr).await.unwrap();
+        assert_eq!(connector.warm_up(addr, 3).await.unwrap(), 1);
+        drop(in_use);
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn applies_keep_alive_policy() {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                monoio::spawn(async move {
+                    let mut served = 0;
+                    while matches!(conn.read(vec![0; 1024]).await.0, Ok(n) if n > 0) {
+                        served += 1;
+                        // The second request of a connection is told it will be closed soon.
+                        let keep_alive = if served == 2 { "timeout=1" } else { "max=5" };
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\nkeep-alive: {keep_alive}\r\ncontent-length: \
+                             0\r\n\r\n"
+                        );
+                        let _ = conn.write_all(response.into_bytes()).await;
+                    }
+                });
+            }
+        });
+        let request = || {
+            request::Builder::new()
+                .uri("/")
+                .header("Host", "localhost")
+                .body(HttpBody::H1(Payload::None))
+                .unwrap()
+        };
+
+        let mut connector = HttpConnector::build_tcp_http1_only();
+        connector.set_max_requests_per_connection(Some(2));
+        for _ in 0..4 {
+            connector.request(addr, request).await.unwrap();
+        }
+        assert_eq!(connector.pool_stats().unwrap().created, 2);
+
+        let mut connector = HttpConnector::build_tcp_http1_only();
+        connector.set_keep_alive_hints(true);
+        for _ in 0..3 {
+            connector.request(addr, request).await.unwrap();
+        }
+        // The connection timed out when the server announced it would close it.
+        assert_eq!(connector.pool_stats().unwrap().created, 2);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[monoio::test(enable_timer = true)]
+    async fn traces_request_phases() {
+        use std::sync::{Arc, Mutex};
+
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        #[derive(Clone, Default)]
+        struct Capture(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Capture {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            while matches!(conn.read(vec![0; 1024]).await.0, Ok(n) if n > 0) {
+                let response = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok".to_vec();
+                let _ = conn.write_all(response).await;
+            }
+        });
+
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let request = || {
+            request::Builder::new()
+                .uri("/")
+                .header("Host", "localhost")
+                .body(HttpBody::H1(Payload::None))
+                .unwrap()
+        };
+        let connector = HttpConnector::build_tcp_http1_only();
+        connector.request(addr, request).await.unwrap();
+        connector.request(addr, request).await.unwrap();
+
+        let logs = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        let position = |line: &str| logs.find(line).unwrap_or_else(|| panic!("{line}: {logs}"));
+        assert!(position("pool checkout miss") < position("tcp_connect"));
+        assert!(position("request written") < position("response head received"));
+        assert!(position("response head received") < position("response body received"));
+        assert!(position("response body received") < position("pool checkout hit"));
+        assert!(logs.contains("http_request{method=GET uri=/ version=HTTP/1.1}"));
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn reports_metrics() {
+        use std::sync::Mutex;
+
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        #[derive(Default)]
+        struct Recorded {
+            checkouts: Mutex<Vec<bool>>,
+            requests: Mutex<Vec<http::StatusCode>>,
+            errors: Mutex<usize>,
+        }
+
+        impl ClientMetrics for Recorded {
+            fn on_checkout(&self, reused: bool) {
+                self.checkouts.lock().unwrap().push(reused);
+            }
+
+            fn on_request(&self, _: http::Version, status: http::StatusCode, _: Duration) {
+                self.requests.lock().unwrap().push(status);
+            }
+
+            fn on_error(&self, _error: &crate::TransportError) {
+                *self.errors.lock().unwrap() += 1;
+            }
+        }
+
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            while matches!(conn.read(vec![0; 1024]).await.0, Ok(n) if n > 0) {
+                let response = b"HTTP/1.1 204 No Content\r\ncontent-length: 0\r\n\r\n".to_vec();
+                let _ = conn.write_all(response).await;
+            }
+        });
+        let request = || {
+            request::Builder::new()
+                .uri("/")
+                .header("Host", "localhost")
+                .body(HttpBody::H1(Payload::None))
+                .unwrap()
+        };
+
+        let recorded = Arc::new(Recorded::default());
+        let mut connector = HttpConnector::build_tcp_http1_only();
+        connector.set_metrics(recorded.clone());
+        connector.request(addr, request).await.unwrap();
+        connector.request(addr, request).await.unwrap();
+        let unreachable = "127.0.0.1:1".parse().unwrap();
+        assert!(connector.request(unreachable, request).await.is_err());
+        assert_eq!(*recorded.checkouts.lock().unwrap(), [false, true, false]);
+        assert_eq!(
+            *recorded.requests.lock().unwrap(),
+            [http::StatusCode::NO_CONTENT; 2]
+        );
+        assert_eq!(*recorded.errors.lock().unwrap(), 1);
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn inserts_connection_info() {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            while matches!(conn.read(vec![0; 1024]).await.0, Ok(n) if n > 0) {
+                let response = b"HTTP/1.1 204 No Content\r\ncontent-length: 0\r\n\r\n".to_vec();
+                let _ = conn.write_all(response).await;
+            }
+        });
+        let request = || {
+            request::Builder::new()
+                .uri("/")
+                .header("Host", "localhost")
+                .body(HttpBody::H1(Payload::None))
+                .unwrap()
+        };
+
+        let connector = HttpConnector::build_tcp_http1_only();
+        let first = connector.request(addr, request).await.unwrap();
+        let info = first.extensions().get::<ConnectionInfo>().unwrap().clone();
+        assert_eq!(info.peer_addr(), Some(addr));
+        assert!(info
+            .local_addr()
+            .is_some_and(|local| local.ip().is_loopback()));
+        assert!(!info.is_reused());
+        assert_eq!(info.alpn(), crate::connectors::Alpn::None);
+        assert_eq!(info.tls_version(), None);
+
+        let second = connector.request(addr, request).await.unwrap();
+        let reused = second.extensions().get::<ConnectionInfo>().unwrap();
+        assert!(reused.is_reused());
+        assert_eq!(reused.local_addr(), info.local_addr());
+        assert_eq!(reused.connect_time(), info.connect_time());
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn captures_exchanges() {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        use crate::http::{capture::Capture, response::ResponseExt};
+
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            while matches!(conn.read(vec![0; 1024]).await.0, Ok(n) if n > 0) {
+                let response = b"HTTP/1.1 200 OK\r\ncontent-length: 11\r\n\r\nhello world";
+                let _ = conn.write_all(response.to_vec()).await;
+            }
+        });
+
+        let capture = Capture::new().with_bodies(5);
+        let mut connector = HttpConnector::build_tcp_http1_only();
+        connector
+            .set_default_auth(Some(Credentials::bearer("t0k3n")))
+            .unwrap();
+        connector.set_capture(capture.clone());
+        let request = || {
+            request::Builder::new()
+                .uri("/items?page=2")
+                .header("Host", "localhost")
+                .body(HttpBody::H1(Payload::None))
+                .unwrap()
+        };
+        let response = connector.request(addr, request).await.unwrap();
+        // The whole body is still returned.
+        assert_eq!(response.bytes().await.unwrap(), "hello world");
+
+        let [exchange] = &capture.exchanges()[..] else {
+            panic!("expected one exchange");
+        };
+        assert_eq!(exchange.uri, "/items?page=2");
+        assert_eq!(exchange.request_headers["authorization"], "Bearer t0k3n");
+        assert!(exchange.error.is_none());
+        let response = exchange.response.as_ref().unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.as_deref(), Some(&b"hello"[..]));
+        assert_eq!(response.body_size, Some(11));
+        assert!(response.is_truncated());
+        assert_eq!(
+            exchange.connection.as_ref().unwrap().peer_addr(),
+            Some(addr)
+        );
+
+        #[cfg(feature = "serde")]
+        {
+            let har = capture.har();
+            let entry = &har["log"]["entries"][0];
+            assert_eq!(har["log"]["version"], "1.2");
+            assert_eq!(entry["request"]["method"], "GET");
+            assert_eq!(entry["request"]["queryString"][0]["value"], "2");
+            assert_eq!(entry["response"]["status"], 200);
+            assert_eq!(entry["response"]["content"]["text"], "hello");
+            assert_eq!(entry["serverIPAddress"], "127.0.0.1");
+        }
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn expects_continue() {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        // Rejects uploads to `/private`, and answers others with the size of their body.
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                monoio::spawn(async move {
+                    let mut buf = Vec::new();
+                    let end = loop {
+                        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                            break end + 4;
+                        }
+                        let (res, chunk) = conn.read(vec![0; 1024]).await;
+                        let Ok(n @ 1..) = res else { return };
+                        buf.extend_from_slice(&chunk[..n]);
+                    };
+                    let head = String::from_utf8_lossy(&buf[..end]).to_ascii_lowercase();
+                    assert!(head.contains("expect: 100-continue"));
+                    if head.starts_with("post /private") {
+                        let response = b"HTTP/1.1 401 Unauthorized\r\ncontent-length: 0\r\n\r\n";
+                        let _ = conn.write_all(response.as_slice()).await;
+                        return;
+                    }
+                    let _ = conn
+                        .write_all(b"HTTP/1.1 100 Continue\r\n\r\n".as_slice())
+                        .await;
+                    let mut body = buf.split_off(end);
+                    while body.len() < 4096 {
+                        let (res, chunk) = conn.read(vec![0; 4096]).await;
+                        let Ok(n @ 1..) = res else { return };
+                        body.extend_from_slice(&chunk[..n]);
+                    }
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
+                        body.len().to_string().len(),
+                        body.len()
+                    );
+                    let _ = conn.write_all(response.into_bytes()).await;
+                });
+            }
+        });
+
+        let mut connector = HttpConnector::build_tcp_http1_only();
+        connector.set_expect_continue(Some(1024), Duration::from_secs(1));
+        let request = |path| {
+            move || {
+                request::Builder::new()
+                    .method("POST")
+                    .uri(path)
+                    .header("Host", "localhost")
+                    .body(HttpBody::Ready(Some(Bytes::from(vec![b'x'; 4096]))))
+                    .unwrap()
+            }
+        };
+        let resp = connector.request(addr, request("/private")).await.unwrap();
+        assert_eq!(resp.status(), 401);
+        let resp = connector.request(addr, request("/upload")).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let body = resp.into_body().next_data().await.unwrap().unwrap();
+        assert_eq!(body, "4096");
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn sends_and_reads_trailers() {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        use crate::http::trailers::{trailers, RequestTrailers};
+
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            while !buf.ends_with(b"0\r\nx-sum: ab\r\n\r\n") {
+                let (res, chunk) = conn.read(vec![0; 1024]).await;
+                let Ok(n @ 1..) = res else { return };
+                buf.extend_from_slice(&chunk[..n]);
+            }
+            let request = String::from_utf8_lossy(&buf).to_ascii_lowercase();
+            assert!(request.contains("transfer-encoding: chunked\r\n"));
+            assert!(request.ends_with("\r\n\r\n5\r\nhello\r\n0\r\nx-sum: ab\r\n\r\n"));
+            let response = b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n\
+                2\r\nok\r\n0\r\ngrpc-status: 0\r\n\r\n";
+            let _ = conn.write_all(response.as_slice()).await;
+        });
+
+        let connector = HttpConnector::build_tcp_http1_only();
+        let fields = http::HeaderMap::from_iter([(
+            http::HeaderName::from_static("x-sum"),
+            http::HeaderValue::from_static("ab"),
+        )]);
+        let mut resp = connector
+            .request(addr, || {
+                request::Builder::new()
+                    .method("POST")
+                    .uri("/upload")
+                    .header("Host", "localhost")
+                    .extension(RequestTrailers::from(fields.clone()))
+                    .body(HttpBody::Ready(Some(Bytes::from_static(b"hello"))))
+                    .unwrap()
+            })
+            .await
+            .unwrap();
+        let fields = trailers(&mut resp).await.unwrap().unwrap();
+        assert_eq!(fields["grpc-status"], "0");
+        let body = resp.into_body().next_data().await.unwrap().unwrap();
+        assert_eq!(body, "ok");
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn requests_over_unix_sockets() {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        use crate::connectors::{UnifiedL4Addr, UnifiedL4Connector};
+
+        let path = std::env::temp_dir().join(format!("monoio-http-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        // Unix sockets do not support `SO_REUSEPORT`, which monoio sets by default.
+        let opts = monoio::net::ListenerOpts::new().reuse_port(false);
+        let listener = monoio::net::UnixListener::bind_with_config(&path, &opts).unwrap();
+        monoio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            loop {
+                let (res, buf) = conn.read(vec![0; 1024]).await;
+                let Ok(n @ 1..) = res else { return };
+                assert!(buf[..n].starts_with(b"GET /info HTTP/1.1\r\n"));
+                let response = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                let _ = conn.write_all(response.as_slice()).await;
+            }
+        });
+
+        let uri = crate::connectors::http_unix_uri(&path, "/info").unwrap();
+        let key = UnifiedL4Addr::try_from(&uri).unwrap();
+        assert_eq!(key, UnifiedL4Addr::Unix(path.clone()));
+
+        let connector = HttpConnector::new(UnifiedL4Connector::default());
+        for _ in 0..2 {
+            let resp = connector
+                .request(key.clone(), || {
+                    request::Builder::new()
+                        .uri(uri.clone())
+                        .header("Host", "localhost")
+                        .body(HttpBody::H1(Payload::None))
+                        .unwrap()
+                })
+                .await
+                .unwrap();
+            let body = resp.into_body().next_data().await.unwrap().unwrap();
+            assert_eq!(body, "ok");
+        }
+        let stats = connector.pool_stats().unwrap();
+        assert_eq!((stats.created, stats.hits), (1, 1));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn routes_by_scheme() {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        async fn serve<S: AsyncReadRent + AsyncWriteRent>(mut conn: S) {
+            loop {
+                let (res, _) = conn.read(vec![0; 1024]).await;
+                let Ok(1..) = res else { return };
+                let response = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                let _ = conn.write_all(response.as_slice()).await;
+            }
+        }
+
+        let tcp = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = tcp.local_addr().unwrap().port();
+        monoio::spawn(async move {
+            while let Ok((conn, _)) = tcp.accept().await {
+                monoio::spawn(serve(conn));
+            }
+        });
+        let path = std::env::temp_dir().join(format!("monoio-unified-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let opts = monoio::net::ListenerOpts::new().reuse_port(false);
+        let unix = monoio::net::UnixListener::bind_with_config(&path, &opts).unwrap();
+        monoio::spawn(async move {
+            while let Ok((conn, _)) = unix.accept().await {
+                monoio::spawn(serve(conn));
+            }
+        });
+
+        let connector = HttpConnector::build_unified();
+        let uris = [
+            format!("http://127.0.0.1:{port}/").parse::<Uri>().unwrap(),
+            crate::connectors::http_unix_uri(&path, "/").unwrap(),
+        ];
+        for uri in uris {
+            let key = UnifiedAddr::try_from(&uri).unwrap();
+            assert!(key.sn.is_none());
+            let resp = connector
+                .request(key, || {
+                    request::Builder::new()
+                        .uri(uri.clone())
+                        .header("Host", "localhost")
+                        .body(HttpBody::Ready(None))
+                        .unwrap()
+                })
+                .await
+                .unwrap();
+            let body = resp.into_body().next_data().await.unwrap().unwrap();
+            assert_eq!(body, "ok");
+        }
+
+        // `https` uris are connected with TLS, which the plain text server does not speak.
+        let uri = format!("https://127.0.0.1:{port}/").parse::<Uri>().unwrap();
+        let key = UnifiedAddr::try_from(&uri).unwrap();
+        assert!(key.sn.is_some());
+        assert!(connector.connect(key).await.is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn test_phase_timeouts() {
+        let addr = silent_server();
+
+        let uri = format!("https://localhost:{}/", addr.port())
+            .parse::<Uri>()
+            .unwrap();
+        let key = TcpTlsAddr::try_from(&uri).unwrap();
+        let tls = TlsConnector::<TcpConnector>::default()
+            .with_handshake_timeout(Some(Duration::from_millis(50)));
+        let err = crate::TransportError::from(tls.connect(key).await.unwrap_err());
+        assert!(matches!(err, crate::TransportError::TlsHandshakeTimeout));
+
+        let connector = HttpConnector::build_tcp_http1_only();
+        let mut conn = connector.connect(addr).await.unwrap();
+        let req = request::Builder::new()
+            .uri("/")
+            .header("Host", "localhost")
+            .body(HttpBody::H1(Payload::None))
+            .unwrap();
+        let (res, reuse) = conn
+            .send_request_timeout(req, Duration::from_millis(50))
+            .await;
+        assert!(matches!(res, Err(crate::TransportError::ResponseTimeout)));
+        assert!(!reuse);
+        assert!(!crate::pool::Poolable::is_open(&conn));
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn enforces_response_limits() {
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let responses: [&'static [u8]; 4] = [
+            b"HTTP/1.1 200 OK\r\nx-padding: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\ncontent-length: 20\r\n\r\n01234567890123456789",
+            b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\na\r\n0123456789\r\n\
+              a\r\n0123456789\r\n0\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\nfine",
+        ];
+        monoio::spawn(async move {
+            for response in responses {
+                let (mut conn, _) = listener.accept().await.unwrap();
+                monoio::spawn(async move {
+                    use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+                    loop {
+                        let (res, _) = conn.read(vec![0; 1024]).await;
+                        if !matches!(res, Ok(n) if n > 0) {
+                            break;
+                        }
+                        let _ = conn.write_all(response).await;
+                    }
+                });
+            }
+        });
+
+        let mut connector: HttpConnector<TcpConnector, std::net::SocketAddr, _> =
+            HttpConnector::build_tcp_http1_only();
+        connector.set_max_response_header_size(Some(64));
+        connector.set_max_response_body_size(Some(16));
+        let request = || {
+            request::Builder::new()
+                .uri("/")
+                .header("Host", "localhost")
+                .body(HttpBody::H1(Payload::None))
+                .unwrap()
+        };
+        assert!(matches!(
+            connector.request(addr, request).await,
+            Err(crate::TransportError::HeadersTooLarge(64))
+        ));
+        for _ in 0..2 {
+            assert!(matches!(
+                connector.request(addr, request).await,
+                Err(crate::TransportError::BodyTooLarge(16))
+            ));
+        }
+        // Each failure closed its connection, the last one is reused.
+        for _ in 0..2 {
+            let response = connector.request(addr, request).await.unwrap();
+            assert_eq!(response.status(), 200);
+        }
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn cancels_requests() {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        use crate::connectors::MockConnector;
+
+        // Never answers requests to `/slow`.
+        let server = MockConnector::new().with_handler("api", |mut stream| async move {
+            let mut buf = Vec::new();
+            loop {
+                let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+                    let (res, chunk) = stream.read(vec![0; 1024]).await;
+                    let Ok(n @ 1..) = res else { return };
+                    buf.extend_from_slice(&chunk[..n]);
+                    continue;
+                };
+                if buf.starts_with(b"GET /slow") {
+                    monoio::time::sleep(Duration::from_secs(60)).await;
+                }
+                buf.drain(..end + 4);
+                let response = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                stream.write_all(response.as_slice()).await.0.unwrap();
+            }
+        });
+        let mut connector = HttpConnector::new(server);
+        connector.set_http1_only();
+        let request = |path: &'static str| {
+            move || {
+                request::Builder::new()
+                    .uri(path)
+                    .header("Host", "localhost")
+                    .body(HttpBody::Ready(None))
+                    .unwrap()
+            }
+        };
+        let abort = AbortHandle::new();
+        let resp = connector
+            .request_abortable("api", &abort, request("/"))
+            .await;
+        assert_eq!(resp.unwrap().status(), 200);
+
+        let aborter = abort.clone();
+        monoio::spawn(async move {
+            monoio::time::sleep(Duration::from_millis(20)).await;
+            aborter.abort();
+        });
+        let err = connector
+            .request_abortable("api", &abort, request("/slow"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::TransportError::Cancelled));
+        assert_eq!(connector.connector.connects(&"api"), 1);
+
+        // The cancelled connection was closed.
+        connector.request("api", request("/")).await.unwrap();
+        assert_eq!(connector.connector.connects(&"api"), 2);
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn shuts_down_gracefully() {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+        use crate::connectors::MockConnector;
+
+        // Answers after 50ms.
+        let server = MockConnector::new().with_handler("api", |mut stream| async move {
+            loop {
+                let (res, _) = stream.read(vec![0; 1024]).await;
+                let Ok(1..) = res else { return };
+                monoio::time::sleep(Duration::from_millis(50)).await;
+                let response = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                let _ = stream.write_all(response.as_slice()).await;
+            }
+        });
+        let mut connector = HttpConnector::new(server);
+        connector.set_http1_only();
+        let request = || {
+            request::Builder::new()
+                .uri("/")
+                .header("Host", "localhost")
+                .body(HttpBody::Ready(None))
+                .unwrap()
+        };
+        let in_flight = monoio::spawn({
+            let connector = connector.clone();
+            async move { connector.request("api", request).await }
+        });
+        monoio::time::sleep(Duration::from_millis(10)).await;
+
+        assert!(connector.shutdown(Duration::from_secs(1)).await);
+        assert!(connector.is_shut_down());
+        assert_eq!(in_flight.await.unwrap().status(), 200);
+        assert_eq!(
+            connector
+                .h1_pool
+                .as_ref()
+                .unwrap()
+                .get_idle_connection_count(),
+            0
+        );
+        let err = connector.request("api", request).await.unwrap_err();
+        assert!(matches!(err, crate::TransportError::Shutdown));
+    }
 }
@@ -0,0 +1,534 @@
+//! An in-memory cookie store following RFC 6265.
+//!
+//! A [`CookieJar`] is fed the `Set-Cookie` headers of responses with
+//! [`store`](CookieJar::store) and adds the matching `Cookie` header to later requests with
+//! [`apply`](CookieJar::apply). Both take the absolute uri of the request, which determines the
+//! domain, path and secure matching.
+//!
+//! ```rust
+//! use http::{header, HeaderMap, Uri};
+//! use monoio_transports::http::cookie::CookieJar;
+//!
+//! let jar = CookieJar::new();
+//! let uri = Uri::from_static("https://example.com/account/login");
+//! jar.add("session=abc; Path=/account; Secure", &uri);
+//!
+//! let mut headers = HeaderMap::new();
+//! jar.apply(
+//!     &Uri::from_static("https://example.com/account/me"),
+//!     &mut headers,
+//! );
+//! assert_eq!(headers[header::COOKIE], "session=abc");
+//! ```
+//!
+//! [`HttpConnector::set_cookie_jar`](super::HttpConnector::set_cookie_jar) does both for the
+//! requests of a connector.
+//!
+//! The public suffix list is not consulted. As a coarse safeguard, a `Domain` attribute without
+//! an inner dot is only accepted when it equals the request host.
+use std::{
+    cell::RefCell,
+    net::IpAddr,
+    rc::Rc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use http::{header, HeaderMap, HeaderValue, Uri};
+
+/// A cookie held by a [`CookieJar`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    host_only: bool,
+    path: String,
+    secure: bool,
+    http_only: bool,
+    expires: Option<SystemTime>,
+}
+
+impl Cookie {
+    /// Parses a `Set-Cookie` header value received for `uri`.
+    ///
+    /// Returns `None` if the cookie is malformed or may not be set by `uri`.
+    pub fn parse(set_cookie: &str, uri: &Uri) -> Option<Self> {
+        let host = uri.host()?.trim_end_matches('.').to_ascii_lowercase();
+        let mut attrs = set_cookie.split(';');
+        let (name, value) = attrs.next()?.split_once('=')?;
+        let (name, value) = (name.trim(), value.trim());
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut cookie = Self {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(uri.path()).to_owned(),
+            secure: false,
+            http_only: false,
+            expires: None,
+        };
+        let mut max_age = None;
+        for attr in attrs {
+            let (key, value) = attr.split_once('=').unwrap_or((attr, ""));
+            let (key, value) = (key.trim(), value.trim());
+            if key.eq_ignore_ascii_case("domain") {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                if !domain.is_empty() {
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+            } else if key.eq_ignore_ascii_case("path") {
+                if value.starts_with('/') {
+                    cookie.path = value.to_owned();
+                }
+            } else if key.eq_ignore_ascii_case("secure") {
+                cookie.secure = true;
+            } else if key.eq_ignore_ascii_case("httponly") {
+                cookie.http_only = true;
+            } else if key.eq_ignore_ascii_case("max-age") {
+                if let Ok(secs) = value.parse::<i64>() {
+                    max_age = Some(secs);
+                }
+            } else if key.eq_ignore_ascii_case("expires") {
+                if let Some(expires) = parse_date(value) {
+                    cookie.expires = Some(expires);
+                }
+            }
+        }
+        // Max-Age takes precedence over Expires.
+        if let Some(secs) = max_age {
+            cookie.expires = Some(match u64::try_from(secs) {
+                Ok(secs) if secs > 0 => SystemTime::now() + Duration::from_secs(secs),
+                _ => UNIX_EPOCH,
+            });
+        }
+
+        if !cookie.host_only {
+            let is_ip = host.parse::<IpAddr>().is_ok();
+            if !domain_match(&host, &cookie.domain)
+                || (is_ip || !cookie.domain.contains('.')) && host != cookie.domain
+            {
+                return None;
+            }
+        }
+        // Secure cookies may only be set over a secure channel.
+        if cookie.secure && !is_secure(uri) {
+            return None;
+        }
+        Some(cookie)
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Returns the domain the cookie is sent to.
+    #[inline]
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Returns true if the cookie is only sent to its exact domain, not to its subdomains.
+    #[inline]
+    pub fn host_only(&self) -> bool {
+        self.host_only
+    }
+
+    #[inline]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns true if the cookie is only sent over `https`.
+    #[inline]
+    pub fn secure(&self) -> bool {
+        self.secure
+    }
+
+    #[inline]
+    pub fn http_only(&self) -> bool {
+        self.http_only
+    }
+
+    /// Returns the expiry time, or `None` for a session cookie.
+    #[inline]
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    /// Returns true if the cookie has expired at `now`.
+    #[inline]
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Returns true if the cookie should be sent with a request to `uri`.
+    pub fn matches(&self, uri: &Uri) -> bool {
+        let Some(host) = uri.host() else {
+            return false;
+        };
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(&host, &self.domain)
+        };
+        domain_ok && path_match(uri.path(), &self.path) && (!self.secure || is_secure(uri))
+    }
+}
+
+/// A cookie store shared by the requests of a client.
+///
+/// Clones share the same cookies.
+#[derive(Clone, Debug, Default)]
+pub struct CookieJar {
+    // In creation order, which breaks ties when ordering the `Cookie` header.
+    cookies: Rc<RefCell<Vec<Cookie>>>,
+}
+
+impl CookieJar {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores the cookies set by a response to a request for `uri`.
+    pub fn store(&self, uri: &Uri, headers: &HeaderMap) {
+        for value in headers.get_all(header::SET_COOKIE) {
+            if let Ok(value) = value.to_str() {
+                self.add(value, uri);
+            }
+        }
+    }
+
+    /// Stores a cookie from a `Set-Cookie` header value as if it was received for `uri`, which
+    /// can be used to seed the jar. Returns false if the cookie was rejected.
+    pub fn add(&self, set_cookie: &str, uri: &Uri) -> bool {
+        match Cookie::parse(set_cookie, uri) {
+            Some(cookie) => {
+                self.insert(cookie);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stores `cookie`, replacing the one with the same name, domain and path. An expired cookie
+    /// only removes the one it replaces.
+    pub fn insert(&self, cookie: Cookie) {
+        let mut cookies = self.cookies.borrow_mut();
+        let existing = cookies.iter().position(|c| {
+            c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path
+        });
+        let expired = cookie.is_expired(SystemTime::now());
+        match existing {
+            Some(i) if expired => drop(cookies.remove(i)),
+            Some(i) => cookies[i] = cookie,
+            None if expired => {}
+            None => cookies.push(cookie),
+        }
+    }
+
+    /// Returns the cookies to send with a request to `uri`, longest path first.
+    pub fn matching(&self, uri: &Uri) -> Vec<Cookie> {
+        let now = SystemTime::now();
+        let mut cookies = self.cookies.borrow_mut();
+        cookies.retain(|c| !c.is_expired(now));
+        let mut matching: Vec<_> = cookies.iter().filter(|c| c.matches(uri)).cloned().collect();
+        matching.sort_by_key(|c| std::cmp::Reverse(c.path.len()));
+        matching
+    }
+
+    /// Returns the value of the `Cookie` header for a request to `uri`, if any cookie matches.
+    pub fn header_value(&self, uri: &Uri) -> Option<HeaderValue> {
+        let cookies = self.matching(uri);
+        if cookies.is_empty() {
+            return None;
+        }
+        let value = cookies
+            .iter()
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        HeaderValue::from_str(&value).ok()
+    }
+
+    /// Sets the `Cookie` header of a request to `uri` from the matching cookies, replacing any
+    /// present one.
+    pub fn apply(&self, uri: &Uri, headers: &mut HeaderMap) {
+        match self.header_value(uri) {
+            Some(value) => headers.insert(header::COOKIE, value),
+            None => headers.remove(header::COOKIE),
+        };
+    }
+
+    /// Returns all unexpired cookies.
+    pub fn cookies(&self) -> Vec<Cookie> {
+        let now = SystemTime::now();
+        let mut cookies = self.cookies.borrow_mut();
+        cookies.retain(|c| !c.is_expired(now));
+        cookies.clone()
+    }
+
+    /// Removes the cookie with the given name, domain and path, returning it.
+    pub fn remove(&self, name: &str, domain: &str, path: &str) -> Option<Cookie> {
+        let mut cookies = self.cookies.borrow_mut();
+        let i = cookies.iter().position(|c| {
+            c.name == name && c.domain.eq_ignore_ascii_case(domain) && c.path == path
+        })?;
+        Some(cookies.remove(i))
+    }
+
+    /// Removes all cookies.
+    #[inline]
+    pub fn clear(&self) {
+        self.cookies.borrow_mut().clear();
+    }
+}
+
+/// Returns the absolute uri a request of an [`HttpConnector`](super::HttpConnector) is matched
+/// with, completing an origin-form `uri` with the `Host` header and `https` on TLS connections.
+pub(crate) fn request_uri(uri: &Uri, headers: &HeaderMap, tls: bool) -> Option<Uri> {
+    if uri.scheme().is_some() && uri.authority().is_some() {
+        return Some(uri.clone());
+    }
+    let authority = match headers.get(header::HOST) {
+        Some(host) => host.to_str().ok()?,
+        None => uri.authority()?.as_str(),
+    };
+    Uri::builder()
+        .scheme(if tls { "https" } else { "http" })
+        .authority(authority)
+        .path_and_query(uri.path_and_query().map_or("/", |pq| pq.as_str()))
+        .build()
+        .ok()
+}
+
+fn is_secure(uri: &Uri) -> bool {
+    uri.scheme() == Some(&http::uri::Scheme::HTTPS)
+}
+
+/// Returns true if `host` is `domain` or one of its subdomains (RFC 6265 section 5.1.3).
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain
+        || host.ends_with(domain)
+            && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+            && host.parse::<IpAddr>().is_err()
+}
+
+/// Returns the directory of a request path (RFC 6265 section 5.1.4).
+fn default_path(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path.as_bytes()[cookie_path.len()] == b'/')
+}
+
+/// Parses a cookie date with the lenient algorithm of RFC 6265 section 5.1.1.
+fn parse_date(date: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let is_delimiter = |c: char| matches!(c, '\t' | ' '..='/' | ';'..='@' | '['..='`' | '{'..='~');
+    // Returns the leading digits of a token if there are between min and max of them.
+    let digits = |token: &str, min: usize, max: usize| {
+        let n = token.bytes().take_while(u8::is_ascii_digit).count();
+        (min..=max)
+            .contains(&n)
+            .then(|| token[..n].parse::<u32>().ok())
+            .flatten()
+    };
+
+    let (mut time, mut day, mut month, mut year) = (None, None, None, None);
+    for token in date.split(is_delimiter).filter(|t| !t.is_empty()) {
+        if time.is_none() {
+            let mut parts = token.splitn(3, ':');
+            if let (Some(h), Some(m), Some(s)) = (parts.next(), parts.next(), parts.next()) {
+                if let (Some(h), Some(m), Some(s)) =
+                    (digits(h, 1, 2), digits(m, 1, 2), digits(s, 1, 2))
+                {
+                    time = Some((h, m, s));
+                    continue;
+                }
+            }
+        }
+        if day.is_none() {
+            if let Some(d) = digits(token, 1, 2) {
+                day = Some(d);
+                continue;
+            }
+        }
+        if month.is_none() && token.len() >= 3 {
+            let prefix = token[..3].to_ascii_lowercase();
+            if let Some(m) = MONTHS.iter().position(|m| *m == prefix) {
+                month = Some(m as u32 + 1);
+                continue;
+            }
+        }
+        if year.is_none() {
+            if let Some(y) = digits(token, 2, 4) {
+                year = Some(y);
+            }
+        }
+    }
+
+    let ((hour, minute, second), day, month, mut year) = (time?, day?, month?, year?);
+    year += match year {
+        70..=99 => 1900,
+        0..=69 => 2000,
+        _ => 0,
+    };
+    if !(1..=31).contains(&day) || year < 1601 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    // Days since the epoch of a proleptic Gregorian date, from Howard Hinnant's algorithm.
+    let (y, m) = if month <= 2 {
+        (year as i64 - 1, month + 9)
+    } else {
+        (year as i64, month - 3)
+    };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * m as i64 + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    let secs = days * 86400 + (hour * 3600 + minute * 60 + second) as i64;
+    Some(match u64::try_from(secs) {
+        Ok(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+        Err(_) => UNIX_EPOCH,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_domain_path_and_secure() {
+        let jar = CookieJar::new();
+        let login = Uri::from_static("https://www.example.com/account/login");
+        assert!(jar.add("sid=1; Path=/account; Secure; HttpOnly", &login));
+        assert!(jar.add("lang=en; Domain=.Example.com; Path=/", &login));
+        assert!(jar.add("theme=dark", &login));
+        // A domain the host does not belong to, a bare suffix and a secure cookie over http.
+        assert!(!jar.add("evil=1; Domain=other.com", &login));
+        assert!(!jar.add("evil=1; Domain=com", &login));
+        assert!(!jar.add(
+            "evil=1; Secure",
+            &Uri::from_static("http://www.example.com/")
+        ));
+
+        let header = |uri| {
+            jar.header_value(&Uri::from_static(uri))
+                .map(|v| v.to_str().unwrap().to_owned())
+        };
+        assert_eq!(
+            header("https://www.example.com/account/me").as_deref(),
+            Some("sid=1; theme=dark; lang=en")
+        );
+        assert_eq!(
+            header("http://www.example.com/account").as_deref(),
+            Some("theme=dark; lang=en")
+        );
+        assert_eq!(
+            header("https://api.example.com/accounts").as_deref(),
+            Some("lang=en")
+        );
+        assert_eq!(header("https://example.org/"), None);
+
+        // Max-Age=0 deletes the cookie.
+        assert!(jar.add("theme=; Max-Age=0", &login));
+        assert_eq!(jar.cookies().len(), 2);
+        let mut headers = HeaderMap::new();
+        headers.insert(header::SET_COOKIE, "theme=light; Path=/".parse().unwrap());
+        jar.store(&login, &headers);
+        assert!(jar
+            .cookies()
+            .iter()
+            .any(|c| c.name() == "theme" && c.path() == "/"));
+    }
+
+    #[test]
+    fn parses_cookie_dates() {
+        let expected = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(parse_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(expected));
+        assert_eq!(parse_date("Sunday, 06-Nov-94 08:49:37 GMT"), Some(expected));
+        assert_eq!(parse_date("Sun Nov  6 08:49:37 1994"), Some(expected));
+        assert_eq!(parse_date("not a date"), None);
+
+        let uri = Uri::from_static("http://example.com/");
+        let cookie = Cookie::parse("a=b; Expires=Thu, 01 Jan 1970 00:00:00 GMT", &uri).unwrap();
+        assert!(cookie.is_expired(SystemTime::now()));
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn connector_sends_stored_cookies() {
+        use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+        use monoio_http::common::body::HttpBody;
+
+        use crate::{
+            connectors::MockConnector,
+            http::{response::ResponseExt, HttpConnector},
+        };
+
+        // Sets a cookie, echoing the `Cookie` header of the request.
+        let server = MockConnector::new().with_handler("api", |mut stream| async move {
+            let mut head = Vec::new();
+            while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                let (res, buf) = stream.read(vec![0; 1024]).await;
+                match res {
+                    Ok(n) if n > 0 => head.extend_from_slice(&buf[..n]),
+                    _ => return,
+                }
+            }
+            let head = String::from_utf8_lossy(&head).to_ascii_lowercase();
+            let cookie = head
+                .lines()
+                .find_map(|line| line.strip_prefix("cookie: "))
+                .unwrap_or("none")
+                .to_owned();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nconnection: close\r\nset-cookie: sid=1; Path=/\r\nx-cookie: \
+                 {cookie}\r\ncontent-length: 0\r\n\r\n"
+            );
+            let _ = stream.write_all(response.into_bytes()).await;
+        });
+        let request = |path: &str| {
+            http::Request::get(path)
+                .header(header::HOST, "example.com")
+                .body(HttpBody::Ready(None))
+                .unwrap()
+        };
+
+        let jar = CookieJar::new();
+        let mut connector = HttpConnector::new(server);
+        connector.set_cookie_jar(jar.clone());
+        let response = connector
+            .request("api", || request("/login"))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["x-cookie"], "none");
+        response.bytes().await.unwrap();
+        assert_eq!(jar.cookies()[0].domain(), "example.com");
+
+        let response = connector.request("api", || request("/me")).await.unwrap();
+        assert_eq!(response.headers()["x-cookie"], "sid=1");
+    }
+}
@@ -0,0 +1,332 @@
+//! Saving response bodies to files.
+//!
+//! [`ResponseExt::save_to_file`](super::response::ResponseExt::save_to_file) writes a response
+//! body to a file as it is received, and [`Download::fetch`] sends the request itself, resuming a
+//! partially downloaded file with a `Range` request.
+//!
+//! Body chunks are gathered into buffers of [`Download::with_buffer_size`] bytes written with
+//! io_uring positioned writes, the next buffer being received while the previous one is written.
+//! A write or body error leaves the bytes already written in the file, so a later [`fetch`]
+//! resumes from there.
+//!
+//! [`fetch`]: Download::fetch
+use std::path::Path;
+
+use bytes::{Bytes, BytesMut};
+use http::{header, HeaderValue, Response, StatusCode};
+use monoio::{
+    fs::{File, OpenOptions},
+    io::{sink::Sink, AsyncReadRent, AsyncWriteRent},
+};
+use monoio_http::{
+    common::{
+        body::Body,
+        error::HttpError,
+        request::{Request, RequestHead},
+        IntoParts,
+    },
+    h1::codec::ClientCodec,
+};
+
+use super::{body::IntoBody, HttpConnection};
+use crate::{connectors::Connector, pool::Key, TransportError};
+
+/// The bytes gathered before each write by default.
+const DEFAULT_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Options for saving bodies to files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Download {
+    buffer_size: usize,
+    resume: bool,
+}
+
+impl Default for Download {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            resume: true,
+        }
+    }
+}
+
+impl Download {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the bytes gathered before each write to the file, 256 KiB by default. Two buffers
+    /// are in use at a time.
+    #[inline]
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// Sets whether [`fetch`](Self::fetch) resumes an existing file, true by default. Otherwise
+    /// the file is downloaded anew.
+    #[inline]
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    #[inline]
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    #[inline]
+    pub fn resume(&self) -> bool {
+        self.resume
+    }
+
+    /// Writes the body of `response` to the file at `path`, created or truncated, whatever the
+    /// status. Returns the bytes written.
+    pub async fn save<B>(
+        &self,
+        response: Response<B>,
+        path: impl AsRef<Path>,
+    ) -> Result<u64, TransportError>
+    where
+        B: Body<Data = Bytes>,
+        B::Error: Into<HttpError>,
+    {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .await?;
+        self.write_body(response.into_body(), &file, 0).await
+    }
+
+    /// Connects to `key`, sends the request built by `make_request` and saves the body of a
+    /// successful response to the file at `path`. Returns the length of the file.
+    ///
+    /// When resuming a non-empty file, the request asks for the rest of it with a `Range`
+    /// header: a `206 Partial Content` response starting at the end of the file is appended to
+    /// it, and a `416 Range Not Satisfiable` one reporting the length of the file means it is
+    /// complete. Any other successful response replaces the file, and other statuses fail with
+    /// [`TransportError::UnexpectedStatus`] leaving it untouched.
+    pub async fn fetch<C, K, IO, B, E, F>(
+        &self,
+        connector: &C,
+        key: &K,
+        path: impl AsRef<Path>,
+        make_request: F,
+    ) -> Result<u64, TransportError>
+    where
+        C: Connector<K, Connection = HttpConnection<K, IO>>,
+        TransportError: From<C::Error>,
+        K: Key,
+        IO: AsyncReadRent + AsyncWriteRent,
+        F: FnOnce() -> http::Request<B>,
+        B: IntoBody,
+        ClientCodec<IO>: Sink<Request<B::Body>, Error = E>,
+        E: std::fmt::Debug + Into<HttpError>,
+        Request<B::Body>: IntoParts<Parts = RequestHead, Body = B::Body>,
+    {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open(path)
+            .await?;
+        let existing = match self.resume {
+            true => file.metadata().await?.len(),
+            false => 0,
+        };
+
+        let mut request = make_request().map(IntoBody::into_body);
+        if existing > 0 {
+            let range =
+                HeaderValue::try_from(format!("bytes={existing}-")).map_err(http::Error::from)?;
+            request.headers_mut().insert(header::RANGE, range);
+        }
+        let mut conn = connector.connect(key.clone()).await?;
+        let response = conn.send_request(request).await.0?;
+
+        let status = response.status();
+        let offset = match status {
+            StatusCode::PARTIAL_CONTENT if existing > 0 => match content_range(&response) {
+                Some((Some(start), _)) if start == existing => existing,
+                _ => return Err(TransportError::UnexpectedStatus(status)),
+            },
+            StatusCode::RANGE_NOT_SATISFIABLE if existing > 0 => {
+                return match content_range(&response) {
+                    Some((None, Some(len))) if len == existing => Ok(existing),
+                    _ => Err(TransportError::UnexpectedStatus(status)),
+                };
+            }
+            status if status.is_success() && status != StatusCode::PARTIAL_CONTENT => 0,
+            status => return Err(TransportError::UnexpectedStatus(status)),
+        };
+        let file = if offset == 0 && existing > 0 {
+            drop(file);
+            OpenOptions::new()
+                .write(true)
+                .truncate(true)
+                .open(path)
+                .await?
+        } else {
+            file
+        };
+        let written = self.write_body(response.into_body(), &file, offset).await?;
+        Ok(offset + written)
+    }
+
+    /// Writes `body` to `file` from `offset`, returning the bytes written.
+    async fn write_body<B>(
+        &self,
+        mut body: B,
+        file: &File,
+        mut offset: u64,
+    ) -> Result<u64, TransportError>
+    where
+        B: Body<Data = Bytes>,
+        B::Error: Into<HttpError>,
+    {
+        let start = offset;
+        let mut pending = BytesMut::with_capacity(self.buffer_size);
+        let mut ended = fill(&mut body, &mut pending, self.buffer_size).await?;
+        while !pending.is_empty() {
+            let buf = std::mem::replace(&mut pending, BytesMut::with_capacity(self.buffer_size));
+            let len = buf.len() as u64;
+            if ended {
+                file.write_all_at(buf, offset).await.0?;
+            } else {
+                // The next buffer is received while this one is written.
+                let (written, filled) = monoio::join!(
+                    file.write_all_at(buf, offset),
+                    fill(&mut body, &mut pending, self.buffer_size)
+                );
+                written.0?;
+                ended = filled?;
+            }
+            offset += len;
+        }
+        Ok(offset - start)
+    }
+}
+
+/// Moves chunks of `body` into `buf` until it holds `size` bytes, returning whether the body
+/// ended.
+async fn fill<B>(body: &mut B, buf: &mut BytesMut, size: usize) -> Result<bool, TransportError>
+where
+    B: Body<Data = Bytes>,
+    B::Error: Into<HttpError>,
+{
+    while buf.len() < size {
+        match body.next_data().await {
+            Some(chunk) => buf.extend_from_slice(&chunk.map_err(Into::into)?),
+            None => return Ok(true),
+        }
+    }
+    Ok(false)
+}
+
+/// Parses the `Content-Range` of a response into the first byte position, absent for
+/// `bytes */len`, and the complete length, absent when unknown.
+fn content_range<B>(response: &Response<B>) -> Option<(Option<u64>, Option<u64>)> {
+    let value = response
+        .headers()
+        .get(header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?;
+    let (range, len) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let len = match len.trim() {
+        "*" => None,
+        len => Some(len.parse().ok()?),
+    };
+    let start = match range.trim() {
+        "*" => None,
+        range => Some(range.split_once('-')?.0.parse().ok()?),
+    };
+    Some((start, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use monoio::io::AsyncWriteRentExt;
+    use monoio_http::common::body::HttpBody;
+
+    use super::*;
+    use crate::{
+        connectors::MockConnector,
+        http::{response::ResponseExt, HttpConnector},
+    };
+
+    /// Serves `"hello world"`, honoring `Range: bytes=N-`.
+    fn server() -> MockConnector<&'static str> {
+        MockConnector::new().with_handler("files", |mut stream| async move {
+            loop {
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let (res, buf) = stream.read(vec![0; 1024]).await;
+                    match res {
+                        Ok(n) if n > 0 => request.extend_from_slice(&buf[..n]),
+                        _ => return,
+                    }
+                }
+                let request = String::from_utf8(request).unwrap().to_ascii_lowercase();
+                let start = request
+                    .split("range: bytes=")
+                    .nth(1)
+                    .and_then(|range| range.split('-').next())
+                    .map(|start| start.parse::<usize>().unwrap());
+                let body = "hello world";
+                let response = match start {
+                    None => format!("HTTP/1.1 200 OK\r\ncontent-length: 11\r\n\r\n{body}"),
+                    Some(11) => "HTTP/1.1 416 Range Not Satisfiable\r\ncontent-range: bytes \
+                                 */11\r\ncontent-length: 0\r\n\r\n"
+                        .to_string(),
+                    Some(start) => format!(
+                        "HTTP/1.1 206 Partial Content\r\ncontent-range: bytes \
+                         {start}-10/11\r\ncontent-length: {}\r\n\r\n{}",
+                        11 - start,
+                        &body[start..]
+                    ),
+                };
+                stream.write_all(response.into_bytes()).await.0.unwrap();
+            }
+        })
+    }
+
+    fn request() -> http::Request<HttpBody> {
+        http::Request::get("/file")
+            .header(header::HOST, "localhost")
+            .body(HttpBody::Ready(None))
+            .unwrap()
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn saves_and_resumes_downloads() {
+        let path = std::env::temp_dir().join(format!("download-{}.txt", std::process::id()));
+        let mut connector = HttpConnector::new(server());
+        connector.set_http1_only();
+
+        let response = connector.request("files", request).await.unwrap();
+        assert_eq!(response.save_to_file(&path).await.unwrap(), 11);
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello world");
+
+        std::fs::write(&path, "hello ").unwrap();
+        let download = Download::new().with_buffer_size(2);
+        let len = download
+            .fetch(&connector, &"files", &path, request)
+            .await
+            .unwrap();
+        assert_eq!(len, 11);
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello world");
+
+        // The file is complete.
+        let len = download
+            .fetch(&connector, &"files", &path, request)
+            .await
+            .unwrap();
+        assert_eq!(len, 11);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,790 @@
+//! Content codings of HTTP bodies.
+//!
+//! - [`DecodedBody`]: A body decoding a `Content-Encoding` chunk by chunk as it is read.
+//! - [`HttpConnection::send_request_decoded`]: Sends a request advertising the supported codings in
+//!   `Accept-Encoding` and decodes the response body.
+//! - [`DecompressRequestExt`]: Opts a request of [`HttpConnector::request`] out of decoding.
+//! - [`Compression`]: Compresses request bodies above a size threshold into an [`EncodedBody`].
+//!
+//! Every coding is behind its own feature: `gzip`, `brotli` (`br`) and `zstd`, and the module is
+//! only available with at least one of them. With any of them enabled,
+//! [`HttpConnector::request`] advertises the codings and decodes response bodies unless turned off
+//! with [`HttpConnector::set_decompress`]. [`HttpConnection::send_request`] sends and hands out
+//! the raw bytes, and compression is opt-in.
+//!
+//! [`HttpConnector::request`]: super::HttpConnector::request
+//! [`HttpConnector::set_decompress`]: super::HttpConnector::set_decompress
+use std::io::{self, Write};
+
+use bytes::Bytes;
+use http::{header, HeaderMap, HeaderValue, Response};
+use monoio::io::{sink::Sink, AsyncReadRent, AsyncWriteRent};
+use monoio_http::{
+    common::{
+        body::{Body, HttpBody, StreamHint},
+        error::HttpError,
+        request::{Request, RequestHead},
+        IntoParts,
+    },
+    h1::{
+        codec::ClientCodec,
+        payload::{stream_payload_pair, Payload},
+    },
+};
+
+use super::HttpConnection;
+use crate::{pool::Key, TransportError};
+
+/// A content coding supported by this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ContentCoding {
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "brotli")]
+    Brotli,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl ContentCoding {
+    /// The codings enabled by features, in order of preference.
+    pub const ENABLED: &'static [ContentCoding] = &[
+        #[cfg(feature = "zstd")]
+        ContentCoding::Zstd,
+        #[cfg(feature = "brotli")]
+        ContentCoding::Brotli,
+        #[cfg(feature = "gzip")]
+        ContentCoding::Gzip,
+    ];
+
+    /// Returns the token of the coding in `Content-Encoding` and `Accept-Encoding`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => "gzip",
+            #[cfg(feature = "brotli")]
+            Self::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            Self::Zstd => "zstd",
+        }
+    }
+
+    /// Parses a coding token, returning `None` for unknown or disabled codings.
+    pub fn from_token(token: &str) -> Option<Self> {
+        Self::ENABLED
+            .iter()
+            .copied()
+            .find(|coding| token.trim().eq_ignore_ascii_case(coding.as_str()))
+    }
+
+    /// Returns the value of an `Accept-Encoding` header listing the enabled codings.
+    pub fn accept_encoding() -> HeaderValue {
+        let value = Self::ENABLED
+            .iter()
+            .map(|coding| coding.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        HeaderValue::from_str(&value).expect("coding tokens are valid header values")
+    }
+}
+
+// Decoders write their output into a buffer which is drained after every chunk.
+enum Decoder {
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzDecoder<Vec<u8>>),
+    #[cfg(feature = "brotli")]
+    Brotli(Box<brotli::DecompressorWriter<Vec<u8>>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Decoder<'static, Vec<u8>>),
+}
+
+impl Decoder {
+    fn new(coding: ContentCoding) -> io::Result<Self> {
+        Ok(match coding {
+            #[cfg(feature = "gzip")]
+            ContentCoding::Gzip => Self::Gzip(flate2::write::GzDecoder::new(Vec::new())),
+            #[cfg(feature = "brotli")]
+            ContentCoding::Brotli => {
+                Self::Brotli(Box::new(brotli::DecompressorWriter::new(Vec::new(), 4096)))
+            }
+            #[cfg(feature = "zstd")]
+            ContentCoding::Zstd => Self::Zstd(zstd::stream::write::Decoder::new(Vec::new())?),
+        })
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(d) => d.write_all(data),
+            #[cfg(feature = "brotli")]
+            Self::Brotli(d) => d.write_all(data),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(d) => d.write_all(data),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(d) => d.try_finish(),
+            #[cfg(feature = "brotli")]
+            Self::Brotli(d) => d.close(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(d) => d.flush(),
+        }
+    }
+
+    fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(d) => d.get_mut(),
+            #[cfg(feature = "brotli")]
+            Self::Brotli(d) => d.get_mut(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(d) => d.get_mut(),
+        })
+    }
+}
+
+/// A body decoding its content coding while it is read.
+///
+/// Without a coding it passes the inner body through unchanged.
+pub struct DecodedBody {
+    inner: HttpBody,
+    decoder: Option<Decoder>,
+    finished: bool,
+}
+
+impl std::fmt::Debug for DecodedBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecodedBody")
+            .field("inner", &self.inner)
+            .field("decoding", &self.decoder.is_some())
+            .finish()
+    }
+}
+
+impl DecodedBody {
+    /// Wraps `inner`, decoding it with `coding` if given.
+    pub fn new(inner: HttpBody, coding: Option<ContentCoding>) -> io::Result<Self> {
+        Ok(Self {
+            inner,
+            decoder: coding.map(Decoder::new).transpose()?,
+            finished: false,
+        })
+    }
+
+    /// Returns the body of `response` decoded according to its `Content-Encoding`.
+    ///
+    /// `Content-Encoding` and `Content-Length` are removed from decoded responses. Responses with
+    /// an unsupported or a stacked coding are passed through unchanged.
+    pub fn decode_response(response: Response<HttpBody>) -> io::Result<Response<Self>> {
+        let (mut parts, body) = response.into_parts();
+        let coding = content_coding(&parts.headers);
+        if coding.is_some() {
+            parts.headers.remove(header::CONTENT_ENCODING);
+            parts.headers.remove(header::CONTENT_LENGTH);
+        }
+        Ok(Response::from_parts(parts, Self::new(body, coding)?))
+    }
+
+    /// Returns true if the body is being decoded.
+    #[inline]
+    pub fn is_decoding(&self) -> bool {
+        self.decoder.is_some()
+    }
+}
+
+fn content_coding(headers: &HeaderMap) -> Option<ContentCoding> {
+    let mut values = headers.get_all(header::CONTENT_ENCODING).iter();
+    let value = values.next()?.to_str().ok()?;
+    if values.next().is_some() || value.contains(',') {
+        return None;
+    }
+    ContentCoding::from_token(value)
+}
+
+impl Body for DecodedBody {
+    type Data = Bytes;
+    type Error = HttpError;
+
+    async fn next_data(&mut self) -> Option<Result<Self::Data, Self::Error>> {
+        let Some(decoder) = self.decoder.as_mut() else {
+            return self.inner.next_data().await;
+        };
+        loop {
+            if self.finished {
+                return None;
+            }
+            match self.inner.next_data().await {
+                Some(Ok(chunk)) => {
+                    if let Err(e) = decoder.write(&chunk) {
+                        self.finished = true;
+                        return Some(Err(e.into()));
+                    }
+                }
+                Some(Err(e)) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+                None => {
+                    self.finished = true;
+                    if let Err(e) = decoder.finish() {
+                        return Some(Err(e.into()));
+                    }
+                }
+            }
+            let output = decoder.take_output();
+            if !output.is_empty() {
+                return Some(Ok(output.into()));
+            }
+        }
+    }
+
+    fn stream_hint(&self) -> StreamHint {
+        match (&self.decoder, self.inner.stream_hint()) {
+            (Some(_), StreamHint::Fixed) => StreamHint::Stream,
+            (_, hint) => hint,
+        }
+    }
+}
+
+impl<K: Key, IO: AsyncReadRent + AsyncWriteRent> HttpConnection<K, IO> {
+    /// Sends an HTTP request like [`send_request`](Self::send_request), decoding the response
+    /// body according to its `Content-Encoding`.
+    ///
+    /// If the request has no `Accept-Encoding` header, one listing the enabled codings is added.
+    /// See [`DecodedBody::decode_response`].
+    pub async fn send_request_decoded<B, E>(
+        &mut self,
+        mut request: Request<B>,
+    ) -> (Result<Response<DecodedBody>, TransportError>, bool)
+    where
+        ClientCodec<IO>: Sink<Request<B>, Error = E>,
+        E: std::fmt::Debug + Into<HttpError>,
+        Request<B>: IntoParts<Parts = RequestHead, Body = B>,
+        B: Body<Data = Bytes, Error = HttpError>,
+    {
+        request
+            .headers_mut()
+            .entry(header::ACCEPT_ENCODING)
+            .or_insert_with(ContentCoding::accept_encoding);
+        let (res, reusable) = self.send_request(request).await;
+        let res = res
+            .map_err(TransportError::from)
+            .and_then(|response| DecodedBody::decode_response(response).map_err(Into::into));
+        (res, reusable)
+    }
+}
+
+/// Opts a request out of the decoding of [`HttpConnector::request`](super::HttpConnector::request),
+/// in its extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NoDecompress;
+
+/// Opts requests out of decoding on request builders.
+pub trait DecompressRequestExt: Sized {
+    /// Sends the request without adding `Accept-Encoding` and hands out the response body as
+    /// received.
+    fn no_decompress(self) -> Self;
+}
+
+impl DecompressRequestExt for http::request::Builder {
+    #[inline]
+    fn no_decompress(self) -> Self {
+        self.extension(NoDecompress)
+    }
+}
+
+/// Returns whether the response to `request` is decoded, adding `Accept-Encoding` if so and the
+/// request has none.
+pub(crate) fn accept_encoding<B>(request: &mut Request<B>) -> bool {
+    if request.extensions().get::<NoDecompress>().is_some()
+        || request.method() == http::Method::HEAD
+    {
+        return false;
+    }
+    request
+        .headers_mut()
+        .entry(header::ACCEPT_ENCODING)
+        .or_insert_with(ContentCoding::accept_encoding);
+    true
+}
+
+/// Replaces the body of `response` with its decoding, see [`DecodedBody::decode_response`].
+///
+/// The body is decoded by a task as it arrives, until its end, and the trailers of decoded
+/// HTTP/2 responses are dropped.
+pub(crate) fn decode_in_place(response: &mut Response<HttpBody>) -> io::Result<()> {
+    let Some(coding) = content_coding(response.headers()) else {
+        return Ok(());
+    };
+    if matches!(response.body().stream_hint(), StreamHint::None) {
+        return Ok(());
+    }
+    let body = std::mem::replace(response.body_mut(), HttpBody::Ready(None));
+    let mut decoded = DecodedBody::new(body, Some(coding))?;
+    let headers = response.headers_mut();
+    headers.remove(header::CONTENT_ENCODING);
+    headers.remove(header::CONTENT_LENGTH);
+    let (payload, mut sender) = stream_payload_pair();
+    monoio::spawn(async move {
+        while let Some(data) = decoded.next_data().await {
+            match data {
+                Ok(data) => sender.feed_data(Some(data)),
+                Err(e) => return sender.feed_error(e),
+            }
+        }
+        sender.feed_data(None);
+    });
+    *response.body_mut() = HttpBody::H1(Payload::Stream(payload));
+    Ok(())
+}
+
+const DEFAULT_MIN_SIZE: usize = 1024;
+
+// Encoders write their output into a buffer which is drained after every chunk.
+enum Encoder {
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<Vec<u8>>),
+    #[cfg(feature = "brotli")]
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(coding: ContentCoding) -> io::Result<Self> {
+        Ok(match coding {
+            #[cfg(feature = "gzip")]
+            ContentCoding::Gzip => Self::Gzip(flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "brotli")]
+            ContentCoding::Brotli => Self::Brotli(Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                4096,
+                5,
+                22,
+            ))),
+            #[cfg(feature = "zstd")]
+            ContentCoding::Zstd => Self::Zstd(zstd::stream::write::Encoder::new(Vec::new(), 0)?),
+        })
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(e) => e.write_all(data),
+            #[cfg(feature = "brotli")]
+            Self::Brotli(e) => e.write_all(data),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(e) => e.write_all(data),
+        }
+    }
+
+    fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(e) => e.get_mut(),
+            #[cfg(feature = "brotli")]
+            Self::Brotli(e) => e.get_mut(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(e) => e.get_mut(),
+        })
+    }
+
+    /// Ends the stream, returning the remaining output.
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip(e) => e.finish(),
+            #[cfg(feature = "brotli")]
+            Self::Brotli(e) => Ok(e.into_inner()),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(e) => e.finish(),
+        }
+    }
+}
+
+/// Compression of request bodies.
+///
+/// Bodies smaller than the threshold, 1 KiB by default, are sent as is. A body whose length is
+/// known up front is compressed in one go and keeps a `Content-Length`, a streamed body is
+/// compressed chunk by chunk and sent with chunked transfer encoding, unless its
+/// `Content-Length` header shows it is below the threshold.
+///
+/// Only use it with servers known to accept the coding, since requests do not negotiate it.
+#[derive(Debug, Clone, Copy)]
+pub struct Compression {
+    coding: ContentCoding,
+    min_size: usize,
+}
+
+impl Compression {
+    #[inline]
+    pub fn new(coding: ContentCoding) -> Self {
+        Self {
+            coding,
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+
+    /// Sets the body size from which bodies are compressed.
+    #[inline]
+    pub fn with_min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    #[inline]
+    pub fn coding(&self) -> ContentCoding {
+        self.coding
+    }
+
+    /// Compresses the body of `request` and sets its `Content-Encoding`, if it is large enough.
+    ///
+    /// Requests that already have a `Content-Encoding` are left untouched.
+    pub async fn compress_request<B>(
+        &self,
+        request: Request<B>,
+    ) -> io::Result<Request<EncodedBody<B>>>
+    where
+        B: Body<Data = Bytes, Error = HttpError>,
+    {
+        let (mut parts, mut body) = request.into_parts();
+        if parts.headers.contains_key(header::CONTENT_ENCODING) {
+            return Ok(Request::from_parts(parts, EncodedBody::pass(body)));
+        }
+        let body = match body.stream_hint() {
+            StreamHint::None => EncodedBody::pass(body),
+            StreamHint::Fixed => {
+                let data = match body.next_data().await {
+                    Some(data) => data.map_err(io::Error::other)?,
+                    None => Bytes::new(),
+                };
+                if data.len() < self.min_size {
+                    EncodedBody::ready(data)
+                } else {
+                    let mut encoder = Encoder::new(self.coding)?;
+                    encoder.write(&data)?;
+                    let compressed = Bytes::from(encoder.finish()?);
+                    parts
+                        .headers
+                        .insert(header::CONTENT_LENGTH, compressed.len().into());
+                    parts.headers.insert(
+                        header::CONTENT_ENCODING,
+                        HeaderValue::from_static(self.coding.as_str()),
+                    );
+                    EncodedBody::ready(compressed)
+                }
+            }
+            StreamHint::Stream => {
+                let declared = parts
+                    .headers
+                    .get(header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<usize>().ok());
+                if declared.is_some_and(|len| len < self.min_size) {
+                    EncodedBody::pass(body)
+                } else {
+                    parts.headers.remove(header::CONTENT_LENGTH);
+                    parts.headers.insert(
+                        header::CONTENT_ENCODING,
+                        HeaderValue::from_static(self.coding.as_str()),
+                    );
+                    EncodedBody {
+                        kind: EncodedKind::Stream {
+                            inner: body,
+                            encoder: Some(Encoder::new(self.coding)?),
+                        },
+                    }
+                }
+            }
+        };
+        Ok(Request::from_parts(parts, body))
+    }
+}
+
+enum EncodedKind<B> {
+    Pass(B),
+    Ready(Option<Bytes>),
+    // The encoder is taken once the inner body ended.
+    Stream { inner: B, encoder: Option<Encoder> },
+}
+
+/// A request body produced by [`Compression::compress_request`].
+pub struct EncodedBody<B> {
+    kind: EncodedKind<B>,
+}
+
+impl<B> std::fmt::Debug for EncodedBody<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            EncodedKind::Pass(_) => "Pass",
+            EncodedKind::Ready(_) => "Ready",
+            EncodedKind::Stream { .. } => "Stream",
+        };
+        f.debug_struct("EncodedBody").field("kind", &kind).finish()
+    }
+}
+
+impl<B> EncodedBody<B> {
+    #[inline]
+    fn pass(inner: B) -> Self {
+        Self {
+            kind: EncodedKind::Pass(inner),
+        }
+    }
+
+    #[inline]
+    fn ready(data: Bytes) -> Self {
+        Self {
+            kind: EncodedKind::Ready(Some(data)),
+        }
+    }
+
+    /// Returns true if the body is compressed while it is sent.
+    #[inline]
+    pub fn is_streaming(&self) -> bool {
+        matches!(self.kind, EncodedKind::Stream { .. })
+    }
+}
+
+impl<B: Body<Data = Bytes, Error = HttpError>> super::body::IntoBody for EncodedBody<B> {
+    type Body = Self;
+
+    #[inline]
+    fn into_body(self) -> Self::Body {
+        self
+    }
+}
+
+impl<B: Body<Data = Bytes, Error = HttpError>> Body for EncodedBody<B> {
+    type Data = Bytes;
+    type Error = HttpError;
+
+    async fn next_data(&mut self) -> Option<Result<Self::Data, Self::Error>> {
+        let (inner, encoder) = match &mut self.kind {
+            EncodedKind::Pass(inner) => return inner.next_data().await,
+            EncodedKind::Ready(data) => return data.take().map(Ok),
+            EncodedKind::Stream { inner, encoder } => (inner, encoder),
+        };
+        loop {
+            let active = encoder.as_mut()?;
+            let output = match inner.next_data().await {
+                Some(Ok(chunk)) => match active.write(&chunk) {
+                    Ok(()) => active.take_output(),
+                    Err(e) => {
+                        *encoder = None;
+                        return Some(Err(e.into()));
+                    }
+                },
+                Some(Err(e)) => {
+                    *encoder = None;
+                    return Some(Err(e));
+                }
+                None => match encoder.take()?.finish() {
+                    Ok(output) => output,
+                    Err(e) => return Some(Err(e.into())),
+                },
+            };
+            if !output.is_empty() {
+                return Some(Ok(output.into()));
+            }
+        }
+    }
+
+    fn stream_hint(&self) -> StreamHint {
+        match &self.kind {
+            EncodedKind::Pass(inner) => inner.stream_hint(),
+            EncodedKind::Ready(Some(_)) => StreamHint::Fixed,
+            EncodedKind::Ready(None) => StreamHint::None,
+            EncodedKind::Stream { .. } => StreamHint::Stream,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "gzip"))]
+mod tests {
+    use std::net::SocketAddr;
+
+    use monoio::io::AsyncWriteRentExt;
+    use monoio_http::{common::body::BodyExt, h1::payload::Payload};
+
+    use super::*;
+    use crate::{
+        connectors::{Connector, TcpConnector},
+        http::HttpConnector,
+    };
+
+    #[monoio::test(enable_timer = true)]
+    async fn decodes_gzip_response() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&b"hello world ".repeat(100)).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let (_, buf) = monoio::io::AsyncReadRent::read(&mut conn, vec![0; 4096]).await;
+            let head = String::from_utf8_lossy(&buf).to_ascii_lowercase();
+            assert!(head.contains("accept-encoding: ") && head.contains("gzip"));
+            let mut response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-encoding: gzip\r\ncontent-length: {}\r\n\r\n",
+                compressed.len()
+            )
+            .into_bytes();
+            response.extend_from_slice(&compressed);
+            let _ = conn.write_all(response).await;
+        });
+
+        let connector: HttpConnector<TcpConnector, SocketAddr, _> =
+            HttpConnector::build_tcp_http1_only();
+        let mut conn = connector.connect(addr).await.unwrap();
+        let request = http::Request::builder()
+            .uri("/")
+            .header(header::HOST, "localhost")
+            .body(HttpBody::H1(Payload::None))
+            .unwrap();
+        let response = conn.send_request_decoded(request).await.0.unwrap();
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+        assert!(response.body().is_decoding());
+        let body = response.into_body().bytes().await.unwrap();
+        assert_eq!(body, b"hello world ".repeat(100));
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn connector_decodes_unless_opted_out() {
+        use crate::{connectors::MockConnector, http::response::ResponseExt};
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(b"hello").unwrap();
+        let compressed = Bytes::from(encoder.finish().unwrap());
+        // Answers with the compressed body, echoing the `Accept-Encoding` of the request.
+        let body = compressed.clone();
+        let server = MockConnector::new().with_handler("api", move |mut stream| {
+            let body = body.clone();
+            async move {
+                let mut head = Vec::new();
+                while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let (res, buf) =
+                        monoio::io::AsyncReadRent::read(&mut stream, vec![0; 1024]).await;
+                    match res {
+                        Ok(n) if n > 0 => head.extend_from_slice(&buf[..n]),
+                        _ => return,
+                    }
+                }
+                let head = String::from_utf8_lossy(&head).to_ascii_lowercase();
+                let accept = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("accept-encoding: "))
+                    .unwrap_or("none")
+                    .to_owned();
+                let mut response = format!(
+                    "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-encoding: \
+                     gzip\r\nx-accept-encoding: {accept}\r\ncontent-length: {}\r\n\r\n",
+                    body.len()
+                )
+                .into_bytes();
+                response.extend_from_slice(&body);
+                let _ = stream.write_all(response).await;
+            }
+        });
+        let request = |raw: bool| {
+            let builder = http::Request::get("/").header(header::HOST, "localhost");
+            match raw {
+                true => builder.no_decompress(),
+                false => builder,
+            }
+            .body(HttpBody::Ready(None))
+            .unwrap()
+        };
+
+        let mut connector = HttpConnector::new(server);
+        let response = connector.request("api", || request(false)).await.unwrap();
+        assert_eq!(
+            response.headers()["x-accept-encoding"],
+            ContentCoding::accept_encoding().to_str().unwrap()
+        );
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(response.bytes().await.unwrap(), "hello");
+
+        let response = connector.request("api", || request(true)).await.unwrap();
+        assert_eq!(response.headers()["x-accept-encoding"], "none");
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(response.bytes().await.unwrap(), compressed);
+
+        connector.set_decompress(false);
+        let response = connector.request("api", || request(false)).await.unwrap();
+        assert_eq!(response.headers()["x-accept-encoding"], "none");
+        assert_eq!(response.bytes().await.unwrap(), compressed);
+    }
+
+    struct Chunks(Vec<Bytes>);
+
+    impl Body for Chunks {
+        type Data = Bytes;
+        type Error = HttpError;
+
+        async fn next_data(&mut self) -> Option<Result<Self::Data, Self::Error>> {
+            (!self.0.is_empty()).then(|| Ok(self.0.remove(0)))
+        }
+
+        fn stream_hint(&self) -> StreamHint {
+            StreamHint::Stream
+        }
+    }
+
+    #[monoio::test]
+    async fn compresses_request_bodies() {
+        fn request<B>(body: B) -> Request<B> {
+            http::Request::post("/")
+                .header(header::CONTENT_LENGTH, 1200)
+                .body(body)
+                .unwrap()
+        }
+        let gzip = Compression::new(ContentCoding::Gzip);
+        let data = Bytes::from(b"a".repeat(1200));
+
+        let compressed = gzip
+            .compress_request(request(HttpBody::Ready(Some(data.clone()))))
+            .await
+            .unwrap();
+        assert_eq!(compressed.headers()[header::CONTENT_ENCODING], "gzip");
+        let len = compressed.headers()[header::CONTENT_LENGTH].clone();
+        let body = compressed.into_body().bytes().await.unwrap();
+        assert_eq!(len, body.len().to_string());
+        let decoded = DecodedBody::new(HttpBody::Ready(Some(body)), Some(ContentCoding::Gzip))
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        // A streamed body is compressed chunk by chunk and loses its length.
+        let chunks = Chunks(vec![data.slice(..600), data.slice(600..)]);
+        let compressed = gzip.compress_request(request(chunks)).await.unwrap();
+        assert!(compressed.body().is_streaming());
+        assert!(compressed.headers().get(header::CONTENT_LENGTH).is_none());
+        assert_eq!(compressed.body().stream_hint(), StreamHint::Stream);
+        let body = compressed.into_body().bytes().await.unwrap();
+        let decoded = DecodedBody::new(HttpBody::Ready(Some(body)), Some(ContentCoding::Gzip))
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        // Small bodies are sent as is.
+        let small = gzip
+            .compress_request(request(HttpBody::Ready(Some("tiny".into()))))
+            .await
+            .unwrap();
+        assert!(small.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(small.into_body().bytes().await.unwrap(), "tiny");
+    }
+}
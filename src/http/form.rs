@@ -0,0 +1,360 @@
+//! Form bodies.
+//!
+//! - [`FormRequestExt::form`]: Finishes a request builder with an
+//!   `application/x-www-form-urlencoded` body.
+//! - [`Multipart`]: Builds a `multipart/form-data` body of text fields and file parts. Files are
+//!   read while the request is sent, one chunk at a time, and never loaded whole.
+use std::{collections::VecDeque, hash::BuildHasher, io, path::Path};
+
+use bytes::{Bytes, BytesMut};
+use http::{header, HeaderValue, Request};
+use monoio::fs::File;
+use monoio_http::common::{
+    body::{Body, HttpBody, StreamHint},
+    error::HttpError,
+};
+
+use crate::TransportError;
+
+// The size of the reads from file parts.
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Appends `input` to `out` with the `application/x-www-form-urlencoded` byte serializer.
+///
+/// Alphanumerics and `*-._` are kept, spaces become `+` and other bytes are percent-encoded.
+pub(crate) fn form_urlencode(input: &str, out: &mut String) {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    for &b in input.as_bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => {
+                out.push(b as char)
+            }
+            b' ' => out.push('+'),
+            _ => {
+                out.push('%');
+                out.push(HEX[(b >> 4) as usize] as char);
+                out.push(HEX[(b & 0xf) as usize] as char);
+            }
+        }
+    }
+}
+
+/// Builds requests with form bodies.
+pub trait FormRequestExt {
+    /// Encodes `fields` as an `application/x-www-form-urlencoded` body.
+    ///
+    /// The `Content-Type` is set unless the builder already has one.
+    fn form<K, V>(self, fields: &[(K, V)]) -> Result<Request<HttpBody>, TransportError>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>;
+
+    /// Uses `multipart` as the body and sets the matching `Content-Type`.
+    fn multipart(self, multipart: Multipart) -> Result<Request<MultipartBody>, TransportError>;
+}
+
+impl FormRequestExt for http::request::Builder {
+    fn form<K, V>(self, fields: &[(K, V)]) -> Result<Request<HttpBody>, TransportError>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut encoded = String::new();
+        for (key, value) in fields {
+            if !encoded.is_empty() {
+                encoded.push('&');
+            }
+            form_urlencode(key.as_ref(), &mut encoded);
+            encoded.push('=');
+            form_urlencode(value.as_ref(), &mut encoded);
+        }
+        let mut builder = self;
+        if let Some(headers) = builder.headers_mut() {
+            headers
+                .entry(header::CONTENT_TYPE)
+                .or_insert(HeaderValue::from_static(
+                    "application/x-www-form-urlencoded",
+                ));
+        }
+        Ok(builder.body(HttpBody::Ready(Some(Bytes::from(encoded))))?)
+    }
+
+    fn multipart(self, multipart: Multipart) -> Result<Request<MultipartBody>, TransportError> {
+        Ok(self
+            .header(header::CONTENT_TYPE, multipart.content_type())
+            .body(multipart.into_body())?)
+    }
+}
+
+enum PartBody {
+    Bytes(Bytes),
+    File { file: File, offset: u64 },
+}
+
+struct Part {
+    // The boundary line and part headers.
+    head: Bytes,
+    body: PartBody,
+}
+
+/// A `multipart/form-data` body builder.
+///
+/// Parts are sent in the order they were added. The boundary is random, see
+/// [`with_boundary`](Self::with_boundary) to choose it.
+pub struct Multipart {
+    boundary: String,
+    parts: VecDeque<Part>,
+}
+
+impl std::fmt::Debug for Multipart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Multipart")
+            .field("boundary", &self.boundary)
+            .field("parts", &self.parts.len())
+            .finish()
+    }
+}
+
+impl Default for Multipart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Multipart {
+    pub fn new() -> Self {
+        let random = std::collections::hash_map::RandomState::new();
+        Self {
+            boundary: format!(
+                "monoio-{:016x}{:016x}",
+                random.hash_one(0u8),
+                random.hash_one(1u8)
+            ),
+            parts: VecDeque::new(),
+        }
+    }
+
+    /// Uses `boundary` as delimiter, which must not appear in any part.
+    pub fn with_boundary(mut self, boundary: impl Into<String>) -> Self {
+        self.boundary = boundary.into();
+        self
+    }
+
+    #[inline]
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// The `Content-Type` of the body, carrying its boundary.
+    pub fn content_type(&self) -> HeaderValue {
+        HeaderValue::try_from(format!("multipart/form-data; boundary={}", self.boundary))
+            .expect("the boundary is a valid header value")
+    }
+
+    /// Adds a text field.
+    pub fn text(mut self, name: &str, value: impl Into<String>) -> Self {
+        let head = self.part_head(name, None, None);
+        self.parts.push_back(Part {
+            head,
+            body: PartBody::Bytes(Bytes::from(value.into())),
+        });
+        self
+    }
+
+    /// Adds a file part held in memory.
+    pub fn bytes(
+        mut self,
+        name: &str,
+        filename: &str,
+        content_type: &str,
+        data: impl Into<Bytes>,
+    ) -> Self {
+        let head = self.part_head(name, Some(filename), Some(content_type));
+        self.parts.push_back(Part {
+            head,
+            body: PartBody::Bytes(data.into()),
+        });
+        self
+    }
+
+    /// Adds a file part streamed from `file`, starting from its beginning.
+    pub fn file(mut self, name: &str, filename: &str, content_type: &str, file: File) -> Self {
+        let head = self.part_head(name, Some(filename), Some(content_type));
+        self.parts.push_back(Part {
+            head,
+            body: PartBody::File { file, offset: 0 },
+        });
+        self
+    }
+
+    /// Opens the file at `path` and adds it as an `application/octet-stream` part named after
+    /// the file.
+    pub async fn file_path(self, name: &str, path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let filename = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let file = File::open(path).await?;
+        Ok(self.file(name, &filename, "application/octet-stream", file))
+    }
+
+    /// Turns the builder into the request body.
+    pub fn into_body(self) -> MultipartBody {
+        MultipartBody {
+            closing: Some(Bytes::from(format!("--{}--\r\n", self.boundary))),
+            parts: self.parts,
+            in_body: false,
+        }
+    }
+
+    fn part_head(&self, name: &str, filename: Option<&str>, content_type: Option<&str>) -> Bytes {
+        let mut head = format!(
+            "--{}\r\ncontent-disposition: form-data; name=\"{}\"",
+            self.boundary,
+            escape_quoted(name)
+        );
+        if let Some(filename) = filename {
+            head.push_str(&format!("; filename=\"{}\"", escape_quoted(filename)));
+        }
+        head.push_str("\r\n");
+        if let Some(content_type) = content_type {
+            head.push_str(&format!("content-type: {content_type}\r\n"));
+        }
+        head.push_str("\r\n");
+        Bytes::from(head)
+    }
+}
+
+// Escapes a quoted `Content-Disposition` parameter the way browsers do.
+fn escape_quoted(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// A `multipart/form-data` body built by [`Multipart`].
+///
+/// It is always streamed, as chunked transfer encoding over HTTP/1.1.
+pub struct MultipartBody {
+    parts: VecDeque<Part>,
+    // Whether the head of the front part was sent.
+    in_body: bool,
+    closing: Option<Bytes>,
+}
+
+impl std::fmt::Debug for MultipartBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultipartBody")
+            .field("remaining_parts", &self.parts.len())
+            .finish()
+    }
+}
+
+impl super::body::IntoBody for MultipartBody {
+    type Body = Self;
+
+    #[inline]
+    fn into_body(self) -> Self::Body {
+        self
+    }
+}
+
+impl Body for MultipartBody {
+    type Data = Bytes;
+    type Error = HttpError;
+
+    async fn next_data(&mut self) -> Option<Result<Self::Data, Self::Error>> {
+        let Some(part) = self.parts.front_mut() else {
+            return self.closing.take().map(Ok);
+        };
+        if !self.in_body {
+            self.in_body = true;
+            return Some(Ok(std::mem::take(&mut part.head)));
+        }
+        match &mut part.body {
+            PartBody::Bytes(data) if !data.is_empty() => Some(Ok(std::mem::take(data))),
+            PartBody::File { file, offset } => {
+                let (res, buf) = file
+                    .read_at(BytesMut::with_capacity(FILE_CHUNK_SIZE), *offset)
+                    .await;
+                match res {
+                    Ok(0) => Some(Ok(self.finish_part())),
+                    Ok(n) => {
+                        *offset += n as u64;
+                        Some(Ok(buf.freeze()))
+                    }
+                    Err(e) => {
+                        self.parts.clear();
+                        self.closing = None;
+                        Some(Err(e.into()))
+                    }
+                }
+            }
+            PartBody::Bytes(_) => Some(Ok(self.finish_part())),
+        }
+    }
+
+    #[inline]
+    fn stream_hint(&self) -> StreamHint {
+        StreamHint::Stream
+    }
+}
+
+impl MultipartBody {
+    // Drops the front part and returns the line break ending it.
+    fn finish_part(&mut self) -> Bytes {
+        self.parts.pop_front();
+        self.in_body = false;
+        Bytes::from_static(b"\r\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use monoio_http::common::body::BodyExt;
+
+    use super::*;
+
+    #[monoio::test]
+    async fn encodes_form_fields() {
+        let request = http::Request::post("/")
+            .form(&[("name", "monoio transports"), ("q", "a&b=ü")])
+            .unwrap();
+        assert_eq!(
+            request.headers()[header::CONTENT_TYPE],
+            "application/x-www-form-urlencoded"
+        );
+        let body = request.into_body().bytes().await.unwrap();
+        assert_eq!(body, "name=monoio+transports&q=a%26b%3D%C3%BC");
+    }
+
+    #[monoio::test]
+    async fn streams_multipart_parts() {
+        let path = std::env::temp_dir().join(format!("multipart-{}.txt", std::process::id()));
+        std::fs::write(&path, b"file contents").unwrap();
+        let multipart = Multipart::new()
+            .with_boundary("XyZ")
+            .text("title", "a \"quoted\" name")
+            .file_path("upload", &path)
+            .await
+            .unwrap();
+        let request = http::Request::post("/").multipart(multipart).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            request.headers()[header::CONTENT_TYPE],
+            "multipart/form-data; boundary=XyZ"
+        );
+
+        let body = request.into_body().bytes().await.unwrap();
+        let expected = format!(
+            "--XyZ\r\ncontent-disposition: form-data; name=\"title\"\r\n\r\na \"quoted\" \
+             name\r\n--XyZ\r\ncontent-disposition: form-data; name=\"upload\"; \
+             filename=\"{}\"\r\ncontent-type: application/octet-stream\r\n\r\nfile \
+             contents\r\n--XyZ--\r\n",
+            path.file_name().unwrap().to_str().unwrap()
+        );
+        assert_eq!(body, expected);
+    }
+}
@@ -0,0 +1,134 @@
+//! Plain HTTP forwarding through HTTP proxies.
+//!
+//! Proxies are usually asked to open a `CONNECT` tunnel to the target, as the `ProxyConnector`
+//! of the `proxy` feature does. Some HTTP proxies, caching ones in particular, refuse tunnels to
+//! port 80 and only forward plain `http://` requests they receive in absolute form. To use them,
+//! key the requests of an [`HttpConnector`](super::HttpConnector) by the address of the proxy and
+//! mark them with [`ForwardRequestExt::forward`]: their request line then carries the whole uri,
+//! as in `GET http://example.com/ HTTP/1.1`, and the `Proxy-Authorization` of the
+//! [`ProxyForward`] is added. Connections to the proxy are pooled under its key, whatever the
+//! targets of the requests sent over them.
+//!
+//! HTTP/2 requests carry their scheme and authority anyway, so only HTTP/1.1 requests change.
+use http::{header::PROXY_AUTHORIZATION, HeaderValue};
+
+/// Marks a request, in its extensions, to be forwarded by the HTTP proxy it is sent to.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProxyForward {
+    authorization: Option<HeaderValue>,
+}
+
+impl ProxyForward {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends `value` as the `Proxy-Authorization` of the request, unless it has one already.
+    #[inline]
+    pub fn with_authorization(mut self, mut value: HeaderValue) -> Self {
+        value.set_sensitive(true);
+        self.authorization = Some(value);
+        self
+    }
+
+    #[inline]
+    pub fn authorization(&self) -> Option<&HeaderValue> {
+        self.authorization.as_ref()
+    }
+
+    /// Returns the `Proxy-Authorization` header to add to `headers`, if any.
+    pub(crate) fn missing_authorization(&self, headers: &http::HeaderMap) -> Option<&HeaderValue> {
+        self.authorization
+            .as_ref()
+            .filter(|_| !headers.contains_key(PROXY_AUTHORIZATION))
+    }
+}
+
+/// Forwards through `proxy` with its credentials, if any.
+#[cfg(feature = "proxy")]
+impl From<&crate::connectors::ProxyConfig> for ProxyForward {
+    fn from(proxy: &crate::connectors::ProxyConfig) -> Self {
+        match &proxy.auth {
+            Some(auth) => Self::new().with_authorization(auth.basic_header()),
+            None => Self::new(),
+        }
+    }
+}
+
+/// Marks request builders for forwarding.
+pub trait ForwardRequestExt: Sized {
+    /// Has the HTTP proxy the request is sent to forward it, see [`ProxyForward`].
+    fn forward(self, forward: ProxyForward) -> Self;
+}
+
+impl ForwardRequestExt for http::request::Builder {
+    fn forward(self, forward: ProxyForward) -> Self {
+        self.extension(forward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+    use monoio_http::common::body::HttpBody;
+
+    use super::*;
+    use crate::{
+        connectors::MockConnector,
+        http::{response::ResponseExt, HttpConnector},
+    };
+
+    #[monoio::test(enable_timer = true)]
+    async fn forwards_in_absolute_form() {
+        let proxy = MockConnector::new().with_handler("proxy", |mut stream| async move {
+            for _ in 0..2 {
+                let mut head = Vec::new();
+                while !head.ends_with(b"\r\n\r\n") {
+                    let (res, buf) = stream.read(vec![0; 1024]).await;
+                    let Ok(n @ 1..) = res else { return };
+                    head.extend_from_slice(&buf[..n]);
+                }
+                let head = String::from_utf8(head).unwrap();
+                let line = head.lines().next().unwrap_or_default().to_owned();
+                let auth = head.contains("proxy-authorization: Basic dTpw\r\n");
+                let body = format!("{line} {auth}");
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.into_bytes()).await.0.unwrap();
+            }
+        });
+        let connector = HttpConnector::new(proxy.clone());
+        let forward =
+            ProxyForward::new().with_authorization(HeaderValue::from_static("Basic dTpw"));
+        let response = connector
+            .request("proxy", || {
+                http::Request::get("http://user@example.com:8080/a?b=c")
+                    .header(http::header::HOST, "example.com:8080")
+                    .forward(forward.clone())
+                    .body(HttpBody::Ready(None))
+                    .unwrap()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            response.text().await.unwrap(),
+            "GET http://example.com:8080/a?b=c HTTP/1.1 true"
+        );
+
+        // Unmarked requests keep the origin form, on the same connection.
+        let response = connector
+            .request("proxy", || {
+                http::Request::get("http://example.com/a")
+                    .header(http::header::HOST, "example.com")
+                    .body(HttpBody::Ready(None))
+                    .unwrap()
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "GET /a HTTP/1.1 false");
+        assert_eq!(proxy.connects(&"proxy"), 1);
+    }
+}
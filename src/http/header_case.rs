@@ -0,0 +1,103 @@
+//! Casing of HTTP/1.1 header names.
+//!
+//! Header names are lowercase in `http` types, and are written as such by default. Servers
+//! rejecting lowercase names can be sent Title-Case ones with
+//! [`HttpConnector::set_header_case`](super::HttpConnector::set_header_case), or the names of a
+//! request can be written in the casing recorded in its [`OriginalHeaderCase`] extension.
+//!
+//! HTTP/2 header names are always lowercase.
+use std::collections::HashMap;
+
+use bytes::BufMut;
+use http::{header::InvalidHeaderName, HeaderName};
+
+/// How HTTP/1.1 header names without an original casing are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum HeaderCase {
+    /// `content-type`, as stored by `http`.
+    #[default]
+    Lower,
+    /// `Content-Type`, each word capitalized.
+    Title,
+}
+
+/// The casing header names of a request are written in, set as one of its extensions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OriginalHeaderCase(HashMap<HeaderName, String>);
+
+impl OriginalHeaderCase {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the headers named `name`, in any casing, as `name`.
+    pub fn insert(&mut self, name: &str) -> Result<(), InvalidHeaderName> {
+        let key = HeaderName::try_from(name)?;
+        self.0.insert(key, name.to_string());
+        Ok(())
+    }
+
+    #[inline]
+    pub fn get(&self, name: &HeaderName) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+}
+
+/// Appends `name` to `buf`, in its original casing if recorded or else in `case`.
+pub(crate) fn write_name(
+    buf: &mut impl BufMut,
+    name: &HeaderName,
+    case: HeaderCase,
+    original: Option<&OriginalHeaderCase>,
+) {
+    if let Some(original) = original.and_then(|original| original.get(name)) {
+        buf.put_slice(original.as_bytes());
+        return;
+    }
+    match case {
+        HeaderCase::Lower => buf.put_slice(name.as_str().as_bytes()),
+        HeaderCase::Title => {
+            let mut capitalize = true;
+            for &b in name.as_str().as_bytes() {
+                buf.put_u8(if capitalize {
+                    b.to_ascii_uppercase()
+                } else {
+                    b
+                });
+                capitalize = b == b'-';
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_names() {
+        let write = |name: &HeaderName, case, original| {
+            let mut buf = Vec::new();
+            write_name(&mut buf, name, case, original);
+            String::from_utf8(buf).unwrap()
+        };
+        let name = http::header::CONTENT_TYPE;
+        assert_eq!(write(&name, HeaderCase::Lower, None), "content-type");
+        assert_eq!(write(&name, HeaderCase::Title, None), "Content-Type");
+        let www = HeaderName::from_static("www-authenticate");
+        assert_eq!(write(&www, HeaderCase::Title, None), "Www-Authenticate");
+
+        let mut original = OriginalHeaderCase::new();
+        original.insert("CONTENT-type").unwrap();
+        assert!(original.insert("bad name").is_err());
+        assert_eq!(
+            write(&name, HeaderCase::Title, Some(&original)),
+            "CONTENT-type"
+        );
+        assert_eq!(
+            write(&www, HeaderCase::Lower, Some(&original)),
+            "www-authenticate"
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! Hedged requests for cutting tail latency.
+//!
+//! [`send_hedged`] sends a request and, if no response arrived within a latency threshold, sends
+//! a duplicate on another connection. Whichever completes first wins and the other one is
+//! dropped, which also discards its connection instead of returning it to the pool. Only safe
+//! methods (`GET`, `HEAD`, `OPTIONS` and `TRACE`) are hedged, other requests are sent once.
+//!
+//! Over HTTP/2 the duplicate is multiplexed on the pooled connection to the same key, so it only
+//! helps against a slow stream, not a slow connection.
+use std::{
+    future::{poll_fn, Future},
+    pin::pin,
+    task::Poll,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use http::{Method, Response};
+use monoio::io::{sink::Sink, AsyncReadRent, AsyncWriteRent};
+use monoio_http::{
+    common::{
+        body::{Body, HttpBody},
+        error::HttpError,
+        request::{Request, RequestHead},
+        IntoParts,
+    },
+    h1::codec::ClientCodec,
+};
+
+use super::{body::IntoBody, HttpConnection};
+use crate::{connectors::Connector, pool::Key, TransportError};
+
+async fn send_once<C, K, IO, B, E>(
+    connector: &C,
+    key: &K,
+    request: Request<B>,
+) -> Result<Response<HttpBody>, TransportError>
+where
+    C: Connector<K, Connection = HttpConnection<K, IO>>,
+    TransportError: From<C::Error>,
+    K: Key,
+    IO: AsyncReadRent + AsyncWriteRent,
+    ClientCodec<IO>: Sink<Request<B>, Error = E>,
+    E: std::fmt::Debug + Into<HttpError>,
+    Request<B>: IntoParts<Parts = RequestHead, Body = B>,
+    B: Body<Data = Bytes, Error = HttpError>,
+{
+    let mut conn = connector.connect(key.clone()).await?;
+    conn.send_request(request).await.0.map_err(Into::into)
+}
+
+/// Connects to `key` and sends the request built by `make_request`, hedging it with a duplicate
+/// when no response arrived after `delay`.
+///
+/// `make_request` is called a second time only when the hedge is sent. If one attempt fails
+/// while the other is still running, the other one's result is awaited. Waiting for the delay
+/// requires the monoio timer driver.
+pub async fn send_hedged<C, K, IO, B, E, F>(
+    connector: &C,
+    key: &K,
+    delay: Duration,
+    mut make_request: F,
+) -> Result<Response<HttpBody>, TransportError>
+where
+    C: Connector<K, Connection = HttpConnection<K, IO>>,
+    TransportError: From<C::Error>,
+    K: Key,
+    IO: AsyncReadRent + AsyncWriteRent,
+    F: FnMut() -> Request<B>,
+    B: IntoBody,
+    ClientCodec<IO>: Sink<Request<B::Body>, Error = E>,
+    E: std::fmt::Debug + Into<HttpError>,
+    Request<B::Body>: IntoParts<Parts = RequestHead, Body = B::Body>,
+{
+    let request = make_request().map(IntoBody::into_body);
+    if !matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    ) {
+        return send_once(connector, key, request).await;
+    }
+
+    let mut primary = pin!(send_once(connector, key, request));
+    let mut timer = pin!(monoio::time::sleep(delay));
+    let mut hedge = pin!(None);
+    let (mut primary_failed, mut hedge_failed) = (false, false);
+    poll_fn(|cx| {
+        if !primary_failed {
+            match primary.as_mut().poll(cx) {
+                Poll::Ready(Ok(response)) => return Poll::Ready(Ok(response)),
+                Poll::Ready(Err(e)) if hedge_failed => return Poll::Ready(Err(e)),
+                Poll::Ready(Err(_)) => primary_failed = true,
+                Poll::Pending => {}
+            }
+        }
+        // A failed primary attempt sends the hedge right away.
+        if hedge.is_none() && (primary_failed || timer.as_mut().poll(cx).is_ready()) {
+            #[cfg(feature = "logging")]
+            tracing::debug!("no response after {delay:?}, sending a hedged request");
+            hedge.set(Some(send_once(
+                connector,
+                key,
+                make_request().map(IntoBody::into_body),
+            )));
+        }
+        if let Some(attempt) = hedge.as_mut().as_pin_mut() {
+            if !hedge_failed {
+                match attempt.poll(cx) {
+                    Poll::Ready(Ok(response)) => return Poll::Ready(Ok(response)),
+                    Poll::Ready(Err(e)) if primary_failed => return Poll::Ready(Err(e)),
+                    Poll::Ready(Err(_)) => hedge_failed = true,
+                    Poll::Pending => {}
+                }
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, net::SocketAddr, rc::Rc};
+
+    use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+    use monoio_http::h1::payload::Payload;
+
+    use super::*;
+    use crate::{connectors::TcpConnector, http::HttpConnector};
+
+    #[monoio::test(enable_timer = true)]
+    async fn hedge_wins_over_stalled_request() {
+        // The first connection never gets an answer, later ones do.
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Rc::new(Cell::new(0));
+        let count = accepted.clone();
+        monoio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                count.set(count.get() + 1);
+                let stall = count.get() == 1;
+                monoio::spawn(async move {
+                    let (res, _) = conn.read(vec![0; 1024]).await;
+                    if stall || res.is_err() {
+                        monoio::time::sleep(Duration::from_secs(5)).await;
+                        return;
+                    }
+                    let _ = conn
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n".as_slice())
+                        .await;
+                });
+            }
+        });
+
+        let connector: HttpConnector<TcpConnector, SocketAddr, _> =
+            HttpConnector::build_tcp_http1_only();
+        let request = || {
+            http::Request::builder()
+                .uri("/")
+                .header(http::header::HOST, "localhost")
+                .body(HttpBody::H1(Payload::None))
+                .unwrap()
+        };
+        let response = monoio::time::timeout(
+            Duration::from_secs(1),
+            send_hedged(&connector, &addr, Duration::from_millis(20), request),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(accepted.get(), 2);
+    }
+}
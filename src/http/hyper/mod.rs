@@ -402,14 +402,14 @@ where
 
 #[cfg(test)]
 mod tests {
-    use std::{io::Write, net::ToSocketAddrs};
+    use std::io::Write;
 
     use bytes::Bytes;
     use http::Uri;
     use monoio::{io::IntoPollIo, net::TcpListener};
 
     use super::*;
-    use crate::connectors::{pollio::PollIo, TcpConnector};
+    use crate::connectors::{pollio::PollIo, HostPort, TcpConnector};
 
     #[monoio::test(timer = true)]
     async fn h1_hyper() {
@@ -419,10 +419,13 @@ mod tests {
             port: u16,
         }
 
-        impl ToSocketAddrs for Key {
-            type Iter = std::vec::IntoIter<std::net::SocketAddr>;
-            fn to_socket_addrs(&self) -> std::io::Result<Self::Iter> {
-                (self.host.as_str(), self.port).to_socket_addrs()
+        impl HostPort for Key {
+            fn host(&self) -> &str {
+                &self.host
+            }
+
+            fn port(&self) -> u16 {
+                self.port
             }
         }
 
@@ -481,10 +484,13 @@ mod tests {
             port: u16,
         }
 
-        impl ToSocketAddrs for Key {
-            type Iter = std::vec::IntoIter<std::net::SocketAddr>;
-            fn to_socket_addrs(&self) -> std::io::Result<Self::Iter> {
-                (self.host.as_str(), self.port).to_socket_addrs()
+        impl HostPort for Key {
+            fn host(&self) -> &str {
+                &self.host
+            }
+
+            fn port(&self) -> u16 {
+                self.port
             }
         }
 
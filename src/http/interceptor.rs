@@ -0,0 +1,35 @@
+//! Interceptors inspecting and mutating the requests and responses of an
+//! [`HttpConnector`](super::HttpConnector).
+//!
+//! Interceptors are added with
+//! [`HttpConnector::add_interceptor`](super::HttpConnector::add_interceptor) and run by
+//! [`HttpConnector::request`](super::HttpConnector::request): on requests in the order they were
+//! added, once the default `Authorization` header is set, then on responses in the reverse order,
+//! so the first one added sees the request first and the response last.
+//!
+//! They run again when a request is rebuilt to be sent on a fresh connection, so a signature
+//! covering a timestamp is computed anew.
+use http::{request, Response};
+use monoio_http::common::body::HttpBody;
+
+use crate::TransportError;
+
+/// Inspects and mutates requests before they are sent, and responses once their head is
+/// received.
+///
+/// Request bodies are not exposed, they may be streamed.
+pub trait Interceptor {
+    /// Called with the head of each request before it is sent. An error fails the request
+    /// without sending it.
+    fn on_request(&self, request: &mut request::Parts) -> Result<(), TransportError> {
+        let _ = request;
+        Ok(())
+    }
+
+    /// Called with each response before it is returned. An error is returned instead of the
+    /// response.
+    fn on_response(&self, response: &mut Response<HttpBody>) -> Result<(), TransportError> {
+        let _ = response;
+        Ok(())
+    }
+}
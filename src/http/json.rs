@@ -0,0 +1,141 @@
+//! JSON request and response bodies, behind the `serde` feature.
+//!
+//! - [`JsonRequestExt::json`]: Finishes an [`http::request::Builder`] with a JSON body and sets
+//!   `Content-Type: application/json` unless the builder already has one.
+//! - [`JsonResponseExt::json`]: Reads a response body and deserializes it.
+//!
+//! Values are serialized straight into the request buffer, and responses are deserialized by
+//! reading through the received chunks without joining them first. Malformed JSON fails with
+//! [`TransportError::Json`].
+use std::{collections::VecDeque, future::Future, io};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use http::{header, HeaderValue, Request, Response};
+use monoio_http::common::{
+    body::{Body, HttpBody},
+    error::HttpError,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::TransportError;
+
+/// Builds requests with JSON bodies.
+pub trait JsonRequestExt {
+    /// Serializes `value` as the request body.
+    fn json<T: Serialize + ?Sized>(self, value: &T) -> Result<Request<HttpBody>, TransportError>;
+}
+
+impl JsonRequestExt for http::request::Builder {
+    fn json<T: Serialize + ?Sized>(self, value: &T) -> Result<Request<HttpBody>, TransportError> {
+        let mut writer = BytesMut::new().writer();
+        serde_json::to_writer(&mut writer, value)?;
+        let mut builder = self;
+        if let Some(headers) = builder.headers_mut() {
+            headers
+                .entry(header::CONTENT_TYPE)
+                .or_insert(HeaderValue::from_static("application/json"));
+        }
+        Ok(builder.body(HttpBody::Ready(Some(writer.into_inner().freeze())))?)
+    }
+}
+
+/// Reads JSON response bodies.
+pub trait JsonResponseExt {
+    /// Reads the whole body and deserializes it into a `T`.
+    ///
+    /// The status and `Content-Type` are not checked.
+    fn json<T: DeserializeOwned>(self) -> impl Future<Output = Result<T, TransportError>>;
+}
+
+impl<B> JsonResponseExt for Response<B>
+where
+    B: Body<Data = Bytes>,
+    B::Error: Into<HttpError>,
+{
+    async fn json<T: DeserializeOwned>(self) -> Result<T, TransportError> {
+        let mut body = self.into_body();
+        let mut chunks = VecDeque::new();
+        while let Some(chunk) = body.next_data().await {
+            chunks.push_back(chunk.map_err(Into::into)?);
+        }
+        Ok(serde_json::from_reader(ChunksReader { chunks })?)
+    }
+}
+
+struct ChunksReader {
+    chunks: VecDeque<Bytes>,
+}
+
+impl io::Read for ChunksReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(chunk) = self.chunks.front_mut() {
+            if chunk.is_empty() {
+                self.chunks.pop_front();
+                continue;
+            }
+            let n = chunk.len().min(buf.len());
+            buf[..n].copy_from_slice(&chunk.split_to(n));
+            return Ok(n);
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use monoio_http::common::body::StreamHint;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        name: String,
+        id: u32,
+    }
+
+    struct Chunked(VecDeque<&'static str>);
+
+    impl Body for Chunked {
+        type Data = Bytes;
+        type Error = HttpError;
+
+        async fn next_data(&mut self) -> Option<Result<Self::Data, Self::Error>> {
+            self.0
+                .pop_front()
+                .map(|s| Ok(Bytes::from_static(s.as_bytes())))
+        }
+
+        fn stream_hint(&self) -> StreamHint {
+            StreamHint::Stream
+        }
+    }
+
+    #[monoio::test]
+    async fn round_trips_json_bodies() {
+        let user = User {
+            name: "monoio".to_string(),
+            id: 7,
+        };
+        let request = http::Request::post("/users").json(&user).unwrap();
+        assert_eq!(request.headers()[header::CONTENT_TYPE], "application/json");
+        let mut body = request.into_body();
+        assert_eq!(
+            body.next_data().await.unwrap().unwrap(),
+            r#"{"name":"monoio","id":7}"#
+        );
+
+        let response = Response::new(Chunked(VecDeque::from([
+            r#"{"na"#,
+            r#"me":"monoio","#,
+            r#""id":7}"#,
+        ])));
+        assert_eq!(response.json::<User>().await.unwrap(), user);
+
+        let response = Response::new(Chunked(VecDeque::from([r#"{"name":1}"#])));
+        assert!(matches!(
+            response.json::<User>().await,
+            Err(TransportError::Json(_))
+        ));
+    }
+}
@@ -13,9 +13,56 @@
 //!   be used with a `TlsConnector` for HTTPS connections and is specifically designed to work with
 //!   monoio's native IO traits, which are built on top of io_uring.
 //!
+//! - [`ConnectionInfo`]: The addresses, reuse, ALPN, TLS parameters and connect time of the
+//!   connection a response was received on, in the response extensions.
+//!
 //! - [`H1Connector`]: A deprecated HTTP/1.1 connector retained for backwards compatibility. New
 //!   code should use `HttpConnector` instead.
 //!
+//! - [`sse`]: Server-Sent Events parsing and a reconnecting event source.
+//!
+//! - [`response`]: Collecting response bodies into bytes or text decoded in their charset.
+//!
+//! - [`retry`]: An opt-in retry policy with exponential backoff for transient failures.
+//!
+//! - [`encoding`]: Decoding of gzip, brotli and zstd response bodies and compression of request
+//!   bodies, behind the features of the same names.
+//!
+//! - [`forward`]: Plain HTTP requests forwarded by HTTP proxies, sent in absolute form.
+//!
+//! - [`form`]: URL-encoded and `multipart/form-data` request bodies, with files streamed from disk.
+//!
+//! - [`hedge`]: Hedged requests, racing a duplicate against a slow response.
+//!
+//! - [`header_case`]: Title-Case or original casing of HTTP/1.1 header names.
+//!
+//! - [`interceptor`]: Hooks inspecting and mutating requests and responses, e.g. to sign them.
+//!
+//! - [`abort`]: Cancelling requests in flight with an abort handle.
+//!
+//! - [`auth`]: Basic and Bearer `Authorization` headers, per request or as connector defaults.
+//!
+//! - [`body`]: Streamed request bodies, fed by a `Stream`, a bounded channel or a file.
+//!
+//! - [`buffer`]: A pool recycling the read buffers of connections and the buffers of request heads.
+//!
+//! - [`capture`]: Recording of requests, responses and timings, exported as HAR files.
+//!
+//! - [`download`]: Saving response bodies to files, resuming partial downloads with `Range`
+//!   requests.
+//!
+//! - [`cookie`]: An RFC 6265 cookie jar, behind the `cookie` feature.
+//!
+//! - [`json`]: JSON request and response bodies, behind the `serde` feature.
+//!
+//! - [`query`]: Percent-encoded query parameters appended to request URIs.
+//!
+//! - [`redirect`]: Following `3xx` redirects up to a hop limit or as decided by a callback.
+//!
+//! - [`trailers`]: Trailers sent after request bodies and read after response bodies.
+//!
+//! - [`version`]: Requiring HTTP/1.1 or HTTP/2 for a single request, whatever ALPN selects.
+//!
 //! # Features
 //!
 //! - Optimized for monoio's asynchronous runtime and io_uring
@@ -49,8 +96,33 @@
 mod connection;
 mod connector;
 
-pub use connection::HttpConnection;
+pub use connection::{ConnectionInfo, HttpConnection, StreamingBody};
 pub use connector::{H1Connector, HttpConnector};
 
+pub mod abort;
+pub mod auth;
+pub mod body;
+pub mod buffer;
+pub mod capture;
+#[cfg(feature = "cookie")]
+pub mod cookie;
+pub mod download;
+#[cfg(any(feature = "gzip", feature = "brotli", feature = "zstd"))]
+pub mod encoding;
+pub mod form;
+pub mod forward;
+pub mod header_case;
+pub mod hedge;
+pub mod interceptor;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod query;
+pub mod redirect;
+pub mod response;
+pub mod retry;
+pub mod sse;
+pub mod trailers;
+pub mod version;
+
 #[cfg(feature = "hyper")]
 pub mod hyper;
@@ -0,0 +1,153 @@
+//! Query strings built from key-value pairs.
+//!
+//! [`QueryRequestExt`] appends percent-encoded parameters to the URI of a request builder, after
+//! any query it already has. Keys and values are encoded as an `application/x-www-form-urlencoded`
+//! query, the way HTML forms submitted with `GET` are.
+use http::{uri::PathAndQuery, Uri};
+
+use super::form::form_urlencode;
+#[cfg(feature = "serde")]
+use crate::TransportError;
+
+/// Appends query parameters to request URIs.
+pub trait QueryRequestExt: Sized {
+    /// Appends `pairs` to the query of the URI.
+    fn query<K, V>(self, pairs: &[(K, V)]) -> Self
+    where
+        K: AsRef<str>,
+        V: AsRef<str>;
+
+    /// Appends the fields of `value`, which must serialize to a flat map or struct.
+    ///
+    /// Strings are sent as is and numbers and booleans in their JSON form. A sequence repeats the
+    /// key for each of its items and `None` values are skipped. Fields are appended sorted by
+    /// name. Requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    fn query_pairs<T: serde::Serialize + ?Sized>(self, value: &T) -> Result<Self, TransportError>;
+}
+
+impl QueryRequestExt for http::request::Builder {
+    fn query<K, V>(self, pairs: &[(K, V)]) -> Self
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let Some(uri) = self.uri_ref() else {
+            return self;
+        };
+        let uri = append_query(uri, pairs.iter().map(|(k, v)| (k.as_ref(), v.as_ref())));
+        self.uri(uri)
+    }
+
+    #[cfg(feature = "serde")]
+    fn query_pairs<T: serde::Serialize + ?Sized>(self, value: &T) -> Result<Self, TransportError> {
+        use serde_json::Value;
+
+        let Value::Object(fields) = serde_json::to_value(value)? else {
+            return Err(TransportError::Validation(
+                "query parameters must serialize to a map".to_string(),
+            ));
+        };
+        let mut pairs = Vec::new();
+        for (key, value) in fields {
+            let values = match value {
+                Value::Array(items) => items,
+                value => vec![value],
+            };
+            for value in values {
+                let value = match value {
+                    Value::Null => continue,
+                    Value::String(s) => s,
+                    value @ (Value::Bool(_) | Value::Number(_)) => value.to_string(),
+                    Value::Array(_) | Value::Object(_) => {
+                        return Err(TransportError::Validation(format!(
+                            "query parameter {key} is not a scalar"
+                        )))
+                    }
+                };
+                pairs.push((key.clone(), value));
+            }
+        }
+        Ok(self.query(&pairs))
+    }
+}
+
+/// Returns `uri` with `pairs` appended to its query.
+///
+/// The result is expected to be valid since encoded pairs only add valid query characters.
+fn append_query<'a>(uri: &Uri, pairs: impl Iterator<Item = (&'a str, &'a str)>) -> Uri {
+    let mut pairs = pairs.peekable();
+    if pairs.peek().is_none() {
+        return uri.clone();
+    }
+    let mut path_and_query = uri
+        .path_and_query()
+        .map(PathAndQuery::as_str)
+        .unwrap_or("/")
+        .to_string();
+    if uri.query().is_none() {
+        path_and_query.push('?');
+    }
+    let mut needs_separator = uri.query().is_some_and(|q| !q.is_empty());
+    for (key, value) in pairs {
+        if needs_separator {
+            path_and_query.push('&');
+        }
+        needs_separator = true;
+        form_urlencode(key, &mut path_and_query);
+        path_and_query.push('=');
+        form_urlencode(value, &mut path_and_query);
+    }
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query =
+        Some(PathAndQuery::try_from(path_and_query).expect("encoded query parameters are valid"));
+    Uri::from_parts(parts).expect("only the query of a valid uri changed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_encoded_pairs() {
+        let request = http::Request::get("http://example.com/search?lang=en")
+            .query(&[("q", "rust & monoio"), ("page", "2")])
+            .body(())
+            .unwrap();
+        assert_eq!(
+            request.uri(),
+            "http://example.com/search?lang=en&q=rust+%26+monoio&page=2"
+        );
+
+        let request = http::Request::get("/items")
+            .query(&[("id", "ü")])
+            .body(())
+            .unwrap();
+        assert_eq!(request.uri(), "/items?id=%C3%BC");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_query_pairs() {
+        #[derive(serde::Serialize)]
+        struct Search<'a> {
+            q: &'a str,
+            tags: Vec<&'a str>,
+            limit: Option<u32>,
+            exact: bool,
+        }
+
+        let request = http::Request::get("/search")
+            .query_pairs(&Search {
+                q: "a b",
+                tags: vec!["x", "y"],
+                limit: None,
+                exact: true,
+            })
+            .unwrap()
+            .body(())
+            .unwrap();
+        assert_eq!(request.uri(), "/search?exact=true&q=a+b&tags=x&tags=y");
+        assert!(http::Request::get("/").query_pairs(&[1, 2]).is_err());
+    }
+}
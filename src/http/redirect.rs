@@ -0,0 +1,440 @@
+//! Following HTTP redirects.
+//!
+//! - [`RedirectPolicy`]: Decides whether `3xx` responses are followed: never, up to a hop limit, or
+//!   as decided by a callback.
+//! - [`send_following_redirects`]: Connects and sends a request, following redirects according to a
+//!   policy. [`send_following_redirects_with`] derives the key of each hop with a [`DeriveKey`].
+//!
+//! Following is opt-in: [`HttpConnection::send_request`](super::HttpConnection::send_request)
+//! returns redirect responses as is.
+use std::{fmt, rc::Rc};
+
+use http::{
+    header::{self, HeaderMap, HeaderValue},
+    Method, Response, StatusCode, Uri, Version,
+};
+use monoio::io::{sink::Sink, AsyncReadRent, AsyncWriteRent};
+use monoio_http::{
+    common::{body::HttpBody, error::HttpError, request::Request},
+    h1::{codec::ClientCodec, payload::Payload},
+};
+
+use super::HttpConnection;
+use crate::{
+    connectors::{uri_host_port, Connector},
+    pool::{DeriveKey, Key, TryFromUri},
+    FromUriError, TransportError,
+};
+
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// Headers carrying credentials, which are not forwarded to another origin.
+const SENSITIVE_HEADERS: [header::HeaderName; 3] = [
+    header::AUTHORIZATION,
+    header::COOKIE,
+    header::PROXY_AUTHORIZATION,
+];
+
+/// A redirect about to be followed, as seen by a [`RedirectPolicy::Custom`] callback.
+#[derive(Debug)]
+pub struct RedirectAttempt<'a> {
+    /// The status of the redirect response.
+    pub status: StatusCode,
+    /// The resolved target of the redirect.
+    pub location: &'a Uri,
+    /// The uris requested so far, starting with the original one.
+    pub previous: &'a [Uri],
+}
+
+/// The decision of a [`RedirectPolicy::Custom`] callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectAction {
+    /// Follow the redirect.
+    Follow,
+    /// Return the redirect response to the caller.
+    Stop,
+}
+
+/// A policy for following redirects.
+///
+/// The default follows up to 10 redirects.
+#[derive(Clone)]
+pub enum RedirectPolicy {
+    /// Redirect responses are returned as is.
+    None,
+    /// Redirects are followed up to the given number of hops, after which
+    /// [`TransportError::TooManyRedirects`] is returned.
+    Limited(usize),
+    /// Every redirect is submitted to the callback.
+    Custom(Rc<dyn Fn(&RedirectAttempt<'_>) -> RedirectAction>),
+}
+
+impl Default for RedirectPolicy {
+    #[inline]
+    fn default() -> Self {
+        Self::Limited(DEFAULT_MAX_REDIRECTS)
+    }
+}
+
+impl fmt::Debug for RedirectPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => f.write_str("None"),
+            Self::Limited(max) => f.debug_tuple("Limited").field(max).finish(),
+            Self::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+impl RedirectPolicy {
+    /// Creates a policy deciding every redirect with `decide`.
+    #[inline]
+    pub fn custom(decide: impl Fn(&RedirectAttempt<'_>) -> RedirectAction + 'static) -> Self {
+        Self::Custom(Rc::new(decide))
+    }
+}
+
+/// Returns true if `a` and `b` share scheme, host and port.
+fn same_origin(a: &Uri, b: &Uri) -> bool {
+    a.scheme() == b.scheme()
+        && match (uri_host_port(a), uri_host_port(b)) {
+            (Ok((a_host, a_port)), Ok((b_host, b_port))) => {
+                a_host.eq_ignore_ascii_case(b_host) && a_port == b_port
+            }
+            _ => false,
+        }
+}
+
+/// Resolves a `Location` header value against the uri it was returned for.
+fn resolve_location(base: &Uri, location: &str) -> Option<Uri> {
+    // Fragments are never sent to the server.
+    let location = location.split('#').next().unwrap_or_default().trim();
+    let scheme = base.scheme_str()?;
+    let authority = base.authority()?.as_str();
+    let resolved = if location.contains("://") {
+        location.to_owned()
+    } else if let Some(rest) = location.strip_prefix("//") {
+        format!("{scheme}://{rest}")
+    } else if location.starts_with('/') {
+        format!("{scheme}://{authority}{location}")
+    } else {
+        let path = base.path();
+        let dir = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+        let dir = if dir.is_empty() { "/" } else { dir };
+        format!("{scheme}://{authority}{dir}{location}")
+    };
+    let uri = Uri::try_from(resolved).ok()?;
+    uri.authority()?;
+    Some(uri)
+}
+
+/// Returns the method to use for the request following a redirect with `status`.
+fn redirect_method(status: StatusCode, method: &Method) -> Method {
+    match status {
+        StatusCode::SEE_OTHER if *method != Method::HEAD => Method::GET,
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND if *method == Method::POST => Method::GET,
+        _ => method.clone(),
+    }
+}
+
+/// Connects to the origin of `request` and sends it, following redirects according to `policy`.
+///
+/// `request` must have an absolute uri, from which the key of every hop is derived. It is sent
+/// with its path and query only, and a `Host` header set to the authority of the hop. The body of
+/// a hop is built by `make_body`, which is called again for each redirect that preserves the
+/// method.
+///
+/// `301` and `302` redirects turn `POST` into `GET`, `303` turns any method but `HEAD` into `GET`,
+/// dropping the body and its headers, while `307` and `308` preserve the method and body.
+/// `Authorization`, `Cookie` and `Proxy-Authorization` are removed once a redirect leads to
+/// another origin. A redirect without a usable `Location` header is returned as is. Request
+/// extensions are not sent.
+pub async fn send_following_redirects<C, K, IO, E, F>(
+    connector: &C,
+    policy: &RedirectPolicy,
+    request: http::Request<()>,
+    make_body: F,
+) -> Result<Response<HttpBody>, TransportError>
+where
+    C: Connector<K, Connection = HttpConnection<K, IO>>,
+    TransportError: From<C::Error>,
+    for<'a> K: Key + TryFrom<&'a Uri, Error = FromUriError>,
+    IO: AsyncReadRent + AsyncWriteRent,
+    F: FnMut() -> HttpBody,
+    ClientCodec<IO>: Sink<Request<HttpBody>, Error = E>,
+    E: fmt::Debug + Into<HttpError>,
+{
+    send_following_redirects_with(connector, policy, &TryFromUri, request, make_body).await
+}
+
+/// Like [`send_following_redirects`], with the key of every hop derived by `derive_key`.
+pub async fn send_following_redirects_with<C, K, D, IO, E, F>(
+    connector: &C,
+    policy: &RedirectPolicy,
+    derive_key: &D,
+    request: http::Request<()>,
+    mut make_body: F,
+) -> Result<Response<HttpBody>, TransportError>
+where
+    C: Connector<K, Connection = HttpConnection<K, IO>>,
+    TransportError: From<C::Error>,
+    K: Key,
+    D: DeriveKey<K>,
+    IO: AsyncReadRent + AsyncWriteRent,
+    F: FnMut() -> HttpBody,
+    ClientCodec<IO>: Sink<Request<HttpBody>, Error = E>,
+    E: fmt::Debug + Into<HttpError>,
+{
+    let (parts, ()) = request.into_parts();
+    let (mut method, mut uri, mut headers, version): (Method, Uri, HeaderMap, Version) =
+        (parts.method, parts.uri, parts.headers, parts.version);
+    let mut has_body = true;
+    let mut previous = Vec::new();
+    loop {
+        let key = derive_key.derive_key(&uri)?;
+        let authority = uri.authority().ok_or(FromUriError::NoAuthority)?;
+        headers.insert(
+            header::HOST,
+            HeaderValue::from_str(authority.as_str()).map_err(http::Error::from)?,
+        );
+        let mut request = http::Request::builder()
+            .method(method.clone())
+            .version(version)
+            .uri(uri.path_and_query().map_or("/", |pq| pq.as_str()))
+            .body(if has_body {
+                make_body()
+            } else {
+                HttpBody::H1(Payload::None)
+            })?;
+        *request.headers_mut() = headers.clone();
+
+        let mut conn = connector.connect(key).await?;
+        let response = conn.send_request(request).await.0?;
+
+        let status = response.status();
+        if !matches!(
+            status,
+            StatusCode::MOVED_PERMANENTLY
+                | StatusCode::FOUND
+                | StatusCode::SEE_OTHER
+                | StatusCode::TEMPORARY_REDIRECT
+                | StatusCode::PERMANENT_REDIRECT
+        ) {
+            return Ok(response);
+        }
+        let Some(location) = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|l| l.to_str().ok())
+            .and_then(|l| resolve_location(&uri, l))
+        else {
+            return Ok(response);
+        };
+        previous.push(uri);
+        match policy {
+            RedirectPolicy::None => return Ok(response),
+            RedirectPolicy::Limited(max) if previous.len() > *max => {
+                return Err(TransportError::TooManyRedirects(*max));
+            }
+            RedirectPolicy::Limited(_) => {}
+            RedirectPolicy::Custom(decide) => {
+                let attempt = RedirectAttempt {
+                    status,
+                    location: &location,
+                    previous: &previous,
+                };
+                if decide(&attempt) == RedirectAction::Stop {
+                    return Ok(response);
+                }
+            }
+        }
+        #[cfg(feature = "logging")]
+        tracing::debug!("following {status} redirect to {location}");
+
+        let next_method = redirect_method(status, &method);
+        if next_method != method {
+            has_body = false;
+            for name in [
+                header::CONTENT_LENGTH,
+                header::CONTENT_TYPE,
+                header::CONTENT_ENCODING,
+                header::TRANSFER_ENCODING,
+            ] {
+                headers.remove(name);
+            }
+            method = next_method;
+        }
+        if !same_origin(previous.last().unwrap(), &location) {
+            for name in SENSITIVE_HEADERS {
+                headers.remove(name);
+            }
+        }
+        uri = location;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        io,
+        net::{SocketAddr, ToSocketAddrs},
+    };
+
+    use monoio::io::AsyncWriteRentExt;
+
+    use super::*;
+    use crate::{
+        connectors::{TcpConnector, TcpDestination, TcpTarget},
+        http::HttpConnector,
+    };
+
+    /// A key resolving uris to their socket address.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    struct Addr(SocketAddr);
+
+    impl TryFrom<&Uri> for Addr {
+        type Error = FromUriError;
+
+        fn try_from(uri: &Uri) -> Result<Self, Self::Error> {
+            let addr = uri_host_port(uri)?.to_socket_addrs()?.next();
+            addr.map(Self).ok_or(FromUriError::NoResolve)
+        }
+    }
+
+    impl TcpTarget for Addr {
+        fn tcp_target(&self) -> io::Result<TcpDestination<'_>> {
+            Ok(TcpDestination::Addrs(vec![self.0]))
+        }
+    }
+
+    /// Answers every request with the response for its path, recording the request heads.
+    fn serve(routes: Vec<(&'static str, &'static str)>, seen: Rc<RefCell<Vec<String>>>) -> u16 {
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let routes = Rc::new(routes);
+        monoio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                let (routes, seen) = (routes.clone(), seen.clone());
+                monoio::spawn(async move {
+                    let mut buf = Vec::with_capacity(4096);
+                    loop {
+                        let (res, b) = conn.read(buf).await;
+                        buf = b;
+                        if !matches!(res, Ok(n) if n > 0) {
+                            return;
+                        }
+                        let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+                            continue;
+                        };
+                        let head = String::from_utf8_lossy(&buf[..end]).to_ascii_lowercase();
+                        buf.clear();
+                        let path = head.split(' ').nth(1).unwrap_or_default().to_owned();
+                        seen.borrow_mut().push(head);
+                        let response =
+                            routes.iter().find(|(p, _)| *p == path).map_or(
+                                "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n",
+                                |r| r.1,
+                            );
+                        if conn.write_all(response.as_bytes()).await.0.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn follows_redirects() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let port = serve(
+            vec![
+                (
+                    "/submit",
+                    "HTTP/1.1 303 See Other\r\nlocation: done?x=1\r\ncontent-length: 0\r\n\r\n",
+                ),
+                (
+                    "/done?x=1",
+                    "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok",
+                ),
+                (
+                    "/loop",
+                    "HTTP/1.1 307 Temporary Redirect\r\nlocation: /loop\r\ncontent-length: \
+                     0\r\n\r\n",
+                ),
+            ],
+            seen.clone(),
+        );
+        let mut connector: HttpConnector<TcpConnector, Addr, _> =
+            HttpConnector::new(Default::default());
+        connector.set_http1_only();
+        let post = |path: &str| {
+            http::Request::post(format!("http://127.0.0.1:{port}{path}"))
+                .header(header::AUTHORIZATION, "Bearer secret")
+                .header(header::CONTENT_LENGTH, 2)
+                .body(())
+                .unwrap()
+        };
+        let body = || HttpBody::Ready(Some("hi".into()));
+
+        let response = send_following_redirects(
+            &connector,
+            &RedirectPolicy::default(),
+            post("/submit"),
+            body,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        {
+            let seen = seen.borrow();
+            assert!(seen[0].starts_with("post /submit "));
+            // The 303 switches to a body-less GET, keeping credentials on the same origin.
+            assert!(seen[1].starts_with("get /done?x=1 "));
+            assert!(!seen[1].contains("content-length"));
+            assert!(seen[1].contains("authorization: bearer secret"));
+        }
+
+        let response =
+            send_following_redirects(&connector, &RedirectPolicy::None, post("/submit"), body)
+                .await
+                .unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        seen.borrow_mut().clear();
+        let err =
+            send_following_redirects(&connector, &RedirectPolicy::Limited(2), post("/loop"), body)
+                .await
+                .unwrap_err();
+        assert!(matches!(err, TransportError::TooManyRedirects(2)));
+        // The 307 preserves the method on every hop.
+        assert_eq!(seen.borrow().len(), 3);
+        assert!(seen
+            .borrow()
+            .iter()
+            .all(|head| head.starts_with("post /loop ")));
+    }
+
+    #[test]
+    fn resolves_locations() {
+        let base = Uri::from_static("https://example.com/a/b?q=1");
+        let resolve = |l| resolve_location(&base, l).unwrap().to_string();
+        assert_eq!(resolve("http://other.com/x"), "http://other.com/x");
+        assert_eq!(resolve("//cdn.example.com/y"), "https://cdn.example.com/y");
+        assert_eq!(resolve("/root#frag"), "https://example.com/root");
+        assert_eq!(resolve("c?d=2"), "https://example.com/a/c?d=2");
+
+        let other = Uri::from_static("https://example.com:8443/");
+        assert!(same_origin(
+            &base,
+            &Uri::from_static("https://EXAMPLE.com:443/z")
+        ));
+        assert!(!same_origin(&base, &other));
+        assert!(!same_origin(
+            &base,
+            &Uri::from_static("http://example.com/")
+        ));
+    }
+}
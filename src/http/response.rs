@@ -0,0 +1,206 @@
+//! Reading whole response bodies.
+//!
+//! [`ResponseExt`] collects a response body into [`Bytes`] or a [`String`], optionally failing
+//! with [`TransportError::BodyTooLarge`] once it exceeds a size limit. Text is decoded with the
+//! `charset` parameter of the `Content-Type`: UTF-8, US-ASCII, ISO-8859-1 and UTF-16 are
+//! supported, and bodies in any other or no charset are read as UTF-8, replacing invalid
+//! sequences with `U+FFFD`. It also saves bodies to files without buffering them, see
+//! [`download`](super::download).
+use std::{future::Future, path::Path};
+
+use bytes::{Bytes, BytesMut};
+use http::{header, HeaderMap, Response};
+use monoio_http::common::{body::Body, error::HttpError};
+
+use super::download::Download;
+use crate::TransportError;
+
+/// Collects response bodies.
+pub trait ResponseExt {
+    /// Reads the whole body.
+    fn bytes(self) -> impl Future<Output = Result<Bytes, TransportError>>;
+
+    /// Reads the whole body, failing as soon as it exceeds `max_size` bytes.
+    fn bytes_with_limit(
+        self,
+        max_size: usize,
+    ) -> impl Future<Output = Result<Bytes, TransportError>>;
+
+    /// Reads the whole body and decodes it with the charset of the response.
+    fn text(self) -> impl Future<Output = Result<String, TransportError>>;
+
+    /// Reads the whole body like [`bytes_with_limit`](Self::bytes_with_limit) and decodes it
+    /// with the charset of the response.
+    fn text_with_limit(
+        self,
+        max_size: usize,
+    ) -> impl Future<Output = Result<String, TransportError>>;
+
+    /// Writes the body to the file at `path` as it is received, see
+    /// [`Download::save`](super::download::Download::save).
+    fn save_to_file(
+        self,
+        path: impl AsRef<Path>,
+    ) -> impl Future<Output = Result<u64, TransportError>>;
+}
+
+impl<B> ResponseExt for Response<B>
+where
+    B: Body<Data = Bytes>,
+    B::Error: Into<HttpError>,
+{
+    #[inline]
+    fn bytes(self) -> impl Future<Output = Result<Bytes, TransportError>> {
+        self.bytes_with_limit(usize::MAX)
+    }
+
+    async fn bytes_with_limit(self, max_size: usize) -> Result<Bytes, TransportError> {
+        collect(self.into_body(), max_size).await
+    }
+
+    #[inline]
+    fn text(self) -> impl Future<Output = Result<String, TransportError>> {
+        self.text_with_limit(usize::MAX)
+    }
+
+    async fn text_with_limit(self, max_size: usize) -> Result<String, TransportError> {
+        let (parts, body) = self.into_parts();
+        let data = collect(body, max_size).await?;
+        Ok(decode_text(&data, charset(&parts.headers)))
+    }
+
+    async fn save_to_file(self, path: impl AsRef<Path>) -> Result<u64, TransportError> {
+        Download::new().save(self, path).await
+    }
+}
+
+pub(crate) async fn collect<B>(mut body: B, max_size: usize) -> Result<Bytes, TransportError>
+where
+    B: Body<Data = Bytes>,
+    B::Error: Into<HttpError>,
+{
+    let mut first = None;
+    let mut buffer = BytesMut::new();
+    let mut size = 0usize;
+    while let Some(chunk) = body.next_data().await {
+        let chunk = chunk.map_err(Into::into)?;
+        size = size.saturating_add(chunk.len());
+        if size > max_size {
+            return Err(TransportError::BodyTooLarge(max_size));
+        }
+        // A single chunk is returned as is, without copying.
+        match first.take() {
+            None if buffer.is_empty() => first = Some(chunk),
+            None => buffer.extend_from_slice(&chunk),
+            Some(previous) => {
+                buffer.extend_from_slice(&previous);
+                buffer.extend_from_slice(&chunk);
+            }
+        }
+    }
+    Ok(first.unwrap_or_else(|| buffer.freeze()))
+}
+
+/// Returns the `charset` parameter of the `Content-Type`, if any.
+pub fn charset(headers: &HeaderMap) -> Option<&str> {
+    let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// Decodes `data` in `charset`, falling back to lossy UTF-8 for unknown charsets.
+pub fn decode_text(data: &[u8], charset: Option<&str>) -> String {
+    let charset = charset.unwrap_or("utf-8").to_ascii_lowercase();
+    match charset.as_str() {
+        "iso-8859-1" | "latin1" | "l1" | "iso_8859-1" => data.iter().map(|&b| b as char).collect(),
+        "utf-16le" => decode_utf16(data, u16::from_le_bytes),
+        "utf-16be" => decode_utf16(data, u16::from_be_bytes),
+        "utf-16" => match data {
+            [0xfe, 0xff, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+            [0xff, 0xfe, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+            _ => decode_utf16(data, u16::from_be_bytes),
+        },
+        // US-ASCII is a subset of UTF-8.
+        _ => {
+            let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+            String::from_utf8_lossy(data).into_owned()
+        }
+    }
+}
+
+fn decode_utf16(data: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units = data.chunks(2).map(|pair| match pair {
+        [a, b] => from_bytes([*a, *b]),
+        // A trailing odd byte is invalid.
+        _ => 0xfffd,
+    });
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use monoio_http::common::body::StreamHint;
+
+    use super::*;
+
+    struct Chunked(VecDeque<&'static [u8]>);
+
+    impl Body for Chunked {
+        type Data = Bytes;
+        type Error = HttpError;
+
+        async fn next_data(&mut self) -> Option<Result<Self::Data, Self::Error>> {
+            self.0.pop_front().map(|s| Ok(Bytes::from_static(s)))
+        }
+
+        fn stream_hint(&self) -> StreamHint {
+            StreamHint::Stream
+        }
+    }
+
+    fn response(content_type: &str, chunks: &[&'static [u8]]) -> Response<Chunked> {
+        Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Chunked(chunks.iter().copied().collect()))
+            .unwrap()
+    }
+
+    #[monoio::test]
+    async fn collects_text_in_its_charset() {
+        let text = response("text/plain; charset=ISO-8859-1", &[b"caf", b"\xe9"])
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(text, "café");
+        let text = response("text/plain; charset=\"utf-16le\"", &[b"h\0i\0"])
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(text, "hi");
+        let text = response("text/plain", &[b"ok \xff"]).text().await.unwrap();
+        assert_eq!(text, "ok \u{fffd}");
+    }
+
+    #[monoio::test]
+    async fn enforces_size_limit() {
+        let body = response("text/plain", &[b"abc", b"def"])
+            .bytes_with_limit(6)
+            .await
+            .unwrap();
+        assert_eq!(body, "abcdef");
+        assert!(matches!(
+            response("text/plain", &[b"abc", b"defg"])
+                .bytes_with_limit(6)
+                .await,
+            Err(TransportError::BodyTooLarge(6))
+        ));
+    }
+}
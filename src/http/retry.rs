@@ -0,0 +1,298 @@
+//! Retrying requests on transient failures.
+//!
+//! - [`RetryPolicy`]: Decides which requests and responses are retried, and how long to wait in
+//!   between, using exponential backoff with full jitter and honoring `Retry-After`.
+//! - [`send_with_retry`]: Connects and sends a request, retrying it according to a policy.
+//!
+//! Retrying is opt-in: [`HttpConnection::send_request`](super::HttpConnection::send_request) never
+//! retries on its own. Requests are rebuilt for every attempt, since bodies cannot be replayed.
+use std::time::Duration;
+
+use http::{header, Method, Response, StatusCode};
+use monoio::io::{sink::Sink, AsyncReadRent, AsyncWriteRent};
+use monoio_http::{
+    common::{
+        body::HttpBody,
+        error::HttpError,
+        request::{Request, RequestHead},
+        IntoParts,
+    },
+    h1::codec::ClientCodec,
+};
+
+use super::{body::IntoBody, HttpConnection};
+use crate::{connectors::Connector, pool::Key, TransportError};
+
+/// A policy for retrying failed requests.
+///
+/// By default a request is attempted up to 3 times, only if its method is idempotent, and is
+/// retried on connection errors and on `429`, `502` and `503` responses.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    statuses: Vec<StatusCode>,
+    retry_non_idempotent: bool,
+    honor_retry_after: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+            ],
+            retry_non_idempotent: false,
+            honor_retry_after: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of attempts, including the first one.
+    #[inline]
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets the initial backoff and its upper bound.
+    #[inline]
+    pub fn with_backoff(mut self, base_delay: Duration, max_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Sets the response status codes that are retried.
+    #[inline]
+    pub fn with_statuses(mut self, statuses: impl IntoIterator<Item = StatusCode>) -> Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Allows retrying requests with non-idempotent methods, such as `POST`.
+    #[inline]
+    pub fn with_retry_non_idempotent(mut self, retry: bool) -> Self {
+        self.retry_non_idempotent = retry;
+        self
+    }
+
+    /// Sets whether a `Retry-After` header in seconds replaces the computed backoff.
+    #[inline]
+    pub fn with_honor_retry_after(mut self, honor: bool) -> Self {
+        self.honor_retry_after = honor;
+        self
+    }
+
+    #[inline]
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns true if requests with `method` may be retried.
+    pub fn allows_method(&self, method: &Method) -> bool {
+        self.retry_non_idempotent
+            || matches!(
+                *method,
+                Method::GET
+                    | Method::HEAD
+                    | Method::OPTIONS
+                    | Method::TRACE
+                    | Method::PUT
+                    | Method::DELETE
+            )
+    }
+
+    /// Returns true if a response with `status` should be retried.
+    #[inline]
+    pub fn retries_status(&self, status: StatusCode) -> bool {
+        self.statuses.contains(&status)
+    }
+
+    /// Returns the delay before the attempt following the `attempt`-th one (starting at 1):
+    /// a random duration up to `base_delay * 2^(attempt - 1)`, capped at `max_delay`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        use std::hash::{BuildHasher, Hasher};
+
+        let exp = self
+            .base_delay
+            .saturating_mul(1 << attempt.saturating_sub(1).min(31))
+            .min(self.max_delay);
+        let random = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        exp.mul_f64((random >> 11) as f64 / (1u64 << 53) as f64)
+    }
+
+    /// Returns the delay requested by the `Retry-After` header of `response`, if honored.
+    ///
+    /// Only the delay-seconds form is understood, HTTP dates are ignored.
+    pub fn retry_after<B>(&self, response: &Response<B>) -> Option<Duration> {
+        if !self.honor_retry_after {
+            return None;
+        }
+        let secs = response
+            .headers()
+            .get(header::RETRY_AFTER)?
+            .to_str()
+            .ok()?
+            .trim()
+            .parse::<u64>()
+            .ok()?;
+        Some(Duration::from_secs(secs).min(self.max_delay))
+    }
+}
+
+/// Connects to `key` and sends the request built by `make_request`, retrying it according to
+/// `policy`.
+///
+/// A new request is built for every attempt. Connection and send errors are retried, as are
+/// responses with a retried status code until the attempts run out, in which case the last
+/// response is returned. Requests with a method the policy does not allow are sent once.
+/// Sleeping between attempts requires the monoio timer driver.
+pub async fn send_with_retry<C, K, IO, B, E, F>(
+    connector: &C,
+    key: &K,
+    policy: &RetryPolicy,
+    mut make_request: F,
+) -> Result<Response<HttpBody>, TransportError>
+where
+    C: Connector<K, Connection = HttpConnection<K, IO>>,
+    TransportError: From<C::Error>,
+    K: Key,
+    IO: AsyncReadRent + AsyncWriteRent,
+    F: FnMut() -> Request<B>,
+    B: IntoBody,
+    ClientCodec<IO>: Sink<Request<B::Body>, Error = E>,
+    E: std::fmt::Debug + Into<HttpError>,
+    Request<B::Body>: IntoParts<Parts = RequestHead, Body = B::Body>,
+{
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let request = make_request().map(IntoBody::into_body);
+        let retryable = attempt < policy.max_attempts && policy.allows_method(request.method());
+        let result = match connector.connect(key.clone()).await {
+            Ok(mut conn) => conn.send_request(request).await.0.map_err(Into::into),
+            Err(e) => Err(e.into()),
+        };
+        let delay = match result {
+            Ok(response) if retryable && policy.retries_status(response.status()) => policy
+                .retry_after(&response)
+                .unwrap_or_else(|| policy.backoff(attempt)),
+            Ok(response) => return Ok(response),
+            Err(_e) if retryable => {
+                #[cfg(feature = "logging")]
+                tracing::debug!("attempt {attempt} failed, retrying: {_e}");
+                policy.backoff(attempt)
+            }
+            Err(e) => return Err(e),
+        };
+        monoio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, net::SocketAddr, rc::Rc};
+
+    use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+    use monoio_http::h1::payload::Payload;
+
+    use super::*;
+    use crate::{connectors::TcpConnector, http::HttpConnector};
+
+    /// Serves the given raw responses, one per request, over keep-alive connections.
+    fn serve(responses: Vec<&'static str>, hits: Rc<Cell<usize>>) -> SocketAddr {
+        let listener = monoio::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let responses = Rc::new(responses);
+        monoio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                let (responses, hits) = (responses.clone(), hits.clone());
+                monoio::spawn(async move {
+                    let mut buf = Vec::with_capacity(4096);
+                    loop {
+                        let (res, b) = conn.read(buf).await;
+                        buf = b;
+                        if !matches!(res, Ok(n) if n > 0) {
+                            return;
+                        }
+                        if !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+                            continue;
+                        }
+                        buf.clear();
+                        let i = hits.get();
+                        hits.set(i + 1);
+                        let response = responses[i.min(responses.len() - 1)];
+                        if conn.write_all(response.as_bytes()).await.0.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    fn request(method: Method) -> http::Request<HttpBody> {
+        http::Request::builder()
+            .method(method)
+            .uri("/")
+            .header(header::HOST, "localhost")
+            .body(HttpBody::H1(Payload::None))
+            .unwrap()
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn retries_idempotent_requests_on_status() {
+        let hits = Rc::new(Cell::new(0));
+        let addr = serve(
+            vec![
+                "HTTP/1.1 503 Service Unavailable\r\nretry-after: 0\r\ncontent-length: 0\r\n\r\n",
+                "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok",
+            ],
+            hits.clone(),
+        );
+        let connector: HttpConnector<TcpConnector, SocketAddr, _> =
+            HttpConnector::build_tcp_http1_only();
+        let policy = RetryPolicy::new().with_backoff(Duration::ZERO, Duration::ZERO);
+
+        let response = send_with_retry(&connector, &addr, &policy, || request(Method::GET))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.get(), 2);
+
+        // POST is not idempotent, so the 503 is returned as is.
+        hits.set(0);
+        let response = send_with_retry(&connector, &addr, &policy, || request(Method::POST))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(hits.get(), 1);
+    }
+
+    #[test]
+    fn backoff_is_bounded() {
+        let policy =
+            RetryPolicy::new().with_backoff(Duration::from_millis(100), Duration::from_secs(1));
+        for attempt in 1..40 {
+            let cap =
+                Duration::from_millis(100 << (attempt - 1).min(4)).min(Duration::from_secs(1));
+            assert!(policy.backoff(attempt) <= cap);
+        }
+    }
+}
@@ -0,0 +1,450 @@
+//! Server-Sent Events (`text/event-stream`) support.
+//!
+//! - [`EventDecoder`]: An incremental parser for the event stream framing. It can be fed
+//!   arbitrarily split chunks and yields complete [`Event`]s.
+//! - [`EventStream`]: Adapts any response [`Body`] into a [`Stream`] of events.
+//! - [`EventSource`]: Keeps an event stream alive across disconnections, reconnecting with the
+//!   `Last-Event-ID` of the last received event and honoring `retry:` hints from the server.
+//!
+//! Note: the HTTP/1.1 path of [`HttpConnection`](super::HttpConnection) reads the whole response
+//! body before returning it, so long-lived event streams should be consumed over HTTP/2 or through
+//! the hyper connectors (wrapping the body with `MonoioBody`).
+use std::{collections::VecDeque, future::Future, mem, time::Duration};
+
+use bytes::Bytes;
+use http::{header, HeaderValue, Response, StatusCode};
+use monoio::io::stream::Stream;
+use monoio_http::common::body::Body;
+use thiserror::Error as ThisError;
+
+/// Reconnection delay used until the server sends a `retry:` field.
+pub const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+
+/// The MIME type of an event stream.
+pub const EVENT_STREAM_MIME: &str = "text/event-stream";
+
+/// A single dispatched server-sent event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Event {
+    /// The event type, `message` if the server did not set one.
+    pub event: String,
+    /// The event payload. Multiple `data:` lines are joined with `\n`.
+    pub data: String,
+    /// The last event id at the time this event was dispatched.
+    pub id: Option<String>,
+    /// The reconnection time most recently announced by the server, if any.
+    pub retry: Option<Duration>,
+}
+
+/// An incremental `text/event-stream` parser.
+#[derive(Debug, Default)]
+pub struct EventDecoder {
+    line: Vec<u8>,
+    pending_cr: bool,
+    bom_checked: bool,
+    event: String,
+    data: String,
+    has_data: bool,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+    ready: VecDeque<Event>,
+}
+
+impl EventDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a decoder that resumes from a previously received event id.
+    pub fn with_last_event_id(last_event_id: Option<String>) -> Self {
+        Self {
+            last_event_id,
+            ..Default::default()
+        }
+    }
+
+    /// Feeds a chunk of the stream into the decoder.
+    ///
+    /// Complete events can be retrieved with [`EventDecoder::next_event`].
+    pub fn feed(&mut self, mut chunk: &[u8]) {
+        if !self.bom_checked {
+            // The BOM may be split across chunks, so wait until we can tell.
+            let bom = b"\xEF\xBB\xBF";
+            let seen = self.line.len();
+            let take = (bom.len() - seen).min(chunk.len());
+            if chunk[..take] != bom[seen..seen + take] {
+                self.bom_checked = true;
+                let partial = mem::take(&mut self.line);
+                self.feed_lines(&partial);
+            } else if seen + take == bom.len() {
+                self.bom_checked = true;
+                self.line.clear();
+                chunk = &chunk[take..];
+            } else {
+                self.line.extend_from_slice(&chunk[..take]);
+                return;
+            }
+        }
+        self.feed_lines(chunk);
+    }
+
+    fn feed_lines(&mut self, mut chunk: &[u8]) {
+        if self.pending_cr {
+            self.pending_cr = false;
+            if let Some(b'\n') = chunk.first() {
+                chunk = &chunk[1..];
+            }
+        }
+        while let Some(pos) = chunk.iter().position(|b| *b == b'\n' || *b == b'\r') {
+            self.line.extend_from_slice(&chunk[..pos]);
+            let line = mem::take(&mut self.line);
+            self.process_line(&line);
+            self.line = line;
+            self.line.clear();
+
+            if chunk[pos] == b'\r' {
+                match chunk.get(pos + 1) {
+                    Some(b'\n') => chunk = &chunk[pos + 2..],
+                    Some(_) => chunk = &chunk[pos + 1..],
+                    None => {
+                        self.pending_cr = true;
+                        return;
+                    }
+                }
+            } else {
+                chunk = &chunk[pos + 1..];
+            }
+        }
+        self.line.extend_from_slice(chunk);
+    }
+
+    fn process_line(&mut self, line: &[u8]) {
+        if line.is_empty() {
+            self.dispatch();
+            return;
+        }
+        if line[0] == b':' {
+            // Comment line, usually used as a keep-alive.
+            return;
+        }
+        let (field, value) = match line.iter().position(|b| *b == b':') {
+            Some(pos) => {
+                let value = &line[pos + 1..];
+                (&line[..pos], value.strip_prefix(b" ").unwrap_or(value))
+            }
+            None => (line, &b""[..]),
+        };
+        let value = String::from_utf8_lossy(value);
+        match field {
+            b"event" => self.event = value.into_owned(),
+            b"data" => {
+                if self.has_data {
+                    self.data.push('\n');
+                }
+                self.data.push_str(&value);
+                self.has_data = true;
+            }
+            b"id" if !value.contains('\0') => self.last_event_id = Some(value.into_owned()),
+            b"retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(ms) = value.parse() {
+                    self.retry = Some(Duration::from_millis(ms));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn dispatch(&mut self) {
+        let event = mem::take(&mut self.event);
+        if !mem::take(&mut self.has_data) {
+            return;
+        }
+        self.ready.push_back(Event {
+            event: if event.is_empty() {
+                "message".to_string()
+            } else {
+                event
+            },
+            data: mem::take(&mut self.data),
+            id: self.last_event_id.clone(),
+            retry: self.retry,
+        });
+    }
+
+    /// Pops the next complete event, if one is available.
+    #[inline]
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.ready.pop_front()
+    }
+
+    /// Returns the id of the last event seen on the stream.
+    #[inline]
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Returns the reconnection time announced by the server, if any.
+    #[inline]
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+}
+
+/// Adapts a response body into a [`Stream`] of [`Event`]s.
+pub struct EventStream<B> {
+    body: B,
+    decoder: EventDecoder,
+    done: bool,
+}
+
+impl<B> EventStream<B> {
+    pub fn new(body: B) -> Self {
+        Self::with_decoder(body, EventDecoder::new())
+    }
+
+    pub fn with_decoder(body: B, decoder: EventDecoder) -> Self {
+        Self {
+            body,
+            decoder,
+            done: false,
+        }
+    }
+
+    #[inline]
+    pub fn decoder(&self) -> &EventDecoder {
+        &self.decoder
+    }
+
+    #[inline]
+    pub fn into_decoder(self) -> EventDecoder {
+        self.decoder
+    }
+}
+
+impl<B: Body<Data = Bytes>> Stream for EventStream<B> {
+    type Item = Result<Event, B::Error>;
+
+    async fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.decoder.next_event() {
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+            match self.body.next_data().await {
+                Some(Ok(data)) => self.decoder.feed(&data),
+                Some(Err(e)) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+                None => {
+                    // An incomplete trailing event is discarded, as the spec requires.
+                    self.done = true;
+                }
+            }
+        }
+    }
+}
+
+#[derive(ThisError, Debug)]
+pub enum SseError<E, BE> {
+    #[error("Connect error")]
+    Connect(E),
+    #[error("Body error")]
+    Body(BE),
+    #[error("Unexpected status {0}")]
+    Status(StatusCode),
+    #[error("Unexpected content type")]
+    ContentType,
+}
+
+/// Returns `true` if the response is a valid event stream response.
+pub fn is_event_stream<B>(resp: &Response<B>) -> bool {
+    resp.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().eq_ignore_ascii_case(EVENT_STREAM_MIME))
+        .unwrap_or(false)
+}
+
+/// Builds the `Last-Event-ID` header value used when reconnecting.
+pub fn last_event_id_header(id: &str) -> Option<HeaderValue> {
+    HeaderValue::from_str(id).ok()
+}
+
+/// A reconnecting event stream.
+///
+/// `connect` is called with the last received event id whenever a new connection is needed. It
+/// should send the request (with `Accept: text/event-stream` and, if an id is given, a
+/// `Last-Event-ID` header) and return the response.
+///
+/// When the body ends or fails, the source waits for the reconnection time and connects again.
+/// Connect and body errors are yielded to the caller, and polling again resumes reconnection.
+/// A non-200 status or a non event-stream content type ends the stream.
+///
+/// Note: This requires the timer to be enabled on the runtime.
+pub struct EventSource<F, B> {
+    connect: F,
+    stream: Option<EventStream<B>>,
+    last_event_id: Option<String>,
+    retry: Duration,
+    reconnecting: bool,
+    closed: bool,
+}
+
+impl<F, B> EventSource<F, B> {
+    pub fn new(connect: F) -> Self {
+        Self {
+            connect,
+            stream: None,
+            last_event_id: None,
+            retry: DEFAULT_RETRY,
+            reconnecting: false,
+            closed: false,
+        }
+    }
+
+    /// Sets the initial event id to resume from.
+    pub fn with_last_event_id(mut self, id: Option<String>) -> Self {
+        self.last_event_id = id;
+        self
+    }
+
+    /// Sets the reconnection delay used until the server announces one.
+    pub fn with_retry(mut self, retry: Duration) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    #[inline]
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Closes the source. Subsequent polls return `None`.
+    pub fn close(&mut self) {
+        self.stream = None;
+        self.closed = true;
+    }
+
+    fn sync_state(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            let decoder = stream.into_decoder();
+            if let Some(id) = decoder.last_event_id() {
+                self.last_event_id = Some(id.to_string());
+            }
+            if let Some(retry) = decoder.retry() {
+                self.retry = retry;
+            }
+        }
+    }
+}
+
+impl<F, Fut, B, E> Stream for EventSource<F, B>
+where
+    F: FnMut(Option<String>) -> Fut,
+    Fut: Future<Output = Result<Response<B>, E>>,
+    B: Body<Data = Bytes>,
+{
+    type Item = Result<Event, SseError<E, B::Error>>;
+
+    async fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.closed {
+                return None;
+            }
+            if let Some(stream) = self.stream.as_mut() {
+                match stream.next().await {
+                    Some(Ok(event)) => {
+                        if let Some(id) = &event.id {
+                            self.last_event_id = Some(id.clone());
+                        }
+                        if let Some(retry) = event.retry {
+                            self.retry = retry;
+                        }
+                        return Some(Ok(event));
+                    }
+                    Some(Err(e)) => {
+                        self.sync_state();
+                        self.reconnecting = true;
+                        return Some(Err(SseError::Body(e)));
+                    }
+                    None => {
+                        self.sync_state();
+                        self.reconnecting = true;
+                        continue;
+                    }
+                }
+            }
+
+            if self.reconnecting {
+                monoio::time::sleep(self.retry).await;
+            }
+            self.reconnecting = true;
+            let resp = match (self.connect)(self.last_event_id.clone()).await {
+                Ok(resp) => resp,
+                Err(e) => return Some(Err(SseError::Connect(e))),
+            };
+            if resp.status() != StatusCode::OK {
+                self.closed = true;
+                return Some(Err(SseError::Status(resp.status())));
+            }
+            if !is_event_stream(&resp) {
+                self.closed = true;
+                return Some(Err(SseError::ContentType));
+            }
+            let decoder = EventDecoder::with_last_event_id(self.last_event_id.clone());
+            self.stream = Some(EventStream::with_decoder(resp.into_body(), decoder));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(chunks: &[&[u8]]) -> Vec<Event> {
+        let mut decoder = EventDecoder::new();
+        let mut events = Vec::new();
+        for chunk in chunks {
+            decoder.feed(chunk);
+            while let Some(event) = decoder.next_event() {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn decode_split_chunks() {
+        let events = decode_all(&[
+            b"\xEF\xBB",
+            b"\xBFdata: hel",
+            b"lo\r",
+            b"\ndata:world\r\n\r",
+            b"\nevent: update\nid: 7\nretry: 1500\ndata: x\n\n",
+        ]);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, "message");
+        assert_eq!(events[0].data, "hello\nworld");
+        assert_eq!(events[0].id, None);
+        assert_eq!(events[1].event, "update");
+        assert_eq!(events[1].data, "x");
+        assert_eq!(events[1].id.as_deref(), Some("7"));
+        assert_eq!(events[1].retry, Some(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn decode_comments_and_empty_events() {
+        let events = decode_all(&[b": keep-alive\n\nevent: ping\n\ndata\n\nid: 1\0\ndata: a\n"]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "");
+        // The trailing event is not dispatched without a blank line.
+        let mut decoder = EventDecoder::new();
+        decoder.feed(b"id: 1\0\ndata: a\n");
+        assert!(decoder.next_event().is_none());
+        assert_eq!(decoder.last_event_id(), None);
+    }
+}
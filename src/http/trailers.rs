@@ -0,0 +1,252 @@
+//! Trailers, header fields sent after a body.
+//!
+//! Request trailers are set with a [`RequestTrailers`] extension. They are sent after HTTP/1.1
+//! bodies, which are then chunked whatever their size, and in a final HEADERS frame on HTTP/2.
+//! Their fields are computed once the body has been sent, so a body can fill in a checksum of
+//! what it produced.
+//!
+//! Response trailers are returned by [`trailers`] once the body has been read, and by
+//! [`StreamingBody::trailers`](super::StreamingBody::trailers) for streamed responses.
+use std::sync::Arc;
+
+use bytes::{Bytes, BytesMut};
+use http::{HeaderMap, HeaderName, HeaderValue, Response};
+use monoio_http::{
+    common::{body::HttpBody, error::HttpError},
+    h1::codec::decoder::DecodeError,
+};
+
+use crate::error::LimitExceeded;
+
+/// The trailers of a request, set as one of its extensions.
+#[derive(Clone)]
+pub struct RequestTrailers(Arc<dyn Fn() -> HeaderMap + Send + Sync>);
+
+impl RequestTrailers {
+    /// Sends the fields returned by `f`, called once the body has been sent.
+    #[inline]
+    pub fn new(f: impl Fn() -> HeaderMap + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    #[inline]
+    pub(crate) fn fields(&self) -> HeaderMap {
+        (self.0)()
+    }
+}
+
+impl From<HeaderMap> for RequestTrailers {
+    #[inline]
+    fn from(fields: HeaderMap) -> Self {
+        Self::new(move || fields.clone())
+    }
+}
+
+impl std::fmt::Debug for RequestTrailers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RequestTrailers")
+    }
+}
+
+/// The trailers of a HTTP/1.1 response, added to its extensions once its body has been read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseTrailers(pub HeaderMap);
+
+/// Returns the trailers of `response`, if the server sent any.
+///
+/// HTTP/2 trailers follow the body on the stream, they are only returned once the body has been
+/// read to its end.
+pub async fn trailers(response: &mut Response<HttpBody>) -> Result<Option<HeaderMap>, HttpError> {
+    if let Some(ResponseTrailers(fields)) = response.extensions_mut().remove() {
+        return Ok(Some(fields));
+    }
+    match response.body_mut() {
+        HttpBody::H2(body) => Ok(body.trailers().await?),
+        _ => Ok(None),
+    }
+}
+
+/// Encodes the last chunk of a chunked body, followed by `trailers`.
+pub(crate) fn encode_last_chunk(trailers: Option<&RequestTrailers>) -> Vec<u8> {
+    let mut buf = b"0\r\n".to_vec();
+    for (name, value) in trailers.map(RequestTrailers::fields).iter().flatten() {
+        buf.extend_from_slice(name.as_ref());
+        buf.extend_from_slice(b": ");
+        buf.extend_from_slice(value.as_bytes());
+        buf.extend_from_slice(b"\r\n");
+    }
+    buf.extend_from_slice(b"\r\n");
+    buf
+}
+
+/// The longest chunk size line accepted, extensions included.
+const MAX_CHUNK_LINE: usize = 4096;
+
+pub(crate) enum Chunk {
+    Data(Bytes),
+    // The end of the body, with its trailers.
+    End(HeaderMap),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChunkState {
+    Size,
+    Data(usize),
+    DataEnd,
+    Trailers,
+}
+
+/// Decodes a chunked body like the decoder of the codec, keeping the trailers it discards.
+pub(crate) struct ChunkedDecoder {
+    state: ChunkState,
+    trailers: HeaderMap,
+    // The size limit of the trailers, and what they used of it.
+    max_trailer_size: Option<usize>,
+    trailer_size: usize,
+}
+
+impl ChunkedDecoder {
+    pub(crate) fn new(max_trailer_size: Option<usize>) -> Self {
+        Self {
+            state: ChunkState::Size,
+            trailers: HeaderMap::new(),
+            max_trailer_size,
+            trailer_size: 0,
+        }
+    }
+}
+
+/// Splits the line at the start of `src`, without its CRLF.
+fn split_line(src: &mut BytesMut, max_len: usize) -> Result<Option<BytesMut>, HttpError> {
+    match src.windows(2).position(|w| w == b"\r\n") {
+        Some(end) => {
+            let line = src.split_to(end);
+            let _ = src.split_to(2);
+            Ok(Some(line))
+        }
+        None if src.len() > max_len => Err(DecodeError::Chunked.into()),
+        None => Ok(None),
+    }
+}
+
+impl monoio_codec::Decoder for ChunkedDecoder {
+    type Item = Chunk;
+    type Error = HttpError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<monoio_codec::Decoded<Chunk>, HttpError> {
+        use monoio_codec::Decoded;
+
+        loop {
+            match self.state {
+                ChunkState::Size => {
+                    let Some(line) = split_line(src, MAX_CHUNK_LINE)? else {
+                        return Ok(Decoded::Insufficient);
+                    };
+                    let size = std::str::from_utf8(&line)
+                        .ok()
+                        .and_then(|line| line.split(';').next())
+                        .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+                        .ok_or(DecodeError::Chunked)?;
+                    self.state = match size {
+                        0 => ChunkState::Trailers,
+                        size => ChunkState::Data(size),
+                    };
+                }
+                ChunkState::Data(remaining) => {
+                    if src.is_empty() {
+                        return Ok(Decoded::Insufficient);
+                    }
+                    let n = src.len().min(remaining);
+                    self.state = match remaining - n {
+                        0 => ChunkState::DataEnd,
+                        rest => ChunkState::Data(rest),
+                    };
+                    return Ok(Decoded::Some(Chunk::Data(src.split_to(n).freeze())));
+                }
+                ChunkState::DataEnd => {
+                    if src.len() < 2 {
+                        return Ok(Decoded::Insufficient);
+                    }
+                    if &src[..2] != b"\r\n" {
+                        return Err(DecodeError::Chunked.into());
+                    }
+                    let _ = src.split_to(2);
+                    self.state = ChunkState::Size;
+                }
+                ChunkState::Trailers => {
+                    let max = self.max_trailer_size.unwrap_or(usize::MAX);
+                    let line = match split_line(src, max.saturating_sub(self.trailer_size)) {
+                        Ok(Some(line)) => line,
+                        Ok(None) => return Ok(Decoded::Insufficient),
+                        Err(_) => {
+                            return Err(std::io::Error::from(LimitExceeded::Headers(max)).into())
+                        }
+                    };
+                    self.trailer_size = self.trailer_size.saturating_add(line.len() + 2);
+                    if self.trailer_size > max {
+                        return Err(std::io::Error::from(LimitExceeded::Headers(max)).into());
+                    }
+                    if line.is_empty() {
+                        self.state = ChunkState::Size;
+                        self.trailer_size = 0;
+                        let trailers = std::mem::take(&mut self.trailers);
+                        return Ok(Decoded::Some(Chunk::End(trailers)));
+                    }
+                    let (name, value) = line
+                        .iter()
+                        .position(|&b| b == b':')
+                        .map(|colon| (&line[..colon], &line[colon + 1..]))
+                        .ok_or(DecodeError::Header)?;
+                    let name = HeaderName::from_bytes(name).map_err(|_| DecodeError::Header)?;
+                    let value = HeaderValue::from_bytes(value.trim_ascii())
+                        .map_err(|_| DecodeError::Header)?;
+                    self.trailers.append(name, value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use monoio_codec::{Decoded, Decoder};
+
+    use super::*;
+
+    #[test]
+    fn decodes_trailers() {
+        let mut decoder = ChunkedDecoder::new(None);
+        let mut src =
+            BytesMut::from(&b"5;ext=1\r\nhello\r\n0\r\nGrpc-Status: 0\r\nx-sum:  ab \r\n"[..]);
+        let mut data = Vec::new();
+        loop {
+            match decoder.decode(&mut src).unwrap() {
+                Decoded::Some(Chunk::Data(chunk)) => data.extend_from_slice(&chunk),
+                Decoded::Some(Chunk::End(_)) => panic!("the trailers are not complete"),
+                _ => break,
+            }
+        }
+        assert_eq!(data, b"hello");
+        src.extend_from_slice(b"\r\n");
+        let Ok(Decoded::Some(Chunk::End(trailers))) = decoder.decode(&mut src) else {
+            panic!("expected the end of the body");
+        };
+        assert_eq!(trailers["grpc-status"], "0");
+        assert_eq!(trailers["x-sum"], "ab");
+        assert!(src.is_empty());
+
+        let mut limited = ChunkedDecoder::new(Some(8));
+        let mut src = BytesMut::from(&b"0\r\nx-long-field: value\r\n\r\n"[..]);
+        assert!(limited.decode(&mut src).is_err());
+
+        let trailers = RequestTrailers::from(HeaderMap::from_iter([(
+            HeaderName::from_static("x-sum"),
+            HeaderValue::from_static("ab"),
+        )]));
+        assert_eq!(
+            encode_last_chunk(Some(&trailers)),
+            b"0\r\nx-sum: ab\r\n\r\n"
+        );
+        assert_eq!(encode_last_chunk(None), b"0\r\n\r\n");
+    }
+}
@@ -0,0 +1,110 @@
+//! Per-request HTTP version selection.
+//!
+//! [`HttpConnector`](super::HttpConnector) sends a request over a pooled HTTP/2 connection when
+//! there is one, and otherwise over the protocol negotiated with ALPN. Some upstreams mishandle
+//! HTTP/2, so [`VersionRequestExt::require_version`] overrides this choice for a single request:
+//!
+//! - HTTP/1.0 and HTTP/1.1 requests only use HTTP/1.1 connections. A TLS connector offering `h2`
+//!   would negotiate it again, so new connections are established with the connector set with
+//!   [`HttpConnector::set_http1_connector`](super::HttpConnector::set_http1_connector) when there
+//!   is one, e.g. a `TlsConnector` offering only `http/1.1`.
+//! - HTTP/2 requests only use HTTP/2 connections, a new connection without ALPN speaking HTTP/2
+//!   with prior knowledge.
+//!
+//! A request whose version cannot be used on a new connection, e.g. HTTP/2 when the server
+//! selected `http/1.1`, fails with [`TransportError::VersionUnavailable`].
+//!
+//! [`TransportError::VersionUnavailable`]: crate::TransportError::VersionUnavailable
+use http::Version;
+
+/// The HTTP version a request must be sent with, in its extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequiredVersion(pub Version);
+
+impl RequiredVersion {
+    /// Returns whether the version is served by HTTP/1.1 connections.
+    #[inline]
+    pub(crate) fn is_http1(self) -> bool {
+        matches!(self.0, Version::HTTP_10 | Version::HTTP_11)
+    }
+
+    /// Returns whether the version is served by HTTP/2 connections.
+    #[inline]
+    pub(crate) fn is_http2(self) -> bool {
+        self.0 == Version::HTTP_2
+    }
+}
+
+/// Requires HTTP versions on request builders.
+pub trait VersionRequestExt: Sized {
+    /// Sends the request with `version`, whatever connection the connector would pick. Only
+    /// HTTP/1.0, HTTP/1.1 and HTTP/2 are supported.
+    fn require_version(self, version: Version) -> Self;
+}
+
+impl VersionRequestExt for http::request::Builder {
+    fn require_version(self, version: Version) -> Self {
+        self.version(version).extension(RequiredVersion(version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use monoio_http::common::body::HttpBody;
+
+    use super::*;
+    use crate::{
+        connectors::MockConnector,
+        http::{response::ResponseExt, HttpConnector},
+        TransportError,
+    };
+
+    fn request(version: Option<Version>) -> http::Request<HttpBody> {
+        let builder = http::Request::get("/").header(http::header::HOST, "localhost");
+        match version {
+            Some(version) => builder.require_version(version),
+            None => builder,
+        }
+        .body(HttpBody::Ready(None))
+        .unwrap()
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn requires_versions() {
+        let server = MockConnector::new()
+            .with_responses("api", ["HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok"; 2]);
+        let connector = HttpConnector::new(server.clone());
+        let response = connector.request("api", || request(None)).await.unwrap();
+        assert_eq!(response.bytes().await.unwrap(), "ok");
+
+        // The idle HTTP/1.1 connection is not used for HTTP/2, spoken on a new one.
+        assert!(connector
+            .request("api", || request(Some(Version::HTTP_2)))
+            .await
+            .is_err());
+        assert_eq!(server.connects(&"api"), 2);
+        assert!(matches!(
+            connector
+                .request("api", || request(Some(Version::HTTP_3)))
+                .await,
+            Err(TransportError::VersionUnavailable(Version::HTTP_3))
+        ));
+        assert_eq!(server.connects(&"api"), 2);
+
+        // HTTP/1.1 requests use the HTTP/1.1 connector.
+        let h2_server = MockConnector::new().with_handler("api", |_| async {});
+        let h1_server = MockConnector::new()
+            .with_responses("api", ["HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok"]);
+        let mut connector = HttpConnector::new(h2_server.clone());
+        connector.set_http2_only();
+        connector.set_http1_connector(h1_server.clone());
+        let response = connector
+            .request("api", || request(Some(Version::HTTP_11)))
+            .await
+            .unwrap();
+        assert_eq!(response.version(), Version::HTTP_11);
+        assert_eq!(response.bytes().await.unwrap(), "ok");
+        assert_eq!(h1_server.connects(&"api"), 1);
+        assert_eq!(h2_server.connects(&"api"), 0);
+    }
+}
@@ -55,10 +55,19 @@
 //! - [`UnifiedL4Connector`](crate::connectors::UnifiedL4Connector): A unified connector supporting
 //!   both TCP and Unix Domain Sockets
 //!
+//! ### DNS Resolution
+//!
+//! [`DnsConnector`](crate::dns::DnsConnector) resolves keys through a pluggable
+//! [`Resolve`](crate::dns::Resolve) implementation before connecting, so name resolution never
+//! blocks the io_uring reactor.
+//!
 //! ### TLS Connector
 //!
 //! [`TlsConnector`](crate::connectors::TlsConnector) adds TLS encryption to an underlying L4
-//! connector, supporting both native-tls and rustls backends.
+//! connector, supporting both native-tls and rustls backends. The backend is picked at build
+//! time and both implement the same `Connector` interface, configured through
+//! [`TlsConfig`](crate::connectors::TlsConfig), including trusted roots and client
+//! certificates for mutual TLS.
 //!
 //! ### HTTP Connector
 //!
@@ -89,6 +98,11 @@
 //! significantly improving throughput and reducing latency in high-load scenarios across
 //! various protocols and connection types.
 //!
+//! ### Metrics
+//!
+//! [`ClientMetrics`](crate::metrics::ClientMetrics) receives connect and handshake durations, pool
+//! checkouts, request latencies, bytes and errors, see [`metrics`](crate::metrics).
+//!
 //! ## Stacking Connectors
 //!
 //! Connectors can be easily stacked to create powerful, flexible connection setups. For example:
@@ -110,11 +124,26 @@
 //! This example creates a connector stack that uses TCP for the base connection, adds TLS
 //! encryption, and then provides HTTP protocol handling on top with built-in connection pooling.
 //!
+//! A single `HttpConnector::build_unified()` serves `http`, `https` and `http+unix` uris, routed
+//! by the scheme of their `UnifiedAddr` key, instead of one connector per scheme.
+//!
 //! ## Feature Flags
 //!
-//! - `native-tls`: Enables the native-tls backend for TLS connections
+//! - `rustls` (default): Uses rustls as the TLS backend
+//! - `native-tls`: Enables the native-tls backend for TLS connections, taking precedence over
+//!   rustls. It uses the platform TLS library and certificate store: OpenSSL on Linux,
+//!   Security.framework on macOS and SChannel on Windows. Disable the default features to build
+//!   without rustls
+//! - `native-tls-vendored`: Like `native-tls`, with a statically linked OpenSSL built from source
 //! - `hyper`: Enables integration with the Hyper HTTP library, including Hyper-compatible
 //!   connectors with efficient connection pooling
+//! - `proxy`: Enables HTTP `CONNECT` and SOCKS5 proxy connectors
+//! - `hickory-dns`: Enables a [hickory-dns](https://github.com/hickory-dns/hickory-dns) backed
+//!   resolver
+//! - `tracing`: Emits [tracing](https://docs.rs/tracing) spans for name resolution, TCP connects
+//!   and TLS handshakes, and events for pool checkouts and the phases of requests, see
+//!   [`HttpConnection::send_request`](crate::http::HttpConnection::send_request)
+//! - `logging`: Like `tracing`, with debug and error logs of failures
 //!
 //! By leveraging monoio's efficient asynchronous runtime, io_uring, and advanced connection
 //! pooling, `monoio-transports` provides a powerful and flexible toolkit for building
@@ -123,5 +152,7 @@ mod error;
 pub use error::*;
 
 pub mod connectors;
+pub mod dns;
 pub mod http;
+pub mod metrics;
 pub mod pool;
@@ -0,0 +1,319 @@
+//! Hooks reporting client metrics, to be wired into Prometheus or any other system.
+//!
+//! A single [`ClientMetrics`] implementation is shared by the places measuring it:
+//!
+//! - [`MetricsConnector`], usually added with a [`MetricsLayer`], reports how long its inner
+//!   connector took to connect and the bytes sent and received over the connections.
+//! - [`TlsConnector::with_metrics`](crate::connectors::TlsConnector::with_metrics) reports TLS
+//!   handshake durations.
+//! - [`HttpConnector::set_metrics`](crate::http::HttpConnector::set_metrics) reports pool
+//!   checkouts, request latencies and failed requests.
+//!
+//! ```rust
+//! use std::{
+//!     sync::{
+//!         atomic::{AtomicU64, Ordering},
+//!         Arc,
+//!     },
+//!     time::Duration,
+//! };
+//!
+//! use monoio_transports::{
+//!     connectors::{layer::ConnectorBuilder, TcpConnector},
+//!     http::HttpConnector,
+//!     metrics::{ClientMetrics, MeteredStream, MetricsLayer},
+//! };
+//!
+//! #[derive(Default)]
+//! struct Counters {
+//!     requests: AtomicU64,
+//!     received: AtomicU64,
+//! }
+//!
+//! impl ClientMetrics for Counters {
+//!     fn on_request(&self, _: http::Version, _: http::StatusCode, _: Duration) {
+//!         self.requests.fetch_add(1, Ordering::Relaxed);
+//!     }
+//!
+//!     fn on_bytes_received(&self, n: usize) {
+//!         self.received.fetch_add(n as u64, Ordering::Relaxed);
+//!     }
+//! }
+//!
+//! let counters = Arc::new(Counters::default());
+//! let tcp = ConnectorBuilder::new()
+//!     .layer(MetricsLayer::new(counters.clone()))
+//!     .build(TcpConnector::default());
+//! let mut connector: HttpConnector<
+//!     _,
+//!     std::net::SocketAddr,
+//!     MeteredStream<monoio::net::TcpStream>,
+//! > = HttpConnector::new(tcp);
+//! connector.set_metrics(counters);
+//! ```
+use std::{sync::Arc, time::Duration};
+
+use monoio::{
+    buf::{IoBuf, IoBufMut, IoVecBuf, IoVecBufMut},
+    io::{AsyncReadRent, AsyncWriteRent, Split},
+    BufResult,
+};
+
+use crate::{
+    connectors::{layer::ConnectorLayer, Connector, TransportConnMetadata},
+    TransportError,
+};
+
+/// Receives client measurements. Every method does nothing by default.
+///
+/// Methods are called on the hot path of connections and requests, so they should only update
+/// counters or histograms.
+pub trait ClientMetrics: Send + Sync {
+    /// A connection was established in `elapsed`, see [`MetricsConnector`].
+    fn on_connect(&self, elapsed: Duration) {
+        let _ = elapsed;
+    }
+
+    /// A TLS handshake completed in `elapsed`.
+    fn on_handshake(&self, elapsed: Duration) {
+        let _ = elapsed;
+    }
+
+    /// A connection was checked out for a request, `reused` when it was an idle one of the pool
+    /// or a shared HTTP/2 connection.
+    fn on_checkout(&self, reused: bool) {
+        let _ = reused;
+    }
+
+    /// A request completed with `status` in `latency`, from the checkout of its connection until
+    /// its response was received, HTTP/1.1 bodies included.
+    fn on_request(&self, version: http::Version, status: http::StatusCode, latency: Duration) {
+        let _ = (version, status, latency);
+    }
+
+    /// `n` bytes were written to a connection.
+    fn on_bytes_sent(&self, n: usize) {
+        let _ = n;
+    }
+
+    /// `n` bytes were read from a connection.
+    fn on_bytes_received(&self, n: usize) {
+        let _ = n;
+    }
+
+    /// A request failed with `error`, before or after it was sent.
+    fn on_error(&self, error: &TransportError) {
+        let _ = error;
+    }
+}
+
+/// A layer wrapping connectors into a [`MetricsConnector`].
+#[derive(Clone)]
+pub struct MetricsLayer {
+    metrics: Arc<dyn ClientMetrics>,
+}
+
+impl MetricsLayer {
+    #[inline]
+    pub fn new(metrics: Arc<dyn ClientMetrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<C> ConnectorLayer<C> for MetricsLayer {
+    type Connector = MetricsConnector<C>;
+
+    #[inline]
+    fn layer(&self, inner: C) -> Self::Connector {
+        MetricsConnector::new(inner, self.metrics.clone())
+    }
+}
+
+/// A connector reporting how long its inner connector takes to connect, and the bytes sent and
+/// received over its connections.
+///
+/// Wrapped around a [`TlsConnector`](crate::connectors::TlsConnector), connect durations include
+/// the handshake and bytes are counted after encryption; wrapped inside it, they are not.
+#[derive(Clone)]
+pub struct MetricsConnector<C> {
+    inner_connector: C,
+    metrics: Arc<dyn ClientMetrics>,
+}
+
+impl<C> MetricsConnector<C> {
+    #[inline]
+    pub fn new(inner_connector: C, metrics: Arc<dyn ClientMetrics>) -> Self {
+        Self {
+            inner_connector,
+            metrics,
+        }
+    }
+
+    #[inline]
+    pub fn inner_connector(&self) -> &C {
+        &self.inner_connector
+    }
+}
+
+impl<C: std::fmt::Debug> std::fmt::Debug for MetricsConnector<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MetricsConnector")
+            .field("inner_connector", &self.inner_connector)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: Connector<K>, K> Connector<K> for MetricsConnector<C> {
+    type Connection = MeteredStream<C::Connection>;
+    type Error = C::Error;
+
+    async fn connect(&self, key: K) -> Result<Self::Connection, Self::Error> {
+        let start = std::time::Instant::now();
+        let inner = self.inner_connector.connect(key).await?;
+        self.metrics.on_connect(start.elapsed());
+        Ok(MeteredStream {
+            inner,
+            metrics: self.metrics.clone(),
+        })
+    }
+}
+
+/// A connection of a [`MetricsConnector`], counting the bytes sent and received.
+pub struct MeteredStream<S> {
+    inner: S,
+    metrics: Arc<dyn ClientMetrics>,
+}
+
+impl<S> MeteredStream<S> {
+    #[inline]
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for MeteredStream<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MeteredStream")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: TransportConnMetadata> TransportConnMetadata for MeteredStream<S> {
+    type Metadata = S::Metadata;
+
+    #[inline]
+    fn get_conn_metadata(&self) -> Self::Metadata {
+        self.inner.get_conn_metadata()
+    }
+}
+
+impl<S: AsyncReadRent> AsyncReadRent for MeteredStream<S> {
+    async fn read<T: IoBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        let (res, buf) = self.inner.read(buf).await;
+        if let Ok(n) = res {
+            self.metrics.on_bytes_received(n);
+        }
+        (res, buf)
+    }
+
+    async fn readv<T: IoVecBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        let (res, buf) = self.inner.readv(buf).await;
+        if let Ok(n) = res {
+            self.metrics.on_bytes_received(n);
+        }
+        (res, buf)
+    }
+}
+
+impl<S: AsyncWriteRent> AsyncWriteRent for MeteredStream<S> {
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        let (res, buf) = self.inner.write(buf).await;
+        if let Ok(n) = res {
+            self.metrics.on_bytes_sent(n);
+        }
+        (res, buf)
+    }
+
+    async fn writev<T: IoVecBuf>(&mut self, buf_vec: T) -> BufResult<usize, T> {
+        let (res, buf_vec) = self.inner.writev(buf_vec).await;
+        if let Ok(n) = res {
+            self.metrics.on_bytes_sent(n);
+        }
+        (res, buf_vec)
+    }
+
+    #[inline]
+    async fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush().await
+    }
+
+    #[inline]
+    async fn shutdown(&mut self) -> std::io::Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+unsafe impl<S: Split> Split for MeteredStream<S> {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use monoio::{
+        io::{AsyncReadRentExt, AsyncWriteRentExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::connectors::TcpConnector;
+
+    #[derive(Default)]
+    struct Counters {
+        connects: AtomicUsize,
+        sent: AtomicUsize,
+        received: AtomicUsize,
+    }
+
+    impl ClientMetrics for Counters {
+        fn on_connect(&self, _elapsed: Duration) {
+            self.connects.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_bytes_sent(&self, n: usize) {
+            self.sent.fetch_add(n, Ordering::Relaxed);
+        }
+
+        fn on_bytes_received(&self, n: usize) {
+            self.received.fetch_add(n, Ordering::Relaxed);
+        }
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn counts_connects_and_bytes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        monoio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let (res, buf) = conn.read_exact(vec![0; 4]).await;
+            res.unwrap();
+            conn.write_all(buf).await.0.unwrap();
+        });
+
+        let counters = Arc::new(Counters::default());
+        let connector = MetricsLayer::new(counters.clone()).layer(TcpConnector::default());
+        let mut conn = connector.connect(addr).await.unwrap();
+        conn.write_all(b"ping".to_vec()).await.0.unwrap();
+        let (res, buf) = conn.read_exact(vec![0; 4]).await;
+        res.unwrap();
+        assert_eq!(buf, b"ping");
+        assert_eq!(counters.connects.load(Ordering::Relaxed), 1);
+        assert_eq!(counters.sent.load(Ordering::Relaxed), 4);
+        assert_eq!(counters.received.load(Ordering::Relaxed), 4);
+    }
+}
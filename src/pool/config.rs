@@ -0,0 +1,200 @@
+use std::time::Duration;
+
+use super::{DEFAULT_KEEPALIVE_CONNS, MAX_KEEPALIVE_CONNS};
+
+/// The idle connection of a key reused first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ReuseOrder {
+    /// The connection idle for the longest time, spreading requests over every idle
+    /// connection so fewer of them hit the idle timeout of servers.
+    #[default]
+    Fifo,
+    /// The connection released last, which is the most likely to be open with warm caches and
+    /// congestion window, leaving the others to expire.
+    Lifo,
+}
+
+/// Limits on the idle connections kept by a [`ConnectionPool`](super::ConnectionPool).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolConfig {
+    max_idle_per_key: usize,
+    max_idle: Option<usize>,
+    max_connections: Option<usize>,
+    max_connections_per_key: Option<usize>,
+    queue_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    max_connection_age: Option<Duration>,
+    max_connection_age_jitter: Duration,
+    reuse_order: ReuseOrder,
+    #[cfg(feature = "time")]
+    reap_interval: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            max_idle_per_key: DEFAULT_KEEPALIVE_CONNS,
+            max_idle: None,
+            max_connections: None,
+            max_connections_per_key: None,
+            queue_timeout: None,
+            idle_timeout: None,
+            max_connection_age: None,
+            max_connection_age_jitter: Duration::ZERO,
+            reuse_order: ReuseOrder::Fifo,
+            #[cfg(feature = "time")]
+            reap_interval: None,
+        }
+    }
+}
+
+impl PoolConfig {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many idle connections are kept for each key, the oldest ones are closed first.
+    /// A limit of 0 disables pooling. It is capped at 16384 and defaults to 1024.
+    #[inline]
+    pub fn with_max_idle_per_key(mut self, max: usize) -> Self {
+        self.max_idle_per_key = max.min(MAX_KEEPALIVE_CONNS);
+        self
+    }
+
+    #[inline]
+    pub fn max_idle_per_key(&self) -> usize {
+        self.max_idle_per_key
+    }
+
+    /// Sets how many idle connections are kept across all keys. Connections released while the
+    /// pool is full are closed.
+    #[inline]
+    pub fn with_max_idle(mut self, max: Option<usize>) -> Self {
+        self.max_idle = max;
+        self
+    }
+
+    #[inline]
+    pub fn max_idle(&self) -> Option<usize> {
+        self.max_idle
+    }
+
+    /// Sets how many connections, idle and in use, the pool tracks across all keys. When a
+    /// connection is added to a full pool, the least recently used idle connection of any key is
+    /// closed to make room for it.
+    ///
+    /// Connections are still handed out while all the others are in use, the pool closes them
+    /// once released until it is back under the limit.
+    #[inline]
+    pub fn with_max_connections(mut self, max: Option<usize>) -> Self {
+        self.max_connections = max;
+        self
+    }
+
+    #[inline]
+    pub fn max_connections(&self) -> Option<usize> {
+        self.max_connections
+    }
+
+    /// Sets how many connections of each key, usually a host, may be in use or being
+    /// established at once. Callers of [`ConnectionPool::reserve`] past the limit queue until a
+    /// connection of the key is released, instead of opening more sockets to the upstream.
+    ///
+    /// [`HttpConnector`](crate::http::HttpConnector) reserves its HTTP/1.1 connections.
+    ///
+    /// [`ConnectionPool::reserve`]: super::ConnectionPool::reserve
+    #[inline]
+    pub fn with_max_connections_per_key(mut self, max: Option<usize>) -> Self {
+        self.max_connections_per_key = max;
+        self
+    }
+
+    #[inline]
+    pub fn max_connections_per_key(&self) -> Option<usize> {
+        self.max_connections_per_key
+    }
+
+    /// Sets how long a caller may queue for a connection of a key at its limit, before failing
+    /// with [`QueueTimeout`](super::QueueTimeout). Callers queue without limit by default.
+    #[inline]
+    pub fn with_queue_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.queue_timeout = timeout;
+        self
+    }
+
+    #[inline]
+    pub fn queue_timeout(&self) -> Option<Duration> {
+        self.queue_timeout
+    }
+
+    /// Sets how long a connection may stay idle; older ones are closed instead of being reused.
+    #[inline]
+    pub fn with_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    #[inline]
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    /// Sets how long connections are reused after being established, even when healthy, so
+    /// they are spread again over the servers behind a name after deploys or DNS changes.
+    ///
+    /// Connections in use are not interrupted, they are closed when released past their age.
+    #[inline]
+    pub fn with_max_connection_age(mut self, age: Option<Duration>) -> Self {
+        self.max_connection_age = age;
+        self
+    }
+
+    #[inline]
+    pub fn max_connection_age(&self) -> Option<Duration> {
+        self.max_connection_age
+    }
+
+    /// Extends the maximum age of each connection by a random duration up to `jitter`, so
+    /// connections established together are not all replaced at once.
+    #[inline]
+    pub fn with_max_connection_age_jitter(mut self, jitter: Duration) -> Self {
+        self.max_connection_age_jitter = jitter;
+        self
+    }
+
+    #[inline]
+    pub fn max_connection_age_jitter(&self) -> Duration {
+        self.max_connection_age_jitter
+    }
+
+    #[inline]
+    pub fn with_reuse_order(mut self, order: ReuseOrder) -> Self {
+        self.reuse_order = order;
+        self
+    }
+
+    #[inline]
+    pub fn reuse_order(&self) -> ReuseOrder {
+        self.reuse_order
+    }
+
+    /// Closes expired and retired idle connections every `interval` from a task spawned on the
+    /// runtime creating the pool, instead of only when their key is used again, and frees the
+    /// buckets of keys left without idle connections.
+    ///
+    /// The task stops once the pool is dropped. Requires the monoio timer driver.
+    #[cfg(feature = "time")]
+    #[inline]
+    pub fn with_reap_interval(mut self, interval: Option<Duration>) -> Self {
+        self.reap_interval = interval;
+        self
+    }
+
+    #[cfg(feature = "time")]
+    #[inline]
+    pub fn reap_interval(&self) -> Option<Duration> {
+        self.reap_interval
+    }
+}
@@ -67,11 +67,14 @@ where
 
     #[inline]
     async fn connect(&self, key: K) -> Result<Self::Connection, Self::Error> {
+        let start = std::time::Instant::now();
         if let Some(conn) = self.pool.get(&key) {
+            self.pool.record_wait(start.elapsed());
             return Ok(conn);
         }
         let key_owned = key.to_owned();
         let io = self.transport_connector.connect(key).await?;
+        self.pool.record_wait(start.elapsed());
         Ok(self.pool.link(key_owned, io))
     }
 }
@@ -0,0 +1,62 @@
+use http::Uri;
+
+use crate::FromUriError;
+
+/// Derives the pool key of a request from its uri, for the helpers connecting to uris on their
+/// own, like [`send_following_redirects_with`](crate::http::redirect::send_following_redirects_with).
+///
+/// Requests sharing a key share connections, so a custom derivation can split connections that
+/// must not be reused for each other, e.g. by client certificate identity or proxy. Closures
+/// taking a `&Uri` implement it.
+pub trait DeriveKey<K> {
+    fn derive_key(&self, uri: &Uri) -> Result<K, FromUriError>;
+}
+
+/// Derives keys with their `TryFrom<&Uri>` implementation, which lowercases hosts and fills in
+/// the default port of the scheme, so `https://HOST` and `https://host:443` share connections.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TryFromUri;
+
+impl<K> DeriveKey<K> for TryFromUri
+where
+    for<'a> K: TryFrom<&'a Uri, Error = FromUriError>,
+{
+    #[inline]
+    fn derive_key(&self, uri: &Uri) -> Result<K, FromUriError> {
+        K::try_from(uri)
+    }
+}
+
+impl<K, F: Fn(&Uri) -> Result<K, FromUriError>> DeriveKey<K> for F {
+    #[inline]
+    fn derive_key(&self, uri: &Uri) -> Result<K, FromUriError> {
+        self(uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connectors::TcpTlsAddr;
+
+    #[test]
+    fn normalizes_keys() {
+        let derive =
+            |uri: &str| -> TcpTlsAddr { TryFromUri.derive_key(&uri.parse().unwrap()).unwrap() };
+        assert_eq!(
+            derive("https://Example.COM/a"),
+            derive("https://example.com:443/b")
+        );
+        assert_ne!(
+            derive("https://example.com"),
+            derive("https://example.com:8443")
+        );
+
+        // Keeps connections of each tenant apart.
+        let tenant = |uri: &Uri| Ok((TryFromUri.derive_key(uri)?, "tenant-a"));
+        let key: (TcpTlsAddr, &str) = tenant
+            .derive_key(&"https://example.com".parse().unwrap())
+            .unwrap();
+        assert_eq!(key, (derive("https://example.com"), "tenant-a"));
+    }
+}
@@ -2,24 +2,35 @@
 //!
 //! This module includes the `ConnectionPool` for managing connections,
 //! `Pooled` for representing pooled connections, and related traits and types
-//! for implementing and interacting with connection pools.
+//! for implementing and interacting with connection pools. [`PoolConfig`] sets how many
+//! idle connections a pool keeps and for how long, and [`PoolStats`] reports its activity.
+mod config;
 mod connector;
+mod key;
 mod map;
 mod reuse;
+mod stats;
 use std::{
     cell::{RefCell, RefMut, UnsafeCell},
     collections::{HashMap, VecDeque},
     fmt::Debug,
     hash::Hash,
+    io,
     ops::{Deref, DerefMut},
     rc::{Rc, Weak},
     time::{Duration, Instant},
 };
 
+pub use config::{PoolConfig, ReuseOrder};
 pub use connector::PooledConnector;
+pub use key::{DeriveKey, TryFromUri};
+use local_sync::semaphore::{OwnedSemaphorePermit, Semaphore};
 pub use map::{ConnectorMap, ConnectorMapper};
 use monoio::io::{AsyncReadRent, AsyncWriteRent, Split};
 pub use reuse::{Reuse, ReuseConnector};
+use stats::Counters;
+pub use stats::{KeyStats, PoolStats};
+use thiserror::Error as ThisError;
 
 pub(crate) const DEFAULT_KEEPALIVE_CONNS: usize = 1024;
 pub(crate) const DEFAULT_POOL_SIZE: usize = 32;
@@ -28,23 +39,74 @@ pub(crate) const MAX_KEEPALIVE_CONNS: usize = 16384;
 
 pub trait Poolable {
     fn is_open(&self) -> bool;
+
+    /// How long the connection may stay idle, when shorter than the idle timeout of the pool,
+    /// e.g. because the server announced when it closes idle connections.
+    #[inline]
+    fn idle_timeout(&self) -> Option<Duration> {
+        None
+    }
 }
 
 type SharedPool<K, IO> = Rc<UnsafeCell<PoolInner<K, IO>>>;
 type WeakPool<K, IO> = Weak<UnsafeCell<PoolInner<K, IO>>>;
-type WeakQueue<IO> = Weak<RefCell<VecDeque<Idle<IO>>>>;
 pub trait Key: Eq + Hash + Clone + 'static {}
 
 impl<T: Eq + Hash + Clone + 'static> Key for T {}
 
+/// The error returned when no connection of a key could be reserved within the queue timeout,
+/// see [`PoolConfig::with_queue_timeout`].
+#[derive(ThisError, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("no connection available after queueing for {0:?}")]
+pub struct QueueTimeout(pub Duration);
+
+impl From<QueueTimeout> for io::Error {
+    #[inline]
+    fn from(e: QueueTimeout) -> Self {
+        io::Error::new(io::ErrorKind::TimedOut, e)
+    }
+}
+
+/// A slot for one connection of a key, returned by [`ConnectionPool::reserve`] and held by the
+/// connection with [`Pooled::hold`]. The slot is freed once dropped.
+#[derive(Default)]
+pub struct Reservation {
+    _permit: Option<OwnedSemaphorePermit>,
+    // Drops the slots of the key from the pool once unused, see `PoolInner::prune_slots`.
+    release: Option<Box<dyn FnOnce()>>,
+}
+
+impl std::fmt::Debug for Reservation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Reservation")
+            .field("reserved", &self._permit.is_some())
+            .finish()
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self._permit.take();
+        if let Some(release) = self.release.take() {
+            release();
+        }
+    }
+}
+
 // Partly borrow from hyper-util. All rights reserved.
 pub struct Pooled<K: Key, T: Poolable> {
     value: Option<T>,
     is_reused: bool,
     key: Option<K>,
     pool: Option<WeakPool<K, T>>,
-    queue: Option<WeakQueue<T>>,
-    max_idle: usize,
+    // When the connection stops being reused, see `PoolConfig::with_max_connection_age`.
+    retire_at: Option<Instant>,
+    // The evictions of the pool when the connection was handed out, see `ConnectionPool::evict`.
+    epoch: u64,
+    // Freed after the connection went back to the pool, so a queued caller finds it idle.
+    reservation: Reservation,
+    // Linked while the pool was full of connections in use, closed once released.
+    overflow: bool,
 }
 
 unsafe impl<K: Key, T: Poolable + Split> Split for Pooled<K, T> {}
@@ -102,16 +164,21 @@ impl<T: Poolable, K: Key> Pooled<K, T> {
         value: T,
         is_reused: bool,
         pool: WeakPool<K, T>,
-        queue: Option<WeakQueue<T>>,
-        max_idle: usize,
+        retire_at: Option<Instant>,
+        epoch: u64,
     ) -> Self {
         Self {
             value: Some(value),
             is_reused,
             key: Some(key),
             pool: Some(pool),
-            queue,
-            max_idle,
+            retire_at,
+            epoch,
+            reservation: Reservation {
+                _permit: None,
+                release: None,
+            },
+            overflow: false,
         }
     }
 
@@ -122,8 +189,13 @@ impl<T: Poolable, K: Key> Pooled<K, T> {
             is_reused: false,
             key: None,
             pool: None,
-            queue: None,
-            max_idle: DEFAULT_KEEPALIVE_CONNS,
+            retire_at: None,
+            epoch: 0,
+            reservation: Reservation {
+                _permit: None,
+                release: None,
+            },
+            overflow: false,
         }
     }
 
@@ -132,6 +204,12 @@ impl<T: Poolable, K: Key> Pooled<K, T> {
         self.is_reused
     }
 
+    /// Holds the slot of `reservation` until the connection is released.
+    #[inline]
+    pub fn hold(&mut self, reservation: Reservation) {
+        self.reservation = reservation;
+    }
+
     #[inline]
     fn as_ref(&self) -> &T {
         self.value.as_ref().expect("not dropped")
@@ -162,46 +240,26 @@ impl<T: Poolable, K: Key> DerefMut for Pooled<K, T> {
 impl<T: Poolable, K: Key> Drop for Pooled<K, T> {
     fn drop(&mut self) {
         if let Some(value) = self.value.take() {
-            if !value.is_open() {
-                // If we *already* know the connection is done here,
-                // it shouldn't be re-inserted back into the pool.
+            let Some(pool) = self.pool.as_ref().and_then(Weak::upgrade) else {
                 return;
+            };
+            let pool = unsafe { &mut *pool.get() };
+            let key = self.key.take().expect("key is not empty");
+            let evicted = pool.is_evicted(&key, self.epoch);
+            if pool.stats.release(&key) == 0 {
+                pool.evicted.remove(&key);
             }
-
-            // Add the connection back to the queue directly if the weak reference is still alive.
-            if let Some(weak_queue) = &self.queue {
-                if let Some(queue) = weak_queue.upgrade() {
-                    let len = queue.borrow().len();
-                    if len >= self.max_idle {
-                        for _ in 0..len - self.max_idle {
-                            let _ = queue.borrow_mut().pop_front();
-                        }
-                    }
-                    let idle = Idle::new(value);
-                    queue.borrow_mut().push_back(idle);
-                    return;
-                }
-            }
-
-            if let Some(weak) = &self.pool {
-                if let Some(pool) = weak.upgrade() {
-                    let pool = unsafe { &mut *pool.get() };
-                    let key = self.key.take().expect("key is not empty");
-                    let queue = pool.idle_conns.entry(key).or_insert(Rc::new(RefCell::new(
-                        VecDeque::with_capacity(pool.max_idle),
-                    )));
-
-                    let len = queue.borrow().len();
-                    if len >= pool.max_idle {
-                        for _ in 0..len - pool.max_idle {
-                            let _ = queue.borrow_mut().pop_front();
-                        }
-                    }
-
-                    let idle = Idle::new(value);
-                    queue.borrow_mut().push_back(idle);
-                }
+            if self.overflow {
+                pool.overflow -= 1;
+                pool.stats.closed += 1;
+            } else if !value.is_open() || evicted {
+                // If we *already* know the connection is done here,
+                // it shouldn't be re-inserted back into the pool.
+                pool.stats.closed += 1;
+            } else {
+                pool.push_idle(key.clone(), value, self.retire_at);
             }
+            pool.prune_slots(&key);
         }
     }
 }
@@ -209,16 +267,39 @@ impl<T: Poolable, K: Key> Drop for Pooled<K, T> {
 pub(crate) struct Idle<IO> {
     pub(crate) conn: IO,
     idle_at: Instant,
+    retire_at: Option<Instant>,
+    // The idle timeout of the connection itself, see `Poolable::idle_timeout`.
+    timeout: Option<Duration>,
 }
 
-impl<IO> Idle<IO> {
+impl<IO: Poolable> Idle<IO> {
     #[inline]
-    pub(crate) fn new(io: IO) -> Self {
+    pub(crate) fn new(io: IO, retire_at: Option<Instant>) -> Self {
         Self {
+            timeout: io.idle_timeout(),
             conn: io,
             idle_at: Instant::now(),
+            retire_at,
         }
     }
+}
+
+impl<IO> Idle<IO> {
+    /// Whether the connection stayed idle for longer than `pool_timeout` or its own timeout.
+    #[inline]
+    fn timed_out(&self, pool_timeout: Option<Duration>) -> bool {
+        let timeout = match (pool_timeout, self.timeout) {
+            (Some(pool), Some(conn)) => Some(pool.min(conn)),
+            (pool, conn) => pool.or(conn),
+        };
+        self.expired_opt(timeout)
+    }
+
+    /// Whether the connection outlived its maximum age.
+    #[inline]
+    pub(crate) fn retired(&self) -> bool {
+        self.retire_at.is_some_and(|at| Instant::now() >= at)
+    }
 
     #[allow(unused)]
     #[inline]
@@ -244,55 +325,223 @@ impl<IO> Idle<IO> {
 
 pub(crate) struct PoolInner<K, IO> {
     idle_conns: HashMap<K, Rc<RefCell<VecDeque<Idle<IO>>>>>,
-    max_idle: usize,
+    config: PoolConfig,
+    stats: Counters<K>,
+    // Counts evictions, and the keys evicted with connections still in use.
+    epoch: u64,
+    evicted: HashMap<K, u64>,
+    slots: HashMap<K, Rc<Semaphore>>,
+    // The connections in use beyond `PoolConfig::with_max_connections`, see
+    // `ConnectionPool::link`.
+    overflow: usize,
+    // Set along with the first slots, for `reap` which does not require `K: Key`.
     #[cfg(feature = "time")]
-    idle_dur: Option<Duration>,
+    prune_all_slots: Option<fn(&mut Self)>,
     #[cfg(feature = "time")]
     _drop: Option<local_sync::oneshot::Receiver<()>>,
 }
 
 impl<K, IO> PoolInner<K, IO> {
     #[cfg(feature = "time")]
-    fn new_with_dropper(max_idle: Option<usize>) -> (local_sync::oneshot::Sender<()>, Self) {
-        let idle_conns = HashMap::with_capacity(DEFAULT_POOL_SIZE);
-        let max_idle = max_idle
-            .map(|n| n.min(MAX_KEEPALIVE_CONNS))
-            .unwrap_or(DEFAULT_KEEPALIVE_CONNS);
-
+    fn new_with_dropper(config: PoolConfig) -> (local_sync::oneshot::Sender<()>, Self) {
         let (tx, drop) = local_sync::oneshot::channel();
-        (
-            tx,
-            Self {
-                idle_conns,
-                max_idle,
-                idle_dur: None,
-                _drop: Some(drop),
-            },
-        )
+        let mut inner = Self::new(config);
+        inner._drop = Some(drop);
+        (tx, inner)
     }
 
-    fn new(max_idle: Option<usize>) -> Self {
-        let idle_conns = HashMap::with_capacity(DEFAULT_POOL_SIZE);
-        let max_idle = max_idle
-            .map(|n| n.min(MAX_KEEPALIVE_CONNS))
-            .unwrap_or(DEFAULT_KEEPALIVE_CONNS);
+    fn new(config: PoolConfig) -> Self {
         Self {
-            idle_conns,
-            max_idle,
+            idle_conns: HashMap::with_capacity(DEFAULT_POOL_SIZE),
+            config,
+            stats: Counters::default(),
+            epoch: 0,
+            evicted: HashMap::new(),
+            slots: HashMap::new(),
+            overflow: 0,
             #[cfg(feature = "time")]
-            idle_dur: None,
+            prune_all_slots: None,
             #[cfg(feature = "time")]
             _drop: None,
         }
     }
 
-    #[allow(unused)]
-    fn clear_expired(&mut self, dur: Duration) {
-        self.idle_conns.retain(|_, values| {
-            let mut values = values.borrow_mut();
-            values.retain(|entry| !entry.expired(dur));
-            !values.is_empty()
+    fn idle_count(&self) -> usize {
+        self.idle_conns.values().map(|v| v.borrow().len()).sum()
+    }
+
+    /// Returns when a connection established now should be retired, with jitter so
+    /// connections established together are not all closed at once.
+    fn retire_at(&self) -> Option<Instant> {
+        use std::hash::{BuildHasher, Hasher};
+
+        let age = self.config.max_connection_age()?;
+        let random = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        let jitter = self
+            .config
+            .max_connection_age_jitter()
+            .mul_f64((random >> 11) as f64 / (1u64 << 53) as f64);
+        Some(Instant::now() + age + jitter)
+    }
+
+    /// Whether `key` was evicted after a connection was handed out at `epoch`.
+    fn is_evicted(&self, key: &K, epoch: u64) -> bool
+    where
+        K: Key,
+    {
+        self.evicted.get(key).is_some_and(|&at| at > epoch)
+    }
+
+    /// Drops the slots of `key` once it has no reservation, connection in use or idle
+    /// connection left, so keys seen once do not keep them.
+    fn prune_slots(&mut self, key: &K)
+    where
+        K: Key,
+    {
+        // Reservations, and callers queueing for one, hold the slots too.
+        let unused = self
+            .slots
+            .get(key)
+            .is_some_and(|slots| Rc::strong_count(slots) == 1);
+        if unused
+            && self.stats.in_use(key) == 0
+            && self
+                .idle_conns
+                .get(key)
+                .is_none_or(|queue| queue.borrow().is_empty())
+        {
+            self.slots.remove(key);
+        }
+    }
+
+    /// Drops the unused slots of every key, see [`prune_slots`](Self::prune_slots).
+    #[cfg(feature = "time")]
+    fn prune_all_slots(&mut self)
+    where
+        K: Key,
+    {
+        let keys: Vec<K> = self.slots.keys().cloned().collect();
+        for key in keys {
+            self.prune_slots(&key);
+        }
+    }
+
+    /// Closes the least recently used idle connection across all keys, returns false if there
+    /// is none.
+    fn evict_lru(&mut self) -> bool
+    where
+        K: Key,
+    {
+        // Queues are ordered by release, their fronts are their least recently used connections.
+        let Some(key) = self
+            .idle_conns
+            .iter()
+            .filter_map(|(key, queue)| Some((key, queue.borrow().front()?.idle_at)))
+            .min_by_key(|(_, idle_at)| *idle_at)
+            .map(|(key, _)| key.clone())
+        else {
+            return false;
+        };
+        let queue = &self.idle_conns[&key];
+        queue.borrow_mut().pop_front();
+        if queue.borrow().is_empty() {
+            self.idle_conns.remove(&key);
+            self.prune_slots(&key);
+        }
+        self.stats.closed += 1;
+        true
+    }
+
+    /// Closes idle connections until one more connection fits within
+    /// [`PoolConfig::with_max_connections`], returns false if it still does not fit. Overflowing
+    /// connections are not counted, they are closed once released.
+    fn make_room(&mut self) -> bool
+    where
+        K: Key,
+    {
+        let Some(max) = self.config.max_connections() else {
+            return true;
+        };
+        while self.stats.in_use_total() - self.overflow + self.idle_count() >= max {
+            if !self.evict_lru() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Keeps `conn` idle for `key`, closing the oldest connections of `key` beyond its limit,
+    /// or `conn` itself when the pool is full or it must be retired.
+    fn push_idle(&mut self, key: K, conn: IO, retire_at: Option<Instant>)
+    where
+        K: Key,
+        IO: Poolable,
+    {
+        let max_per_key = self.config.max_idle_per_key();
+        let idle = Idle::new(conn, retire_at);
+        if max_per_key == 0 || idle.retired() || !self.make_room() {
+            self.stats.closed += 1;
+            return;
+        }
+        let queue = self.idle_conns.entry(key).or_default().clone();
+        {
+            let mut queue = queue.borrow_mut();
+            while queue.len() >= max_per_key {
+                queue.pop_front();
+                self.stats.closed += 1;
+            }
+        }
+        if self
+            .config
+            .max_idle()
+            .is_some_and(|max| self.idle_count() >= max)
+        {
+            self.stats.closed += 1;
+            return;
+        }
+        queue.borrow_mut().push_back(idle);
+    }
+
+    /// Takes the idle connection of `key` to reuse next, closing the expired ones on the way.
+    fn pop_idle(&mut self, key: &K) -> Option<Idle<IO>>
+    where
+        K: Key,
+    {
+        let queue = self.idle_conns.get(key)?;
+        loop {
+            let idle = match self.config.reuse_order() {
+                ReuseOrder::Fifo => queue.borrow_mut().pop_front(),
+                ReuseOrder::Lifo => queue.borrow_mut().pop_back(),
+            }?;
+            if idle.timed_out(self.config.idle_timeout()) || idle.retired() {
+                self.stats.closed += 1;
+                continue;
+            }
+            return Some(idle);
+        }
+    }
+
+    /// Closes expired idle connections, and drops the buckets of keys without any left.
+    #[cfg(feature = "time")]
+    fn reap(&mut self) {
+        let timeout = self.config.idle_timeout();
+        let closed = &mut self.stats.closed;
+        self.idle_conns.retain(|_, queue| {
+            let mut queue = queue.borrow_mut();
+            let len = queue.len();
+            queue.retain(|idle| !idle.timed_out(timeout) && !idle.retired());
+            *closed += (len - queue.len()) as u64;
+            // Gives back the memory of past bursts.
+            if queue.capacity() > 2 * queue.len() + DEFAULT_POOL_SIZE {
+                queue.shrink_to_fit();
+            }
+            !queue.is_empty()
         });
+        if let Some(prune) = self.prune_all_slots {
+            prune(self);
+        }
     }
 }
 
@@ -321,31 +570,44 @@ impl<K: 'static, T: 'static> ConnectionPool<K, T> {
     ) -> Self {
         const MIN_INTERVAL: Duration = Duration::from_secs(1);
 
-        if let Some(idle_interval) = idle_interval {
-            let idle_dur = idle_interval;
-            let idle_interval = idle_interval.max(MIN_INTERVAL);
+        let config = PoolConfig::new()
+            .with_max_idle_per_key(max_idle.unwrap_or(DEFAULT_KEEPALIVE_CONNS))
+            .with_idle_timeout(idle_interval)
+            .with_reap_interval(idle_interval.map(|interval| interval.max(MIN_INTERVAL)));
+        Self::with_config(config)
+    }
 
-            let (tx, inner) = PoolInner::new_with_dropper(max_idle);
+    #[inline]
+    pub fn new(max_idle: Option<usize>) -> Self {
+        Self::with_config(
+            PoolConfig::new().with_max_idle_per_key(max_idle.unwrap_or(DEFAULT_KEEPALIVE_CONNS)),
+        )
+    }
+
+    /// Creates a pool with the limits of `config`. Expired connections are closed when the
+    /// pool looks for an idle connection of their key, or periodically with
+    /// [`PoolConfig::with_reap_interval`].
+    pub fn with_config(config: PoolConfig) -> Self {
+        #[cfg(feature = "time")]
+        if let Some(interval) = config.reap_interval() {
+            let (tx, inner) = PoolInner::new_with_dropper(config);
             let shared = Rc::new(UnsafeCell::new(inner));
             monoio::spawn(IdleTask {
                 tx,
                 conns: Rc::downgrade(&shared),
-                interval: monoio::time::interval(idle_interval),
-                idle_dur,
+                interval: monoio::time::interval(interval),
             });
-
-            Self { shared }
-        } else {
-            let shared = Rc::new(UnsafeCell::new(PoolInner::new(max_idle)));
-            Self { shared }
+            return Self { shared };
+        }
+        Self {
+            shared: Rc::new(UnsafeCell::new(PoolInner::new(config))),
         }
     }
 
     #[inline]
-    pub fn new(max_idle: Option<usize>) -> Self {
-        Self {
-            shared: Rc::new(UnsafeCell::new(PoolInner::new(max_idle))),
-        }
+    pub fn config(&self) -> PoolConfig {
+        let inner: &PoolInner<K, T> = unsafe { &*self.shared.get() };
+        inner.config
     }
 }
 
@@ -361,64 +623,28 @@ impl<K: Key, T: Poolable> ConnectionPool<K, T> {
     #[inline]
     pub fn get(&self, key: &K) -> Option<Pooled<K, T>> {
         let inner = unsafe { &mut *self.shared.get() };
-
-        #[cfg(feature = "time")]
-        loop {
-            let r = match inner.idle_conns.get_mut(key) {
-                Some(v) => match v.borrow_mut().pop_front() {
-                    Some(idle) if !idle.expired_opt(inner.idle_dur) => Some(Pooled::new(
-                        key.to_owned(),
-                        idle.conn,
-                        true,
-                        Rc::downgrade(&self.shared),
-                        Some(Rc::downgrade(v)),
-                        inner.max_idle,
-                    )),
-                    Some(_) => {
-                        continue;
-                    }
-                    None => None,
-                },
-                None => None,
-            };
-            return r;
-        }
-
-        #[cfg(not(feature = "time"))]
-        match inner.idle_conns.get_mut(key) {
-            Some(v) => match v.pop_front() {
-                Some(idle) => Some(Pooled::new(
-                    key.to_owned(),
-                    idle.conn,
-                    true,
-                    Rc::downgrade(&self.shared),
-                )),
-                None => None,
-            },
-            None => None,
-        }
+        let Some(idle) = inner.pop_idle(key) else {
+            inner.stats.misses += 1;
+            return None;
+        };
+        inner.stats.hits += 1;
+        inner.stats.acquire(key);
+        Some(Pooled::new(
+            key.to_owned(),
+            idle.conn,
+            true,
+            Rc::downgrade(&self.shared),
+            idle.retire_at,
+            inner.epoch,
+        ))
     }
 
     #[inline]
     pub fn put(&self, key: K, conn: T) {
         let inner = unsafe { &mut *self.shared.get() };
-        let queue =
-            inner
-                .idle_conns
-                .entry(key)
-                .or_insert(Rc::new(RefCell::new(VecDeque::with_capacity(
-                    inner.max_idle,
-                ))));
-
-        if queue.borrow().len() > inner.max_idle {
-            for _ in 0..queue.borrow().len() - inner.max_idle {
-                let _ = queue.borrow_mut().pop_front();
-            }
-            let _ = queue.borrow_mut().pop_front();
-        }
-
-        let idle = Idle::new(conn);
-        queue.borrow_mut().push_back(idle);
+        let retire_at = inner.retire_at();
+        inner.stats.created += 1;
+        inner.push_idle(key, conn, retire_at);
     }
 
     /// Get a reference to the element and apply f with map.
@@ -451,38 +677,173 @@ impl<K: Key, T: Poolable> ConnectionPool<K, T> {
             .and_then(f)
     }
 
+    /// Hands out the new connection `conn` of `key`, closing idle connections to fit it within
+    /// [`PoolConfig::with_max_connections`]. When the connections in use already fill the pool,
+    /// `conn` is closed once released instead of being kept idle.
     #[inline]
     pub fn link(&self, key: K, conn: T) -> Pooled<K, T> {
         #[cfg(feature = "logging")]
         tracing::debug!("linked new connection to the pool");
 
         let inner = unsafe { &mut *self.shared.get() };
-        let queue = inner.idle_conns.get_mut(&key).map(|v| Rc::downgrade(v));
-
-        Pooled::new(
+        let overflow = !inner.make_room();
+        inner.overflow += overflow as usize;
+        inner.stats.created += 1;
+        inner.stats.acquire(&key);
+        let mut pooled = Pooled::new(
             key,
             conn,
             false,
             Rc::downgrade(&self.shared),
-            queue,
-            inner.max_idle,
-        )
+            inner.retire_at(),
+            inner.epoch,
+        );
+        pooled.overflow = overflow;
+        pooled
     }
 
     #[inline]
     pub fn get_idle_connection_count(&self) -> usize {
         let inner: &PoolInner<K, T> = unsafe { &*self.shared.get() };
-        inner.idle_conns.values().map(|v| v.borrow().len()).sum()
+        inner.idle_count()
+    }
+
+    /// Returns a snapshot of the connections and counters of the pool.
+    pub fn stats(&self) -> PoolStats<K> {
+        let inner: &PoolInner<K, T> = unsafe { &*self.shared.get() };
+        inner.stats.snapshot(
+            inner
+                .idle_conns
+                .iter()
+                .map(|(key, queue)| (key.clone(), queue.borrow().len())),
+        )
+    }
+
+    /// Reserves a slot for a connection of `key`, queueing while
+    /// [`PoolConfig::with_max_connections_per_key`] connections of `key` are reserved. Callers
+    /// should then look for an idle connection, and establish one otherwise.
+    ///
+    /// Reservations are free without a limit.
+    pub async fn reserve(&self, key: &K) -> Result<Reservation, QueueTimeout>
+    where
+        T: 'static,
+    {
+        let Some((slots, timeout)) = self.slots(key) else {
+            return Ok(Reservation::default());
+        };
+        let acquired = match timeout {
+            Some(timeout) => monoio::time::timeout(timeout, slots.acquire_owned())
+                .await
+                .map_err(|_| QueueTimeout(timeout)),
+            None => Ok(slots.acquire_owned().await),
+        };
+        // Queueing in vain may leave the slots of `key` unused.
+        let release = self.release_slots(key);
+        let permit = match acquired {
+            Ok(permit) => permit.expect("the slots of a key are never closed"),
+            Err(e) => {
+                release();
+                return Err(e);
+            }
+        };
+        Ok(Reservation {
+            _permit: Some(permit),
+            release: Some(release),
+        })
+    }
+
+    /// Reserves a slot for a connection of `key` like [`reserve`](Self::reserve), or returns
+    /// `None` right away when all slots of `key` are reserved.
+    pub fn try_reserve(&self, key: &K) -> Option<Reservation>
+    where
+        T: 'static,
+    {
+        let Some((slots, _)) = self.slots(key) else {
+            return Some(Reservation::default());
+        };
+        let permit = slots.try_acquire_owned().ok()?;
+        Some(Reservation {
+            _permit: Some(permit),
+            release: Some(self.release_slots(key)),
+        })
+    }
+
+    /// Returns a callback dropping the slots of `key` if they are no longer used.
+    fn release_slots(&self, key: &K) -> Box<dyn FnOnce()>
+    where
+        T: 'static,
+    {
+        let pool = Rc::downgrade(&self.shared);
+        let key = key.clone();
+        Box::new(move || {
+            if let Some(pool) = pool.upgrade() {
+                unsafe { &mut *pool.get() }.prune_slots(&key);
+            }
+        })
+    }
+
+    /// Returns the slots of `key` and the queue timeout, `None` without a limit.
+    fn slots(&self, key: &K) -> Option<(Rc<Semaphore>, Option<Duration>)> {
+        let inner = unsafe { &mut *self.shared.get() };
+        let max = inner.config.max_connections_per_key()?;
+        #[cfg(feature = "time")]
+        {
+            inner.prune_all_slots = Some(PoolInner::prune_all_slots);
+        }
+        let slots = inner
+            .slots
+            .entry(key.clone())
+            .or_insert_with(|| Rc::new(Semaphore::new(max)))
+            .clone();
+        Some((slots, inner.config.queue_timeout()))
+    }
+
+    /// Returns the keys with idle or in use connections, in no particular order.
+    #[inline]
+    pub fn keys(&self) -> Vec<KeyStats<K>> {
+        self.stats().keys
+    }
+
+    /// Closes the idle connections of `key`, and the ones in use once they are released, so
+    /// the next lookups of `key` connect again. Returns how many idle connections were closed.
+    ///
+    /// Other keys are left untouched, e.g. after rotating the credentials of one upstream.
+    pub fn evict(&self, key: &K) -> usize {
+        let inner = unsafe { &mut *self.shared.get() };
+        let closed = inner
+            .idle_conns
+            .remove(key)
+            .map_or(0, |queue| queue.borrow().len());
+        inner.stats.closed += closed as u64;
+        inner.epoch += 1;
+        if inner.stats.in_use(key) > 0 {
+            inner.evicted.insert(key.clone(), inner.epoch);
+        }
+        inner.prune_slots(key);
+        closed
+    }
+
+    /// Closes the idle connections of every key, and the ones in use once they are released.
+    /// Returns how many idle connections were closed.
+    pub fn evict_all(&self) -> usize {
+        let keys: Vec<K> = self.keys().into_iter().map(|stats| stats.key).collect();
+        keys.iter().map(|key| self.evict(key)).sum()
+    }
+
+    /// Records how long a caller waited to get a connection, reused or new.
+    #[inline]
+    pub fn record_wait(&self, wait: Duration) {
+        let inner = unsafe { &mut *self.shared.get() };
+        inner.stats.record_wait(wait);
     }
 }
 
-// TODO: make interval not eq to idle_dur
+/// Reaps the idle connections of a pool until the pool is dropped.
 #[cfg(feature = "time")]
 struct IdleTask<K, T> {
     tx: local_sync::oneshot::Sender<()>,
     conns: WeakPool<K, T>,
     interval: monoio::time::Interval,
-    idle_dur: Duration,
 }
 
 #[cfg(feature = "time")]
@@ -507,7 +868,7 @@ impl<K, T> std::future::Future for IdleTask<K, T> {
             std::task::ready!(this.interval.poll_tick(cx));
             if let Some(inner) = this.conns.upgrade() {
                 let inner_mut = unsafe { &mut *inner.get() };
-                inner_mut.clear_expired(this.idle_dur);
+                inner_mut.reap();
                 #[cfg(feature = "logging")]
                 tracing::debug!("pool clear expired");
                 continue;
@@ -518,3 +879,235 @@ impl<K, T> std::future::Future for IdleTask<K, T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Conn(u32);
+
+    impl Poolable for Conn {
+        fn is_open(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn limits_idle_connections() {
+        let pool = ConnectionPool::with_config(
+            PoolConfig::new()
+                .with_max_idle_per_key(2)
+                .with_max_idle(Some(3)),
+        );
+        for id in 0..3 {
+            drop(pool.link("a", Conn(id)));
+        }
+        // The oldest connection of `a` was closed.
+        assert_eq!(pool.get_idle_connection_count(), 2);
+        drop(pool.link("b", Conn(3)));
+        drop(pool.link("c", Conn(4)));
+        // The pool is full, `c` was closed.
+        assert_eq!(pool.get_idle_connection_count(), 3);
+        assert!(pool.get(&"c").is_none());
+        assert_eq!(pool.get(&"a").unwrap().0, 1);
+
+        let pool =
+            ConnectionPool::with_config(PoolConfig::new().with_reuse_order(ReuseOrder::Lifo));
+        pool.put("a", Conn(0));
+        pool.put("a", Conn(1));
+        assert_eq!(pool.get(&"a").unwrap().0, 1);
+
+        let pool = ConnectionPool::with_config(
+            PoolConfig::new().with_idle_timeout(Some(Duration::from_millis(1))),
+        );
+        pool.put("a", Conn(0));
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(pool.get(&"a").is_none());
+        assert_eq!(pool.get_idle_connection_count(), 0);
+    }
+
+    #[test]
+    fn counts_connections() {
+        let pool = ConnectionPool::with_config(PoolConfig::new().with_max_idle_per_key(1));
+        assert!(pool.get(&"a").is_none());
+        let first = pool.link("a", Conn(0));
+        drop(pool.link("a", Conn(1)));
+        let reused = pool.get(&"a").unwrap();
+        drop(first);
+        pool.record_wait(Duration::from_millis(4));
+        pool.record_wait(Duration::from_millis(2));
+
+        let stats = pool.stats();
+        assert_eq!(
+            stats.keys,
+            [KeyStats {
+                key: "a",
+                idle: 1,
+                in_use: 1,
+            }]
+        );
+        assert_eq!((stats.created, stats.closed), (2, 0));
+        assert_eq!(stats.hit_rate(), 0.5);
+        assert_eq!(stats.mean_wait(), Some(Duration::from_millis(3)));
+        assert_eq!(stats.max_wait, Duration::from_millis(4));
+        drop(reused);
+        // The bucket of `a` only keeps one connection.
+        assert_eq!(pool.stats().closed, 1);
+    }
+
+    #[test]
+    fn evicts_keys() {
+        let pool = ConnectionPool::default();
+        let conn = pool.link("a", Conn(0));
+        pool.put("a", Conn(1));
+        pool.put("b", Conn(2));
+        assert_eq!(pool.evict(&"a"), 1);
+        let mut keys = pool.keys();
+        keys.sort_by_key(|stats| stats.key);
+        assert_eq!(
+            keys,
+            [
+                KeyStats {
+                    key: "a",
+                    idle: 0,
+                    in_use: 1,
+                },
+                KeyStats {
+                    key: "b",
+                    idle: 1,
+                    in_use: 0,
+                },
+            ]
+        );
+        // Connections in use when their key was evicted are closed once released.
+        drop(conn);
+        assert!(pool.get(&"a").is_none());
+        drop(pool.link("a", Conn(3)));
+        assert_eq!(pool.get(&"a").unwrap().0, 3);
+        assert_eq!(pool.stats().closed, 2);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let pool = ConnectionPool::with_config(PoolConfig::new().with_max_connections(Some(3)));
+        pool.put("a", Conn(0));
+        pool.put("b", Conn(1));
+        pool.put("a", Conn(2));
+        // The oldest idle connection, of `a`, makes room for `c`.
+        let conn = pool.link("c", Conn(3));
+        assert_eq!(pool.stats().closed, 1);
+        let (a, b) = (pool.get(&"a").unwrap(), pool.get(&"b").unwrap());
+        assert_eq!((a.0, b.0), (2, 1));
+        // Every connection is in use, the released ones are closed until the pool fits.
+        let extra = pool.link("d", Conn(4));
+        drop(extra);
+        assert_eq!(pool.get_idle_connection_count(), 0);
+        drop(conn);
+        assert_eq!(pool.get_idle_connection_count(), 1);
+    }
+
+    #[test]
+    fn closes_overflowing_connections() {
+        let pool = ConnectionPool::with_config(PoolConfig::new().with_max_connections(Some(1)));
+        let conn = pool.link("a", Conn(0));
+        let extra = pool.link("a", Conn(1));
+        // The connection linked beyond the limit does not take the room of the other one.
+        drop(conn);
+        drop(extra);
+        assert_eq!(pool.get(&"a").unwrap().0, 0);
+        assert_eq!(pool.stats().closed, 1);
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn queues_for_connections() {
+        let pool = ConnectionPool::with_config(
+            PoolConfig::new()
+                .with_max_connections_per_key(Some(1))
+                .with_queue_timeout(Some(Duration::from_millis(20))),
+        );
+        let mut conn = pool.link("a", Conn(0));
+        conn.hold(pool.reserve(&"a").await.unwrap());
+        assert_eq!(
+            pool.reserve(&"a").await.unwrap_err(),
+            QueueTimeout(Duration::from_millis(20))
+        );
+        pool.reserve(&"b").await.unwrap();
+
+        let queued = monoio::spawn({
+            let pool = pool.clone();
+            async move {
+                let _reservation = pool.reserve(&"a").await.unwrap();
+                pool.get(&"a").map(|conn| conn.0)
+            }
+        });
+        monoio::time::sleep(Duration::from_millis(5)).await;
+        drop(conn);
+        assert_eq!(queued.await, Some(0));
+    }
+
+    #[monoio::test(enable_timer = true)]
+    async fn drops_unused_slots() {
+        let pool = ConnectionPool::with_config(
+            PoolConfig::new()
+                .with_max_connections_per_key(Some(1))
+                .with_queue_timeout(Some(Duration::from_millis(5))),
+        );
+        let slots = |pool: &ConnectionPool<&'static str, Conn>| {
+            let inner = unsafe { &*pool.shared.get() };
+            let mut keys: Vec<_> = inner.slots.keys().copied().collect();
+            keys.sort_unstable();
+            keys
+        };
+        // Connecting failed.
+        drop(pool.reserve(&"a").await.unwrap());
+        drop(pool.try_reserve(&"b").unwrap());
+        assert!(slots(&pool).is_empty());
+
+        let mut conn = pool.link("a", Conn(0));
+        conn.hold(pool.reserve(&"a").await.unwrap());
+        assert!(pool.reserve(&"a").await.is_err());
+        drop(conn);
+        // The idle connection keeps the slots of `a`.
+        assert_eq!(slots(&pool), ["a"]);
+        pool.evict(&"a");
+        assert!(slots(&pool).is_empty());
+    }
+
+    #[test]
+    fn retires_old_connections() {
+        let pool = ConnectionPool::with_config(
+            PoolConfig::new()
+                .with_max_connection_age(Some(Duration::from_millis(5)))
+                .with_max_connection_age_jitter(Duration::from_millis(5)),
+        );
+        let conn = pool.link("a", Conn(0));
+        drop(pool.link("a", Conn(1)));
+        assert_eq!(pool.get(&"a").unwrap().0, 1);
+        std::thread::sleep(Duration::from_millis(15));
+        // Connections in use are retired once released, idle ones when looked up.
+        drop(conn);
+        assert_eq!(pool.get_idle_connection_count(), 1);
+        assert!(pool.get(&"a").is_none());
+        pool.put("a", Conn(2));
+        assert!(pool.get(&"a").is_some());
+    }
+
+    #[cfg(feature = "time")]
+    #[monoio::test(enable_timer = true)]
+    async fn reaps_expired_connections() {
+        let pool = ConnectionPool::with_config(
+            PoolConfig::new()
+                .with_idle_timeout(Some(Duration::from_millis(10)))
+                .with_reap_interval(Some(Duration::from_millis(20)))
+                .with_max_connections_per_key(Some(1)),
+        );
+        let mut conn = pool.link("a", Conn(0));
+        conn.hold(pool.reserve(&"a").await.unwrap());
+        drop(conn);
+        pool.put("b", Conn(1));
+        monoio::time::sleep(Duration::from_millis(60)).await;
+        let inner = unsafe { &*pool.shared.get() };
+        assert!(inner.idle_conns.is_empty());
+        assert!(inner.slots.is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+use std::{collections::HashMap, hash::Hash, time::Duration};
+
+/// A snapshot of the activity of a [`ConnectionPool`](super::ConnectionPool).
+///
+/// Counters start when the pool is created and are shared by its clones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolStats<K> {
+    /// The keys with idle or in use connections, in no particular order.
+    pub keys: Vec<KeyStats<K>>,
+    /// Connections added to the pool.
+    pub created: u64,
+    /// Connections closed by the pool, or found closed when released.
+    pub closed: u64,
+    /// Lookups that reused an idle connection.
+    pub hits: u64,
+    /// Lookups that found no idle connection.
+    pub misses: u64,
+    /// How many times a connection was waited for, and the total and longest waits.
+    pub waits: u64,
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+}
+
+/// The connections of a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyStats<K> {
+    pub key: K,
+    pub idle: usize,
+    pub in_use: usize,
+}
+
+impl<K> PoolStats<K> {
+    /// The share of lookups that reused a connection, 0 before any lookup.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+
+    /// The mean time waited for a connection.
+    pub fn mean_wait(&self) -> Option<Duration> {
+        let waits = u32::try_from(self.waits).unwrap_or(u32::MAX);
+        (waits > 0).then(|| self.total_wait / waits)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Counters<K> {
+    in_use: HashMap<K, usize>,
+    in_use_total: usize,
+    pub(crate) created: u64,
+    pub(crate) closed: u64,
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+    waits: u64,
+    total_wait: Duration,
+    max_wait: Duration,
+}
+
+impl<K> Default for Counters<K> {
+    fn default() -> Self {
+        Self {
+            in_use: HashMap::new(),
+            in_use_total: 0,
+            created: 0,
+            closed: 0,
+            hits: 0,
+            misses: 0,
+            waits: 0,
+            total_wait: Duration::ZERO,
+            max_wait: Duration::ZERO,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> Counters<K> {
+    pub(crate) fn acquire(&mut self, key: &K) {
+        self.in_use_total += 1;
+        match self.in_use.get_mut(key) {
+            Some(count) => *count += 1,
+            None => {
+                self.in_use.insert(key.clone(), 1);
+            }
+        }
+    }
+
+    /// Returns how many connections of `key` are still in use.
+    pub(crate) fn release(&mut self, key: &K) -> usize {
+        let Some(count) = self.in_use.get_mut(key) else {
+            return 0;
+        };
+        self.in_use_total -= 1;
+        *count -= 1;
+        let left = *count;
+        if left == 0 {
+            self.in_use.remove(key);
+        }
+        left
+    }
+
+    #[inline]
+    pub(crate) fn in_use(&self, key: &K) -> usize {
+        self.in_use.get(key).copied().unwrap_or(0)
+    }
+
+    #[inline]
+    pub(crate) fn in_use_total(&self) -> usize {
+        self.in_use_total
+    }
+
+    pub(crate) fn record_wait(&mut self, wait: Duration) {
+        self.waits += 1;
+        self.total_wait += wait;
+        self.max_wait = self.max_wait.max(wait);
+    }
+
+    /// Returns the counters, with the idle connection count of each key.
+    pub(crate) fn snapshot(&self, idle: impl Iterator<Item = (K, usize)>) -> PoolStats<K> {
+        let mut keys: HashMap<K, KeyStats<K>> = HashMap::new();
+        for (key, idle) in idle.filter(|(_, idle)| *idle > 0) {
+            keys.insert(
+                key.clone(),
+                KeyStats {
+                    key,
+                    idle,
+                    in_use: 0,
+                },
+            );
+        }
+        for (key, in_use) in &self.in_use {
+            keys.entry(key.clone())
+                .or_insert_with(|| KeyStats {
+                    key: key.clone(),
+                    idle: 0,
+                    in_use: 0,
+                })
+                .in_use = *in_use;
+        }
+        PoolStats {
+            keys: keys.into_values().collect(),
+            created: self.created,
+            closed: self.closed,
+            hits: self.hits,
+            misses: self.misses,
+            waits: self.waits,
+            total_wait: self.total_wait,
+            max_wait: self.max_wait,
+        }
+    }
+}